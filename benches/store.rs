@@ -0,0 +1,120 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use std::{fs, path::Path};
+use tuggerah::data::{
+    binary_file_entry_store::BinaryFileEntryStore, data_store::DataStore,
+    favorites::FavoriteFilter, generator::generate_entries,
+    indexed_binary_file_entry_store::IndexedBinaryFileEntryStore, model::Entry,
+};
+use uuid::Uuid;
+
+const ENTRY_COUNTS: [usize; 2] = [100, 1000];
+const FIELD_SIZE: usize = 32;
+
+fn bench_path(name: &str) -> String {
+    format!("bench_{}_{}.bin", name, Uuid::new_v4())
+}
+
+// Takes the store, as dropping it writes its shutdown files next to `paths`
+fn cleanup<S>(store: S, paths: &[&str]) {
+    drop(store);
+    for path in paths {
+        let sidecars = ["journal", "bloom", "clean", "mac", "secondary"];
+        let sidecars = sidecars.iter().map(|ext| format!("{}.{}", path, ext));
+        for path in std::iter::once(path.to_string()).chain(sidecars) {
+            if Path::new(&path).exists() {
+                fs::remove_file(path).unwrap();
+            }
+        }
+    }
+}
+
+fn simple_store(entries: &[Entry]) -> (BinaryFileEntryStore, String) {
+    let path = bench_path("simple");
+    let mut store = BinaryFileEntryStore::new(path.clone());
+    for entry in entries {
+        store.save(&entry.id, entry).unwrap();
+    }
+    (store, path)
+}
+
+fn indexed_store(entries: &[Entry]) -> (IndexedBinaryFileEntryStore, String, String) {
+    let data_path = bench_path("data");
+    let index_path = bench_path("index");
+    let mut store = IndexedBinaryFileEntryStore::new(data_path.clone(), index_path.clone());
+    for entry in entries {
+        store.save(&entry.id, entry).unwrap();
+    }
+    (store, data_path, index_path)
+}
+
+fn bench_simple_store(c: &mut Criterion) {
+    let mut group = c.benchmark_group("binary_file_entry_store");
+    group.sample_size(10);
+
+    for count in ENTRY_COUNTS {
+        let entries = generate_entries(1, count, FIELD_SIZE);
+        let (mut store, path) = simple_store(&entries);
+        let extra = generate_entries(2, 1, FIELD_SIZE).remove(0);
+        let last_id = entries.last().unwrap().id.clone();
+
+        group.bench_with_input(BenchmarkId::new("save", count), &extra, |b, entry| {
+            b.iter(|| store.save(&entry.id, entry).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("load", count), &last_id, |b, id| {
+            b.iter(|| store.load(id).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("search", count), |b| {
+            b.iter(|| store.search(&FavoriteFilter).unwrap())
+        });
+
+        cleanup(store, &[&path]);
+    }
+
+    group.finish();
+}
+
+fn bench_indexed_store(c: &mut Criterion) {
+    let mut group = c.benchmark_group("indexed_binary_file_entry_store");
+    group.sample_size(10);
+
+    for count in ENTRY_COUNTS {
+        let entries = generate_entries(1, count, FIELD_SIZE);
+        let (mut store, data_path, index_path) = indexed_store(&entries);
+        let extra = generate_entries(2, 1, FIELD_SIZE).remove(0);
+        let last_id = entries.last().unwrap().id.clone();
+
+        group.bench_with_input(BenchmarkId::new("save", count), &extra, |b, entry| {
+            b.iter(|| store.save(&entry.id, entry).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("load", count), &last_id, |b, id| {
+            b.iter(|| store.load(id).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("search", count), |b| {
+            b.iter(|| store.search(&FavoriteFilter).unwrap())
+        });
+        cleanup(store, &[&data_path, &index_path]);
+
+        // Compaction of a store where half of the entries were deleted
+        group.bench_function(BenchmarkId::new("compaction", count), |b| {
+            b.iter_batched(
+                || {
+                    let (mut store, data_path, index_path) = indexed_store(&entries);
+                    for entry in entries.iter().step_by(2) {
+                        store.delete(&entry.id).unwrap();
+                    }
+                    (store, data_path, index_path)
+                },
+                |(mut store, data_path, index_path)| {
+                    store.write_data().unwrap();
+                    cleanup(store, &[&data_path, &index_path]);
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_simple_store, bench_indexed_store);
+criterion_main!(benches);
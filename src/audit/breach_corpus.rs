@@ -0,0 +1,208 @@
+use md4::Md4;
+use memmap2::Mmap;
+use sha1::{Digest, Sha1};
+use std::{cmp::Ordering, fs::File, io, path::Path};
+
+use crate::data::model::Entry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashKind {
+    Sha1,
+    Ntlm,
+}
+
+impl HashKind {
+    fn hash(&self, password: &str) -> String {
+        match self {
+            HashKind::Sha1 => hex::encode_upper(Sha1::digest(password.as_bytes())),
+            HashKind::Ntlm => {
+                let utf16: Vec<u8> = password
+                    .encode_utf16()
+                    .flat_map(|unit| unit.to_le_bytes())
+                    .collect();
+                hex::encode_upper(Md4::digest(&utf16))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreachedEntry {
+    pub id: String,
+    pub occurrences: u64,
+}
+
+// A local copy of a breach corpus in the HIBP "ordered by hash" layout:
+// one `HASH:COUNT` line per password, sorted by hash.
+pub struct BreachCorpus {
+    mmap: Mmap,
+    kind: HashKind,
+}
+
+impl BreachCorpus {
+    pub fn open<P: AsRef<Path>>(path: P, kind: HashKind) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the corpus is opened read-only and is not expected to be
+        // modified while it is mapped.
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(Self { mmap, kind })
+    }
+
+    // Number of times the password appears in the corpus, if it appears at all
+    pub fn occurrences(&self, password: &str) -> Option<u64> {
+        let hash = self.kind.hash(password);
+        self.find(hash.as_bytes()).map(|line| {
+            line.iter()
+                .position(|&b| b == b':')
+                .and_then(|i| std::str::from_utf8(&line[i + 1..]).ok())
+                .and_then(|count| count.trim().parse().ok())
+                .unwrap_or(1)
+        })
+    }
+
+    pub fn is_breached(&self, password: &str) -> bool {
+        self.occurrences(password).is_some()
+    }
+
+    pub fn check_entries(&self, entries: &[Entry]) -> Vec<BreachedEntry> {
+        entries
+            .iter()
+            .filter_map(|entry| {
+                let password = entry.password.as_deref()?;
+                self.occurrences(password).map(|occurrences| BreachedEntry {
+                    id: entry.id.clone(),
+                    occurrences,
+                })
+            })
+            .collect()
+    }
+
+    // Binary search over byte offsets, snapping each probe to its line.
+    fn find(&self, hash: &[u8]) -> Option<&[u8]> {
+        let data = &self.mmap[..];
+        let (mut low, mut high) = (0, data.len());
+
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let start = data[low..middle]
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or(low, |i| low + i + 1);
+            let end = data[middle..high]
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(high, |i| middle + i);
+
+            let line = &data[start..end];
+            match compare_hash(line, hash) {
+                Ordering::Equal => return Some(line),
+                Ordering::Less => low = end + 1,
+                Ordering::Greater => high = start,
+            }
+        }
+
+        None
+    }
+}
+
+fn compare_hash(line: &[u8], hash: &[u8]) -> Ordering {
+    let line_hash = line
+        .iter()
+        .take_while(|&&b| b != b':' && b != b'\r')
+        .map(|b| b.to_ascii_uppercase());
+
+    line_hash.cmp(hash.iter().copied())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use uuid::Uuid;
+
+    fn corpus_file(kind: HashKind, passwords: &[(&str, u64)]) -> String {
+        let mut lines: Vec<String> = passwords
+            .iter()
+            .map(|(password, count)| format!("{}:{}", kind.hash(password), count))
+            .collect();
+        lines.sort();
+
+        let path = format!("test_corpus_{}.txt", Uuid::new_v4());
+        fs::write(&path, lines.join("\r\n")).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_sha1_hash() {
+        assert_eq!(
+            HashKind::Sha1.hash("password"),
+            "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8"
+        );
+    }
+
+    #[test]
+    fn test_ntlm_hash() {
+        assert_eq!(
+            HashKind::Ntlm.hash("password"),
+            "8846F7EAEE8FB117AD06BDD830B7586C"
+        );
+    }
+
+    #[test]
+    fn test_occurrences() {
+        let passwords = [
+            ("password", 100),
+            ("123456", 200),
+            ("qwerty", 3),
+            ("letmein", 4),
+            ("dragon", 5),
+        ];
+        let path = corpus_file(HashKind::Sha1, &passwords);
+        let corpus = BreachCorpus::open(&path, HashKind::Sha1).unwrap();
+
+        for (password, count) in passwords {
+            assert_eq!(corpus.occurrences(password), Some(count));
+        }
+        assert!(!corpus.is_breached("correct horse battery staple"));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_check_entries() {
+        let path = corpus_file(HashKind::Ntlm, &[("password", 7)]);
+        let corpus = BreachCorpus::open(&path, HashKind::Ntlm).unwrap();
+
+        let entry = |id: &str, password: Option<&str>| Entry {
+            id: id.to_string(),
+            title: id.to_string(),
+            password: password.map(|p| p.to_string()),
+            ..Default::default()
+        };
+        let entries = vec![
+            entry("1", Some("password")),
+            entry("2", Some("s3cure!")),
+            entry("3", None),
+        ];
+
+        assert_eq!(
+            corpus.check_entries(&entries),
+            vec![BreachedEntry {
+                id: "1".to_string(),
+                occurrences: 7
+            }]
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_empty_corpus() {
+        let path = corpus_file(HashKind::Sha1, &[]);
+        let corpus = BreachCorpus::open(&path, HashKind::Sha1).unwrap();
+
+        assert!(!corpus.is_breached("password"));
+
+        fs::remove_file(path).unwrap();
+    }
+}
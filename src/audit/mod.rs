@@ -0,0 +1,8 @@
+#[cfg(feature = "breach")]
+pub mod breach_corpus;
+#[cfg(feature = "breach")]
+pub mod notify;
+pub mod password_age;
+#[cfg(feature = "breach")]
+pub mod recheck;
+pub mod token_expiry;
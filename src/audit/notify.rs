@@ -0,0 +1,240 @@
+use std::{
+    fmt, io,
+    process::{Command, ExitStatus},
+};
+
+use super::recheck::AuditEvent;
+
+// Passes on what a `RecheckScheduler` finds, so a long-running process can
+// tell the user about newly breached, weak or expired passwords without
+// them having to run reports:
+//
+//     notifier.notify(&scheduler.tick(Instant::now(), &entries, &checks))?;
+//
+// Events name entries by id only, no password or other secret leaves the
+// process.
+
+#[derive(Debug)]
+pub enum NotifyError {
+    IoError(io::Error),
+    // The notification command ran but reported failure
+    CommandFailed(ExitStatus),
+    #[cfg(feature = "webhook")]
+    HttpError(ureq::Error),
+}
+
+impl From<io::Error> for NotifyError {
+    fn from(error: io::Error) -> Self {
+        NotifyError::IoError(error)
+    }
+}
+
+#[cfg(feature = "webhook")]
+impl From<ureq::Error> for NotifyError {
+    fn from(error: ureq::Error) -> Self {
+        NotifyError::HttpError(error)
+    }
+}
+
+impl fmt::Display for NotifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            NotifyError::IoError(ref err) => write!(f, "I/O error: {}", err),
+            NotifyError::CommandFailed(status) => {
+                write!(f, "Notification command failed: {}", status)
+            }
+            #[cfg(feature = "webhook")]
+            NotifyError::HttpError(ref err) => write!(f, "HTTP error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for NotifyError {}
+
+pub trait Notifier {
+    // Called with the events of one recheck, nothing is sent when empty
+    fn notify(&mut self, events: &[AuditEvent]) -> Result<(), NotifyError>;
+}
+
+pub fn describe(event: &AuditEvent) -> String {
+    match event {
+        AuditEvent::Breached { id, occurrences } => format!(
+            "Password of {} was found in breaches {} times",
+            id, occurrences
+        ),
+        AuditEvent::Weak { id, violation } => format!("{}: {}", id, violation),
+        AuditEvent::Expired { id, .. } => format!("Password of {} is due to be changed", id),
+    }
+}
+
+fn kind(event: &AuditEvent) -> &'static str {
+    match event {
+        AuditEvent::Breached { .. } => "breached",
+        AuditEvent::Weak { .. } => "weak",
+        AuditEvent::Expired { .. } => "expired",
+    }
+}
+
+// Runs a command with the events, one per line, as its last argument
+pub struct CommandNotifier {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandNotifier {
+    pub fn new(program: &str, args: &[&str]) -> Self {
+        CommandNotifier {
+            program: program.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    // A desktop notification through the tool the platform ships with,
+    // None where there is none
+    pub fn desktop() -> Option<Self> {
+        if cfg!(target_os = "linux") {
+            Some(Self::new("notify-send", &["Tuggerah"]))
+        } else if cfg!(target_os = "macos") {
+            // The message comes in as an argument, so it needs no quoting
+            Some(Self::new(
+                "osascript",
+                &[
+                    "-e",
+                    "on run argv",
+                    "-e",
+                    "display notification (item 1 of argv) with title \"Tuggerah\"",
+                    "-e",
+                    "end run",
+                ],
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl Notifier for CommandNotifier {
+    fn notify(&mut self, events: &[AuditEvent]) -> Result<(), NotifyError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let message: Vec<String> = events.iter().map(describe).collect();
+        let status = Command::new(&self.program)
+            .args(&self.args)
+            .arg(message.join("\n"))
+            .status()?;
+        if !status.success() {
+            return Err(NotifyError::CommandFailed(status));
+        }
+        Ok(())
+    }
+}
+
+// The body a `WebhookNotifier` posts, e.g.
+// {"events":[{"kind":"breached","id":"…","message":"…"}]}
+pub fn webhook_payload(events: &[AuditEvent]) -> serde_json::Value {
+    let events: Vec<serde_json::Value> = events
+        .iter()
+        .map(|event| {
+            let id = match event {
+                AuditEvent::Breached { id, .. }
+                | AuditEvent::Weak { id, .. }
+                | AuditEvent::Expired { id, .. } => id,
+            };
+            serde_json::json!({
+                "kind": kind(event),
+                "id": id,
+                "message": describe(event),
+            })
+        })
+        .collect();
+    serde_json::json!({ "events": events })
+}
+
+// Posts the events as JSON to a url, e.g. a chat or paging webhook
+#[cfg(feature = "webhook")]
+pub struct WebhookNotifier {
+    url: String,
+    agent: ureq::Agent,
+}
+
+#[cfg(feature = "webhook")]
+impl WebhookNotifier {
+    pub fn new(url: &str) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(std::time::Duration::from_secs(10)))
+            .build()
+            .into();
+        WebhookNotifier {
+            url: url.to_string(),
+            agent,
+        }
+    }
+}
+
+#[cfg(feature = "webhook")]
+impl Notifier for WebhookNotifier {
+    fn notify(&mut self, events: &[AuditEvent]) -> Result<(), NotifyError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        self.agent
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .send(webhook_payload(events).to_string())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secret::password_policy::PolicyViolation;
+
+    fn events() -> Vec<AuditEvent> {
+        vec![
+            AuditEvent::Breached {
+                id: "1".to_string(),
+                occurrences: 3,
+            },
+            AuditEvent::Weak {
+                id: "2".to_string(),
+                violation: PolicyViolation::DenyListed,
+            },
+            AuditEvent::Expired {
+                id: "3".to_string(),
+                password_changed_at: 1,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_webhook_payload() {
+        let payload = webhook_payload(&events());
+        let kinds: Vec<&str> = payload["events"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|event| event["kind"].as_str().unwrap())
+            .collect();
+        assert_eq!(kinds, vec!["breached", "weak", "expired"]);
+        assert_eq!(
+            payload["events"][1]["message"],
+            "2: Password is on the deny-list"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_notifier() {
+        let mut notifier = CommandNotifier::new("sh", &["-c", "test -n \"$0\""]);
+        notifier.notify(&events()).unwrap();
+
+        let mut failing = CommandNotifier::new("false", &[]);
+        failing.notify(&[]).unwrap();
+        assert!(matches!(
+            failing.notify(&events()),
+            Err(NotifyError::CommandFailed(_))
+        ));
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+
+use crate::data::model::Entry;
+
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+const THREE_MONTHS_MILLIS: u64 = 90 * DAY_MILLIS;
+const YEAR_MILLIS: u64 = 365 * DAY_MILLIS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AgeBucket {
+    UnderThreeMonths,
+    ThreeToTwelveMonths,
+    OverAYear,
+    // No recorded password change, see `Entry::password_changed_at`
+    Unknown,
+}
+
+impl AgeBucket {
+    // By the time the password last changed, so renaming or tagging an
+    // entry doesn't make it younger. Times in the future (a clock that went
+    // back) count as recent.
+    pub fn of(changed_at: u64, now: u64) -> Self {
+        if changed_at == 0 {
+            return AgeBucket::Unknown;
+        }
+        match now.saturating_sub(changed_at) {
+            age if age < THREE_MONTHS_MILLIS => AgeBucket::UnderThreeMonths,
+            age if age < YEAR_MILLIS => AgeBucket::ThreeToTwelveMonths,
+            _ => AgeBucket::OverAYear,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AgeCounts {
+    pub under_three_months: usize,
+    pub three_to_twelve_months: usize,
+    pub over_a_year: usize,
+    pub unknown: usize,
+}
+
+impl AgeCounts {
+    pub fn add(&mut self, bucket: AgeBucket) {
+        match bucket {
+            AgeBucket::UnderThreeMonths => self.under_three_months += 1,
+            AgeBucket::ThreeToTwelveMonths => self.three_to_twelve_months += 1,
+            AgeBucket::OverAYear => self.over_a_year += 1,
+            AgeBucket::Unknown => self.unknown += 1,
+        }
+    }
+
+    pub fn get(&self, bucket: AgeBucket) -> usize {
+        match bucket {
+            AgeBucket::UnderThreeMonths => self.under_three_months,
+            AgeBucket::ThreeToTwelveMonths => self.three_to_twelve_months,
+            AgeBucket::OverAYear => self.over_a_year,
+            AgeBucket::Unknown => self.unknown,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.under_three_months + self.three_to_twelve_months + self.over_a_year + self.unknown
+    }
+}
+
+// Password ages of a vault, overall and per tag. Tags stand in for folders,
+// an entry with several tags is counted under each of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PasswordAgeReport {
+    pub overall: AgeCounts,
+    pub by_tag: BTreeMap<String, AgeCounts>,
+    pub untagged: AgeCounts,
+}
+
+impl PasswordAgeReport {
+    // Only active entries that have a password are counted. `now` is unix
+    // time in milliseconds, like `password_changed_at`.
+    pub fn new(entries: &[Entry], now: u64) -> Self {
+        let mut report = Self::default();
+
+        for entry in entries {
+            if entry.archived || entry.password.is_none() {
+                continue;
+            }
+            let bucket = AgeBucket::of(entry.password_changed_at, now);
+            report.overall.add(bucket);
+            if entry.tags.is_empty() {
+                report.untagged.add(bucket);
+            }
+            for tag in &entry.tags {
+                report.by_tag.entry(tag.clone()).or_default().add(bucket);
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_000 * DAY_MILLIS;
+
+    fn entry(days_old: Option<u64>, tags: &[&str]) -> Entry {
+        Entry {
+            id: "1".to_string(),
+            title: "Example".to_string(),
+            password: Some("hunter2".to_string()),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            password_changed_at: days_old.map_or(0, |days| NOW - days * DAY_MILLIS),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_bucket_boundaries() {
+        let at = |days: u64| AgeBucket::of(NOW - days * DAY_MILLIS, NOW);
+
+        assert_eq!(at(89), AgeBucket::UnderThreeMonths);
+        assert_eq!(at(90), AgeBucket::ThreeToTwelveMonths);
+        assert_eq!(at(364), AgeBucket::ThreeToTwelveMonths);
+        assert_eq!(at(365), AgeBucket::OverAYear);
+        assert_eq!(AgeBucket::of(0, NOW), AgeBucket::Unknown);
+        assert_eq!(
+            AgeBucket::of(NOW + DAY_MILLIS, NOW),
+            AgeBucket::UnderThreeMonths
+        );
+    }
+
+    #[test]
+    fn test_report_by_tag() {
+        let mut archived = entry(Some(500), &["work"]);
+        archived.archived = true;
+        let mut no_password = entry(Some(500), &["work"]);
+        no_password.password = None;
+
+        let entries = vec![
+            entry(Some(10), &["work", "email"]),
+            entry(Some(400), &["work"]),
+            entry(None, &[]),
+            archived,
+            no_password,
+        ];
+
+        let report = PasswordAgeReport::new(&entries, NOW);
+
+        assert_eq!(report.overall.total(), 3);
+        assert_eq!(report.overall.get(AgeBucket::Unknown), 1);
+        assert_eq!(
+            report.by_tag["work"],
+            AgeCounts {
+                under_three_months: 1,
+                over_a_year: 1,
+                ..AgeCounts::default()
+            }
+        );
+        assert_eq!(report.by_tag["email"].total(), 1);
+        assert_eq!(report.untagged.unknown, 1);
+    }
+}
@@ -0,0 +1,274 @@
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use super::breach_corpus::BreachCorpus;
+use crate::data::model::Entry;
+use crate::secret::password_policy::{PasswordPolicy, PolicyViolation};
+
+// What passwords are checked against
+pub struct PasswordChecks<'a> {
+    pub corpus: Option<&'a BreachCorpus>,
+    pub policy: &'a PasswordPolicy,
+}
+
+// A password that was clean when last checked no longer is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditEvent {
+    // It showed up in the breach corpus, e.g. after the corpus was updated
+    Breached {
+        id: String,
+        occurrences: u64,
+    },
+    // It breaks the policy, e.g. after the policy was tightened
+    Weak {
+        id: String,
+        violation: PolicyViolation,
+    },
+    // It is older than the maximum age, see `with_max_age`
+    Expired {
+        id: String,
+        password_changed_at: u64,
+    },
+}
+
+// How a password fared the last time it was checked. It is remembered by
+// digest, so a changed password starts over rather than being compared.
+struct Checked {
+    digest: [u8; 32],
+    breached: bool,
+    weak: bool,
+    expired: bool,
+}
+
+// Re-runs the breach and policy checks every `interval`, or on the next tick
+// after `corpus_updated`, and reports passwords that were clean before.
+// There is no background thread, the caller ticks it from its own loop.
+pub struct RecheckScheduler {
+    interval: Duration,
+    next_run: Option<Instant>,
+    forced: bool,
+    max_age: Option<Duration>,
+    checked: HashMap<String, Checked>,
+}
+
+impl RecheckScheduler {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next_run: None,
+            forced: false,
+            max_age: None,
+            checked: HashMap::new(),
+        }
+    }
+
+    // Also reports passwords older than `max_age`. Like the password age
+    // report, the age is taken from `Entry::password_changed_at`, and entries
+    // that never recorded one don't expire.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    // The corpus was replaced with a newer one, so check again right away
+    pub fn corpus_updated(&mut self) {
+        self.forced = true;
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.forced || self.next_run.is_none_or(|next_run| now >= next_run)
+    }
+
+    // Checks `entries` when due. The first run only records where every
+    // password stands, later runs return an event for each that got worse.
+    // Archived entries and entries without a password are left out.
+    pub fn tick(
+        &mut self,
+        now: Instant,
+        entries: &[Entry],
+        checks: &PasswordChecks,
+    ) -> Vec<AuditEvent> {
+        if !self.is_due(now) {
+            return Vec::new();
+        }
+        self.next_run = Some(now + self.interval);
+        self.forced = false;
+
+        // The cutoff is wall-clock time, as `password_changed_at` is
+        let expired_before = self.max_age.map(|max_age| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_millis() as u64);
+            now.saturating_sub(max_age.as_millis() as u64)
+        });
+
+        let mut events = Vec::new();
+        let mut checked = HashMap::with_capacity(entries.len());
+        for entry in entries.iter().filter(|entry| !entry.archived) {
+            let password = match entry.password.as_deref() {
+                Some(password) => password,
+                None => continue,
+            };
+            let digest: [u8; 32] = Sha256::digest(password.as_bytes()).into();
+            let occurrences = checks
+                .corpus
+                .and_then(|corpus| corpus.occurrences(password));
+            let violation = checks.policy.check(password).err();
+            let expired = expired_before.is_some_and(|before| {
+                entry.password_changed_at != 0 && entry.password_changed_at < before
+            });
+
+            let before = self
+                .checked
+                .get(&entry.id)
+                .filter(|before| before.digest == digest);
+            if let Some(before) = before {
+                match occurrences {
+                    Some(occurrences) if !before.breached => events.push(AuditEvent::Breached {
+                        id: entry.id.clone(),
+                        occurrences,
+                    }),
+                    _ => {}
+                }
+                match &violation {
+                    Some(violation) if !before.weak => events.push(AuditEvent::Weak {
+                        id: entry.id.clone(),
+                        violation: violation.clone(),
+                    }),
+                    _ => {}
+                }
+                if expired && !before.expired {
+                    events.push(AuditEvent::Expired {
+                        id: entry.id.clone(),
+                        password_changed_at: entry.password_changed_at,
+                    });
+                }
+            }
+
+            checked.insert(
+                entry.id.clone(),
+                Checked {
+                    digest,
+                    breached: occurrences.is_some(),
+                    weak: violation.is_some(),
+                    expired,
+                },
+            );
+        }
+        self.checked = checked;
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::breach_corpus::HashKind;
+    use sha1::Sha1;
+    use std::fs;
+    use uuid::Uuid;
+
+    fn entry(id: &str, password: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: id.to_string(),
+            password: Some(password.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn write_corpus(path: &str, passwords: &[&str]) {
+        let mut lines: Vec<String> = passwords
+            .iter()
+            .map(|password| format!("{}:1", hex::encode_upper(Sha1::digest(password))))
+            .collect();
+        lines.sort();
+        fs::write(path, lines.join("\n")).unwrap();
+    }
+
+    #[test]
+    fn test_reports_passwords_that_became_compromised() {
+        let path = format!("test_recheck_corpus_{}.txt", Uuid::new_v4());
+        write_corpus(&path, &["password"]);
+        let policy = PasswordPolicy::default();
+        let entries = vec![entry("1", "password"), entry("2", "Tr0ub4dor")];
+        let start = Instant::now();
+        let mut scheduler = RecheckScheduler::new(Duration::from_secs(3600));
+
+        let corpus = BreachCorpus::open(&path, HashKind::Sha1).unwrap();
+        let checks = PasswordChecks {
+            corpus: Some(&corpus),
+            policy: &policy,
+        };
+        // The first run is the baseline, "1" was never clean
+        assert!(scheduler.tick(start, &entries, &checks).is_empty());
+        assert!(!scheduler.is_due(start + Duration::from_secs(60)));
+
+        drop(corpus);
+        write_corpus(&path, &["password", "Tr0ub4dor"]);
+        let corpus = BreachCorpus::open(&path, HashKind::Sha1).unwrap();
+        scheduler.corpus_updated();
+        let checks = PasswordChecks {
+            corpus: Some(&corpus),
+            policy: &policy,
+        };
+        assert_eq!(
+            scheduler.tick(start + Duration::from_secs(60), &entries, &checks),
+            vec![AuditEvent::Breached {
+                id: "2".to_string(),
+                occurrences: 1
+            }]
+        );
+
+        // A tightened policy shows up on the next scheduled run
+        let policy = PasswordPolicy::default().with_min_length(12);
+        let checks = PasswordChecks {
+            corpus: Some(&corpus),
+            policy: &policy,
+        };
+        let later = start + Duration::from_secs(2 * 3600);
+        assert_eq!(scheduler.tick(later, &entries, &checks).len(), 2);
+        assert!(scheduler.tick(later, &entries, &checks).is_empty());
+
+        drop(corpus);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_reports_passwords_that_expired() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let entries = vec![
+            Entry {
+                password_changed_at: now,
+                ..entry("1", "fresh")
+            },
+            // No recorded change, so it doesn't expire
+            entry("2", "unknown"),
+        ];
+        let policy = PasswordPolicy::default();
+        let checks = PasswordChecks {
+            corpus: None,
+            policy: &policy,
+        };
+        let mut scheduler =
+            RecheckScheduler::new(Duration::ZERO).with_max_age(Duration::from_millis(50));
+
+        assert!(scheduler.tick(Instant::now(), &entries, &checks).is_empty());
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(
+            scheduler.tick(Instant::now(), &entries, &checks),
+            vec![AuditEvent::Expired {
+                id: "1".to_string(),
+                password_changed_at: now
+            }]
+        );
+        assert!(scheduler.tick(Instant::now(), &entries, &checks).is_empty());
+    }
+}
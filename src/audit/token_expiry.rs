@@ -0,0 +1,80 @@
+use crate::data::model::Entry;
+
+// A token that has to be renewed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpiringToken {
+    pub id: String,
+    pub title: String,
+    pub expires_at: u64,
+    // False when it is only about to expire
+    pub expired: bool,
+}
+
+// Tokens past their expiry at `now`, and those expiring within
+// `warn_within`, soonest first. Both are unix milliseconds like
+// `TokenInfo::expires_at`. Archived entries and tokens that don't expire are
+// left out.
+pub fn expiring_tokens(entries: &[Entry], now: u64, warn_within: u64) -> Vec<ExpiringToken> {
+    let mut tokens: Vec<ExpiringToken> = entries
+        .iter()
+        .filter(|entry| !entry.archived)
+        .filter_map(|entry| {
+            let token = entry.token.as_ref().filter(|token| token.expires_at != 0)?;
+            (token.expires_at <= now.saturating_add(warn_within)).then(|| ExpiringToken {
+                id: entry.id.clone(),
+                title: entry.title.clone(),
+                expires_at: token.expires_at,
+                expired: token.is_expired(now),
+            })
+        })
+        .collect();
+    tokens.sort_by(|a, b| a.expires_at.cmp(&b.expires_at).then(a.id.cmp(&b.id)));
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::model::{EntryKind, TokenInfo};
+
+    const NOW: u64 = 1_000_000;
+
+    fn token(id: &str, expires_at: u64) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: format!("Token {}", id),
+            kind: EntryKind::Token,
+            token: Some(TokenInfo {
+                prefix: Some("ghp_".to_string()),
+                scopes: vec!["repo".to_string()],
+                expires_at,
+            }),
+            password: Some("ghp_secret".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_flags_expired_and_expiring_tokens() {
+        let archived = Entry {
+            archived: true,
+            ..token("archived", NOW - 1)
+        };
+        let entries = [
+            token("later", NOW + 500),
+            token("never", 0),
+            token("past", NOW - 10),
+            token("soon", NOW + 50),
+            archived,
+        ];
+
+        let flagged = expiring_tokens(&entries, NOW, 100);
+        let summary: Vec<(&str, bool)> = flagged
+            .iter()
+            .map(|token| (token.id.as_str(), token.expired))
+            .collect();
+        assert_eq!(summary, vec![("past", true), ("soon", false)]);
+
+        assert_eq!(expiring_tokens(&entries, NOW, 0).len(), 1);
+    }
+}
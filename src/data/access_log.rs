@@ -0,0 +1,342 @@
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    data_store::{DataStore, Filter},
+    file_swap::swap_in,
+    model::Entry,
+    store_error::StoreError,
+};
+
+// The sidecar is compacted once it holds this many records and more than
+// twice as many as there are entries in it
+const COMPACT_MIN_RECORDS: usize = 1024;
+
+// Wraps a store to remember when each entry was last loaded, e.g. to list
+// recently used entries first. A load appends a small record to a sidecar
+// file rather than saving the entry again, so reads never write the data
+// file. The sidecar is read once, on the first load or when a time is first
+// asked for. Access times aren't worth a sync, so the last few may be lost
+// in a crash.
+pub struct AccessTrackingStore<S> {
+    store: S,
+    log: Mutex<AccessLog>,
+}
+
+// One load of an entry, or with `at` 0 its deletion
+#[derive(Serialize, Deserialize)]
+struct AccessRecord {
+    id: String,
+    at: u64,
+}
+
+struct AccessLog {
+    file_path: String,
+    // Opened on the first append
+    file: Option<File>,
+    // None until first asked for
+    times: Option<HashMap<String, u64>>,
+    // Records in the file, when `times` is loaded
+    records: usize,
+}
+
+impl AccessLog {
+    fn append(&mut self, id: &str, at: u64) -> Result<(), StoreError> {
+        if self.file.is_none() {
+            // Drops a record a crash cut off, which would hide every later one
+            self.times()?;
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            file => file.insert(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.file_path)?,
+            ),
+        };
+        file.write_all(&bincode::serialize(&AccessRecord {
+            id: id.to_string(),
+            at,
+        })?)?;
+
+        if let Some(times) = &mut self.times {
+            apply(times, id.to_string(), at);
+            self.records += 1;
+        }
+        Ok(())
+    }
+
+    // Reads the sidecar the first time, compacting it if need be
+    fn times(&mut self) -> Result<&HashMap<String, u64>, StoreError> {
+        if self.times.is_none() {
+            let mut times = HashMap::new();
+            let mut records = 0;
+            let bytes = match fs::read(&self.file_path) {
+                Ok(bytes) => bytes,
+                Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+                Err(e) => return Err(e.into()),
+            };
+            let mut rest = &bytes[..];
+            let mut readable_len = 0;
+            while !rest.is_empty() {
+                // The length of a torn record can be anything, so nothing may
+                // be allocated beyond what is left of the file
+                let record = bincode::DefaultOptions::new()
+                    .with_fixint_encoding()
+                    .allow_trailing_bytes()
+                    .with_limit(rest.len() as u64)
+                    .deserialize_from::<_, AccessRecord>(&mut rest);
+                match record {
+                    Ok(record) => {
+                        apply(&mut times, record.id, record.at);
+                        records += 1;
+                        readable_len = bytes.len() - rest.len();
+                    }
+                    Err(_) => break,
+                }
+            }
+            // A record cut off by a crash ends the log, and is cut off so
+            // later records aren't appended behind it
+            if readable_len < bytes.len() {
+                OpenOptions::new()
+                    .write(true)
+                    .open(&self.file_path)?
+                    .set_len(readable_len as u64)?;
+            }
+            self.times = Some(times);
+            self.records = records;
+            self.compact_if_superseded()?;
+        }
+        Ok(self.times.as_ref().expect("loaded above"))
+    }
+
+    fn compact_if_superseded(&mut self) -> Result<(), StoreError> {
+        let live = self.times.as_ref().map_or(0, HashMap::len);
+        if self.records >= COMPACT_MIN_RECORDS && self.records > 2 * live {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    // Rewrites the sidecar with one record per entry
+    fn compact(&mut self) -> Result<(), StoreError> {
+        self.times()?;
+        let times = self.times.as_ref().expect("loaded above");
+        let mut bytes = Vec::new();
+        for (id, at) in times {
+            bytes.extend(bincode::serialize(&AccessRecord {
+                id: id.clone(),
+                at: *at,
+            })?);
+        }
+
+        let temp_file_path = format!("{}.compact", self.file_path);
+        fs::write(&temp_file_path, bytes)?;
+        self.file = None;
+        swap_in(&temp_file_path, &self.file_path)?;
+        self.records = times.len();
+        Ok(())
+    }
+}
+
+fn apply(times: &mut HashMap<String, u64>, id: String, at: u64) {
+    if at == 0 {
+        times.remove(&id);
+    } else {
+        times.insert(id, at);
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(1, |elapsed| elapsed.as_millis().max(1) as u64)
+}
+
+impl<S: DataStore<String, Entry, StoreError>> AccessTrackingStore<S> {
+    pub fn new(store: S, file_path: String) -> Self {
+        AccessTrackingStore {
+            store,
+            log: Mutex::new(AccessLog {
+                file_path,
+                file: None,
+                times: None,
+                records: 0,
+            }),
+        }
+    }
+
+    // Unix milliseconds of the last load through the wrapper
+    pub fn last_used_at(&self, id: &str) -> Result<Option<u64>, StoreError> {
+        Ok(self.log().times()?.get(id).copied())
+    }
+
+    // Ids with their last use, most recent first
+    pub fn recently_used(&self, limit: usize) -> Result<Vec<(String, u64)>, StoreError> {
+        let mut log = self.log();
+        let mut used: Vec<(String, u64)> = log
+            .times()?
+            .iter()
+            .map(|(id, at)| (id.clone(), *at))
+            .collect();
+        used.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        used.truncate(limit);
+        Ok(used)
+    }
+
+    pub fn compact(&self) -> Result<(), StoreError> {
+        self.log().compact()
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    fn log(&self) -> MutexGuard<'_, AccessLog> {
+        self.log.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<S: DataStore<String, Entry, StoreError>> DataStore<String, Entry, StoreError>
+    for AccessTrackingStore<S>
+{
+    fn save(&mut self, id: &String, entry: &Entry) -> Result<(), StoreError> {
+        self.store.save(id, entry)
+    }
+
+    fn load(&self, id: &String) -> Result<Option<Entry>, StoreError> {
+        let entry = self.store.load(id)?;
+        if entry.is_some() {
+            self.log().append(id, now())?;
+        }
+        Ok(entry)
+    }
+
+    fn delete(&mut self, id: &String) -> Result<(), StoreError> {
+        self.store.delete(id)?;
+        self.log().append(id, 0)
+    }
+
+    // Searching isn't using an entry, so it isn't tracked
+    fn search(&self, filter: &dyn Filter<Entry>) -> Result<Vec<Entry>, StoreError> {
+        self.store.search(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::binary_file_entry_store::BinaryFileEntryStore;
+    use uuid::Uuid;
+
+    fn entry(id: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: "Mail".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_loads_are_tracked_in_the_sidecar() {
+        let id = Uuid::new_v4();
+        let store_path = format!("test_access_store_{}.bin", id);
+        let log_path = format!("test_access_log_{}.bin", id);
+        let mut store = AccessTrackingStore::new(
+            BinaryFileEntryStore::new(store_path.clone()),
+            log_path.clone(),
+        );
+        for id in ["1", "2", "3"] {
+            store.save(&id.to_string(), &entry(id)).unwrap();
+        }
+        let data = fs::read(&store_path).unwrap();
+
+        store.load(&"1".to_string()).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        store.load(&"2".to_string()).unwrap();
+        store.load(&"missing".to_string()).unwrap();
+        assert_eq!(fs::read(&store_path).unwrap(), data);
+        assert_eq!(store.last_used_at("3").unwrap(), None);
+        let used: Vec<String> = store
+            .recently_used(10)
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(used, vec!["2", "1"]);
+
+        store.delete(&"2".to_string()).unwrap();
+        let store = AccessTrackingStore::new(store.into_inner(), log_path.clone());
+        assert!(store.last_used_at("1").unwrap().is_some());
+        assert_eq!(store.last_used_at("2").unwrap(), None);
+
+        drop(store);
+        fs::remove_file(store_path).unwrap();
+        fs::remove_file(log_path).unwrap();
+    }
+
+    #[test]
+    fn test_superseded_records_are_compacted() {
+        let id = Uuid::new_v4();
+        let store_path = format!("test_access_store_{}.bin", id);
+        let log_path = format!("test_access_log_{}.bin", id);
+        let mut store = AccessTrackingStore::new(
+            BinaryFileEntryStore::new(store_path.clone()),
+            log_path.clone(),
+        );
+        store.save(&"1".to_string(), &entry("1")).unwrap();
+        for _ in 0..COMPACT_MIN_RECORDS {
+            store.load(&"1".to_string()).unwrap();
+        }
+        // A record cut off by a crash
+        let mut log = OpenOptions::new().append(true).open(&log_path).unwrap();
+        log.write_all(&[5, 0, 0]).unwrap();
+        drop(log);
+        let full = fs::metadata(&log_path).unwrap().len();
+
+        let store = AccessTrackingStore::new(store.into_inner(), log_path.clone());
+        assert!(store.last_used_at("1").unwrap().is_some());
+        assert!(fs::metadata(&log_path).unwrap().len() < full / 100);
+        store.load(&"1".to_string()).unwrap();
+        assert_eq!(store.recently_used(10).unwrap().len(), 1);
+
+        drop(store);
+        fs::remove_file(store_path).unwrap();
+        fs::remove_file(log_path).unwrap();
+    }
+
+    #[test]
+    fn test_torn_record_is_dropped_before_appending() {
+        let id = Uuid::new_v4();
+        let store_path = format!("test_access_store_{}.bin", id);
+        let log_path = format!("test_access_log_{}.bin", id);
+        let mut store = AccessTrackingStore::new(
+            BinaryFileEntryStore::new(store_path.clone()),
+            log_path.clone(),
+        );
+        store.save(&"1".to_string(), &entry("1")).unwrap();
+        store.save(&"2".to_string(), &entry("2")).unwrap();
+        store.load(&"1".to_string()).unwrap();
+        let mut log = OpenOptions::new().append(true).open(&log_path).unwrap();
+        log.write_all(&[5, 0, 0]).unwrap();
+        drop(log);
+
+        let store = AccessTrackingStore::new(store.into_inner(), log_path.clone());
+        store.load(&"2".to_string()).unwrap();
+        let store = AccessTrackingStore::new(store.into_inner(), log_path.clone());
+        assert!(store.last_used_at("1").unwrap().is_some());
+        assert!(store.last_used_at("2").unwrap().is_some());
+
+        drop(store);
+        fs::remove_file(store_path).unwrap();
+        fs::remove_file(log_path).unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    data_store::{DataStore, Filter},
+    durability::Syncer,
+    file_swap::swap_in,
+    model::Entry,
+    store_error::StoreError,
+};
+
+// Changes kept by default, older ones are dropped as new ones come in
+const DEFAULT_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ActivityKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Activity {
+    pub id: String,
+    // As of the change, so deleted entries can still be named
+    pub title: String,
+    pub kind: ActivityKind,
+    // Unix milliseconds
+    pub at: u64,
+}
+
+// Wraps a store to keep a list of its latest changes for a changelog pane.
+// The list is written to its own file after every change, so it survives
+// reopening. Changes made without the wrapper aren't in it.
+pub struct ActivityStore<S> {
+    store: S,
+    file_path: String,
+    limit: usize,
+    // Oldest first
+    recent: VecDeque<Activity>,
+    syncer: Syncer,
+}
+
+impl<S: DataStore<String, Entry, StoreError>> ActivityStore<S> {
+    // A missing file is an empty list
+    pub fn open(store: S, file_path: String) -> Result<Self, StoreError> {
+        let recent = match fs::read(&file_path) {
+            Ok(bytes) => bincode::deserialize(&bytes)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => VecDeque::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(ActivityStore {
+            store,
+            file_path,
+            limit: DEFAULT_LIMIT,
+            recent,
+            syncer: Syncer::default(),
+        })
+    }
+
+    // Takes effect with the next change
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    // Newest first, at most `limit` of them
+    pub fn recent_activity(&self, limit: usize) -> Vec<&Activity> {
+        self.recent.iter().rev().take(limit).collect()
+    }
+
+    pub fn clear_activity(&mut self) -> Result<(), StoreError> {
+        self.recent.clear();
+        self.write()
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    fn record(&mut self, id: &str, title: &str, kind: ActivityKind) -> Result<(), StoreError> {
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        self.recent.push_back(Activity {
+            id: id.to_string(),
+            title: title.to_string(),
+            kind,
+            at,
+        });
+        while self.recent.len() > self.limit {
+            self.recent.pop_front();
+        }
+        self.write()
+    }
+
+    fn write(&self) -> Result<(), StoreError> {
+        let temp_file_path = format!("{}.tmp", self.file_path);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&temp_file_path)?;
+        file.write_all(&bincode::serialize(&self.recent)?)?;
+        self.syncer.rewritten(&mut file)?;
+
+        swap_in(&temp_file_path, &self.file_path)?;
+        Ok(self.syncer.renamed(&self.file_path)?)
+    }
+}
+
+impl<S: DataStore<String, Entry, StoreError>> DataStore<String, Entry, StoreError>
+    for ActivityStore<S>
+{
+    fn save(&mut self, id: &String, entry: &Entry) -> Result<(), StoreError> {
+        let kind = match self.store.load(id)? {
+            Some(_) => ActivityKind::Updated,
+            None => ActivityKind::Created,
+        };
+        self.store.save(id, entry)?;
+        self.record(id, &entry.title, kind)
+    }
+
+    fn load(&self, id: &String) -> Result<Option<Entry>, StoreError> {
+        self.store.load(id)
+    }
+
+    // Deleting an id that isn't stored isn't recorded
+    fn delete(&mut self, id: &String) -> Result<(), StoreError> {
+        let existing = self.store.load(id)?;
+        self.store.delete(id)?;
+        match existing {
+            Some(entry) => self.record(id, &entry.title, ActivityKind::Deleted),
+            None => Ok(()),
+        }
+    }
+
+    fn search(&self, filter: &dyn Filter<Entry>) -> Result<Vec<Entry>, StoreError> {
+        self.store.search(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::binary_file_entry_store::BinaryFileEntryStore;
+    use uuid::Uuid;
+
+    fn entry(id: &str, title: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: title.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn kinds(store: &ActivityStore<BinaryFileEntryStore>) -> Vec<(&str, ActivityKind)> {
+        store
+            .recent_activity(usize::MAX)
+            .into_iter()
+            .map(|activity| (activity.title.as_str(), activity.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_records_changes_newest_first() {
+        let id = Uuid::new_v4();
+        let store_path = format!("test_activity_store_{}.bin", id);
+        // In a directory, as the temp file has to go next to it
+        let activity_dir = format!("test_activity_{}", id);
+        fs::create_dir(&activity_dir).unwrap();
+        let activity_path = format!("{}/activity.bin", activity_dir);
+        let mut store = ActivityStore::open(
+            BinaryFileEntryStore::new(store_path.clone()),
+            activity_path.clone(),
+        )
+        .unwrap();
+
+        store.save(&"1".to_string(), &entry("1", "Mail")).unwrap();
+        store.save(&"1".to_string(), &entry("1", "Email")).unwrap();
+        store.delete(&"2".to_string()).unwrap();
+        store.delete(&"1".to_string()).unwrap();
+
+        assert_eq!(
+            kinds(&store),
+            vec![
+                ("Email", ActivityKind::Deleted),
+                ("Email", ActivityKind::Updated),
+                ("Mail", ActivityKind::Created),
+            ]
+        );
+        assert_eq!(store.recent_activity(1).len(), 1);
+        assert!(store.recent_activity(1)[0].at > 0);
+
+        // The list survives reopening
+        let store = ActivityStore::open(store.into_inner(), activity_path.clone()).unwrap();
+        assert_eq!(kinds(&store).len(), 3);
+
+        drop(store);
+        fs::remove_file(store_path).unwrap();
+        fs::remove_dir_all(activity_dir).unwrap();
+    }
+
+    #[test]
+    fn test_limit_drops_oldest() {
+        let id = Uuid::new_v4();
+        let store_path = format!("test_activity_store_{}.bin", id);
+        let activity_path = format!("test_activity_{}.bin", id);
+        let mut store = ActivityStore::open(
+            BinaryFileEntryStore::new(store_path.clone()),
+            activity_path.clone(),
+        )
+        .unwrap()
+        .with_limit(2);
+
+        for title in ["A", "B", "C"] {
+            store
+                .save(&title.to_string(), &entry(title, title))
+                .unwrap();
+        }
+
+        assert_eq!(
+            kinds(&store),
+            vec![("C", ActivityKind::Created), ("B", ActivityKind::Created)]
+        );
+
+        drop(store);
+        fs::remove_file(store_path).unwrap();
+        fs::remove_file(activity_path).unwrap();
+    }
+}
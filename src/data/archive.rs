@@ -0,0 +1,106 @@
+use super::{
+    data_store::{filter_fn, DataStore, Filter},
+    model::Entry,
+};
+
+// Archived entries are out of the way but, unlike deleted ones, kept for good
+
+pub struct ArchivedFilter;
+
+impl Filter<Entry> for ArchivedFilter {
+    fn pass(&self, entry: &Entry) -> bool {
+        entry.archived
+    }
+}
+
+// Returns false when there is no entry with `id`
+pub fn set_archived<S, E>(store: &mut S, id: &String, archived: bool) -> Result<bool, E>
+where
+    S: DataStore<String, Entry, E> + ?Sized,
+{
+    let mut entry = match store.load(id)? {
+        Some(entry) => entry,
+        None => return Ok(false),
+    };
+
+    if entry.archived != archived {
+        entry.archived = archived;
+        store.save(id, &entry)?;
+    }
+    Ok(true)
+}
+
+pub fn archive<S, E>(store: &mut S, id: &String) -> Result<bool, E>
+where
+    S: DataStore<String, Entry, E> + ?Sized,
+{
+    set_archived(store, id, true)
+}
+
+pub fn unarchive<S, E>(store: &mut S, id: &String) -> Result<bool, E>
+where
+    S: DataStore<String, Entry, E> + ?Sized,
+{
+    set_archived(store, id, false)
+}
+
+// The default search, which leaves out archived entries
+pub fn search_active<S, E>(store: &S, filter: &dyn Filter<Entry>) -> Result<Vec<Entry>, E>
+where
+    S: DataStore<String, Entry, E> + ?Sized,
+{
+    store.search(&filter_fn(|entry: &Entry| {
+        !entry.archived && filter.pass(entry)
+    }))
+}
+
+pub fn list_archived<S, E>(store: &S) -> Result<Vec<Entry>, E>
+where
+    S: DataStore<String, Entry, E> + ?Sized,
+{
+    store.search(&ArchivedFilter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{binary_file_entry_store::BinaryFileEntryStore, text_search::TextFilter};
+    use std::fs;
+    use uuid::Uuid;
+
+    fn entry(id: &str, title: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: title.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_archive_and_unarchive() {
+        let test_file_path = format!("test_archive_{}.bin", Uuid::new_v4());
+        let mut store = BinaryFileEntryStore::new(test_file_path.clone());
+        for entry in [entry("1", "Old job VPN"), entry("2", "Home VPN")] {
+            store.save(&entry.id, &entry).unwrap();
+        }
+
+        assert!(archive(&mut store, &"1".to_string()).unwrap());
+        assert!(!archive(&mut store, &"missing".to_string()).unwrap());
+
+        let found = search_active(&store, &TextFilter::new("vpn")).unwrap();
+        assert_eq!(found, vec![entry("2", "Home VPN")]);
+        let archived = list_archived(&store).unwrap();
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].id, "1");
+
+        assert!(unarchive(&mut store, &"1".to_string()).unwrap());
+        assert_eq!(
+            search_active(&store, &TextFilter::new("vpn"))
+                .unwrap()
+                .len(),
+            2
+        );
+
+        fs::remove_file(test_file_path).unwrap();
+    }
+}
@@ -0,0 +1,315 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt,
+    fs::{self, File},
+    io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    data_store::{filter_fn, DataStore},
+    file_swap::swap_in,
+    model::Entry,
+    store_error::StoreError,
+};
+
+// A backup is a directory with copies of a store's files and a manifest
+// describing them, so a backup can be checked on its own before anything
+// live is replaced with it. The manifest is written last, a directory
+// without one is an unfinished backup.
+
+pub const MANIFEST_VERSION: u32 = 1;
+const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Debug)]
+pub enum BackupError {
+    StoreError(StoreError),
+    IoError(io::Error),
+    ManifestError(serde_json::Error),
+    UnsupportedVersion(u32),
+    // A file the manifest lists is missing or differs from its checksum
+    ChecksumMismatch(String),
+    // A file to restore that the backup doesn't have
+    NotInBackup(String),
+}
+
+impl From<StoreError> for BackupError {
+    fn from(error: StoreError) -> Self {
+        BackupError::StoreError(error)
+    }
+}
+
+impl From<io::Error> for BackupError {
+    fn from(error: io::Error) -> Self {
+        BackupError::IoError(error)
+    }
+}
+
+impl From<serde_json::Error> for BackupError {
+    fn from(error: serde_json::Error) -> Self {
+        BackupError::ManifestError(error)
+    }
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            BackupError::StoreError(ref err) => write!(f, "Store error: {}", err),
+            BackupError::IoError(ref err) => write!(f, "I/O error: {}", err),
+            BackupError::ManifestError(ref err) => write!(f, "Invalid manifest: {}", err),
+            BackupError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported backup version: {}", version)
+            }
+            BackupError::ChecksumMismatch(ref name) => {
+                write!(f, "Backup file {} is missing or damaged", name)
+            }
+            BackupError::NotInBackup(ref name) => write!(f, "Backup has no file {}", name),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BackupFile {
+    // File name within the backup directory
+    pub name: String,
+    pub length: u64,
+    // Hex SHA-256
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BackupManifest {
+    pub format_version: u32,
+    // Unix milliseconds
+    pub created_at: u64,
+    // How the files are encrypted, e.g. "field-level", None for plain files
+    pub cipher: Option<String>,
+    pub entry_count: usize,
+    pub files: Vec<BackupFile>,
+}
+
+fn file_name(file_path: &str) -> Result<String, BackupError> {
+    Path::new(file_path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| BackupError::NotInBackup(file_path.to_string()))
+}
+
+fn checksum(file_path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(file_path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+// Copies `files` of `store` into `backup_dir`, which is created if needed.
+// Files are stored under their names, so they must differ. The store
+// shouldn't be written to while this runs.
+pub fn create_backup<S>(
+    store: &S,
+    files: &[&str],
+    backup_dir: &str,
+    cipher: Option<&str>,
+) -> Result<BackupManifest, BackupError>
+where
+    S: DataStore<String, Entry, StoreError> + ?Sized,
+{
+    fs::create_dir_all(backup_dir)?;
+    let entry_count = store.search(&filter_fn(|_: &Entry| true))?.len();
+
+    let mut backed_up = Vec::with_capacity(files.len());
+    for file_path in files {
+        let name = file_name(file_path)?;
+        let copy = Path::new(backup_dir).join(&name);
+        let length = fs::copy(file_path, &copy)?;
+        backed_up.push(BackupFile {
+            name,
+            length,
+            sha256: checksum(&copy)?,
+        });
+    }
+
+    let manifest = BackupManifest {
+        format_version: MANIFEST_VERSION,
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64),
+        cipher: cipher.map(str::to_string),
+        entry_count,
+        files: backed_up,
+    };
+    fs::write(
+        Path::new(backup_dir).join(MANIFEST_FILE),
+        serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    Ok(manifest)
+}
+
+// Reads the manifest of a backup and checks every file it lists
+pub fn verify_backup(backup_dir: &str) -> Result<BackupManifest, BackupError> {
+    let manifest: BackupManifest =
+        serde_json::from_slice(&fs::read(Path::new(backup_dir).join(MANIFEST_FILE))?)?;
+    if manifest.format_version == 0 || manifest.format_version > MANIFEST_VERSION {
+        return Err(BackupError::UnsupportedVersion(manifest.format_version));
+    }
+
+    for file in &manifest.files {
+        let copy = Path::new(backup_dir).join(&file.name);
+        let intact = match fs::metadata(&copy) {
+            Ok(metadata) => metadata.len() == file.length && checksum(&copy)? == file.sha256,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+            Err(e) => return Err(e.into()),
+        };
+        if !intact {
+            return Err(BackupError::ChecksumMismatch(file.name.clone()));
+        }
+    }
+    Ok(manifest)
+}
+
+// Replaces each of `targets` with the file of the same name in the backup.
+// The whole backup is verified and every file copied next to its target
+// before any is swapped in, so a damaged backup or a failed copy, e.g. on a
+// full disk, changes nothing. Stores using the targets should be closed,
+// and reopened after.
+pub fn import_backup(backup_dir: &str, targets: &[&str]) -> Result<BackupManifest, BackupError> {
+    let manifest = verify_backup(backup_dir)?;
+    let mut sources = Vec::with_capacity(targets.len());
+    for target in targets {
+        let name = file_name(target)?;
+        if !manifest.files.iter().any(|file| file.name == name) {
+            return Err(BackupError::NotInBackup(name));
+        }
+        sources.push(Path::new(backup_dir).join(name));
+    }
+
+    let staged: Vec<String> = targets
+        .iter()
+        .map(|target| format!("{}.restore", target))
+        .collect();
+    for (source, temp_file_path) in sources.iter().zip(&staged) {
+        if let Err(e) = fs::copy(source, temp_file_path) {
+            for temp_file_path in &staged {
+                let _ = fs::remove_file(temp_file_path);
+            }
+            return Err(e.into());
+        }
+    }
+    for (temp_file_path, target) in staged.iter().zip(targets) {
+        swap_in(temp_file_path, target)?;
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::binary_file_entry_store::BinaryFileEntryStore;
+    use uuid::Uuid;
+
+    fn entry(id: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: "Mail".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_backup_and_import() {
+        let id = Uuid::new_v4();
+        let store_path = format!("test_backup_store_{}.bin", id);
+        let backup_dir = format!("test_backup_{}", id);
+        let mut store = BinaryFileEntryStore::new(store_path.clone());
+        store.save(&"1".to_string(), &entry("1")).unwrap();
+        store.save(&"2".to_string(), &entry("2")).unwrap();
+
+        let manifest = create_backup(&store, &[&store_path], &backup_dir, None).unwrap();
+        assert_eq!(manifest.entry_count, 2);
+        assert_eq!(manifest.files[0].name, store_path);
+        assert_eq!(verify_backup(&backup_dir).unwrap(), manifest);
+
+        store.delete(&"1".to_string()).unwrap();
+        drop(store);
+        import_backup(&backup_dir, &[&store_path]).unwrap();
+        let store = BinaryFileEntryStore::new(store_path.clone());
+        assert_eq!(store.load(&"1".to_string()).unwrap(), Some(entry("1")));
+
+        assert!(matches!(
+            import_backup(&backup_dir, &["other.bin"]),
+            Err(BackupError::NotInBackup(_))
+        ));
+
+        drop(store);
+        fs::remove_file(store_path).unwrap();
+        fs::remove_dir_all(backup_dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_copy_changes_nothing() {
+        let id = Uuid::new_v4();
+        let store_path = format!("test_backup_store_{}.bin", id);
+        let index_path = format!("test_backup_index_{}.bin", id);
+        let backup_dir = format!("test_backup_{}", id);
+        let store = BinaryFileEntryStore::new(store_path.clone());
+        fs::write(&index_path, b"index").unwrap();
+        create_backup(&store, &[&store_path, &index_path], &backup_dir, None).unwrap();
+        drop(store);
+        fs::write(&store_path, b"live data").unwrap();
+
+        // The copy of the index can't be written
+        let blocked = format!("{}.restore", index_path);
+        fs::create_dir(&blocked).unwrap();
+        assert!(import_backup(&backup_dir, &[&store_path, &index_path]).is_err());
+        assert_eq!(fs::read(&store_path).unwrap(), b"live data");
+        assert!(!Path::new(&format!("{}.restore", store_path)).exists());
+
+        fs::remove_dir(blocked).unwrap();
+        fs::remove_file(store_path).unwrap();
+        fs::remove_file(index_path).unwrap();
+        fs::remove_dir_all(backup_dir).unwrap();
+    }
+
+    #[test]
+    fn test_damaged_backup_changes_nothing() {
+        let id = Uuid::new_v4();
+        let store_path = format!("test_backup_store_{}.bin", id);
+        let backup_dir = format!("test_backup_{}", id);
+        let mut store = BinaryFileEntryStore::new(store_path.clone());
+        store.save(&"1".to_string(), &entry("1")).unwrap();
+        create_backup(&store, &[&store_path], &backup_dir, Some("full-record")).unwrap();
+        store.save(&"2".to_string(), &entry("2")).unwrap();
+        let live = fs::read(&store_path).unwrap();
+
+        let copy = Path::new(&backup_dir).join(&store_path);
+        let mut bytes = fs::read(&copy).unwrap();
+        bytes[0] ^= 1;
+        fs::write(&copy, bytes).unwrap();
+
+        assert!(matches!(
+            import_backup(&backup_dir, &[&store_path]),
+            Err(BackupError::ChecksumMismatch(_))
+        ));
+        assert_eq!(fs::read(&store_path).unwrap(), live);
+
+        let manifest_path = Path::new(&backup_dir).join(MANIFEST_FILE);
+        let manifest = fs::read_to_string(&manifest_path).unwrap();
+        fs::write(
+            &manifest_path,
+            manifest.replace("\"format_version\": 1", "\"format_version\": 9"),
+        )
+        .unwrap();
+        assert!(matches!(
+            verify_backup(&backup_dir),
+            Err(BackupError::UnsupportedVersion(9))
+        ));
+
+        drop(store);
+        fs::remove_file(store_path).unwrap();
+        fs::remove_dir_all(backup_dir).unwrap();
+    }
+}
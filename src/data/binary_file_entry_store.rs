@@ -1,23 +1,104 @@
 use super::{
-    binary_record_iterator::BinaryRecordIterator,
-    binary_store_error::BinaryStoreError,
-    data_store::{DataStore, Filter},
+    binary_record_iterator::{
+        write_raw, write_record, write_tombstone, BinaryRecordIterator, RawRecord,
+    },
+    bloom_filter::BloomFilter,
+    compaction::{fragmentation_of, Compactable, CompactionStats},
+    data_store::{DataStore, Filter, StoreKey},
+    durability::{SyncLevel, SyncStats, Syncer},
+    file_swap::{recover_swap, swap_in},
+    generations::{GenerationLease, Generations},
+    migration::{MigrationRegistry, MigrationReport, SchemaHeader},
     model::Entry,
+    store_error::StoreError,
 };
-use byteorder::{LittleEndian, WriteBytesExt};
 use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
 use std::{
-    fs::{remove_file, rename, File, OpenOptions},
-    io::Write,
+    collections::{BTreeMap, HashMap},
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
     path::Path,
+    sync::{Mutex, PoisonError},
+    time::Instant,
 };
 
-pub struct BinaryFileEntryStore {
+pub struct BinaryFileEntryStore<K: StoreKey = String> {
     file_path: String,
+    generations: Option<Generations>,
+    syncer: Syncer,
+    // None unless enabled by `with_id_filter`, inside None until first built
+    id_filter: Option<Mutex<Option<IdFilter>>>,
+    key: PhantomData<K>,
 }
 
-impl BinaryFileEntryStore {
+// The encoded ids of the records in `file_path` up to `covered_len`, so a
+// load of an id that was never saved doesn't have to read the whole file
+#[derive(Serialize, Deserialize)]
+struct IdFilter {
+    file_path: String,
+    covered_len: u64,
+    filter: BloomFilter,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl IdFilter {
+    fn build<K: StoreKey>(snapshot: &StoreSnapshot<K>) -> Result<Self, StoreError> {
+        let ids = snapshot.encoded_ids_after(snapshot.start)?;
+        let mut filter = BloomFilter::with_capacity(ids.len() * 2);
+        for id in &ids {
+            filter.insert(id);
+        }
+        Ok(Self {
+            file_path: snapshot.file_path.clone(),
+            covered_len: snapshot.len,
+            filter,
+            dirty: true,
+        })
+    }
+
+    // Adds the records appended since the filter was last updated, e.g. by
+    // another process. Returns false when it has to be built again instead.
+    fn catch_up<K: StoreKey>(&mut self, snapshot: &StoreSnapshot<K>) -> Result<bool, StoreError> {
+        if self.file_path != snapshot.file_path
+            || self.covered_len > snapshot.len
+            || self.filter.is_full()
+        {
+            return Ok(false);
+        }
+        if self.covered_len < snapshot.len {
+            for id in snapshot.encoded_ids_after(self.covered_len)? {
+                self.filter.insert(&id);
+            }
+            self.covered_len = snapshot.len;
+            self.dirty = true;
+        }
+        Ok(true)
+    }
+}
+
+// The records of the store as of when the snapshot was taken. For a
+// generational store it keeps reading that generation while compactions
+// publish new ones.
+pub struct StoreSnapshot<K = String> {
+    file_path: String,
+    // Records appended after the snapshot was taken are past this
+    len: u64,
+    // Where the records start, past the schema header
+    start: u64,
+    _lease: Option<GenerationLease>,
+    key: PhantomData<K>,
+}
+
+impl<K: StoreKey> BinaryFileEntryStore<K> {
     pub fn new(file_path: String) -> Self {
+        let temp_file_path = Self::temp_file_path(&file_path);
+        if let Err(e) = recover_swap(&temp_file_path, &file_path) {
+            error!("Recovering {} failed! {}", temp_file_path, e);
+        }
+
         if !Self::file_exists(&file_path) {
             debug!("File {} does not exist. Creating...", &file_path);
 
@@ -27,7 +108,229 @@ impl BinaryFileEntryStore {
             }
         }
 
-        Self { file_path }
+        Self {
+            file_path,
+            generations: None,
+            syncer: Syncer::default(),
+            id_filter: None,
+            key: PhantomData,
+        }
+    }
+
+    // Like `new`, but fails where `new` logs and carries on, e.g. when the
+    // file can't be created
+    pub fn open(file_path: String) -> Result<Self, StoreError> {
+        recover_swap(&Self::temp_file_path(&file_path), &file_path)?;
+        if !Self::file_exists(&file_path) {
+            File::create(&file_path)?;
+            info!("File {} has been created.", file_path);
+        }
+
+        Ok(Self {
+            file_path,
+            generations: None,
+            syncer: Syncer::default(),
+            id_filter: None,
+            key: PhantomData,
+        })
+    }
+
+    // Opens an existing store only to read it. Unlike `open` nothing is
+    // created, recovered or migrated, the file is left as it is.
+    pub fn open_read_only(file_path: String) -> Result<Self, StoreError> {
+        File::open(&file_path)?;
+        Ok(Self {
+            file_path,
+            generations: None,
+            syncer: Syncer::default(),
+            id_filter: None,
+            key: PhantomData,
+        })
+    }
+
+    // Every entry with the key it is saved under, in key order
+    pub fn entries(&self) -> Result<Vec<(K, Entry)>, StoreError> {
+        self.snapshot()?.live_records()
+    }
+
+    // A store whose compactions write a new generation of the file,
+    // `{file_path}.0001`, `{file_path}.0002`, ..., instead of replacing it, so
+    // open snapshots are never affected by a compaction. An existing
+    // `file_path` becomes the first generation and is left as it was.
+    pub fn generational(file_path: String) -> Self {
+        let generations = Generations::new(file_path.clone());
+
+        match generations.current() {
+            Ok(Some(_)) => {}
+            Ok(None) => match generations.publish(|file| {
+                if Self::file_exists(&file_path) {
+                    io::copy(&mut File::open(&file_path)?, file)?;
+                }
+                Ok(())
+            }) {
+                Ok(_) => info!("File {} has been created.", generations.path(1)),
+                Err(e) => error!("File creation failed! {}: {}", generations.path(1), e),
+            },
+            Err(e) => error!("Listing generations of {} failed! {}", file_path, e),
+        }
+
+        Self {
+            file_path,
+            generations: Some(generations),
+            syncer: Syncer::default(),
+            id_filter: None,
+            key: PhantomData,
+        }
+    }
+
+    pub fn with_sync_level(mut self, level: SyncLevel) -> Self {
+        self.syncer = Syncer::new(level);
+        self
+    }
+
+    pub fn sync_stats(&self) -> SyncStats {
+        self.syncer.stats()
+    }
+
+    // Keeps a bloom filter of the saved ids in `{file_path}.bloom`, so
+    // loading an id that isn't in the store returns without reading the
+    // file. Records appended by others are picked up, rewrites of the file
+    // are expected to go through a store.
+    pub fn with_id_filter(mut self) -> Self {
+        let loaded = fs::read(Self::id_filter_path(&self.file_path))
+            .ok()
+            .and_then(|bytes| bincode::deserialize::<IdFilter>(&bytes).ok());
+        self.id_filter = Some(Mutex::new(loaded));
+        self
+    }
+
+    fn id_filter_path(file_path: &str) -> String {
+        format!("{}.bloom", file_path)
+    }
+
+    // False only when no record of `id` was ever written to the file
+    fn may_contain(&self, snapshot: &StoreSnapshot<K>, id: &K) -> Result<bool, StoreError> {
+        let id_filter = match &self.id_filter {
+            Some(id_filter) => id_filter,
+            None => return Ok(true),
+        };
+        let mut id_filter = id_filter.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let current = match id_filter.as_mut() {
+            // A failed catch-up reads past a rewrite, so build it again
+            Some(filter) => filter.catch_up(snapshot).unwrap_or(false),
+            None => false,
+        };
+        if !current {
+            *id_filter = Some(IdFilter::build(snapshot)?);
+        }
+        let id = bincode::serialize(id)?;
+        Ok(id_filter
+            .as_ref()
+            .is_none_or(|filter| filter.filter.may_contain(&id)))
+    }
+
+    // Adds an appended record to the filter when it was up to date before
+    fn appended(&self, file_path: &str, id: &K, before: u64, after: u64) -> Result<(), StoreError> {
+        let id_filter = match &self.id_filter {
+            Some(id_filter) => id_filter,
+            None => return Ok(()),
+        };
+        let mut id_filter = id_filter.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(filter) = id_filter.as_mut() {
+            if filter.file_path == file_path && filter.covered_len == before {
+                filter.filter.insert(&bincode::serialize(id)?);
+                filter.covered_len = after;
+                filter.dirty = true;
+            }
+        }
+        Ok(())
+    }
+
+    // Checks the id filter passes every entry in the file, a filter that
+    // doesn't would make loads miss entries. Deleted ids may still pass.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn check_invariants(&self) -> Result<(), String> {
+        let snapshot = self.snapshot().map_err(|e| e.to_string())?;
+        for (id, _) in snapshot.live_records().map_err(|e| e.to_string())? {
+            if !self
+                .may_contain(&snapshot, &id)
+                .map_err(|e| e.to_string())?
+            {
+                return Err("the id filter misses a stored entry".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    // After rewriting the file, offsets in the saved filter mean nothing
+    fn reset_id_filter(&self) {
+        let id_filter = match &self.id_filter {
+            Some(id_filter) => id_filter,
+            None => return,
+        };
+        *id_filter.lock().unwrap_or_else(PoisonError::into_inner) = None;
+
+        let id_filter_path = Self::id_filter_path(&self.file_path);
+        if let Err(e) = fs::remove_file(&id_filter_path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                error!("Removing {} failed! {}", id_filter_path, e);
+            }
+        }
+    }
+
+    fn write_id_filter(&self) -> Result<(), StoreError> {
+        let id_filter = match &self.id_filter {
+            Some(id_filter) => id_filter.lock().unwrap_or_else(PoisonError::into_inner),
+            None => return Ok(()),
+        };
+        let filter = match id_filter.as_ref() {
+            Some(filter) if filter.dirty => filter,
+            _ => return Ok(()),
+        };
+
+        let id_filter_path = Self::id_filter_path(&self.file_path);
+        let temp_file_path = Self::temp_file_path(&id_filter_path);
+        let mut file = File::create(&temp_file_path)?;
+        file.write_all(&bincode::serialize(filter)?)?;
+        self.syncer.rewritten(&mut file)?;
+        swap_in(&temp_file_path, &id_filter_path)?;
+        Ok(())
+    }
+
+    // Fails with `MigrationRequired` while the records are in an older
+    // layout, until they are upgraded with `migrate`
+    pub fn snapshot(&self) -> Result<StoreSnapshot<K>, StoreError> {
+        let (snapshot, header) = self.snapshot_of_any_version()?;
+        header.check()?;
+        Ok(snapshot)
+    }
+
+    fn snapshot_of_any_version(&self) -> Result<(StoreSnapshot<K>, SchemaHeader), StoreError> {
+        let lease = match &self.generations {
+            Some(generations) => Some(generations.lease_current()?.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} has no generations", self.file_path),
+                )
+            })?),
+            None => None,
+        };
+
+        let file_path = lease.as_ref().map_or(self.file_path.clone(), |lease| {
+            lease.file_path().to_string()
+        });
+        let mut file = File::open(&file_path)?;
+        let header = SchemaHeader::read(&mut file)?;
+        let snapshot = StoreSnapshot {
+            len: file.metadata()?.len(),
+            start: header.len,
+            file_path,
+            _lease: lease,
+            key: PhantomData,
+        };
+        Ok((snapshot, header))
     }
 
     fn file_exists(file_path: &str) -> bool {
@@ -40,88 +343,347 @@ impl BinaryFileEntryStore {
         }
     }
 
-    fn move_to_new_file<P: AsRef<Path>>(
+    fn temp_file_path(file_path: &str) -> String {
+        format!("{}-tmp", file_path)
+    }
+
+    // The file saves and deletes are appended to
+    fn current_file_path(&self) -> Result<String, StoreError> {
+        Ok(match &self.generations {
+            Some(generations) => generations
+                .current()?
+                .map(|generation| generations.path(generation))
+                .unwrap_or_else(|| self.file_path.clone()),
+            None => self.file_path.clone(),
+        })
+    }
+
+    fn append(
+        &self,
+        id: &K,
+        write: impl FnOnce(&mut File) -> Result<(), StoreError>,
+    ) -> Result<(), StoreError> {
+        let file_path = self.current_file_path()?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&file_path)?;
+        let before = file.metadata()?.len();
+        SchemaHeader::for_append(&mut file)?;
+        write(&mut file)?;
+        self.syncer.saved(&mut file)?;
+        self.appended(&file_path, id, before, file.metadata()?.len())
+    }
+
+    // Returns how many were written
+    fn write_live_records(
+        &self,
+        snapshot: &StoreSnapshot<K>,
+        new_file: &mut File,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<usize, StoreError> {
+        let records = snapshot.live_records()?;
+        SchemaHeader::write_current(new_file)?;
+        for (written, (id, entry)) in records.iter().enumerate() {
+            self.write_entry(id, entry, new_file)?;
+            progress(written + 1, records.len());
+        }
+        new_file.flush()?;
+        Ok(records.len())
+    }
+
+    // Rewrites the file with only the latest record of each entry, dropping
+    // replaced records and tombstones. A generational store publishes the
+    // result as a new generation.
+    pub fn compact(&mut self) -> Result<(), StoreError> {
+        self.compact_reporting(&mut |_, _| {}).map(|_| ())
+    }
+
+    fn compact_reporting(
+        &mut self,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<usize, StoreError> {
+        let snapshot = self.snapshot()?;
+        self.reset_id_filter();
+
+        let generations = match &self.generations {
+            Some(generations) => generations,
+            None => {
+                let new_path = Self::temp_file_path(&self.file_path);
+                let mut new_file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&new_path)?;
+                let written = self.write_live_records(&snapshot, &mut new_file, progress)?;
+                self.syncer.rewritten(&mut new_file)?;
+                swap_in(&new_path, &self.file_path)?;
+                self.syncer.renamed(&self.file_path)?;
+                return Ok(written);
+            }
+        };
+
+        let mut written = 0;
+        generations.publish_with(&self.syncer, |new_file| {
+            written = self.write_live_records(&snapshot, new_file, progress)?;
+            Ok(())
+        })?;
+        drop(snapshot);
+
+        // Collection only tidies up, the compaction itself already succeeded
+        if let Err(e) = generations.collect_garbage() {
+            error!("Collecting generations of {} failed! {}", self.file_path, e);
+        }
+        Ok(written)
+    }
+
+    // Upgrades records written with an older layout of `Entry`, after backing
+    // up the file. A dry run only reports what would be migrated. Nothing
+    // migrates a store on its own, until this is called a store in an older
+    // layout fails with `MigrationRequired`.
+    pub fn migrate(
         &self,
-        new_file_path: P,
-        deleting_keys: &[String],
-        appending_entries: Vec<&Entry>,
-    ) -> Result<(), BinaryStoreError> {
-        let mut new_file = OpenOptions::new()
-            .write(true)
-            .create_new(true)
-            .open(new_file_path)?;
-
-        let existing_file = File::open(&self.file_path)?;
-
-        for result in BinaryRecordIterator::new(existing_file) {
-            let (existing_id, existing_entry) = result?;
-            if !deleting_keys.contains(&existing_id) {
-                let _ = self.write_entry(&existing_entry, &mut new_file)?;
+        registry: &MigrationRegistry,
+        dry_run: bool,
+    ) -> Result<MigrationReport, StoreError> {
+        let (snapshot, header) = self.snapshot_of_any_version()?;
+        let mut report = MigrationReport::new(dry_run);
+        let mut migrated = Vec::new();
+
+        let mut records = snapshot.records()?;
+        while let Some(raw) = records.next_raw() {
+            let raw = raw?;
+            if raw.tombstone {
+                migrated.push(raw);
+                continue;
             }
+            let (id, entry) = split_id::<K>(&raw.payload)?;
+            let upgraded = registry.upgrade(header.version, entry)?;
+            report.add(&upgraded);
+            migrated.push(RawRecord {
+                tombstone: false,
+                payload: [id, upgraded.bytes.as_slice()].concat(),
+            });
         }
 
-        for new_entry in appending_entries {
-            let _ = self.write_entry(&new_entry, &mut new_file)?;
+        if dry_run || report.migrated() == 0 {
+            return Ok(report);
         }
+        report.back_up(&[&snapshot.file_path])?;
+        self.reset_id_filter();
 
-        new_file.flush()?;
-        Ok(())
+        let write = |file: &mut File| -> Result<(), StoreError> {
+            SchemaHeader::write_current(file)?;
+            for record in &migrated {
+                write_raw(file, record)?;
+            }
+            Ok(())
+        };
+        match &self.generations {
+            Some(generations) => {
+                generations.publish_with(&self.syncer, write)?;
+            }
+            None => {
+                let new_path = Self::temp_file_path(&self.file_path);
+                let mut new_file = File::create(&new_path)?;
+                write(&mut new_file)?;
+                self.syncer.rewritten(&mut new_file)?;
+                swap_in(&new_path, &self.file_path)?;
+                self.syncer.renamed(&self.file_path)?;
+            }
+        }
+
+        Ok(report)
     }
 
-    fn write_entry<W: Write>(&self, entry: &Entry, writer: &mut W) -> Result<(), BinaryStoreError> {
-        let serialized = &bincode::serialize(&(&entry.id, entry))?;
-        writer.write_u64::<LittleEndian>(serialized.len() as u64)?;
-        writer.write_all(&serialized)?;
-        Ok(())
+    // Cuts off a record left half-written by a crash so the records before
+    // it can be read again. Returns the new length of the file, or None when
+    // the last record was complete. Corrupt records are left for inspection.
+    pub fn truncate_torn_record(&mut self) -> Result<Option<u64>, StoreError> {
+        let (snapshot, _) = self.snapshot_of_any_version()?;
+        let mut file = File::open(&snapshot.file_path)?;
+        file.seek(SeekFrom::Start(snapshot.start))?;
+
+        for record in BinaryRecordIterator::<_, K>::starting_at(file, snapshot.start) {
+            match record {
+                Ok(_) => {}
+                Err(StoreError::TruncatedRecord { offset }) => {
+                    let mut file = OpenOptions::new().write(true).open(&snapshot.file_path)?;
+                    self.reset_id_filter();
+                    file.set_len(offset)?;
+                    self.syncer.saved(&mut file)?;
+                    info!(
+                        "Truncated torn record at {} in {}",
+                        offset, snapshot.file_path
+                    );
+                    return Ok(Some(offset));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn write_entry<W: Write>(
+        &self,
+        id: &K,
+        entry: &Entry,
+        writer: &mut W,
+    ) -> Result<(), StoreError> {
+        write_record(writer, id, entry)
     }
 }
 
-impl DataStore<String, Entry, BinaryStoreError> for BinaryFileEntryStore {
-    fn save(&mut self, id: &String, value: &Entry) -> Result<(), BinaryStoreError> {
-        let to_delete: Vec<String> = vec![id.into()];
-        let to_append = vec![value];
-        let new_path_string = format!("{}-tmp", self.file_path);
-        let new_path = &new_path_string;
-        self.move_to_new_file(new_path, &to_delete, to_append)?;
+// Splits the payload of a record into the encoded id and entry
+fn split_id<K: StoreKey>(payload: &[u8]) -> Result<(&[u8], &[u8]), StoreError> {
+    let mut entry = payload;
+    bincode::deserialize_from::<_, K>(&mut entry)?;
+    Ok(payload.split_at(payload.len() - entry.len()))
+}
 
-        remove_file(&self.file_path)?;
-        rename(new_path, &self.file_path)?;
-        Ok(())
+impl<K: StoreKey> DataStore<K, Entry, StoreError> for BinaryFileEntryStore<K> {
+    // Appends the entry, its older records are dropped by `compact`
+    fn save(&mut self, id: &K, value: &Entry) -> Result<(), StoreError> {
+        self.append(id, |file| self.write_entry(id, value, file))
     }
 
-    fn load(&self, id: &String) -> Result<Option<Entry>, BinaryStoreError> {
-        // Use OpenOptions to open the file
-        let file = OpenOptions::new().read(true).open(&self.file_path)?;
+    fn load(&self, id: &K) -> Result<Option<Entry>, StoreError> {
+        let snapshot = self.snapshot()?;
+        if !self.may_contain(&snapshot, id)? {
+            return Ok(None);
+        }
+        snapshot.load(id)
+    }
 
-        for record in BinaryRecordIterator::new(file) {
-            let (existing_id, existing_value) = record?;
-            if existing_id == *id {
-                return Ok(Some(existing_value));
+    // Appends a tombstone that hides the entry's earlier records
+    fn delete(&mut self, id: &K) -> Result<(), StoreError> {
+        self.append(id, |file| write_tombstone(file, id))
+    }
+
+    fn search(&self, filter: &dyn Filter<Entry>) -> Result<Vec<Entry>, StoreError> {
+        self.snapshot()?.search(filter)
+    }
+}
+
+impl<K: StoreKey> Compactable for BinaryFileEntryStore<K> {
+    fn fragmentation(&self) -> Result<f32, StoreError> {
+        let snapshot = self.snapshot()?;
+        Ok(fragmentation_of(
+            snapshot.live_bytes()?,
+            snapshot.len - snapshot.start,
+        ))
+    }
+
+    fn compact_with_progress(
+        &mut self,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<CompactionStats, StoreError> {
+        let started = Instant::now();
+        let bytes_before = self.snapshot()?.len;
+        let entries = self.compact_reporting(progress)?;
+
+        Ok(CompactionStats {
+            bytes_before,
+            bytes_after: self.snapshot()?.len,
+            entries,
+            duration: started.elapsed(),
+        })
+    }
+}
+
+// The filter is written when the store is dropped rather than on every save
+impl<K: StoreKey> Drop for BinaryFileEntryStore<K> {
+    fn drop(&mut self) {
+        // A store whose file was removed has nothing left to filter
+        if !Self::file_exists(&self.file_path) && self.generations.is_none() {
+            return;
+        }
+        if let Err(e) = self.write_id_filter() {
+            error!("Writing the id filter of {} failed! {}", self.file_path, e);
+        }
+    }
+}
+
+impl<K: StoreKey> StoreSnapshot<K> {
+    fn records(&self) -> Result<BinaryRecordIterator<io::Take<File>, K>, StoreError> {
+        let mut file = OpenOptions::new().read(true).open(&self.file_path)?;
+        file.seek(SeekFrom::Start(self.start))?;
+        Ok(BinaryRecordIterator::starting_at(
+            file.take(self.len - self.start),
+            self.start,
+        ))
+    }
+
+    // The ids of the records from `offset` on, still encoded
+    fn encoded_ids_after(&self, offset: u64) -> Result<Vec<Vec<u8>>, StoreError> {
+        let mut file = OpenOptions::new().read(true).open(&self.file_path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut records =
+            BinaryRecordIterator::<_, K>::starting_at(file.take(self.len - offset), offset);
+
+        let mut ids = Vec::new();
+        while let Some(raw) = records.next_raw() {
+            let raw = raw?;
+            let id = if raw.tombstone {
+                &raw.payload[..]
+            } else {
+                split_id::<K>(&raw.payload)?.0
+            };
+            ids.push(id.to_vec());
+        }
+        Ok(ids)
+    }
+
+    // The size on disk of the latest record of every entry that isn't deleted
+    fn live_bytes(&self) -> Result<u64, StoreError> {
+        let mut records = self.records()?;
+        let mut latest: HashMap<Vec<u8>, u64> = HashMap::new();
+        let mut offset = self.start;
+        while let Some(raw) = records.next_raw() {
+            let raw = raw?;
+            let size = records.offset() - offset;
+            offset = records.offset();
+            if raw.tombstone {
+                latest.insert(raw.payload, 0);
+            } else {
+                latest.insert(split_id::<K>(&raw.payload)?.0.to_vec(), size);
             }
         }
+        Ok(latest.values().sum())
+    }
 
-        Ok(None)
+    // The latest record of every entry that isn't deleted, ordered by id so
+    // saving an entry doesn't move it
+    fn live_records(&self) -> Result<Vec<(K, Entry)>, StoreError> {
+        let mut latest: BTreeMap<K, Option<Entry>> = BTreeMap::new();
+        for record in self.records()? {
+            let (id, entry) = record?;
+            latest.insert(id, entry);
+        }
+
+        Ok(latest
+            .into_iter()
+            .filter_map(|(id, entry)| entry.map(|entry| (id, entry)))
+            .collect())
     }
 
-    fn delete(&mut self, id: &String) -> Result<(), BinaryStoreError> {
-        let to_delete: Vec<String> = vec![id.into()];
-        let to_append = vec![];
-        let new_path_string = format!("{}-tmp", self.file_path);
-        let new_path = &new_path_string;
-        self.move_to_new_file(new_path, &to_delete, to_append)?;
+    pub fn load(&self, id: &K) -> Result<Option<Entry>, StoreError> {
+        let mut result = None;
 
-        remove_file(&self.file_path)?;
-        rename(new_path, &self.file_path)?;
-        Ok(())
+        for record in self.records()? {
+            let (existing_id, existing_value) = record?;
+            if existing_id == *id {
+                result = existing_value;
+            }
+        }
+
+        Ok(result)
     }
 
-    fn search(&self, filter: &dyn Filter<Entry>) -> Result<Vec<Entry>, BinaryStoreError> {
-        // Use OpenOptions to open the file
-        let file = OpenOptions::new().read(true).open(&self.file_path)?;
+    pub fn search(&self, filter: &dyn Filter<Entry>) -> Result<Vec<Entry>, StoreError> {
         let mut result: Vec<Entry> = vec![];
 
-        for record in BinaryRecordIterator::new(file) {
-            let (_, existing_value) = record?;
+        for (_, existing_value) in self.live_records()? {
             if filter.pass(&existing_value) {
                 result.push(existing_value);
             }
@@ -134,6 +696,7 @@ impl DataStore<String, Entry, BinaryStoreError> for BinaryFileEntryStore {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::data::migration::SCHEMA_HEADER_LEN;
     use std::fs::{self};
     use std::path::Path;
     use uuid::Uuid;
@@ -170,6 +733,7 @@ mod tests {
             password: Some("pass1".to_string()),
             url: Some("http://example.com".to_string()),
             note: Some("This is a note".to_string()),
+            ..Default::default()
         };
 
         // Save the entry
@@ -193,9 +757,7 @@ mod tests {
             id: "1".to_string(),
             title: "Entry to delete".to_string(),
             username: Some("user1".to_string()),
-            password: None,
-            url: None,
-            note: None,
+            ..Default::default()
         };
 
         // Save the entry
@@ -222,8 +784,7 @@ mod tests {
             title: "Searchable Entry 1".to_string(),
             username: Some("user1".to_string()),
             password: Some("pass1".to_string()),
-            url: None,
-            note: None,
+            ..Default::default()
         };
 
         let entry2 = Entry {
@@ -231,17 +792,13 @@ mod tests {
             title: "Another Searchable Entry".to_string(),
             username: Some("user2".to_string()),
             password: Some("pass2".to_string()),
-            url: None,
-            note: None,
+            ..Default::default()
         };
 
         let entry3 = Entry {
             id: "3".to_string(),
             title: "Non-Matching Entry".to_string(),
-            username: None,
-            password: None,
-            url: None,
-            note: None,
+            ..Default::default()
         };
 
         //Save entries
@@ -275,4 +832,304 @@ mod tests {
         // Clean up
         fs::remove_file(test_file_path).unwrap();
     }
+
+    #[test]
+    fn test_new_discards_interrupted_temp_file() {
+        let test_file_path = setup_test_file();
+        let mut store = BinaryFileEntryStore::new(test_file_path.clone());
+
+        let entry = Entry {
+            id: "1".to_string(),
+            title: "Survivor".to_string(),
+            ..Default::default()
+        };
+        store.save(&entry.id, &entry).unwrap();
+
+        // Simulate a save that died while writing the temp file
+        let temp_file_path = format!("{}-tmp", test_file_path);
+        fs::write(&temp_file_path, b"partial").unwrap();
+
+        let mut store = BinaryFileEntryStore::new(test_file_path.clone());
+        assert!(!Path::new(&temp_file_path).exists());
+        assert_eq!(store.load(&entry.id).unwrap(), Some(entry.clone()));
+
+        // Saving works again now that the temp file is gone
+        store.save(&entry.id, &entry).unwrap();
+
+        // Clean up
+        fs::remove_file(test_file_path).unwrap();
+    }
+
+    #[test]
+    fn test_truncate_torn_record() {
+        let test_file_path = setup_test_file();
+        let mut store = BinaryFileEntryStore::new(test_file_path.clone());
+
+        let entry = Entry {
+            id: "1".to_string(),
+            title: "Complete".to_string(),
+            ..Default::default()
+        };
+        store.save(&entry.id, &entry).unwrap();
+        let complete_len = fs::metadata(&test_file_path).unwrap().len();
+        assert_eq!(store.truncate_torn_record().unwrap(), None);
+
+        // Simulate a crash in the middle of appending a record
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&test_file_path)
+            .unwrap();
+        write_record(&mut file, &"2".to_string(), &entry).unwrap();
+        file.set_len(complete_len + 10).unwrap();
+
+        assert!(matches!(
+            store.load(&"2".to_string()),
+            Err(StoreError::TruncatedRecord { offset }) if offset == complete_len
+        ));
+        assert_eq!(store.truncate_torn_record().unwrap(), Some(complete_len));
+        assert_eq!(store.load(&entry.id).unwrap(), Some(entry));
+
+        // Clean up
+        fs::remove_file(test_file_path).unwrap();
+    }
+
+    #[test]
+    fn test_integer_keys() {
+        let test_file_path = setup_test_file();
+        let mut store: BinaryFileEntryStore<u32> =
+            BinaryFileEntryStore::new(test_file_path.clone());
+
+        let entry = Entry {
+            id: "1".to_string(),
+            title: "Numbered".to_string(),
+            ..Default::default()
+        };
+        store.save(&1, &entry).unwrap();
+        store.save(&2, &entry).unwrap();
+        store.delete(&2).unwrap();
+
+        assert_eq!(store.load(&1).unwrap(), Some(entry));
+        assert_eq!(store.load(&2).unwrap(), None);
+
+        // Clean up
+        fs::remove_file(test_file_path).unwrap();
+    }
+
+    #[test]
+    fn test_generational_snapshot_keeps_reading_its_generation() {
+        let test_file_path = setup_test_file();
+        let mut store = BinaryFileEntryStore::generational(test_file_path.clone());
+
+        let mut entry = Entry {
+            id: "1".to_string(),
+            title: "Before".to_string(),
+            ..Default::default()
+        };
+        store.save(&entry.id, &entry).unwrap();
+        let snapshot = store.snapshot().unwrap();
+
+        let before = entry.clone();
+        entry.title = "After".to_string();
+        store.save(&entry.id, &entry).unwrap();
+
+        assert_eq!(snapshot.load(&entry.id).unwrap(), Some(before.clone()));
+        assert_eq!(store.load(&entry.id).unwrap(), Some(entry.clone()));
+        assert!(!Path::new(&test_file_path).exists());
+
+        // Compacting publishes a new generation, the snapshot's is kept until
+        // it's dropped
+        let generations = Generations::new(test_file_path.clone());
+        store.compact().unwrap();
+        assert_eq!(generations.list().unwrap(), vec![1, 2]);
+        assert_eq!(snapshot.load(&entry.id).unwrap(), Some(before));
+        drop(snapshot);
+        store.delete(&entry.id).unwrap();
+        store.compact().unwrap();
+        assert_eq!(generations.list().unwrap(), vec![3]);
+        assert_eq!(
+            fs::metadata(generations.path(3)).unwrap().len(),
+            SCHEMA_HEADER_LEN
+        );
+
+        // Clean up
+        fs::remove_file(generations.path(3)).unwrap();
+    }
+
+    #[test]
+    fn test_generational_keeps_existing_entries() {
+        let test_file_path = setup_test_file();
+        let entry = Entry {
+            id: "1".to_string(),
+            title: "Existing".to_string(),
+            ..Default::default()
+        };
+        let mut store = BinaryFileEntryStore::new(test_file_path.clone());
+        store.save(&entry.id, &entry).unwrap();
+        drop(store);
+
+        let store = BinaryFileEntryStore::generational(test_file_path.clone());
+        assert_eq!(store.load(&entry.id).unwrap(), Some(entry));
+        drop(store);
+
+        // Clean up
+        let generations = Generations::new(test_file_path.clone());
+        fs::remove_file(generations.path(1)).unwrap();
+        fs::remove_file(test_file_path).unwrap();
+    }
+
+    #[test]
+    fn test_sync_level_is_recorded_in_stats() {
+        let test_file_path = setup_test_file();
+        let mut store = BinaryFileEntryStore::new(test_file_path.clone())
+            .with_sync_level(SyncLevel::FlushOnSave);
+        let entry = Entry {
+            id: "1".to_string(),
+            title: "First".to_string(),
+            ..Default::default()
+        };
+
+        store.save(&entry.id, &entry).unwrap();
+        store.delete(&entry.id).unwrap();
+        store.compact().unwrap();
+
+        let stats = store.sync_stats();
+        assert_eq!(stats.level, SyncLevel::FlushOnSave);
+        assert_eq!((stats.flushes, stats.syncs), (3, 0));
+
+        fs::remove_file(test_file_path).unwrap();
+    }
+
+    #[test]
+    fn test_saves_and_deletes_append_until_compacted() {
+        let test_file_path = setup_test_file();
+        let mut store = BinaryFileEntryStore::new(test_file_path.clone());
+
+        let mut entry = Entry {
+            id: "1".to_string(),
+            title: "First".to_string(),
+            ..Default::default()
+        };
+        let other = Entry {
+            id: "2".to_string(),
+            ..entry.clone()
+        };
+        store.save(&other.id, &other).unwrap();
+        let record_len = fs::metadata(&test_file_path).unwrap().len();
+        store.save(&entry.id, &entry).unwrap();
+
+        entry.title = "Later".to_string();
+        store.save(&entry.id, &entry).unwrap();
+        store.delete(&other.id).unwrap();
+        assert!(fs::metadata(&test_file_path).unwrap().len() > 3 * record_len);
+
+        assert_eq!(store.load(&entry.id).unwrap(), Some(entry.clone()));
+        assert_eq!(store.load(&other.id).unwrap(), None);
+        let filter = TitleFilter {
+            keyword: String::new(),
+        };
+        assert_eq!(store.search(&filter).unwrap(), vec![entry.clone()]);
+
+        store.compact().unwrap();
+        assert_eq!(fs::metadata(&test_file_path).unwrap().len(), record_len);
+        assert_eq!(store.search(&filter).unwrap(), vec![entry]);
+
+        // A deleted entry can be saved again
+        store.save(&other.id, &other).unwrap();
+        assert_eq!(store.load(&other.id).unwrap(), Some(other));
+
+        // Clean up
+        fs::remove_file(test_file_path).unwrap();
+    }
+
+    #[test]
+    fn test_migrates_legacy_records() {
+        use crate::data::migration::tests::{entry, v1_bytes};
+
+        let test_file_path = setup_test_file();
+        {
+            let mut file = File::create(&test_file_path).unwrap();
+            let record = RawRecord {
+                tombstone: false,
+                payload: [bincode::serialize("1").unwrap(), v1_bytes()].concat(),
+            };
+            write_raw(&mut file, &record).unwrap();
+        }
+        let legacy = fs::read(&test_file_path).unwrap();
+        let registry = MigrationRegistry::default();
+
+        // Opening doesn't migrate, the store refuses to be used instead
+        let mut store = BinaryFileEntryStore::open(test_file_path.clone()).unwrap();
+        assert!(matches!(
+            store.load(&"1".to_string()),
+            Err(StoreError::MigrationRequired { version: 1 })
+        ));
+        assert!(matches!(
+            store.save(&"2".to_string(), &entry()),
+            Err(StoreError::MigrationRequired { version: 1 })
+        ));
+        assert_eq!(fs::read(&test_file_path).unwrap(), legacy);
+
+        let report = store.migrate(&registry, true).unwrap();
+        assert_eq!(report.migrated(), 1);
+        assert_eq!(report.oldest_version(), Some(1));
+        assert!(report.backups.is_empty());
+        assert_eq!(fs::read(&test_file_path).unwrap(), legacy);
+
+        let report = store.migrate(&registry, false).unwrap();
+        assert_eq!(report.steps.len(), 1);
+        assert_eq!(fs::read(&report.backups[0]).unwrap(), legacy);
+        assert_eq!(store.load(&"1".to_string()).unwrap(), Some(entry()));
+
+        // Already current, so reopening leaves the file alone
+        let migrated = fs::read(&test_file_path).unwrap();
+        let store = BinaryFileEntryStore::<String>::new(test_file_path.clone());
+        assert_eq!(store.migrate(&registry, true).unwrap().migrated(), 0);
+        assert_eq!(fs::read(&test_file_path).unwrap(), migrated);
+
+        // Clean up
+        fs::remove_file(&report.backups[0]).unwrap();
+        fs::remove_file(test_file_path).unwrap();
+    }
+
+    #[test]
+    fn test_id_filter_catches_up_and_persists() {
+        let test_file_path = setup_test_file();
+        let bloom_path = format!("{}.bloom", test_file_path);
+        let entry = |id: &str| Entry {
+            id: id.to_string(),
+            title: format!("Entry {}", id),
+            ..Default::default()
+        };
+
+        let mut store = BinaryFileEntryStore::new(test_file_path.clone()).with_id_filter();
+        store.save(&"1".to_string(), &entry("1")).unwrap();
+        assert_eq!(store.load(&"2".to_string()).unwrap(), None);
+        store.save(&"2".to_string(), &entry("2")).unwrap();
+        assert_eq!(store.load(&"2".to_string()).unwrap(), Some(entry("2")));
+
+        // Appended behind the filter's back
+        let mut other = BinaryFileEntryStore::new(test_file_path.clone());
+        other.save(&"3".to_string(), &entry("3")).unwrap();
+        drop(other);
+        assert_eq!(store.load(&"3".to_string()).unwrap(), Some(entry("3")));
+        store.check_invariants().unwrap();
+
+        drop(store);
+        assert!(Path::new(&bloom_path).exists());
+        let mut store = BinaryFileEntryStore::new(test_file_path.clone()).with_id_filter();
+        assert_eq!(store.load(&"1".to_string()).unwrap(), Some(entry("1")));
+        assert_eq!(store.load(&"4".to_string()).unwrap(), None);
+
+        // Compaction moves every record, so the saved filter is dropped
+        store.delete(&"1".to_string()).unwrap();
+        store.compact().unwrap();
+        assert!(!Path::new(&bloom_path).exists());
+        assert_eq!(store.load(&"1".to_string()).unwrap(), None);
+        assert_eq!(store.load(&"3".to_string()).unwrap(), Some(entry("3")));
+        store.check_invariants().unwrap();
+
+        drop(store);
+        fs::remove_file(bloom_path).unwrap();
+        fs::remove_file(test_file_path).unwrap();
+    }
 }
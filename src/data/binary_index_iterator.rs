@@ -1,33 +1,37 @@
-use std::io::{self, Read};
+use serde::de::DeserializeOwned;
+use std::{
+    io::{self, Read},
+    marker::PhantomData,
+};
 
-use super::{binary_store_error::BinaryStoreError, indexed_binary_file_entry_store::IndexEntry};
+use super::{compact_index::decode_record, store_error::StoreError};
 
-pub struct BinaryIndexIterator<R: Read> {
+// Reads fixed size `(key, value)` records as written by `encode_record`
+pub struct BinaryIndexIterator<R: Read, K, V> {
     reader: R,
     record_size: usize,
+    record: PhantomData<(K, V)>,
 }
 
-impl<R: Read> BinaryIndexIterator<R> {
+impl<R: Read, K, V> BinaryIndexIterator<R, K, V> {
     pub fn new(reader: R, record_size: usize) -> Self {
         BinaryIndexIterator {
             reader,
             record_size,
+            record: PhantomData,
         }
     }
 }
 
-impl<R: Read> Iterator for BinaryIndexIterator<R> {
-    type Item = Result<IndexEntry, BinaryStoreError>;
+impl<R: Read, K: DeserializeOwned, V: DeserializeOwned> Iterator for BinaryIndexIterator<R, K, V> {
+    type Item = Result<(K, V), StoreError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         let mut buffer = vec![0; self.record_size];
         match self.reader.read_exact(&mut buffer) {
-            Ok(_) => {
-                let record: Result<IndexEntry, _> = bincode::deserialize(&buffer);
-                record.map_err(BinaryStoreError::SerializationError).into()
-            }
+            Ok(_) => Some(decode_record(&buffer)),
             Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => None,
-            Err(e) => Some(Err(BinaryStoreError::IoError(e))),
+            Err(e) => Some(Err(StoreError::IoError(e))),
         }
     }
 }
@@ -1,35 +1,309 @@
-use byteorder::{LittleEndian, ReadBytesExt};
-use std::io::{self, Read};
+use byteorder::{LittleEndian, WriteBytesExt};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    io::{self, Read, Write},
+    marker::PhantomData,
+};
 
-use super::{binary_store_error::BinaryStoreError, model::Entry};
+use super::{model::Entry, store_error::StoreError};
 
-pub struct BinaryRecordIterator<R: Read> {
+// Each record is framed as `magic | length | payload | crc32(payload)`. The
+// magic tells framed records apart from the older `length | payload` ones,
+// whose length never gets anywhere near it.
+const RECORD_MAGIC: u32 = 0x5452_4731;
+// A tombstone's payload is just the id of the deleted entry
+const TOMBSTONE_MAGIC: u32 = 0x5452_4730;
+
+fn write_frame<W: Write>(writer: &mut W, magic: u32, payload: &[u8]) -> Result<(), StoreError> {
+    writer.write_u32::<LittleEndian>(magic)?;
+    writer.write_u64::<LittleEndian>(payload.len() as u64)?;
+    writer.write_all(payload)?;
+    writer.write_u32::<LittleEndian>(crc32fast::hash(payload))?;
+    Ok(())
+}
+
+pub fn write_record<W: Write, K: Serialize>(
+    writer: &mut W,
+    id: &K,
+    entry: &Entry,
+) -> Result<(), StoreError> {
+    write_frame(writer, RECORD_MAGIC, &bincode::serialize(&(id, entry))?)
+}
+
+// Marks the entry with `id` as deleted as of this point in the file
+pub fn write_tombstone<W: Write, K: Serialize>(writer: &mut W, id: &K) -> Result<(), StoreError> {
+    write_frame(writer, TOMBSTONE_MAGIC, &bincode::serialize(id)?)
+}
+
+// Writes `record` back with a fresh frame, e.g. after migrating its payload
+pub fn write_raw<W: Write>(writer: &mut W, record: &RawRecord) -> Result<(), StoreError> {
+    let magic = if record.tombstone {
+        TOMBSTONE_MAGIC
+    } else {
+        RECORD_MAGIC
+    };
+    write_frame(writer, magic, &record.payload)
+}
+
+pub struct BinaryRecordIterator<R: Read, K = String> {
     reader: R,
+    offset: u64,
+    done: bool,
+    key: PhantomData<K>,
 }
 
-impl<R: Read> BinaryRecordIterator<R> {
+impl<R: Read, K: DeserializeOwned> BinaryRecordIterator<R, K> {
     pub fn new(reader: R) -> Self {
-        BinaryRecordIterator { reader }
+        Self::starting_at(reader, 0)
+    }
+
+    // Iterates a reader already `offset` bytes into the file, e.g. past its
+    // header, so offsets in errors are still offsets in the file
+    pub fn starting_at(reader: R, offset: u64) -> Self {
+        BinaryRecordIterator {
+            reader,
+            offset,
+            done: false,
+            key: PhantomData,
+        }
+    }
+
+    // End of the last complete record, i.e. where a torn tail starts
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    // Like `read_exact`, but returns how much was read instead of failing at EOF
+    fn read_up_to(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        while read < buffer.len() {
+            match self.reader.read(&mut buffer[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(read)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], StoreError> {
+        let mut buffer = [0; N];
+        if self.read_up_to(&mut buffer)? < N {
+            return Err(self.truncated());
+        }
+        Ok(buffer)
+    }
+
+    fn truncated(&self) -> StoreError {
+        StoreError::TruncatedRecord {
+            offset: self.offset,
+        }
+    }
+
+    fn read_record(&mut self) -> Option<Result<(K, Option<Entry>), StoreError>> {
+        let raw = match self.read_raw()? {
+            Ok(raw) => raw,
+            Err(e) => return Some(Err(e)),
+        };
+
+        Some(if raw.tombstone {
+            bincode::deserialize(&raw.payload)
+                .map(|id| (id, None))
+                .map_err(StoreError::from)
+        } else {
+            bincode::deserialize(&raw.payload)
+                .map(|(id, entry)| (id, Some(entry)))
+                .map_err(StoreError::from)
+        })
+    }
+
+    fn read_raw(&mut self) -> Option<Result<RawRecord, StoreError>> {
+        let mut head = [0; 4];
+        match self.read_up_to(&mut head) {
+            Ok(0) => return None,
+            Ok(4) => {}
+            Ok(_) => return Some(Err(self.truncated())),
+            Err(e) => return Some(Err(StoreError::IoError(e))),
+        }
+
+        Some(self.read_body(head))
+    }
+
+    fn read_body(&mut self, head: [u8; 4]) -> Result<RawRecord, StoreError> {
+        let head = u32::from_le_bytes(head);
+        let framed = head == RECORD_MAGIC || head == TOMBSTONE_MAGIC;
+        let length = if framed {
+            u64::from_le_bytes(self.read_array()?)
+        } else {
+            // Legacy records start with the low half of a u64 length
+            let high = u32::from_le_bytes(self.read_array()?);
+            (u64::from(high) << 32) | u64::from(head)
+        };
+
+        // `take` so a corrupt length can't allocate more than the file holds
+        let mut payload = Vec::new();
+        (&mut self.reader).take(length).read_to_end(&mut payload)?;
+        if (payload.len() as u64) < length {
+            return Err(self.truncated());
+        }
+
+        // The length and payload, with the magic and checksum when framed
+        let mut record_size = 8 + length;
+        if framed {
+            let checksum = u32::from_le_bytes(self.read_array()?);
+            if checksum != crc32fast::hash(&payload) {
+                return Err(StoreError::CorruptRecord {
+                    offset: self.offset,
+                });
+            }
+            record_size += 8;
+        }
+
+        self.offset += record_size;
+        Ok(RawRecord {
+            tombstone: head == TOMBSTONE_MAGIC,
+            payload,
+        })
+    }
+
+    // The next record without decoding its payload, e.g. to migrate records
+    // written with an older layout of `Entry`
+    pub fn next_raw(&mut self) -> Option<Result<RawRecord, StoreError>> {
+        if self.done {
+            return None;
+        }
+
+        let record = self.read_raw();
+        if !matches!(record, Some(Ok(_))) {
+            self.done = true;
+        }
+        record
     }
 }
 
-impl<R: Read> Iterator for BinaryRecordIterator<R> {
-    type Item = Result<(String, Entry), BinaryStoreError>;
+// A record as stored. The payload of a tombstone is the bincode of the id,
+// of any other record the bincode of `(id, entry)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawRecord {
+    pub tombstone: bool,
+    pub payload: Vec<u8>,
+}
+
+impl<R: Read, K: DeserializeOwned> Iterator for BinaryRecordIterator<R, K> {
+    // A None entry is a tombstone
+    type Item = Result<(K, Option<Entry>), StoreError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.reader.read_u64::<LittleEndian>() {
-            Ok(len) => {
-                let mut buffer = vec![0; len as usize];
-                match self.reader.read_exact(&mut buffer) {
-                    Ok(()) => {
-                        let record: Result<(String, Entry), _> = bincode::deserialize(&buffer);
-                        record.map_err(BinaryStoreError::SerializationError).into()
-                    }
-                    Err(e) => Some(Err(BinaryStoreError::IoError(e))),
-                }
-            }
-            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => None,
-            Err(e) => Some(Err(BinaryStoreError::IoError(e))),
+        if self.done {
+            return None;
+        }
+
+        let record = self.read_record();
+        // Nothing after a bad record can be trusted
+        if !matches!(record, Some(Ok(_))) {
+            self.done = true;
+        }
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: format!("Entry {}", id),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn records(ids: &[&str]) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        for id in ids {
+            write_record(&mut buffer, &id.to_string(), &entry(id)).unwrap();
+        }
+        buffer
+    }
+
+    #[test]
+    fn test_reads_framed_records() {
+        let buffer = records(&["1", "2"]);
+        let mut iterator = BinaryRecordIterator::<_, String>::new(buffer.as_slice());
+
+        let ids: Vec<String> = iterator.by_ref().map(|r| r.unwrap().0).collect();
+
+        assert_eq!(ids, vec!["1", "2"]);
+        assert_eq!(iterator.offset(), buffer.len() as u64);
+    }
+
+    #[test]
+    fn test_reads_legacy_records() {
+        let serialized = bincode::serialize(&("1".to_string(), entry("1"))).unwrap();
+        let mut buffer = Vec::new();
+        for _ in 0..2 {
+            buffer
+                .write_u64::<LittleEndian>(serialized.len() as u64)
+                .unwrap();
+            buffer.extend_from_slice(&serialized);
         }
+
+        let mut iterator = BinaryRecordIterator::<_, String>::new(buffer.as_slice());
+        assert_eq!(iterator.next().unwrap().unwrap().1, Some(entry("1")));
+        assert_eq!(iterator.offset(), buffer.len() as u64 / 2);
+        assert_eq!(iterator.next().unwrap().unwrap().1, Some(entry("1")));
+        assert_eq!(iterator.offset(), buffer.len() as u64);
+        assert!(iterator.next().is_none());
+    }
+
+    #[test]
+    fn test_reads_tombstones() {
+        let mut buffer = records(&["1"]);
+        write_tombstone(&mut buffer, &"1".to_string()).unwrap();
+
+        let records: Vec<_> = BinaryRecordIterator::<_, String>::new(buffer.as_slice())
+            .map(|r| r.unwrap())
+            .collect();
+
+        assert_eq!(
+            records,
+            vec![("1".to_string(), Some(entry("1"))), ("1".to_string(), None)]
+        );
+    }
+
+    #[test]
+    fn test_reports_truncated_record() {
+        let valid = records(&["1"]);
+        let mut buffer = records(&["1", "2"]);
+        for cut in [buffer.len() - 1, valid.len() + 10, valid.len() + 2] {
+            buffer.truncate(cut);
+            let mut iterator = BinaryRecordIterator::<_, String>::new(buffer.as_slice());
+
+            assert_eq!(iterator.next().unwrap().unwrap().0, "1");
+            assert!(matches!(
+                iterator.next(),
+                Some(Err(StoreError::TruncatedRecord { offset })) if offset == valid.len() as u64
+            ));
+            assert!(iterator.next().is_none());
+        }
+    }
+
+    #[test]
+    fn test_reports_corrupt_record() {
+        let valid = records(&["1"]);
+        let mut buffer = records(&["1", "2"]);
+        // Flip a byte inside the second payload
+        buffer[valid.len() + 20] ^= 0xff;
+
+        let results: Vec<_> = BinaryRecordIterator::<_, String>::new(buffer.as_slice()).collect();
+
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(StoreError::CorruptRecord { offset }) if offset == valid.len() as u64
+        ));
     }
 }
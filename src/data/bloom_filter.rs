@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+// Filters are never sized below this, so a new store doesn't rebuild its
+// filter after every few saves
+const MIN_CAPACITY: usize = 1024;
+// About 1% false positives at capacity
+const BITS_PER_ITEM: usize = 10;
+const HASHES: u32 = 7;
+
+// A set that can answer "definitely not present" without holding the items.
+// It is persisted, so it hashes with FNV-1a rather than the std hasher,
+// whose output may change between Rust versions.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    capacity: usize,
+    len: usize,
+}
+
+fn fnv1a(seed: u64, item: &[u8]) -> u64 {
+    item.iter().fold(seed, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+impl BloomFilter {
+    pub fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.max(MIN_CAPACITY);
+        Self {
+            bits: vec![0; (capacity * BITS_PER_ITEM).div_ceil(64)],
+            capacity,
+            len: 0,
+        }
+    }
+
+    // Bit positions of `item`, by double hashing
+    fn positions(&self, item: &[u8]) -> impl Iterator<Item = usize> {
+        let first = fnv1a(0xcbf2_9ce4_8422_2325, item);
+        let second = fnv1a(0x8422_2325_cbf2_9ce4, item) | 1;
+        let bit_count = self.bits.len() as u64 * 64;
+        (0..u64::from(HASHES))
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % bit_count) as usize)
+    }
+
+    pub fn insert(&mut self, item: &[u8]) {
+        for position in self.positions(item).collect::<Vec<_>>() {
+            self.bits[position / 64] |= 1 << (position % 64);
+        }
+        self.len += 1;
+    }
+
+    // False only when `item` was never inserted
+    pub fn may_contain(&self, item: &[u8]) -> bool {
+        self.positions(item)
+            .all(|position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+
+    // Insertions so far, counting repeated items every time
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Past its capacity the false positive rate climbs, so it is worth
+    // building a bigger one
+    pub fn is_full(&self) -> bool {
+        self.len > self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_false_negatives_and_few_false_positives() {
+        let mut filter = BloomFilter::with_capacity(1000);
+        for i in 0..1000 {
+            filter.insert(format!("inserted {}", i).as_bytes());
+        }
+
+        assert!((0..1000).all(|i| filter.may_contain(format!("inserted {}", i).as_bytes())));
+        let false_positives = (0..10_000)
+            .filter(|i| filter.may_contain(format!("missing {}", i).as_bytes()))
+            .count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+        assert!(!filter.is_full());
+    }
+}
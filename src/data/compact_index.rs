@@ -0,0 +1,228 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::{collections::HashMap, hash::Hash};
+use uuid::Uuid;
+
+use super::store_error::StoreError;
+
+// Ids are mostly UUIDs, which as strings take 24 bytes plus 36 on the heap.
+// The index keeps those as their 16 bytes and every other id as it is. On
+// disk a UUID record starts with a length no string can have, followed by
+// the 16 bytes, so records of other ids read the same as they always have.
+const UUID_MARKER: [u8; 8] = u64::MAX.to_le_bytes();
+
+// Keys that can be held as the bytes of a UUID. Lookups ask every key, so
+// this mustn't allocate.
+pub trait CompactKey {
+    // The bytes of the key when it is a UUID in the lowercase hyphenated
+    // form, the one form that turns back into the same key
+    fn uuid(&self) -> Option<[u8; 16]> {
+        None
+    }
+}
+
+impl CompactKey for str {
+    fn uuid(&self) -> Option<[u8; 16]> {
+        // Of the forms `try_parse` takes only the hyphenated one is 36 long
+        if self.len() != 36 || self.bytes().any(|b| b.is_ascii_uppercase()) {
+            return None;
+        }
+        Uuid::try_parse(self).ok().map(|uuid| *uuid.as_bytes())
+    }
+}
+
+impl CompactKey for String {
+    fn uuid(&self) -> Option<[u8; 16]> {
+        self.as_str().uuid()
+    }
+}
+
+macro_rules! never_uuid {
+    ($($key:ty),*) => {
+        $(impl CompactKey for $key {})*
+    };
+}
+
+never_uuid!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+fn key_of<K: DeserializeOwned>(uuid: &[u8; 16]) -> Result<K, StoreError> {
+    let text = Uuid::from_bytes(*uuid).hyphenated().to_string();
+    Ok(bincode::deserialize(&bincode::serialize(&text)?)?)
+}
+
+// A map from ids to positions that holds UUID ids compactly
+#[derive(Debug, Clone)]
+pub struct CompactIndex<K, P> {
+    uuids: HashMap<[u8; 16], P>,
+    others: HashMap<K, P>,
+}
+
+impl<K, P> Default for CompactIndex<K, P> {
+    fn default() -> Self {
+        Self {
+            uuids: HashMap::new(),
+            others: HashMap::new(),
+        }
+    }
+}
+
+impl<K: CompactKey + Serialize + DeserializeOwned + Eq + Hash, P> CompactIndex<K, P> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.uuids.len() + self.others.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.uuids.is_empty() && self.others.is_empty()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&P> {
+        match key.uuid() {
+            Some(uuid) => self.uuids.get(&uuid),
+            None => self.others.get(key),
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn insert(&mut self, key: K, value: P) -> Option<P> {
+        match key.uuid() {
+            Some(uuid) => self.uuids.insert(uuid, value),
+            None => self.others.insert(key, value),
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<P> {
+        match key.uuid() {
+            Some(uuid) => self.uuids.remove(&uuid),
+            None => self.others.remove(key),
+        }
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &P> {
+        self.uuids.values().chain(self.others.values())
+    }
+}
+
+impl<K: CompactKey + Serialize + DeserializeOwned + Eq + Hash + Clone, P> CompactIndex<K, P> {
+    // Keys are handed out by value, UUIDs are turned back into keys on the way
+    pub fn iter(&self) -> impl Iterator<Item = (K, &P)> {
+        self.uuids
+            .iter()
+            .map(|(uuid, value)| {
+                let key = key_of(uuid).expect("only keys that encode as strings are held as UUIDs");
+                (key, value)
+            })
+            .chain(self.others.iter().map(|(key, value)| (key.clone(), value)))
+    }
+}
+
+impl<K: CompactKey + Serialize + DeserializeOwned + Eq + Hash, P> FromIterator<(K, P)>
+    for CompactIndex<K, P>
+{
+    fn from_iter<I: IntoIterator<Item = (K, P)>>(iter: I) -> Self {
+        let mut index = Self::new();
+        for (key, value) in iter {
+            index.insert(key, value);
+        }
+        index
+    }
+}
+
+// A record of `key` and `value` zero padded to `record_size`, for the index
+// file and the journal
+pub fn encode_record<K: CompactKey + Serialize + ?Sized, V: Serialize>(
+    key: &K,
+    value: &V,
+    record_size: usize,
+) -> Result<Vec<u8>, StoreError> {
+    let mut record = match key.uuid() {
+        Some(uuid) => [&UUID_MARKER[..], &uuid].concat(),
+        None => bincode::serialize(key)?,
+    };
+    record.extend(bincode::serialize(value)?);
+    if record.len() > record_size {
+        return Err(StoreError::IndexRecordTooLarge);
+    }
+    record.resize(record_size, 0);
+    Ok(record)
+}
+
+pub fn decode_record<K: DeserializeOwned, V: DeserializeOwned>(
+    record: &[u8],
+) -> Result<(K, V), StoreError> {
+    match record.strip_prefix(&UUID_MARKER[..]) {
+        Some(rest) if rest.len() >= 16 => {
+            let (uuid, value) = rest.split_at(16);
+            let uuid: [u8; 16] = uuid.try_into().expect("split at 16 bytes");
+            Ok((key_of(&uuid)?, bincode::deserialize(value)?))
+        }
+        _ => Ok(bincode::deserialize(record)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UUID: &str = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+
+    #[test]
+    fn test_uuids_are_held_as_bytes() {
+        let mut index = CompactIndex::new();
+        index.insert(UUID.to_string(), 1u64);
+        // Not the form it would turn back into
+        index.insert(UUID.to_uppercase(), 2);
+        index.insert("short".to_string(), 3);
+
+        assert_eq!(index.uuids.len(), 1);
+        assert_eq!(index.get(&UUID.to_string()), Some(&1));
+        assert_eq!(index.get(&UUID.to_uppercase()), Some(&2));
+        let mut keys: Vec<_> = index.iter().map(|(key, _)| key).collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![UUID.to_uppercase(), UUID.to_string(), "short".to_string()]
+        );
+
+        assert_eq!(index.remove(&UUID.to_string()), Some(1));
+        assert_eq!(index.len(), 2);
+    }
+
+    #[test]
+    fn test_only_the_hyphenated_form_is_a_uuid() {
+        assert!(UUID.uuid().is_some());
+        assert_eq!(UUID.to_string().uuid(), UUID.uuid());
+        for other in [
+            UUID.to_uppercase(),
+            UUID.replace('-', ""),
+            format!("{{{}}}", UUID),
+            format!("urn:uuid:{}", UUID),
+            UUID.replace('-', "x"),
+        ] {
+            assert_eq!(other.uuid(), None, "{}", other);
+        }
+        assert_eq!(7u64.uuid(), None);
+    }
+
+    #[test]
+    fn test_records_round_trip() {
+        for key in [UUID.to_string(), "short".to_string()] {
+            let record = encode_record(&key, &Some((7u64, 9usize)), 52).unwrap();
+            assert_eq!(record.len(), 52);
+            let (decoded, value): (String, Option<(u64, usize)>) = decode_record(&record).unwrap();
+            assert_eq!((decoded, value), (key, Some((7, 9))));
+        }
+
+        // A UUID record takes 24 bytes before the value, as a string it took 44
+        assert_eq!(encode_record(UUID, &(0u64, 0usize), 40).unwrap().len(), 40);
+        assert!(matches!(
+            encode_record(&UUID.to_uppercase(), &(0u64, 0usize), 52),
+            Err(StoreError::IndexRecordTooLarge)
+        ));
+    }
+}
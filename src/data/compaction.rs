@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use super::store_error::StoreError;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompactionStats {
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    // Entries written to the compacted file
+    pub entries: usize,
+    pub duration: Duration,
+}
+
+impl CompactionStats {
+    pub fn reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+// Maintenance the stores have in common, so whoever schedules it can decide
+// when to compact without knowing which store it has
+pub trait Compactable {
+    // The share of the store's bytes taken by replaced or deleted records,
+    // from 0 when there is nothing to reclaim to nearly 1
+    fn fragmentation(&self) -> Result<f32, StoreError>;
+
+    // Like the store's own compaction, calling `progress` with the entries
+    // written so far and how many there are in all
+    fn compact_with_progress(
+        &mut self,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<CompactionStats, StoreError>;
+}
+
+pub(crate) fn fragmentation_of(live_bytes: u64, total_bytes: u64) -> f32 {
+    match total_bytes {
+        0 => 0.0,
+        total_bytes => 1.0 - (live_bytes.min(total_bytes) as f64 / total_bytes as f64) as f32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{
+        binary_file_entry_store::BinaryFileEntryStore, data_store::DataStore,
+        indexed_binary_file_entry_store::IndexedBinaryFileEntryStore, model::Entry,
+    };
+    use std::fs;
+    use uuid::Uuid;
+
+    fn entry(id: &str, title: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: title.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn exercise<S>(store: &mut S)
+    where
+        S: Compactable + DataStore<String, Entry, StoreError>,
+    {
+        assert_eq!(store.fragmentation().unwrap(), 0.0);
+        for id in ["1", "2", "3"] {
+            store.save(&id.to_string(), &entry(id, "First")).unwrap();
+        }
+        assert_eq!(store.fragmentation().unwrap(), 0.0);
+        store.save(&"1".to_string(), &entry("1", "Second")).unwrap();
+        store.delete(&"2".to_string()).unwrap();
+        let fragmentation = store.fragmentation().unwrap();
+        assert!(fragmentation > 0.3, "{}", fragmentation);
+
+        let mut calls = Vec::new();
+        let stats = store
+            .compact_with_progress(&mut |written, total| calls.push((written, total)))
+            .unwrap();
+
+        assert_eq!(calls, vec![(1, 2), (2, 2)]);
+        assert_eq!(stats.entries, 2);
+        assert!(stats.reclaimed() > 0);
+        assert_eq!(store.fragmentation().unwrap(), 0.0);
+        assert_eq!(
+            store.load(&"1".to_string()).unwrap(),
+            Some(entry("1", "Second"))
+        );
+    }
+
+    #[test]
+    fn test_binary_store() {
+        let file_path = format!("test_compaction_{}.bin", Uuid::new_v4());
+        let mut store: BinaryFileEntryStore = BinaryFileEntryStore::new(file_path.clone());
+
+        exercise(&mut store);
+
+        drop(store);
+        fs::remove_file(file_path).unwrap();
+    }
+
+    #[test]
+    fn test_indexed_binary_store() {
+        let id = Uuid::new_v4();
+        let data_file_path = format!("test_compaction_data_{}.bin", id);
+        let index_file_path = format!("test_compaction_index_{}.bin", id);
+        let mut store: IndexedBinaryFileEntryStore =
+            IndexedBinaryFileEntryStore::new(data_file_path.clone(), index_file_path.clone());
+
+        exercise(&mut store);
+
+        drop(store);
+        for file_path in [
+            data_file_path,
+            format!("{}.journal", index_file_path),
+            index_file_path,
+        ] {
+            let _ = fs::remove_file(file_path);
+        }
+    }
+}
@@ -1,3 +1,18 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    hash::Hash,
+    marker::PhantomData,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::compact_index::CompactKey;
+
+// Keys the binary stores can persist, e.g. `String` ids or integers for
+// auxiliary tables
+pub trait StoreKey: Serialize + DeserializeOwned + Eq + Hash + Ord + Clone + CompactKey {}
+
+impl<K: Serialize + DeserializeOwned + Eq + Hash + Ord + Clone + CompactKey> StoreKey for K {}
+
 pub trait DataStore<K, V, E> {
     fn save(&mut self, id: &K, value: &V) -> Result<(), E>;
 
@@ -5,9 +20,295 @@ pub trait DataStore<K, V, E> {
 
     fn delete(&mut self, id: &K) -> Result<(), E>;
 
+    // Results are ordered by key, so repeated searches list the same
+    // entries in the same order. See `sort` for other orders.
     fn search(&self, filter: &dyn Filter<V>) -> Result<Vec<V>, E>;
+
+    fn save_returning_previous(&mut self, id: &K, value: &V) -> Result<Option<V>, E> {
+        let previous = self.load(id)?;
+        self.save(id, value)?;
+        Ok(previous)
+    }
+
+    // Saves only if the stored value still has `expected_updated_at`, or
+    // doesn't exist when that is None. The saved value gets a newer
+    // `updated_at`, so the next writer has to have seen this save.
+    fn save_if_version(
+        &mut self,
+        id: &K,
+        value: &V,
+        expected_updated_at: Option<u64>,
+    ) -> Result<SaveOutcome<V>, E>
+    where
+        V: Versioned + Clone,
+    {
+        let current = self.load(id)?;
+        let current_updated_at = current.as_ref().map(Versioned::updated_at);
+        if current_updated_at != expected_updated_at {
+            return Ok(SaveOutcome::Conflict(current));
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let mut saved = value.clone();
+        // Stay ahead of the previous version even if the clock went back
+        saved.set_updated_at(now.max(current_updated_at.map_or(0, |t| t + 1)));
+        saved.replacing(current.as_ref());
+        self.save(id, &saved)?;
+
+        Ok(SaveOutcome::Saved(saved))
+    }
+}
+
+pub trait Versioned {
+    fn updated_at(&self) -> u64;
+
+    fn set_updated_at(&mut self, updated_at: u64);
+
+    // Called by `save_if_version` once the value has its new `updated_at`,
+    // with the version it replaces, e.g. to track when a field last changed
+    fn replacing(&mut self, _previous: Option<&Self>) {}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveOutcome<V> {
+    // The value as saved, with its new `updated_at`
+    Saved(V),
+    // Someone else saved first, this is what is stored now
+    Conflict(Option<V>),
 }
 
 pub trait Filter<V> {
     fn pass(&self, v: &V) -> bool;
+
+    fn and<F: Filter<V>>(self, other: F) -> And<Self, F>
+    where
+        Self: Sized,
+    {
+        And(self, other)
+    }
+
+    fn or<F: Filter<V>>(self, other: F) -> Or<Self, F>
+    where
+        Self: Sized,
+    {
+        Or(self, other)
+    }
+
+    fn not(self) -> Not<Self>
+    where
+        Self: Sized,
+    {
+        Not(self)
+    }
+}
+
+impl<V, F: Filter<V> + ?Sized> Filter<V> for &F {
+    fn pass(&self, v: &V) -> bool {
+        (**self).pass(v)
+    }
+}
+
+impl<V, F: Filter<V> + ?Sized> Filter<V> for Box<F> {
+    fn pass(&self, v: &V) -> bool {
+        (**self).pass(v)
+    }
+}
+
+pub struct And<F1, F2>(pub F1, pub F2);
+
+impl<V, F1: Filter<V>, F2: Filter<V>> Filter<V> for And<F1, F2> {
+    fn pass(&self, v: &V) -> bool {
+        self.0.pass(v) && self.1.pass(v)
+    }
+}
+
+pub struct Or<F1, F2>(pub F1, pub F2);
+
+impl<V, F1: Filter<V>, F2: Filter<V>> Filter<V> for Or<F1, F2> {
+    fn pass(&self, v: &V) -> bool {
+        self.0.pass(v) || self.1.pass(v)
+    }
+}
+
+pub struct Not<F>(pub F);
+
+impl<V, F: Filter<V>> Filter<V> for Not<F> {
+    fn pass(&self, v: &V) -> bool {
+        !self.0.pass(v)
+    }
+}
+
+// Wraps a closure so one-off queries don't need a struct of their own
+pub struct FnFilter<V, P> {
+    predicate: P,
+    value: PhantomData<fn(&V)>,
+}
+
+impl<V, P: Fn(&V) -> bool> Filter<V> for FnFilter<V, P> {
+    fn pass(&self, v: &V) -> bool {
+        (self.predicate)(v)
+    }
+}
+
+pub fn filter_fn<V, P: Fn(&V) -> bool>(predicate: P) -> FnFilter<V, P> {
+    FnFilter {
+        predicate,
+        value: PhantomData,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    struct Even;
+
+    impl Filter<i32> for Even {
+        fn pass(&self, v: &i32) -> bool {
+            v % 2 == 0
+        }
+    }
+
+    fn matching(filter: &dyn Filter<i32>) -> Vec<i32> {
+        (1..=10).filter(|v| filter.pass(v)).collect()
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Doc {
+        text: &'static str,
+        updated_at: u64,
+    }
+
+    impl Versioned for Doc {
+        fn updated_at(&self) -> u64 {
+            self.updated_at
+        }
+
+        fn set_updated_at(&mut self, updated_at: u64) {
+            self.updated_at = updated_at;
+        }
+    }
+
+    #[derive(Default)]
+    struct MemoryStore(HashMap<u32, Doc>);
+
+    impl DataStore<u32, Doc, ()> for MemoryStore {
+        fn save(&mut self, id: &u32, value: &Doc) -> Result<(), ()> {
+            self.0.insert(*id, value.clone());
+            Ok(())
+        }
+
+        fn load(&self, id: &u32) -> Result<Option<Doc>, ()> {
+            Ok(self.0.get(id).cloned())
+        }
+
+        fn delete(&mut self, id: &u32) -> Result<(), ()> {
+            self.0.remove(id);
+            Ok(())
+        }
+
+        fn search(&self, filter: &dyn Filter<Doc>) -> Result<Vec<Doc>, ()> {
+            Ok(self
+                .0
+                .values()
+                .filter(|d| filter.pass(d))
+                .cloned()
+                .collect())
+        }
+    }
+
+    fn doc(text: &'static str) -> Doc {
+        Doc {
+            text,
+            updated_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_save_returning_previous() {
+        let mut store = MemoryStore::default();
+
+        assert_eq!(store.save_returning_previous(&1, &doc("a")), Ok(None));
+        assert_eq!(
+            store.save_returning_previous(&1, &doc("b")),
+            Ok(Some(doc("a")))
+        );
+    }
+
+    #[test]
+    fn test_save_if_version_detects_lost_update() {
+        let mut store = MemoryStore::default();
+
+        let first = match store.save_if_version(&1, &doc("a"), None).unwrap() {
+            SaveOutcome::Saved(saved) => saved,
+            conflict => panic!("unexpected {:?}", conflict),
+        };
+        assert!(first.updated_at > 0);
+
+        // Two writers read `first`, the second one to save loses
+        let second = match store
+            .save_if_version(&1, &doc("b"), Some(first.updated_at))
+            .unwrap()
+        {
+            SaveOutcome::Saved(saved) => saved,
+            conflict => panic!("unexpected {:?}", conflict),
+        };
+        assert!(second.updated_at > first.updated_at);
+        assert_eq!(
+            store.save_if_version(&1, &doc("c"), Some(first.updated_at)),
+            Ok(SaveOutcome::Conflict(Some(second.clone())))
+        );
+        assert_eq!(
+            store.save_if_version(&2, &doc("d"), Some(first.updated_at)),
+            Ok(SaveOutcome::Conflict(None))
+        );
+        assert_eq!(store.load(&1), Ok(Some(second)));
+    }
+
+    #[test]
+    fn test_fn_filter() {
+        assert_eq!(matching(&filter_fn(|v: &i32| *v > 7)), vec![8, 9, 10]);
+    }
+
+    #[test]
+    fn test_and() {
+        let filter = Even.and(filter_fn(|v: &i32| *v > 5));
+
+        assert_eq!(matching(&filter), vec![6, 8, 10]);
+    }
+
+    #[test]
+    fn test_or() {
+        let filter = filter_fn(|v: &i32| *v == 1).or(filter_fn(|v: &i32| *v == 10));
+
+        assert_eq!(matching(&filter), vec![1, 10]);
+    }
+
+    #[test]
+    fn test_not() {
+        assert_eq!(matching(&Even.not()), vec![1, 3, 5, 7, 9]);
+    }
+
+    #[test]
+    fn test_nested() {
+        // even and not (greater than 4 and less than 9)
+        let filter = Even.and(
+            filter_fn(|v: &i32| *v > 4)
+                .and(filter_fn(|v: &i32| *v < 9))
+                .not(),
+        );
+
+        assert_eq!(matching(&filter), vec![2, 4, 10]);
+    }
+
+    #[test]
+    fn test_boxed_filters() {
+        let filters: Vec<Box<dyn Filter<i32>>> = vec![Box::new(Even), Box::new(Even.not())];
+        let filter = Or(&filters[0], &filters[1]);
+
+        assert_eq!(matching(&filter).len(), 10);
+    }
 }
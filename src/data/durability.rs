@@ -0,0 +1,162 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    // Nothing is synced automatically, the caller decides when to persist
+    #[default]
+    Manual,
+    // Writes are batched and committed together once the interval has passed
+    GroupCommit(Duration),
+    // Every write is synced and committed before returning
+    Immediate,
+}
+
+// How hard writes are pushed to disk. `Durability` decides when the indexed
+// store commits, the sync level what a save or commit does to the files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncLevel {
+    // Left to the OS, a crash can lose anything not yet written back
+    None,
+    // Written to the OS, survives the process dying but not the machine
+    FlushOnSave,
+    // Saves and rewritten files are synced before they are relied on
+    #[default]
+    FsyncOnSave,
+    // Also syncs file metadata and the directory after every rename
+    FsyncEverything,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SyncStats {
+    pub level: SyncLevel,
+    pub flushes: u64,
+    pub syncs: u64,
+}
+
+// Applies a `SyncLevel` to the files a store writes and counts what it did
+#[derive(Debug, Default)]
+pub struct Syncer {
+    level: SyncLevel,
+    flushes: AtomicU64,
+    syncs: AtomicU64,
+}
+
+impl Syncer {
+    pub fn new(level: SyncLevel) -> Self {
+        Self {
+            level,
+            ..Self::default()
+        }
+    }
+
+    pub fn level(&self) -> SyncLevel {
+        self.level
+    }
+
+    fn flush(&self, file: &mut File) -> io::Result<()> {
+        file.flush()?;
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn sync(&self, file: &File, with_metadata: bool) -> io::Result<()> {
+        if with_metadata {
+            file.sync_all()?;
+        } else {
+            file.sync_data()?;
+        }
+        self.syncs.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    // After appending a save, or committing saves, to `file`
+    pub fn saved(&self, file: &mut File) -> io::Result<()> {
+        match self.level {
+            SyncLevel::None => Ok(()),
+            SyncLevel::FlushOnSave => self.flush(file),
+            SyncLevel::FsyncOnSave => {
+                self.flush(file)?;
+                self.sync(file, false)
+            }
+            SyncLevel::FsyncEverything => {
+                self.flush(file)?;
+                self.sync(file, true)
+            }
+        }
+    }
+
+    // After writing a whole file that is about to replace another one
+    pub fn rewritten(&self, file: &mut File) -> io::Result<()> {
+        match self.level {
+            SyncLevel::None => Ok(()),
+            SyncLevel::FlushOnSave => self.flush(file),
+            SyncLevel::FsyncOnSave | SyncLevel::FsyncEverything => {
+                self.flush(file)?;
+                self.sync(file, true)
+            }
+        }
+    }
+
+    // After a file was renamed to `path`, makes the rename itself durable
+    pub fn renamed(&self, path: &str) -> io::Result<()> {
+        if self.level != SyncLevel::FsyncEverything || cfg!(not(unix)) {
+            return Ok(());
+        }
+        let dir = match Path::new(path).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        self.sync(&File::open(dir)?, true)
+    }
+
+    pub fn stats(&self) -> SyncStats {
+        SyncStats {
+            level: self.level,
+            flushes: self.flushes.load(Ordering::Relaxed),
+            syncs: self.syncs.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use uuid::Uuid;
+
+    fn stats_after_save_and_rename(level: SyncLevel) -> SyncStats {
+        let file_path = format!("test_durability_{}.bin", Uuid::new_v4());
+        let mut file = File::create(&file_path).unwrap();
+        let syncer = Syncer::new(level);
+
+        syncer.saved(&mut file).unwrap();
+        syncer.rewritten(&mut file).unwrap();
+        syncer.renamed(&file_path).unwrap();
+
+        fs::remove_file(file_path).unwrap();
+        syncer.stats()
+    }
+
+    #[test]
+    fn test_levels_sync_progressively_more() {
+        let none = stats_after_save_and_rename(SyncLevel::None);
+        assert_eq!((none.flushes, none.syncs), (0, 0));
+
+        let flush = stats_after_save_and_rename(SyncLevel::FlushOnSave);
+        assert_eq!((flush.flushes, flush.syncs), (2, 0));
+
+        let fsync = stats_after_save_and_rename(SyncLevel::FsyncOnSave);
+        assert_eq!((fsync.flushes, fsync.syncs), (2, 2));
+
+        let everything = stats_after_save_and_rename(SyncLevel::FsyncEverything);
+        assert_eq!(everything.level, SyncLevel::FsyncEverything);
+        let directory_syncs = if cfg!(unix) { 1 } else { 0 };
+        assert_eq!(everything.syncs, 2 + directory_syncs);
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use super::{model::Entry, secondary_index::domain_of};
+
+// Per-site email addresses, e.g. "jo+shop@example.com" for a shop and
+// "jo+bank@example.com" for a bank, so a leaked or spammed address shows
+// which site it came from. Most mail providers deliver plus-addresses to
+// the base address. The aliases in use are read from the usernames of
+// entries, so there is nothing to keep in sync with the store.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailAddress {
+    pub local: String,
+    // After the '+', "shop" for "jo+shop@example.com"
+    pub tag: Option<String>,
+    pub domain: String,
+}
+
+impl EmailAddress {
+    // `None` for anything that isn't a plain "local@domain" address
+    pub fn parse(address: &str) -> Option<Self> {
+        let (local, domain) = address.trim().rsplit_once('@')?;
+        if local.is_empty() || domain.is_empty() || local.contains(char::is_whitespace) {
+            return None;
+        }
+        let (local, tag) = match local.split_once('+') {
+            Some((local, tag)) => (local, Some(tag.to_string())),
+            None => (local, None),
+        };
+        if local.is_empty() {
+            return None;
+        }
+        Some(EmailAddress {
+            local: local.to_string(),
+            tag,
+            domain: domain.to_lowercase(),
+        })
+    }
+
+    // The address mail is delivered to, without the tag
+    pub fn base(&self) -> String {
+        format!("{}@{}", self.local, self.domain)
+    }
+
+    pub fn with_tag(&self, tag: &str) -> Self {
+        EmailAddress {
+            tag: Some(tag.to_string()),
+            ..self.clone()
+        }
+    }
+}
+
+impl std::fmt::Display for EmailAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.tag {
+            Some(tag) => write!(f, "{}+{}@{}", self.local, tag, self.domain),
+            None => write!(f, "{}@{}", self.local, self.domain),
+        }
+    }
+}
+
+// The aliases used per site, by url domain, see `domain_of`
+#[derive(Debug, Default)]
+pub struct AliasTracker {
+    by_site: BTreeMap<String, BTreeSet<String>>,
+    // Every alias in use, lower-cased, whatever the site
+    used: HashSet<String>,
+}
+
+impl AliasTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Records the usernames of `entries` that are email addresses with a tag
+    pub fn from_entries(entries: &[Entry]) -> Self {
+        let mut tracker = Self::new();
+        for entry in entries {
+            if let (Some(url), Some(username)) = (&entry.url, &entry.username) {
+                tracker.record(url, username);
+            }
+        }
+        tracker
+    }
+
+    // Returns whether `address` was recorded, i.e. is a tagged address
+    pub fn record(&mut self, url: &str, address: &str) -> bool {
+        let parsed = match EmailAddress::parse(address) {
+            Some(parsed) if parsed.tag.is_some() => parsed,
+            _ => return false,
+        };
+        let alias = parsed.to_string();
+        self.used.insert(alias.to_lowercase());
+        if let Some(site) = domain_of(url) {
+            self.by_site.entry(site).or_default().insert(alias);
+        }
+        true
+    }
+
+    // The aliases recorded for the site of `url`, in order
+    pub fn aliases_for(&self, url: &str) -> Vec<String> {
+        domain_of(url)
+            .and_then(|site| self.by_site.get(&site))
+            .map_or_else(Vec::new, |aliases| aliases.iter().cloned().collect())
+    }
+
+    pub fn is_used(&self, address: &str) -> bool {
+        self.used.contains(&address.trim().to_lowercase())
+    }
+
+    // An alias of `base` for the site of `url` that isn't in use yet: tagged
+    // with the site's name, then with a number after it, e.g. "jo+shop@…",
+    // "jo+shop2@…". A tag already on `base` is replaced. `None` when `base`
+    // isn't an email address or `url` has no domain.
+    pub fn suggest(&self, base: &str, url: &str) -> Option<String> {
+        let address = EmailAddress::parse(base)?;
+        let tag = site_tag(&domain_of(url)?)?;
+
+        (1..)
+            .map(|n| match n {
+                1 => address.with_tag(&tag).to_string(),
+                n => address.with_tag(&format!("{}{}", tag, n)).to_string(),
+            })
+            .find(|alias| !self.is_used(alias))
+    }
+}
+
+// The name a site goes by, the label before the top level domain, e.g.
+// "example" for "shop.example.com". Country domains such as ".co.uk" give
+// "co", which is still unique per site once numbered.
+fn site_tag(domain: &str) -> Option<String> {
+    let mut labels = domain.rsplit('.');
+    let top = labels.next()?;
+    let name = labels.next().unwrap_or(top);
+    let tag: String = name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect::<String>()
+        .to_lowercase();
+    (!tag.is_empty()).then_some(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, url: &str, username: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: id.to_string(),
+            username: Some(username.to_string()),
+            url: Some(url.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let address = EmailAddress::parse(" jo+Shop@Example.com").unwrap();
+        assert_eq!(address.local, "jo");
+        assert_eq!(address.tag.as_deref(), Some("Shop"));
+        assert_eq!(address.base(), "jo@example.com");
+        assert_eq!(address.to_string(), "jo+Shop@example.com");
+        for invalid in ["jo", "@example.com", "jo@", "+shop@example.com"] {
+            assert_eq!(EmailAddress::parse(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_suggest_next_unique_alias() {
+        let tracker = AliasTracker::from_entries(&[
+            entry("1", "https://shop.example.com/login", "jo+example@mail.com"),
+            entry("2", "https://www.example.com", "JO+Example2@mail.com"),
+            entry("3", "https://bank.test", "jo@mail.com"),
+        ]);
+
+        assert_eq!(
+            tracker.aliases_for("example.com"),
+            vec!["JO+Example2@mail.com"]
+        );
+        assert_eq!(
+            tracker.aliases_for("https://shop.example.com"),
+            vec!["jo+example@mail.com"]
+        );
+        assert!(tracker.aliases_for("https://bank.test").is_empty());
+
+        assert_eq!(
+            tracker.suggest("jo@mail.com", "https://example.com/signup"),
+            Some("jo+example3@mail.com".to_string())
+        );
+        // The tag of the base is replaced
+        assert_eq!(
+            tracker.suggest("jo+old@mail.com", "https://bank.test"),
+            Some("jo+bank@mail.com".to_string())
+        );
+        assert_eq!(tracker.suggest("jo", "https://bank.test"), None);
+        assert_eq!(tracker.suggest("jo@mail.com", ""), None);
+    }
+}
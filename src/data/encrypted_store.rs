@@ -0,0 +1,601 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+};
+
+use super::{
+    data_store::{filter_fn, DataStore, Filter},
+    durability::Syncer,
+    model::{Entry, EntryKind},
+    secondary_index::{domain_of, username_key},
+    store_error::StoreError,
+};
+use crate::secret::sealed::{self, derive_key};
+
+// Wraps a store to keep its entries encrypted, in one of three modes chosen
+// when the store is created:
+//
+// - `FullRecord` encrypts whole entries. Only the id is left in the clear,
+//   so listing or searching decrypts every entry.
+// - `FieldLevel` encrypts the password and note. Titles, usernames, urls,
+//   tags and the rest stay readable, so `list_metadata` can list entries
+//   without decrypting anything, at the cost of showing what accounts the
+//   store holds to anyone who can read its files.
+// - `SearchableFields` also encrypts usernames and urls, but next to each
+//   it keeps a token, the deterministic encryption of the url's domain or
+//   the lower-cased username, so `find_by_username` and `find_by_domain`
+//   match on ciphertext without decrypting the store. The price is that
+//   equal domains and usernames have equal tokens: without the key one
+//   can't read them, but can see which entries share an account or site
+//   and count them. It is never on unless chosen.
+//
+// The mode is kept in a header file next to the store, with a MAC that
+// checks the key and keeps the mode from being changed. Every record read
+// is checked against the mode, so modes never get mixed in one store.
+
+const MAGIC: &[u8; 6] = b"TUGENC";
+const HEADER_VERSION: u8 = 1;
+// Marks a value as encrypted, followed by the base64 of a sealed message
+const PREFIX: &str = "$tug1$";
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptionMode {
+    FullRecord,
+    FieldLevel,
+    SearchableFields,
+}
+
+impl EncryptionMode {
+    fn to_byte(self) -> u8 {
+        match self {
+            EncryptionMode::FullRecord => 1,
+            EncryptionMode::FieldLevel => 2,
+            EncryptionMode::SearchableFields => 3,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            1 => Some(EncryptionMode::FullRecord),
+            2 => Some(EncryptionMode::FieldLevel),
+            3 => Some(EncryptionMode::SearchableFields),
+            _ => None,
+        }
+    }
+}
+
+fn header_mac(key: &[u8; 32], fields: &[u8]) -> HmacSha256 {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(&derive_key(key, b"encrypted store header"))
+        .expect("HMAC accepts keys of any length");
+    mac.update(fields);
+    mac
+}
+
+pub struct EncryptedStore<S> {
+    store: S,
+    key: [u8; 32],
+    mode: EncryptionMode,
+}
+
+impl<S: DataStore<String, Entry, StoreError>> EncryptedStore<S> {
+    // Writes the header for a new encrypted store, failing if there is one
+    pub fn create(
+        store: S,
+        header_path: &str,
+        key: &[u8; 32],
+        mode: EncryptionMode,
+    ) -> Result<Self, StoreError> {
+        let fields = [&MAGIC[..], &[HEADER_VERSION, mode.to_byte()]].concat();
+        let tag = header_mac(key, &fields).finalize().into_bytes();
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(header_path)?;
+        file.write_all(&[&fields[..], &tag].concat())?;
+        Syncer::default().rewritten(&mut file)?;
+
+        Ok(EncryptedStore {
+            store,
+            key: *key,
+            mode,
+        })
+    }
+
+    // Opens a store in the mode its header records. A wrong key fails with
+    // `IntegrityMismatch`.
+    pub fn open(store: S, header_path: &str, key: &[u8; 32]) -> Result<Self, StoreError> {
+        let header = fs::read(header_path)?;
+        let fields_len = MAGIC.len() + 2;
+        if header.len() != fields_len + 32
+            || !header.starts_with(MAGIC)
+            || header[MAGIC.len()] != HEADER_VERSION
+        {
+            return Err(StoreError::EncryptionMismatch);
+        }
+        let (fields, tag) = header.split_at(fields_len);
+        header_mac(key, fields)
+            .verify_slice(tag)
+            .map_err(|_| StoreError::IntegrityMismatch)?;
+        let mode = EncryptionMode::from_byte(fields[fields_len - 1])
+            .ok_or(StoreError::EncryptionMismatch)?;
+
+        Ok(EncryptedStore {
+            store,
+            key: *key,
+            mode,
+        })
+    }
+
+    pub fn mode(&self) -> EncryptionMode {
+        self.mode
+    }
+
+    // Entries that pass `filter` with their password and note left out. In
+    // field level mode nothing is decrypted, in the others everything is,
+    // as there is no other way to read the rest.
+    pub fn list_metadata(&self, filter: &dyn Filter<Entry>) -> Result<Vec<Entry>, StoreError> {
+        let stored = self.store.search(&filter_fn(|_: &Entry| true))?;
+        let mut listed = Vec::new();
+        for entry in stored {
+            let mut entry = match self.mode {
+                EncryptionMode::FieldLevel => {
+                    self.check_field_level(&entry)?;
+                    entry
+                }
+                _ => self.decrypt(entry)?,
+            };
+            entry.password = None;
+            entry.note = None;
+            if filter.pass(&entry) {
+                listed.push(entry);
+            }
+        }
+        Ok(listed)
+    }
+
+    // Entries whose username is `username`, ignoring case
+    pub fn find_by_username(&self, username: &str) -> Result<Vec<Entry>, StoreError> {
+        let key = username_key(username);
+        self.find(
+            "username",
+            key.as_deref(),
+            |stored| &stored.username,
+            |entry| entry.username.as_deref().and_then(username_key) == key,
+        )
+    }
+
+    // Entries whose url is on the same domain as `url`, see `domain_of`
+    pub fn find_by_domain(&self, url: &str) -> Result<Vec<Entry>, StoreError> {
+        let domain = domain_of(url);
+        self.find(
+            "domain",
+            domain.as_deref(),
+            |stored| &stored.url,
+            |entry| entry.url.as_deref().and_then(domain_of) == domain,
+        )
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    // Looks `key` up by token in searchable mode, decrypting only the
+    // entries that match. Other modes compare the decrypted entries.
+    fn find(
+        &self,
+        label: &str,
+        key: Option<&str>,
+        field: impl Fn(&Entry) -> &Option<String>,
+        matches: impl Fn(&Entry) -> bool,
+    ) -> Result<Vec<Entry>, StoreError> {
+        let key = match key {
+            Some(key) => key,
+            None => return Ok(Vec::new()),
+        };
+        if self.mode != EncryptionMode::SearchableFields {
+            return self.search(&filter_fn(|entry: &Entry| matches(entry)));
+        }
+
+        let tagged = format!("{}{}$", PREFIX, self.token(label, key));
+        let stored = self.store.search(&filter_fn(|stored: &Entry| {
+            field(stored)
+                .as_ref()
+                .is_some_and(|value| value.starts_with(&tagged))
+        }))?;
+        stored
+            .into_iter()
+            .map(|stored| self.decrypt(stored))
+            .collect()
+    }
+
+    fn token(&self, label: &str, key: &str) -> String {
+        let header = format!("{}-token", label);
+        STANDARD.encode(sealed::seal_deterministic(
+            &self.key,
+            header.as_bytes(),
+            key.as_bytes(),
+        ))
+    }
+
+    // The value sealed as usual, after the token of `key` when there is one
+    fn seal_searchable(
+        &self,
+        label: &str,
+        id: &str,
+        value: &str,
+        token_label: &str,
+        key: Option<String>,
+    ) -> String {
+        let sealed = self.seal(label, id, value.as_bytes());
+        match key {
+            Some(key) => format!(
+                "{}{}${}",
+                PREFIX,
+                self.token(token_label, &key),
+                &sealed[PREFIX.len()..]
+            ),
+            None => sealed,
+        }
+    }
+
+    fn seal(&self, label: &str, id: &str, plaintext: &[u8]) -> String {
+        let header = format!("{}:{}", label, id);
+        let sealed = sealed::seal(&self.key, header.as_bytes(), plaintext);
+        format!("{}{}", PREFIX, STANDARD.encode(sealed))
+    }
+
+    // The value must have been sealed for the same field of the same entry,
+    // so encrypted values can't be moved between entries
+    fn open_value(&self, label: &str, id: &str, value: &str) -> Result<Vec<u8>, StoreError> {
+        let encoded = value
+            .strip_prefix(PREFIX)
+            .ok_or(StoreError::EncryptionMismatch)?;
+        // After the token of a searchable value, base64 has no '$'
+        let encoded = encoded.rsplit('$').next().unwrap_or(encoded);
+        let sealed = STANDARD
+            .decode(encoded)
+            .map_err(|_| StoreError::IntegrityMismatch)?;
+        let header = format!("{}:{}", label, id);
+        let opened = sealed::open(&self.key, header.len(), &sealed)
+            .map_err(|_| StoreError::IntegrityMismatch)?;
+        if opened.header != header.as_bytes() {
+            return Err(StoreError::IntegrityMismatch);
+        }
+        Ok(opened.plaintext)
+    }
+
+    fn encrypt(&self, entry: &Entry) -> Result<Entry, StoreError> {
+        let id = entry.id.as_str();
+        let seal_text = |label: &str, value: &Option<String>| {
+            value
+                .as_ref()
+                .map(|value| self.seal(label, id, value.as_bytes()))
+        };
+
+        Ok(match self.mode {
+            EncryptionMode::FullRecord => Entry {
+                id: entry.id.clone(),
+                title: self.seal("record", id, &bincode::serialize(entry)?),
+                kind: EntryKind::Login,
+                token: None,
+                username: None,
+                password: None,
+                url: None,
+                note: None,
+                favorite: false,
+                label: None,
+                tags: Vec::new(),
+                archived: false,
+                sort_key: None,
+                updated_at: 0,
+                password_changed_at: 0,
+            },
+            EncryptionMode::FieldLevel => Entry {
+                password: seal_text("password", &entry.password),
+                note: seal_text("note", &entry.note),
+                ..entry.clone()
+            },
+            EncryptionMode::SearchableFields => Entry {
+                username: entry.username.as_ref().map(|username| {
+                    let key = username_key(username);
+                    self.seal_searchable("username", id, username, "username", key)
+                }),
+                url: entry
+                    .url
+                    .as_ref()
+                    .map(|url| self.seal_searchable("url", id, url, "domain", domain_of(url))),
+                password: seal_text("password", &entry.password),
+                note: seal_text("note", &entry.note),
+                ..entry.clone()
+            },
+        })
+    }
+
+    fn decrypt(&self, stored: Entry) -> Result<Entry, StoreError> {
+        match self.mode {
+            EncryptionMode::FullRecord => {
+                let record = self.open_value("record", &stored.id, &stored.title)?;
+                let entry: Entry = bincode::deserialize(&record)?;
+                if entry.id != stored.id {
+                    return Err(StoreError::IntegrityMismatch);
+                }
+                Ok(entry)
+            }
+            EncryptionMode::FieldLevel | EncryptionMode::SearchableFields => {
+                self.check_field_level(&stored)?;
+                let open_text = |label: &str, value: &Option<String>| {
+                    value
+                        .as_ref()
+                        .map(|value| {
+                            let plaintext = self.open_value(label, &stored.id, value)?;
+                            String::from_utf8(plaintext).map_err(|_| StoreError::IntegrityMismatch)
+                        })
+                        .transpose()
+                };
+                let (username, url) = match self.mode {
+                    EncryptionMode::SearchableFields => (
+                        open_text("username", &stored.username)?,
+                        open_text("url", &stored.url)?,
+                    ),
+                    _ => (stored.username.clone(), stored.url.clone()),
+                };
+                Ok(Entry {
+                    username,
+                    url,
+                    password: open_text("password", &stored.password)?,
+                    note: open_text("note", &stored.note)?,
+                    ..stored.clone()
+                })
+            }
+        }
+    }
+
+    // A whole encrypted record isn't a field level one, nor is a record
+    // with encrypted usernames and urls one of the plain field level mode
+    fn check_field_level(&self, stored: &Entry) -> Result<(), StoreError> {
+        let encrypted = |value: &Option<String>| {
+            value
+                .as_ref()
+                .is_some_and(|value| value.starts_with(PREFIX))
+        };
+        if stored.title.starts_with(PREFIX)
+            || (self.mode == EncryptionMode::FieldLevel
+                && (encrypted(&stored.username) || encrypted(&stored.url)))
+        {
+            return Err(StoreError::EncryptionMismatch);
+        }
+        Ok(())
+    }
+}
+
+impl<S: DataStore<String, Entry, StoreError>> DataStore<String, Entry, StoreError>
+    for EncryptedStore<S>
+{
+    fn save(&mut self, id: &String, entry: &Entry) -> Result<(), StoreError> {
+        let encrypted = self.encrypt(entry)?;
+        self.store.save(id, &encrypted)
+    }
+
+    fn load(&self, id: &String) -> Result<Option<Entry>, StoreError> {
+        self.store
+            .load(id)?
+            .map(|stored| self.decrypt(stored))
+            .transpose()
+    }
+
+    fn delete(&mut self, id: &String) -> Result<(), StoreError> {
+        self.store.delete(id)
+    }
+
+    fn search(&self, filter: &dyn Filter<Entry>) -> Result<Vec<Entry>, StoreError> {
+        let mut found = Vec::new();
+        for stored in self.store.search(&filter_fn(|_: &Entry| true))? {
+            let entry = self.decrypt(stored)?;
+            if filter.pass(&entry) {
+                found.push(entry);
+            }
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::binary_file_entry_store::BinaryFileEntryStore;
+    use uuid::Uuid;
+
+    const KEY: [u8; 32] = [9; 32];
+
+    fn entry(id: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: "Bank".to_string(),
+            username: Some("alice".to_string()),
+            password: Some("hunter2".to_string()),
+            note: Some("pin 1234".to_string()),
+            favorite: true,
+            tags: vec!["money".to_string()],
+            updated_at: 7,
+            ..Default::default()
+        }
+    }
+
+    fn paths() -> (String, String) {
+        let id = Uuid::new_v4();
+        (
+            format!("test_encrypted_store_{}.bin", id),
+            format!("test_encrypted_header_{}.bin", id),
+        )
+    }
+
+    fn contains(file_path: &str, text: &str) -> bool {
+        fs::read(file_path)
+            .unwrap()
+            .windows(text.len())
+            .any(|window| window == text.as_bytes())
+    }
+
+    #[test]
+    fn test_field_level() {
+        let (store_path, header_path) = paths();
+        let inner = BinaryFileEntryStore::new(store_path.clone());
+        let mut store =
+            EncryptedStore::create(inner, &header_path, &KEY, EncryptionMode::FieldLevel).unwrap();
+        store.save(&"1".to_string(), &entry("1")).unwrap();
+
+        assert!(contains(&store_path, "Bank"));
+        assert!(!contains(&store_path, "hunter2"));
+        assert!(!contains(&store_path, "pin 1234"));
+        assert_eq!(store.load(&"1".to_string()).unwrap(), Some(entry("1")));
+        let listed = store
+            .list_metadata(&filter_fn(|e: &Entry| e.favorite))
+            .unwrap();
+        assert_eq!(listed[0].title, "Bank");
+        assert_eq!(listed[0].password, None);
+
+        // A password moved to another entry doesn't decrypt there
+        let mut inner = store.into_inner();
+        let mut moved = inner.load(&"1".to_string()).unwrap().unwrap();
+        moved.id = "2".to_string();
+        inner.save(&"2".to_string(), &moved).unwrap();
+        let store = EncryptedStore::open(inner, &header_path, &KEY).unwrap();
+        assert_eq!(store.mode(), EncryptionMode::FieldLevel);
+        assert!(matches!(
+            store.load(&"2".to_string()),
+            Err(StoreError::IntegrityMismatch)
+        ));
+
+        drop(store);
+        fs::remove_file(store_path).unwrap();
+        fs::remove_file(header_path).unwrap();
+    }
+
+    #[test]
+    fn test_full_record() {
+        let (store_path, header_path) = paths();
+        let inner = BinaryFileEntryStore::new(store_path.clone());
+        let mut store =
+            EncryptedStore::create(inner, &header_path, &KEY, EncryptionMode::FullRecord).unwrap();
+        store.save(&"1".to_string(), &entry("1")).unwrap();
+
+        assert!(!contains(&store_path, "Bank"));
+        assert!(!contains(&store_path, "alice"));
+        assert_eq!(
+            store
+                .search(&filter_fn(|e: &Entry| e.title == "Bank"))
+                .unwrap(),
+            vec![entry("1")]
+        );
+
+        // An entry saved around the wrapper is in neither mode's form
+        let mut inner = store.into_inner();
+        inner.save(&"2".to_string(), &entry("2")).unwrap();
+        let store = EncryptedStore::open(inner, &header_path, &KEY).unwrap();
+        assert!(matches!(
+            store.load(&"2".to_string()),
+            Err(StoreError::EncryptionMismatch)
+        ));
+
+        drop(store);
+        fs::remove_file(store_path).unwrap();
+        fs::remove_file(header_path).unwrap();
+    }
+
+    #[test]
+    fn test_searchable_fields() {
+        let (store_path, header_path) = paths();
+        let inner = BinaryFileEntryStore::new(store_path.clone());
+        let mut store =
+            EncryptedStore::create(inner, &header_path, &KEY, EncryptionMode::SearchableFields)
+                .unwrap();
+        let bank = Entry {
+            url: Some("https://www.bank.example/login".to_string()),
+            ..entry("1")
+        };
+        let mail = Entry {
+            username: Some("Alice ".to_string()),
+            url: Some("https://mail.example".to_string()),
+            ..entry("2")
+        };
+        store.save(&bank.id, &bank).unwrap();
+        store.save(&mail.id, &mail).unwrap();
+
+        assert!(!contains(&store_path, "alice"));
+        assert!(!contains(&store_path, "bank.example"));
+        assert_eq!(store.load(&"2".to_string()).unwrap(), Some(mail.clone()));
+        assert_eq!(
+            store.find_by_username("ALICE").unwrap(),
+            vec![bank.clone(), mail.clone()]
+        );
+        assert_eq!(
+            store.find_by_domain("http://bank.example").unwrap(),
+            vec![bank.clone()]
+        );
+        assert_eq!(store.find_by_domain("other.example").unwrap(), vec![]);
+
+        // The tokens are all the inner store sees, and equal for equal keys
+        let inner = store.into_inner();
+        let token = |entry: &Entry| {
+            let username = entry.username.clone().unwrap();
+            username[..username.rfind('$').unwrap()].to_string()
+        };
+        let stored = inner.search(&filter_fn(|_: &Entry| true)).unwrap();
+        assert_eq!(token(&stored[0]), token(&stored[1]));
+        assert_ne!(stored[0].username, stored[1].username);
+
+        // Field level mode can't read these records
+        fs::remove_file(&header_path).unwrap();
+        let store =
+            EncryptedStore::create(inner, &header_path, &KEY, EncryptionMode::FieldLevel).unwrap();
+        assert!(matches!(
+            store.load(&"1".to_string()),
+            Err(StoreError::EncryptionMismatch)
+        ));
+        assert!(matches!(
+            store.find_by_username("nobody"),
+            Err(StoreError::EncryptionMismatch)
+        ));
+
+        drop(store);
+        fs::remove_file(store_path).unwrap();
+        fs::remove_file(header_path).unwrap();
+    }
+
+    #[test]
+    fn test_header() {
+        let (store_path, header_path) = paths();
+        let new_inner = || BinaryFileEntryStore::new(store_path.clone());
+        EncryptedStore::create(new_inner(), &header_path, &KEY, EncryptionMode::FullRecord)
+            .unwrap();
+
+        assert!(EncryptedStore::create(
+            new_inner(),
+            &header_path,
+            &KEY,
+            EncryptionMode::FieldLevel
+        )
+        .is_err());
+        assert!(matches!(
+            EncryptedStore::open(new_inner(), &header_path, &[1; 32]),
+            Err(StoreError::IntegrityMismatch)
+        ));
+        // The mode can't be switched without the key
+        let mut header = fs::read(&header_path).unwrap();
+        header[MAGIC.len() + 1] = EncryptionMode::FieldLevel.to_byte();
+        fs::write(&header_path, header).unwrap();
+        assert!(matches!(
+            EncryptedStore::open(new_inner(), &header_path, &KEY),
+            Err(StoreError::IntegrityMismatch)
+        ));
+
+        let _ = fs::remove_file(store_path);
+        fs::remove_file(header_path).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+use std::fmt;
+
+use super::model::{Entry, TokenInfo};
+
+const REDACTED: &str = "***";
+
+// The fields a user edits. The id and `updated_at` identify a version rather
+// than describe it, so they aren't compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntryField {
+    Title,
+    Kind,
+    Token,
+    Username,
+    Password,
+    Url,
+    Note,
+    Favorite,
+    Label,
+    Tags,
+    Archived,
+    SortKey,
+}
+
+impl EntryField {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EntryField::Title => "title",
+            EntryField::Kind => "kind",
+            EntryField::Token => "token",
+            EntryField::Username => "username",
+            EntryField::Password => "password",
+            EntryField::Url => "url",
+            EntryField::Note => "note",
+            EntryField::Favorite => "favorite",
+            EntryField::Label => "label",
+            EntryField::Tags => "tags",
+            EntryField::Archived => "archived",
+            EntryField::SortKey => "sort key",
+        }
+    }
+
+    pub fn is_secret(&self) -> bool {
+        *self == EntryField::Password
+    }
+}
+
+// A field that differs between two versions of an entry, with both values
+// rendered as text. None is a value that isn't set.
+//
+// `Debug` and `Display` hide secret values, only saying that they changed.
+#[derive(Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: EntryField,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl FieldChange {
+    fn shown(&self, value: &Option<String>) -> Option<String> {
+        match value {
+            Some(_) if self.field.is_secret() => Some(REDACTED.to_string()),
+            value => value.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FieldChange")
+            .field("field", &self.field)
+            .field("before", &self.shown(&self.before))
+            .field("after", &self.shown(&self.after))
+            .finish()
+    }
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |value: &Option<String>| match self.shown(value) {
+            Some(value) => format!("{:?}", value),
+            None => "(none)".to_string(),
+        };
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.field.name(),
+            show(&self.before),
+            show(&self.after)
+        )
+    }
+}
+
+fn change(field: EntryField, before: Option<String>, after: Option<String>) -> Option<FieldChange> {
+    (before != after).then_some(FieldChange {
+        field,
+        before,
+        after,
+    })
+}
+
+impl Entry {
+    // What changed from `self` to `other`, in the order the fields are
+    // declared. Empty when they differ only in id or `updated_at`.
+    pub fn diff(&self, other: &Entry) -> Vec<FieldChange> {
+        let tags = |entry: &Entry| Some(entry.tags.join(", ")).filter(|tags| !tags.is_empty());
+        let label = |entry: &Entry| entry.label.map(|label| format!("{:?}", label));
+
+        [
+            change(
+                EntryField::Title,
+                Some(self.title.clone()),
+                Some(other.title.clone()),
+            ),
+            change(
+                EntryField::Kind,
+                Some(self.kind.name().to_string()),
+                Some(other.kind.name().to_string()),
+            ),
+            change(
+                EntryField::Token,
+                self.token.as_ref().map(TokenInfo::summary),
+                other.token.as_ref().map(TokenInfo::summary),
+            ),
+            change(
+                EntryField::Username,
+                self.username.clone(),
+                other.username.clone(),
+            ),
+            change(
+                EntryField::Password,
+                self.password.clone(),
+                other.password.clone(),
+            ),
+            change(EntryField::Url, self.url.clone(), other.url.clone()),
+            change(EntryField::Note, self.note.clone(), other.note.clone()),
+            change(
+                EntryField::Favorite,
+                Some(self.favorite.to_string()),
+                Some(other.favorite.to_string()),
+            ),
+            change(EntryField::Label, label(self), label(other)),
+            change(EntryField::Tags, tags(self), tags(other)),
+            change(
+                EntryField::Archived,
+                Some(self.archived.to_string()),
+                Some(other.archived.to_string()),
+            ),
+            change(
+                EntryField::SortKey,
+                self.sort_key.clone(),
+                other.sort_key.clone(),
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::model::Label;
+
+    fn entry() -> Entry {
+        Entry {
+            id: "1".to_string(),
+            title: "Example".to_string(),
+            username: Some("alice".to_string()),
+            password: Some("hunter2".to_string()),
+            tags: vec!["work".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_diff_lists_changed_fields_in_order() {
+        let before = entry();
+        let mut after = entry();
+        after.updated_at = 5;
+        assert!(before.diff(&after).is_empty());
+
+        after.url = Some("https://example.com".to_string());
+        after.title = "Renamed".to_string();
+        after.label = Some(Label::Red);
+        after.tags.clear();
+
+        let fields: Vec<_> = before
+            .diff(&after)
+            .into_iter()
+            .map(|change| change.field)
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                EntryField::Title,
+                EntryField::Url,
+                EntryField::Label,
+                EntryField::Tags
+            ]
+        );
+        assert_eq!(
+            before.diff(&after)[3].to_string(),
+            "tags: \"work\" -> (none)"
+        );
+    }
+
+    #[test]
+    fn test_password_change_is_redacted() {
+        let before = entry();
+        let mut after = entry();
+        after.password = Some("correct horse".to_string());
+
+        let changes = before.diff(&after);
+        assert_eq!(changes[0].after.as_deref(), Some("correct horse"));
+
+        let shown = format!("{} {:?}", changes[0], changes[0]);
+        assert!(!shown.contains("hunter2"));
+        assert!(!shown.contains("correct horse"));
+        assert_eq!(changes[0].to_string(), "password: \"***\" -> \"***\"");
+    }
+}
@@ -0,0 +1,90 @@
+use std::cmp::Ordering;
+
+use super::{
+    archive::search_active,
+    data_store::{DataStore, Filter},
+    model::Entry,
+};
+
+pub struct FavoriteFilter;
+
+impl Filter<Entry> for FavoriteFilter {
+    fn pass(&self, entry: &Entry) -> bool {
+        entry.favorite
+    }
+}
+
+pub fn list_favorites<S, E>(store: &S) -> Result<Vec<Entry>, E>
+where
+    S: DataStore<String, Entry, E> + ?Sized,
+{
+    let mut favorites = search_active(store, &FavoriteFilter)?;
+    sort_pinned(&mut favorites);
+    Ok(favorites)
+}
+
+// Favorites first, then by title ignoring case, then by id so the order is stable
+pub fn compare_pinned(a: &Entry, b: &Entry) -> Ordering {
+    b.favorite
+        .cmp(&a.favorite)
+        .then_with(|| a.title.to_lowercase().cmp(&b.title.to_lowercase()))
+        .then_with(|| a.id.cmp(&b.id))
+}
+
+pub fn sort_pinned(entries: &mut [Entry]) {
+    entries.sort_by(compare_pinned);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{binary_file_entry_store::BinaryFileEntryStore, model::Label};
+    use std::fs;
+    use uuid::Uuid;
+
+    fn entry(id: &str, title: &str, favorite: bool) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: title.to_string(),
+            favorite,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sort_pinned() {
+        let mut entries = vec![
+            entry("1", "banana", false),
+            entry("2", "Cherry", true),
+            entry("3", "apple", false),
+            entry("4", "avocado", true),
+        ];
+
+        sort_pinned(&mut entries);
+
+        let ids: Vec<&str> = entries.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["4", "2", "3", "1"]);
+    }
+
+    #[test]
+    fn test_list_favorites() {
+        let test_file_path = format!("test_favorites_{}.bin", Uuid::new_v4());
+        let mut store = BinaryFileEntryStore::new(test_file_path.clone());
+
+        let mut labelled = entry("1", "Bank", true);
+        labelled.label = Some(Label::Red);
+        store.save(&labelled.id, &labelled).unwrap();
+        store
+            .save(&"2".to_string(), &entry("2", "Forum", false))
+            .unwrap();
+        store
+            .save(&"3".to_string(), &entry("3", "Email", true))
+            .unwrap();
+
+        let favorites = list_favorites(&store).unwrap();
+
+        assert_eq!(favorites, vec![labelled, entry("3", "Email", true)]);
+
+        fs::remove_file(test_file_path).unwrap();
+    }
+}
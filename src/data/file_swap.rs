@@ -0,0 +1,474 @@
+use log::{info, warn};
+
+use super::{
+    fs::{Fs, OsFs},
+    retry::RetryPolicy,
+    store_error::StoreError,
+};
+
+// A temp file is only swapped in once its marker exists, so the marker tells
+// recovery whether the temp file was fully written before the process died.
+fn marker_path(temp_file_path: &str) -> String {
+    format!("{}.complete", temp_file_path)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapRecovery {
+    // Nothing was left over from a previous swap
+    Clean,
+    // A complete temp file was moved into place
+    RolledForward,
+    // An incomplete temp file was discarded, the target was kept
+    RolledBack,
+}
+
+// Replaces `target_file_path` with the fully written `temp_file_path`.
+pub fn swap_in(temp_file_path: &str, target_file_path: &str) -> Result<(), StoreError> {
+    swap_in_with_retry(temp_file_path, target_file_path, &RetryPolicy::default())
+}
+
+pub fn swap_in_with_retry(
+    temp_file_path: &str,
+    target_file_path: &str,
+    retry: &RetryPolicy,
+) -> Result<(), StoreError> {
+    swap_in_with(&OsFs, temp_file_path, target_file_path, retry)
+}
+
+pub fn swap_in_with(
+    fs: &dyn Fs,
+    temp_file_path: &str,
+    target_file_path: &str,
+    retry: &RetryPolicy,
+) -> Result<(), StoreError> {
+    swap_in_all_with(fs, &[(temp_file_path, target_file_path)], retry)
+}
+
+// Replaces every target of `pairs`, (temp file, target) each, as one. The
+// marker of the first temp file stands for all of them, so recovery moves
+// either every temp file into place or none.
+pub fn swap_in_all(pairs: &[(&str, &str)]) -> Result<(), StoreError> {
+    swap_in_all_with(&OsFs, pairs, &RetryPolicy::default())
+}
+
+pub fn swap_in_all_with(
+    fs: &dyn Fs,
+    pairs: &[(&str, &str)],
+    retry: &RetryPolicy,
+) -> Result<(), StoreError> {
+    let marker = match pairs.first() {
+        Some((temp_file_path, _)) => marker_path(temp_file_path),
+        None => return Ok(()),
+    };
+    retry.run("create swap marker", || fs.write_file(&marker, b""))?;
+
+    for (temp_file_path, target_file_path) in pairs {
+        if fs.exists(target_file_path) {
+            retry.run("remove swap target", || fs.remove_file(target_file_path))?;
+        }
+        retry.run("rename temp file", || {
+            fs.rename(temp_file_path, target_file_path)
+        })?;
+    }
+    retry.run("remove swap marker", || fs.remove_file(&marker))?;
+
+    Ok(())
+}
+
+// Finishes or undoes a swap that was interrupted before `swap_in` returned.
+pub fn recover_swap(
+    temp_file_path: &str,
+    target_file_path: &str,
+) -> Result<SwapRecovery, StoreError> {
+    recover_swap_with_retry(temp_file_path, target_file_path, &RetryPolicy::default())
+}
+
+pub fn recover_swap_with_retry(
+    temp_file_path: &str,
+    target_file_path: &str,
+    retry: &RetryPolicy,
+) -> Result<SwapRecovery, StoreError> {
+    recover_swap_with(&OsFs, temp_file_path, target_file_path, retry)
+}
+
+pub fn recover_swap_with(
+    fs: &dyn Fs,
+    temp_file_path: &str,
+    target_file_path: &str,
+    retry: &RetryPolicy,
+) -> Result<SwapRecovery, StoreError> {
+    let marker = marker_path(temp_file_path);
+    let temp_exists = fs.exists(temp_file_path);
+    let marker_exists = fs.exists(&marker);
+    let target_exists = fs.exists(target_file_path);
+
+    let recovery = match (temp_exists, marker_exists, target_exists) {
+        (false, _, _) => SwapRecovery::Clean,
+        // The target is only removed after the marker is written, so a
+        // missing target means the temp file is complete either way.
+        (true, true, _) | (true, false, false) => {
+            if target_exists {
+                retry.run("remove swap target", || fs.remove_file(target_file_path))?;
+            }
+            retry.run("rename temp file", || {
+                fs.rename(temp_file_path, target_file_path)
+            })?;
+            info!(
+                "Rolled forward interrupted swap {} -> {}",
+                temp_file_path, target_file_path
+            );
+            SwapRecovery::RolledForward
+        }
+        (true, false, true) => {
+            retry.run("remove temp file", || fs.remove_file(temp_file_path))?;
+            warn!(
+                "Discarded incomplete temp file {} for {}",
+                temp_file_path, target_file_path
+            );
+            SwapRecovery::RolledBack
+        }
+    };
+
+    if marker_exists {
+        retry.run("remove swap marker", || fs.remove_file(&marker))?;
+    }
+
+    Ok(recovery)
+}
+
+// Finishes or undoes a `swap_in_all` of the same `pairs` that was
+// interrupted. Without the marker of the group the first file is recovered
+// as a swap of its own and the rest as a group, as any tail of `pairs` may
+// have been swapped in without the files before it.
+pub fn recover_swap_all(pairs: &[(&str, &str)]) -> Result<SwapRecovery, StoreError> {
+    recover_swap_all_with(&OsFs, pairs, &RetryPolicy::default())
+}
+
+pub fn recover_swap_all_with(
+    fs: &dyn Fs,
+    pairs: &[(&str, &str)],
+    retry: &RetryPolicy,
+) -> Result<SwapRecovery, StoreError> {
+    let marker = match pairs.first() {
+        Some((temp_file_path, _)) => marker_path(temp_file_path),
+        None => return Ok(SwapRecovery::Clean),
+    };
+
+    if !fs.exists(&marker) {
+        let (temp_file_path, target_file_path) = pairs[0];
+        let first = recover_swap_with(fs, temp_file_path, target_file_path, retry)?;
+        return match recover_swap_all_with(fs, &pairs[1..], retry)? {
+            SwapRecovery::Clean => Ok(first),
+            recovered => Ok(recovered),
+        };
+    }
+
+    // Targets are only removed once the marker exists, so every temp file
+    // left is complete
+    for (temp_file_path, target_file_path) in pairs {
+        if !fs.exists(temp_file_path) {
+            continue;
+        }
+        if fs.exists(target_file_path) {
+            retry.run("remove swap target", || fs.remove_file(target_file_path))?;
+        }
+        retry.run("rename temp file", || {
+            fs.rename(temp_file_path, target_file_path)
+        })?;
+        info!(
+            "Rolled forward interrupted swap {} -> {}",
+            temp_file_path, target_file_path
+        );
+    }
+    retry.run("remove swap marker", || fs.remove_file(&marker))?;
+
+    Ok(SwapRecovery::RolledForward)
+}
+
+// Like `recover_swap_all`, for temp files that are only ever swapped in as
+// a group, e.g. new files that have no target yet: without the marker of
+// the group none of them was, so every temp file left is discarded.
+pub fn recover_group_swap(pairs: &[(&str, &str)]) -> Result<SwapRecovery, StoreError> {
+    recover_group_swap_with(&OsFs, pairs, &RetryPolicy::default())
+}
+
+pub fn recover_group_swap_with(
+    fs: &dyn Fs,
+    pairs: &[(&str, &str)],
+    retry: &RetryPolicy,
+) -> Result<SwapRecovery, StoreError> {
+    let marker = match pairs.first() {
+        Some((temp_file_path, _)) => marker_path(temp_file_path),
+        None => return Ok(SwapRecovery::Clean),
+    };
+    if fs.exists(&marker) {
+        return recover_swap_all_with(fs, pairs, retry);
+    }
+
+    let mut recovery = SwapRecovery::Clean;
+    for (temp_file_path, target_file_path) in pairs {
+        if fs.exists(temp_file_path) {
+            retry.run("remove temp file", || fs.remove_file(temp_file_path))?;
+            warn!(
+                "Discarded incomplete temp file {} for {}",
+                temp_file_path, target_file_path
+            );
+            recovery = SwapRecovery::RolledBack;
+        }
+    }
+    Ok(recovery)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::fs::FaultyFs;
+    use std::{fs, fs::File, path::Path};
+    use uuid::Uuid;
+
+    fn paths() -> (String, String) {
+        let target = format!("test_swap_{}.bin", Uuid::new_v4());
+        let temp = format!("{}-tmp", target);
+        (target, temp)
+    }
+
+    fn cleanup(paths: &[&str]) {
+        for path in paths {
+            if Path::new(path).exists() {
+                fs::remove_file(path).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_swap_in_replaces_target() {
+        let (target, temp) = paths();
+        fs::write(&target, b"old").unwrap();
+        fs::write(&temp, b"new").unwrap();
+
+        swap_in(&temp, &target).unwrap();
+
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+        assert!(!Path::new(&temp).exists());
+        assert!(!Path::new(&marker_path(&temp)).exists());
+
+        cleanup(&[&target]);
+    }
+
+    #[test]
+    fn test_recover_clean() {
+        let (target, temp) = paths();
+        fs::write(&target, b"old").unwrap();
+
+        assert_eq!(recover_swap(&temp, &target).unwrap(), SwapRecovery::Clean);
+        assert_eq!(fs::read(&target).unwrap(), b"old");
+
+        cleanup(&[&target]);
+    }
+
+    #[test]
+    fn test_recover_rolls_back_incomplete_temp() {
+        let (target, temp) = paths();
+        fs::write(&target, b"old").unwrap();
+        fs::write(&temp, b"half").unwrap();
+
+        assert_eq!(
+            recover_swap(&temp, &target).unwrap(),
+            SwapRecovery::RolledBack
+        );
+        assert_eq!(fs::read(&target).unwrap(), b"old");
+        assert!(!Path::new(&temp).exists());
+
+        cleanup(&[&target]);
+    }
+
+    #[test]
+    fn test_recover_rolls_forward_marked_temp() {
+        let (target, temp) = paths();
+        fs::write(&target, b"old").unwrap();
+        fs::write(&temp, b"new").unwrap();
+        File::create(marker_path(&temp)).unwrap();
+
+        assert_eq!(
+            recover_swap(&temp, &target).unwrap(),
+            SwapRecovery::RolledForward
+        );
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+        assert!(!Path::new(&marker_path(&temp)).exists());
+
+        cleanup(&[&target]);
+    }
+
+    #[test]
+    fn test_recover_rolls_forward_when_target_removed() {
+        let (target, temp) = paths();
+        fs::write(&temp, b"new").unwrap();
+
+        assert_eq!(
+            recover_swap(&temp, &target).unwrap(),
+            SwapRecovery::RolledForward
+        );
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+
+        cleanup(&[&target]);
+    }
+
+    const OLD: &[u8] = b"old contents";
+    const NEW: &[u8] = b"new contents";
+
+    // Writes the temp file and swaps it in, the way the stores replace files
+    fn replace(fs: &dyn Fs, temp: &str, target: &str) -> Result<(), StoreError> {
+        fs.write_file(temp, NEW)?;
+        swap_in_with(fs, temp, target, &RetryPolicy::none())
+    }
+
+    // How many writes a crash can interrupt during `replace`
+    fn replace_writes() -> usize {
+        let (target, temp) = paths();
+        fs::write(&target, OLD).unwrap();
+        let fs = FaultyFs::counting();
+        replace(&fs, &temp, &target).unwrap();
+        cleanup(&[&target]);
+        fs.writes()
+    }
+
+    fn assert_consistent(temp: &str, target: &str) {
+        let contents = fs::read(target).unwrap();
+        assert!(contents == OLD || contents == NEW, "{:?}", contents);
+        assert!(!Path::new(temp).exists());
+        assert!(!Path::new(&marker_path(temp)).exists());
+    }
+
+    #[test]
+    fn test_crash_at_any_write_recovers_consistently() {
+        for crash_point in 0..replace_writes() {
+            let (target, temp) = paths();
+            fs::write(&target, OLD).unwrap();
+
+            let faulty = FaultyFs::crash_after(crash_point);
+            assert!(replace(&faulty, &temp, &target).is_err());
+            recover_swap(&temp, &target).unwrap();
+
+            assert_consistent(&temp, &target);
+            cleanup(&[&target]);
+        }
+    }
+
+    // Two targets replaced as one, the way a data file and its index are
+    fn replace_all(fs: &dyn Fs, pairs: &[(&str, &str)]) -> Result<(), StoreError> {
+        for (temp, _) in pairs {
+            fs.write_file(temp, NEW)?;
+        }
+        swap_in_all_with(fs, pairs, &RetryPolicy::none())
+    }
+
+    #[test]
+    fn test_crash_during_swap_in_all_recovers_every_file_or_none() {
+        let writes = {
+            let ((data, data_temp), (index, index_temp)) = (paths(), paths());
+            fs::write(&data, OLD).unwrap();
+            fs::write(&index, OLD).unwrap();
+            let fs = FaultyFs::counting();
+            replace_all(&fs, &[(&data_temp, &data), (&index_temp, &index)]).unwrap();
+            cleanup(&[&data, &index]);
+            fs.writes()
+        };
+
+        for crash_point in 0..writes {
+            let ((data, data_temp), (index, index_temp)) = (paths(), paths());
+            fs::write(&data, OLD).unwrap();
+            fs::write(&index, OLD).unwrap();
+            let pairs = [(data_temp.as_str(), data.as_str()), (&index_temp, &index)];
+
+            let faulty = FaultyFs::crash_after(crash_point);
+            assert!(replace_all(&faulty, &pairs).is_err());
+            recover_swap_all(&pairs).unwrap();
+
+            assert_consistent(&data_temp, &data);
+            assert_consistent(&index_temp, &index);
+            assert_eq!(fs::read(&data).unwrap(), fs::read(&index).unwrap());
+            cleanup(&[&data, &index]);
+        }
+    }
+
+    #[test]
+    fn test_crash_during_swap_of_a_tail_group_recovers_it_as_one() {
+        let writes = {
+            let ((index, index_temp), (mac, mac_temp)) = (paths(), paths());
+            fs::write(&index, OLD).unwrap();
+            fs::write(&mac, OLD).unwrap();
+            let fs = FaultyFs::counting();
+            replace_all(&fs, &[(&index_temp, &index), (&mac_temp, &mac)]).unwrap();
+            cleanup(&[&index, &mac]);
+            fs.writes()
+        };
+
+        for crash_point in 0..writes {
+            let ((data, data_temp), (index, index_temp), (mac, mac_temp)) =
+                (paths(), paths(), paths());
+            for file in [&data, &index, &mac] {
+                fs::write(file, OLD).unwrap();
+            }
+
+            let faulty = FaultyFs::crash_after(crash_point);
+            let tail = [(index_temp.as_str(), index.as_str()), (&mac_temp, &mac)];
+            assert!(replace_all(&faulty, &tail).is_err());
+            recover_swap_all(&[(&data_temp, &data), tail[0], tail[1]]).unwrap();
+
+            assert_eq!(fs::read(&data).unwrap(), OLD);
+            assert_consistent(&index_temp, &index);
+            assert_consistent(&mac_temp, &mac);
+            assert_eq!(fs::read(&index).unwrap(), fs::read(&mac).unwrap());
+            cleanup(&[&data, &index, &mac]);
+        }
+    }
+
+    #[test]
+    fn test_crash_during_group_swap_of_new_files_recovers_all_or_none() {
+        let writes = {
+            let ((data, data_temp), (index, index_temp)) = (paths(), paths());
+            let fs = FaultyFs::counting();
+            replace_all(&fs, &[(&data_temp, &data), (&index_temp, &index)]).unwrap();
+            cleanup(&[&data, &index]);
+            fs.writes()
+        };
+
+        for crash_point in 0..writes {
+            let ((data, data_temp), (index, index_temp)) = (paths(), paths());
+            let pairs = [(data_temp.as_str(), data.as_str()), (&index_temp, &index)];
+
+            let faulty = FaultyFs::crash_after(crash_point);
+            assert!(replace_all(&faulty, &pairs).is_err());
+            recover_group_swap(&pairs).unwrap();
+
+            let exists = [&data, &index].map(|file| Path::new(file).exists());
+            assert_eq!(exists[0], exists[1], "crash after {} writes", crash_point);
+            for (temp, target) in pairs {
+                assert!(!Path::new(temp).exists());
+                if Path::new(target).exists() {
+                    assert_eq!(fs::read(target).unwrap(), NEW);
+                }
+            }
+            assert!(!Path::new(&marker_path(&data_temp)).exists());
+            cleanup(&[&data, &index]);
+        }
+    }
+
+    #[test]
+    fn test_crash_during_recovery_recovers_consistently() {
+        for crash_point in 0..replace_writes() {
+            for recovery_crash_point in 0..3 {
+                let (target, temp) = paths();
+                fs::write(&target, OLD).unwrap();
+                let _ = replace(&FaultyFs::crash_after(crash_point), &temp, &target);
+
+                let faulty = FaultyFs::crash_after(recovery_crash_point);
+                let _ = recover_swap_with(&faulty, &temp, &target, &RetryPolicy::none());
+                recover_swap(&temp, &target).unwrap();
+
+                assert_consistent(&temp, &target);
+                cleanup(&[&target, &temp, &marker_path(&temp)]);
+            }
+        }
+    }
+}
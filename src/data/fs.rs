@@ -0,0 +1,106 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+};
+
+// The file operations the crash-safe helpers are built from, so tests can
+// stop them at any point and check what recovery makes of the result
+pub trait Fs {
+    fn exists(&self, path: &str) -> bool;
+
+    // Writes `contents` to a new or truncated file and syncs it
+    fn write_file(&self, path: &str, contents: &[u8]) -> io::Result<()>;
+
+    fn remove_file(&self, path: &str) -> io::Result<()>;
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()>;
+}
+
+pub struct OsFs;
+
+impl Fs for OsFs {
+    fn exists(&self, path: &str) -> bool {
+        Path::new(path).exists()
+    }
+
+    fn write_file(&self, path: &str, contents: &[u8]) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        file.write_all(contents)?;
+        file.sync_all()
+    }
+
+    fn remove_file(&self, path: &str) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+}
+
+// Behaves like `OsFs` for a number of writes, then acts as if the process
+// died: the write in progress is cut short and every later one fails
+#[cfg(any(test, feature = "testing"))]
+pub struct FaultyFs {
+    writes_left: std::cell::Cell<usize>,
+    writes: std::cell::Cell<usize>,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl FaultyFs {
+    pub fn crash_after(writes: usize) -> Self {
+        Self {
+            writes_left: std::cell::Cell::new(writes),
+            writes: std::cell::Cell::new(0),
+        }
+    }
+
+    // Never crashes, to count the writes an operation makes
+    pub fn counting() -> Self {
+        Self::crash_after(usize::MAX)
+    }
+
+    pub fn writes(&self) -> usize {
+        self.writes.get()
+    }
+
+    pub fn crashed(&self) -> bool {
+        self.writes_left.get() == 0
+    }
+
+    fn attempt(&self) -> io::Result<()> {
+        if self.crashed() {
+            return Err(io::Error::other("injected crash"));
+        }
+        self.writes_left.set(self.writes_left.get() - 1);
+        self.writes.set(self.writes.get() + 1);
+        Ok(())
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl Fs for FaultyFs {
+    fn exists(&self, path: &str) -> bool {
+        OsFs.exists(path)
+    }
+
+    fn write_file(&self, path: &str, contents: &[u8]) -> io::Result<()> {
+        if self.crashed() {
+            // A torn write leaves part of the data behind
+            OsFs.write_file(path, &contents[..contents.len() / 2])?;
+        }
+        self.attempt()?;
+        OsFs.write_file(path, contents)
+    }
+
+    fn remove_file(&self, path: &str) -> io::Result<()> {
+        self.attempt()?;
+        OsFs.remove_file(path)
+    }
+
+    fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        self.attempt()?;
+        OsFs.rename(from, to)
+    }
+}
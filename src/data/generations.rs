@@ -0,0 +1,356 @@
+use log::{debug, info};
+use std::{
+    fs::{self, File},
+    io,
+    path::Path,
+    process,
+};
+use uuid::Uuid;
+
+use super::{durability::Syncer, store_error::StoreError};
+
+// Copy-on-write versions of a file, named `{base}.0001`, `{base}.0002`, ...
+// A rewrite publishes a new generation with a single rename that never
+// replaces a file, and readers keep using the generation they opened until
+// they drop their lease. Generations older than the current one are removed
+// once nobody holds a lease on them.
+#[derive(Debug, Clone)]
+pub struct Generations {
+    base_path: String,
+}
+
+impl Generations {
+    pub fn new(base_path: String) -> Self {
+        Self { base_path }
+    }
+
+    pub fn path(&self, generation: u64) -> String {
+        format!("{}.{:04}", self.base_path, generation)
+    }
+
+    fn temp_path(&self, generation: u64) -> String {
+        format!("{}-tmp", self.path(generation))
+    }
+
+    fn lease_prefix(&self, generation: u64) -> String {
+        format!("{}.reader-", self.path(generation))
+    }
+
+    // Exists while `collect_garbage` decides whether to remove a generation
+    fn collecting_path(&self, generation: u64) -> String {
+        format!("{}.collecting", self.path(generation))
+    }
+
+    // (directory, file name prefix) of the generation files
+    fn location(&self) -> (&Path, String) {
+        let path = Path::new(&self.base_path);
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        (dir, format!("{}.", name))
+    }
+
+    // Published generations, oldest first
+    pub fn list(&self) -> Result<Vec<u64>, StoreError> {
+        let (dir, prefix) = self.location();
+        let mut generations = Vec::new();
+
+        for dir_entry in fs::read_dir(dir)? {
+            let name = dir_entry?.file_name().to_string_lossy().to_string();
+            let generation = name
+                .strip_prefix(&prefix)
+                .filter(|suffix| suffix.len() >= 4 && suffix.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|suffix| suffix.parse().ok());
+            if let Some(generation) = generation {
+                generations.push(generation);
+            }
+        }
+
+        generations.sort_unstable();
+        Ok(generations)
+    }
+
+    pub fn current(&self) -> Result<Option<u64>, StoreError> {
+        Ok(self.list()?.last().copied())
+    }
+
+    // Writes the next generation with `write` and publishes it. A temp file
+    // left by a writer that died is simply overwritten. Fails with
+    // `AlreadyExists` if another writer published the same generation first.
+    pub fn publish(
+        &self,
+        write: impl FnOnce(&mut File) -> Result<(), StoreError>,
+    ) -> Result<u64, StoreError> {
+        self.publish_with(&Syncer::default(), write)
+    }
+
+    // Like `publish`, syncing the new generation as `syncer` says
+    pub fn publish_with(
+        &self,
+        syncer: &Syncer,
+        write: impl FnOnce(&mut File) -> Result<(), StoreError>,
+    ) -> Result<u64, StoreError> {
+        let generation = self.current()?.map_or(1, |current| current + 1);
+        let temp_path = self.temp_path(generation);
+
+        let mut file = File::create(&temp_path)?;
+        write(&mut file)?;
+        syncer.rewritten(&mut file)?;
+        drop(file);
+
+        // Unlike a rename, linking fails if another writer already published
+        // this generation, instead of replacing it
+        let linked = fs::hard_link(&temp_path, self.path(generation));
+        fs::remove_file(&temp_path)?;
+        linked?;
+        syncer.renamed(&self.path(generation))?;
+        info!("Published {}", self.path(generation));
+
+        Ok(generation)
+    }
+
+    // Keeps `generation` from being collected until the lease is dropped.
+    // Leases are files, so they hold off collection in other processes too.
+    // Each holds the id of the process that took it, so the lease of a
+    // process that died doesn't keep the generation forever.
+    pub fn lease(&self, generation: u64) -> Result<GenerationLease, StoreError> {
+        let lease_path = format!("{}{}", self.lease_prefix(generation), Uuid::new_v4());
+        fs::write(&lease_path, process::id().to_string())?;
+
+        // The generation may have been collected before the lease existed, or
+        // be about to be. The collector checks for leases only after marking
+        // the generation, so a lease made before the mark is always seen.
+        if Path::new(&self.collecting_path(generation)).exists()
+            || !Path::new(&self.path(generation)).exists()
+        {
+            fs::remove_file(&lease_path)?;
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("generation {} was collected", generation),
+            )
+            .into());
+        }
+
+        Ok(GenerationLease {
+            generation,
+            file_path: self.path(generation),
+            lease_path,
+        })
+    }
+
+    // Leases the current generation, retrying if it is collected in between
+    pub fn lease_current(&self) -> Result<Option<GenerationLease>, StoreError> {
+        loop {
+            let generation = match self.current()? {
+                Some(generation) => generation,
+                None => return Ok(None),
+            };
+            match self.lease(generation) {
+                Err(StoreError::IoError(e)) if e.kind() == io::ErrorKind::NotFound => continue,
+                lease => return lease.map(Some),
+            }
+        }
+    }
+
+    fn has_leases(&self, generation: u64) -> Result<bool, StoreError> {
+        let (dir, _) = self.location();
+        let lease_prefix = self.lease_prefix(generation);
+        let lease_prefix = Path::new(&lease_prefix)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let mut leased = false;
+        for dir_entry in fs::read_dir(dir)? {
+            let dir_entry = dir_entry?;
+            if !dir_entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(&lease_prefix)
+            {
+                continue;
+            }
+            if is_stale(&dir_entry.path()) {
+                info!("Removing stale lease {}", dir_entry.path().display());
+                let _ = fs::remove_file(dir_entry.path());
+                continue;
+            }
+            leased = true;
+        }
+        Ok(leased)
+    }
+
+    // Removes the generations before the current one that nobody is reading,
+    // and returns them
+    pub fn collect_garbage(&self) -> Result<Vec<u64>, StoreError> {
+        let mut generations = self.list()?;
+        generations.pop();
+
+        let mut collected = Vec::new();
+        for generation in generations {
+            // Marked first, so a reader leasing it from here on backs off
+            let collecting_path = self.collecting_path(generation);
+            File::create(&collecting_path)?;
+            if self.has_leases(generation)? {
+                debug!("Keeping leased {}", self.path(generation));
+                fs::remove_file(&collecting_path)?;
+                continue;
+            }
+            fs::remove_file(self.path(generation))?;
+            fs::remove_file(&collecting_path)?;
+            collected.push(generation);
+        }
+
+        Ok(collected)
+    }
+}
+
+// Whether the process that took a lease is gone. Only known where processes
+// can be looked up, elsewhere and for unreadable leases it is assumed alive.
+fn is_stale(lease_path: &Path) -> bool {
+    let pid = fs::read_to_string(lease_path)
+        .ok()
+        .and_then(|contents| contents.trim().parse::<u32>().ok());
+    match pid {
+        Some(pid) if cfg!(target_os = "linux") => !Path::new(&format!("/proc/{}", pid)).exists(),
+        _ => false,
+    }
+}
+
+// A generation held open for reading
+#[derive(Debug)]
+pub struct GenerationLease {
+    generation: u64,
+    file_path: String,
+    lease_path: String,
+}
+
+impl GenerationLease {
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+}
+
+impl Drop for GenerationLease {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.lease_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn generations() -> Generations {
+        Generations::new(format!("test_generations_{}.bin", Uuid::new_v4()))
+    }
+
+    fn cleanup(generations: &Generations) {
+        for generation in generations.list().unwrap() {
+            fs::remove_file(generations.path(generation)).unwrap();
+        }
+    }
+
+    fn publish(generations: &Generations, contents: &[u8]) -> u64 {
+        generations
+            .publish(|file| Ok(file.write_all(contents)?))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_publish_increments_generation() {
+        let generations = generations();
+        assert_eq!(generations.current().unwrap(), None);
+
+        assert_eq!(publish(&generations, b"one"), 1);
+        assert_eq!(publish(&generations, b"two"), 2);
+
+        assert_eq!(generations.list().unwrap(), vec![1, 2]);
+        assert_eq!(fs::read(generations.path(2)).unwrap(), b"two");
+
+        cleanup(&generations);
+    }
+
+    #[test]
+    fn test_leased_generation_survives_collection() {
+        let generations = generations();
+        publish(&generations, b"one");
+        let lease = generations.lease_current().unwrap().unwrap();
+        publish(&generations, b"two");
+
+        assert!(generations.collect_garbage().unwrap().is_empty());
+        assert_eq!(fs::read(lease.file_path()).unwrap(), b"one");
+
+        drop(lease);
+        assert_eq!(generations.collect_garbage().unwrap(), vec![1]);
+        assert_eq!(generations.list().unwrap(), vec![2]);
+
+        cleanup(&generations);
+    }
+
+    #[test]
+    fn test_lease_of_collected_generation_fails() {
+        let generations = generations();
+        publish(&generations, b"one");
+        publish(&generations, b"two");
+        generations.collect_garbage().unwrap();
+
+        assert!(generations.lease(1).is_err());
+        assert_eq!(
+            generations.lease_current().unwrap().unwrap().generation(),
+            2
+        );
+
+        cleanup(&generations);
+    }
+
+    #[test]
+    fn test_generation_being_collected_cant_be_leased() {
+        let generations = generations();
+        publish(&generations, b"one");
+        publish(&generations, b"two");
+
+        // A collector between marking generation 1 and removing it
+        File::create(generations.collecting_path(1)).unwrap();
+        assert!(matches!(
+            generations.lease(1),
+            Err(StoreError::IoError(ref e)) if e.kind() == io::ErrorKind::NotFound
+        ));
+        fs::remove_file(generations.collecting_path(1)).unwrap();
+
+        // A lease taken before the mark keeps the generation
+        let lease = generations.lease(1).unwrap();
+        assert!(generations.collect_garbage().unwrap().is_empty());
+        assert!(!Path::new(&generations.collecting_path(1)).exists());
+        assert_eq!(fs::read(lease.file_path()).unwrap(), b"one");
+
+        drop(lease);
+        cleanup(&generations);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_lease_of_dead_process_is_removed() {
+        let generations = generations();
+        publish(&generations, b"one");
+        publish(&generations, b"two");
+        // Above the largest pid Linux hands out
+        let lease_path = format!("{}dead", generations.lease_prefix(1));
+        fs::write(&lease_path, "4294967295").unwrap();
+
+        assert_eq!(generations.collect_garbage().unwrap(), vec![1]);
+        assert!(!Path::new(&lease_path).exists());
+
+        cleanup(&generations);
+    }
+}
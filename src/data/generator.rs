@@ -0,0 +1,143 @@
+use rand::{distr::Alphanumeric, rngs::StdRng, Rng, SeedableRng};
+use uuid::Uuid;
+
+use super::{
+    data_store::DataStore,
+    model::{Entry, EntryKind, Label},
+};
+
+const LABELS: [Label; 7] = [
+    Label::Red,
+    Label::Orange,
+    Label::Yellow,
+    Label::Green,
+    Label::Blue,
+    Label::Purple,
+    Label::Gray,
+];
+
+// Produces `count` reproducible entries whose text fields are roughly
+// `field_size` characters long, for benchmarks and tests.
+pub fn generate_entries(seed: u64, count: usize, field_size: usize) -> Vec<Entry> {
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    (0..count)
+        .map(|i| {
+            let mut text = |len: usize| -> String {
+                (&mut rng)
+                    .sample_iter(&Alphanumeric)
+                    .take(len)
+                    .map(char::from)
+                    .collect()
+            };
+
+            let title = format!("Entry {} {}", i, text(field_size));
+            let username = text(field_size);
+            let password = text(field_size);
+            let url = format!("https://{}.example.com", text(field_size).to_lowercase());
+            let note = text(field_size * 4);
+
+            Entry {
+                id: Uuid::from_u128(rng.random()).to_string(),
+                title,
+                kind: EntryKind::Login,
+                token: None,
+                username: Some(username),
+                password: Some(password),
+                url: Some(url),
+                note: Some(note),
+                favorite: rng.random_bool(0.1),
+                label: None,
+                tags: Vec::new(),
+                archived: false,
+                sort_key: None,
+                updated_at: 0,
+                password_changed_at: 0,
+            }
+        })
+        .collect()
+}
+
+// A reproducible vault that looks like a real one: optional fields are
+// sometimes missing, some entries are favorites and some carry a label.
+pub fn generate_test_vault(seed: u64, n_entries: usize) -> Vec<Entry> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut entries = generate_entries(rng.random(), n_entries, 12);
+
+    for entry in entries.iter_mut() {
+        let mut drop_some = |field: &mut Option<String>| {
+            if rng.random_bool(0.2) {
+                *field = None;
+            }
+        };
+        drop_some(&mut entry.username);
+        drop_some(&mut entry.url);
+        drop_some(&mut entry.note);
+
+        entry.label = rng
+            .random_bool(0.3)
+            .then(|| LABELS[rng.random_range(0..LABELS.len())]);
+    }
+
+    entries
+}
+
+// Saves the vault from `generate_test_vault` into `store`, e.g. to get one
+// on disk, and returns the entries that were saved
+pub fn write_test_vault<S, E>(store: &mut S, seed: u64, n_entries: usize) -> Result<Vec<Entry>, E>
+where
+    S: DataStore<String, Entry, E> + ?Sized,
+{
+    let entries = generate_test_vault(seed, n_entries);
+    for entry in &entries {
+        store.save(&entry.id, entry)?;
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::binary_file_entry_store::BinaryFileEntryStore;
+    use std::fs;
+
+    #[test]
+    fn test_generate_entries_is_reproducible() {
+        let first = generate_entries(42, 10, 8);
+        let second = generate_entries(42, 10, 8);
+
+        assert_eq!(first.len(), 10);
+        assert_eq!(first, second);
+        assert_ne!(first, generate_entries(43, 10, 8));
+    }
+
+    #[test]
+    fn test_generate_test_vault_is_reproducible() {
+        let vault = generate_test_vault(7, 50);
+
+        assert_eq!(vault, generate_test_vault(7, 50));
+        assert!(vault.iter().any(|entry| entry.label.is_some()));
+        assert!(vault.iter().any(|entry| entry.url.is_none()));
+    }
+
+    #[test]
+    fn test_write_test_vault() {
+        let path = format!("test_vault_{}.bin", Uuid::new_v4());
+        let mut store = BinaryFileEntryStore::new(path.clone());
+
+        let entries = write_test_vault(&mut store, 7, 5).unwrap();
+
+        for entry in &entries {
+            assert_eq!(store.load(&entry.id).unwrap().as_ref(), Some(entry));
+        }
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_generated_ids_fit_index_records() {
+        for entry in generate_entries(1, 5, 4) {
+            assert!(Uuid::parse_str(&entry.id).is_ok());
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::{BTreeSet, HashMap};
+
+use super::{
+    data_store::{filter_fn, DataStore, Filter},
+    model::Entry,
+    secondary_index::domain_of,
+    store_error::StoreError,
+    text_search::searchable_text,
+};
+
+// Values worked out from an entry when it is saved, so queries can read
+// them rather than recompute them for every entry. Each derive hook fills in
+// what it knows, the rest stays empty.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Derived {
+    pub domain: Option<String>,
+    // A rough entropy estimate of the password, see `derive_strength`
+    pub strength_bits: Option<u32>,
+    pub search_tokens: BTreeSet<String>,
+}
+
+// Runs before an entry is saved. It may change the entry, or fail the save.
+pub type PreSaveHook = Box<dyn Fn(&mut Entry) -> Result<(), StoreError>>;
+// Runs after an entry is saved, to fill in its derived values
+pub type DeriveHook = Box<dyn Fn(&Entry, &mut Derived)>;
+
+pub fn derive_domain(entry: &Entry, derived: &mut Derived) {
+    derived.domain = entry.url.as_deref().and_then(domain_of);
+}
+
+// The size of the alphabet the password draws from to the power of its
+// length, as bits. It doesn't know about words or patterns, so it is an
+// upper bound rather than a score to trust.
+pub fn derive_strength(entry: &Entry, derived: &mut Derived) {
+    derived.strength_bits = entry.password.as_deref().map(|password| {
+        let mut alphabet = 0;
+        if password.chars().any(char::is_lowercase) {
+            alphabet += 26;
+        }
+        if password.chars().any(char::is_uppercase) {
+            alphabet += 26;
+        }
+        if password.chars().any(char::is_numeric) {
+            alphabet += 10;
+        }
+        if password.chars().any(|c| !c.is_alphanumeric()) {
+            alphabet += 33;
+        }
+        match alphabet {
+            0 => 0,
+            alphabet => (password.chars().count() as f64 * f64::from(alphabet).log2()) as u32,
+        }
+    });
+}
+
+// The lowercased words of the fields text search looks at
+pub fn derive_search_tokens(entry: &Entry, derived: &mut Derived) {
+    derived.search_tokens = searchable_text(entry)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+        .collect();
+}
+
+// Wraps a store to run hooks around saves and keep what the derive hooks
+// compute next to it. The derived values live in memory, entries saved
+// before the store was wrapped get theirs from `derive_existing`.
+pub struct HookedStore<S> {
+    store: S,
+    pre_save: Vec<PreSaveHook>,
+    derive: Vec<DeriveHook>,
+    derived: HashMap<String, Derived>,
+}
+
+impl<S: DataStore<String, Entry, StoreError>> HookedStore<S> {
+    pub fn new(store: S) -> Self {
+        HookedStore {
+            store,
+            pre_save: Vec::new(),
+            derive: Vec::new(),
+            derived: HashMap::new(),
+        }
+    }
+
+    // Hooks run in the order they were added
+    pub fn with_pre_save<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&mut Entry) -> Result<(), StoreError> + 'static,
+    {
+        self.pre_save.push(Box::new(hook));
+        self
+    }
+
+    pub fn with_derive<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Entry, &mut Derived) + 'static,
+    {
+        self.derive.push(Box::new(hook));
+        self
+    }
+
+    // Domain, strength and search tokens
+    pub fn with_standard_derivations(self) -> Self {
+        self.with_derive(derive_domain)
+            .with_derive(derive_strength)
+            .with_derive(derive_search_tokens)
+    }
+
+    pub fn derived(&self, id: &str) -> Option<&Derived> {
+        self.derived.get(id)
+    }
+
+    // Computes the derived values of every entry in the store, returning
+    // how many there were
+    pub fn derive_existing(&mut self) -> Result<usize, StoreError> {
+        let entries = self.store.search(&filter_fn(|_: &Entry| true))?;
+        for entry in &entries {
+            let derived = self.derive_from(entry);
+            self.derived.insert(entry.id.clone(), derived);
+        }
+        Ok(entries.len())
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    fn derive_from(&self, entry: &Entry) -> Derived {
+        let mut derived = Derived::default();
+        for hook in &self.derive {
+            hook(entry, &mut derived);
+        }
+        derived
+    }
+}
+
+impl<S: DataStore<String, Entry, StoreError>> DataStore<String, Entry, StoreError>
+    for HookedStore<S>
+{
+    fn save(&mut self, id: &String, entry: &Entry) -> Result<(), StoreError> {
+        let mut entry = entry.clone();
+        for hook in &self.pre_save {
+            hook(&mut entry)?;
+        }
+        self.store.save(id, &entry)?;
+
+        let derived = self.derive_from(&entry);
+        self.derived.insert(id.clone(), derived);
+        Ok(())
+    }
+
+    fn load(&self, id: &String) -> Result<Option<Entry>, StoreError> {
+        self.store.load(id)
+    }
+
+    fn delete(&mut self, id: &String) -> Result<(), StoreError> {
+        self.store.delete(id)?;
+        self.derived.remove(id);
+        Ok(())
+    }
+
+    fn search(&self, filter: &dyn Filter<Entry>) -> Result<Vec<Entry>, StoreError> {
+        self.store.search(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secret::password_policy::PolicyViolation;
+
+    #[derive(Default)]
+    struct MemoryStore(HashMap<String, Entry>);
+
+    impl DataStore<String, Entry, StoreError> for MemoryStore {
+        fn save(&mut self, id: &String, entry: &Entry) -> Result<(), StoreError> {
+            self.0.insert(id.clone(), entry.clone());
+            Ok(())
+        }
+
+        fn load(&self, id: &String) -> Result<Option<Entry>, StoreError> {
+            Ok(self.0.get(id).cloned())
+        }
+
+        fn delete(&mut self, id: &String) -> Result<(), StoreError> {
+            self.0.remove(id);
+            Ok(())
+        }
+
+        fn search(&self, filter: &dyn Filter<Entry>) -> Result<Vec<Entry>, StoreError> {
+            Ok(self
+                .0
+                .values()
+                .filter(|e| filter.pass(e))
+                .cloned()
+                .collect())
+        }
+    }
+
+    fn entry(id: &str, password: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: "Work Mail".to_string(),
+            password: Some(password.to_string()),
+            url: Some("https://www.Example.com/login".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_derives_on_save() {
+        let mut store = HookedStore::new(MemoryStore::default())
+            .with_pre_save(|entry| {
+                entry.title = entry.title.trim().to_string();
+                Ok(())
+            })
+            .with_standard_derivations();
+        let mut saved = entry("1", "abcd1234");
+        saved.title = "  Work Mail ".to_string();
+
+        store.save(&"1".to_string(), &saved).unwrap();
+
+        assert_eq!(
+            store.load(&"1".to_string()).unwrap(),
+            Some(entry("1", "abcd1234"))
+        );
+        let derived = store.derived("1").unwrap();
+        assert_eq!(derived.domain.as_deref(), Some("example.com"));
+        // 8 characters from 36
+        assert_eq!(derived.strength_bits, Some(41));
+        assert!(derived.search_tokens.contains("mail"));
+        assert!(derived.search_tokens.contains("example"));
+
+        store.delete(&"1".to_string()).unwrap();
+        assert_eq!(store.derived("1"), None);
+    }
+
+    #[test]
+    fn test_failing_pre_save_hook_stops_the_save() {
+        let mut store = HookedStore::new(MemoryStore::default())
+            .with_pre_save(|_| Err(StoreError::PolicyViolation(PolicyViolation::DenyListed)));
+
+        assert!(store.save(&"1".to_string(), &entry("1", "x")).is_err());
+        assert_eq!(store.load(&"1".to_string()).unwrap(), None);
+        assert_eq!(store.derived("1"), None);
+    }
+
+    #[test]
+    fn test_derive_existing() {
+        let mut inner = MemoryStore::default();
+        inner.save(&"1".to_string(), &entry("1", "x")).unwrap();
+        let mut store = HookedStore::new(inner).with_derive(derive_domain);
+
+        assert_eq!(store.derive_existing().unwrap(), 1);
+        assert_eq!(
+            store.derived("1").unwrap().domain.as_deref(),
+            Some("example.com")
+        );
+    }
+}
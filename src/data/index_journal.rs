@@ -0,0 +1,198 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fs::{remove_file, OpenOptions},
+    hash::Hash,
+    io::Write,
+    path::Path,
+};
+
+use super::{
+    binary_index_iterator::BinaryIndexIterator,
+    compact_index::{encode_record, CompactIndex, CompactKey},
+    durability::Syncer,
+    store_error::StoreError,
+};
+
+// Append-only log of index changes since the index file was last rewritten.
+// Each record is `(id, Some(position))` for a save or `(id, None)` for a
+// delete, padded to a fixed size like the index records.
+pub struct IndexJournal {
+    file_path: String,
+    record_size: usize,
+    records: usize,
+}
+
+impl IndexJournal {
+    pub fn new(file_path: String, record_size: usize) -> Self {
+        Self {
+            file_path,
+            record_size,
+            records: 0,
+        }
+    }
+
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+
+    pub fn exists(&self) -> bool {
+        Path::new(&self.file_path).exists()
+    }
+
+    // Number of records appended or replayed since the journal was cleared
+    pub fn len(&self) -> usize {
+        self.records
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records == 0
+    }
+
+    pub fn append<K: CompactKey + Serialize + ?Sized, P: Serialize>(
+        &mut self,
+        id: &K,
+        position: Option<&P>,
+    ) -> Result<(), StoreError> {
+        let record = encode_record(id, &position, self.record_size)?;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file_path)?;
+        file.write_all(&record)?;
+
+        self.records += 1;
+        Ok(())
+    }
+
+    // Applies the journal on top of `index`. A torn record at the end of the
+    // file is ignored, it was never acknowledged to the caller.
+    pub fn replay<K: CompactKey + Serialize + DeserializeOwned + Eq + Hash, P: DeserializeOwned>(
+        &mut self,
+        index: &mut CompactIndex<K, P>,
+    ) -> Result<(), StoreError> {
+        self.records = 0;
+        if !self.exists() {
+            return Ok(());
+        }
+
+        let file = OpenOptions::new().read(true).open(&self.file_path)?;
+        for record in BinaryIndexIterator::<_, K, Option<P>>::new(file, self.record_size) {
+            match record? {
+                (id, Some(position)) => index.insert(id, position),
+                (id, None) => index.remove(&id),
+            };
+            self.records += 1;
+        }
+
+        Ok(())
+    }
+
+    pub fn sync(&self, syncer: &Syncer) -> Result<(), StoreError> {
+        if self.exists() {
+            syncer.saved(&mut OpenOptions::new().write(true).open(&self.file_path)?)?;
+        }
+        Ok(())
+    }
+
+    // Drops whatever follows the first `len` bytes, e.g. records that were
+    // never committed. Call before replaying.
+    pub fn truncate(&mut self, len: u64, syncer: &Syncer) -> Result<(), StoreError> {
+        if len == 0 {
+            return self.clear();
+        }
+        let mut file = OpenOptions::new().write(true).open(&self.file_path)?;
+        if file.metadata()?.len() > len {
+            file.set_len(len)?;
+            syncer.saved(&mut file)?;
+        }
+        Ok(())
+    }
+
+    // Called once the index file holds everything the journal recorded
+    pub fn clear(&mut self) -> Result<(), StoreError> {
+        if self.exists() {
+            remove_file(&self.file_path)?;
+        }
+        self.records = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashMap, fs};
+    use uuid::Uuid;
+
+    const RECORD_SIZE: usize = 32;
+
+    fn journal() -> IndexJournal {
+        IndexJournal::new(format!("test_journal_{}.bin", Uuid::new_v4()), RECORD_SIZE)
+    }
+
+    #[test]
+    fn test_replay_applies_saves_and_deletes() {
+        let mut journal = journal();
+        journal.append("a", Some(&1u64)).unwrap();
+        journal.append("b", Some(&2u64)).unwrap();
+        journal.append("a", None::<&u64>).unwrap();
+        journal.append("b", Some(&3u64)).unwrap();
+
+        let mut index = CompactIndex::from_iter([("c".to_string(), 9u64)]);
+        let mut replayed = IndexJournal::new(journal.file_path().to_string(), RECORD_SIZE);
+        replayed.replay(&mut index).unwrap();
+
+        assert_eq!(
+            index
+                .iter()
+                .map(|(id, p)| (id, *p))
+                .collect::<HashMap<_, _>>(),
+            HashMap::from([("b".to_string(), 3u64), ("c".to_string(), 9u64)])
+        );
+        assert_eq!(replayed.len(), 4);
+
+        journal.clear().unwrap();
+    }
+
+    #[test]
+    fn test_replay_ignores_torn_record() {
+        let mut journal = journal();
+        journal.append("a", Some(&1u64)).unwrap();
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(journal.file_path())
+            .unwrap();
+        file.write_all(&[1, 2, 3]).unwrap();
+
+        let mut index = CompactIndex::new();
+        journal.replay(&mut index).unwrap();
+
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.get(&"a".to_string()), Some(&1u64));
+
+        journal.clear().unwrap();
+    }
+
+    #[test]
+    fn test_clear_removes_file() {
+        let mut journal = journal();
+        journal.append("a", Some(&1u64)).unwrap();
+
+        journal.clear().unwrap();
+
+        assert!(!Path::new(journal.file_path()).exists());
+        assert!(journal.is_empty());
+    }
+
+    #[test]
+    fn test_record_too_large() {
+        let mut journal = journal();
+        let id = "x".repeat(RECORD_SIZE);
+
+        let result = journal.append(&id, Some(&1u64));
+
+        assert!(matches!(result, Err(StoreError::IndexRecordTooLarge)));
+        assert!(fs::metadata(journal.file_path()).is_err());
+    }
+}
@@ -1,18 +1,42 @@
+#[cfg(feature = "watch")]
+use super::store_watcher::{StoreEvent, StoreWatcher};
 use super::{
-    binary_index_iterator::BinaryIndexIterator, binary_store_error::BinaryStoreError,
-    data_store::DataStore, model::Entry,
+    binary_index_iterator::BinaryIndexIterator,
+    compact_index::{encode_record, CompactIndex},
+    compaction::{fragmentation_of, Compactable, CompactionStats},
+    data_store::{filter_fn, DataStore, StoreKey},
+    durability::{Durability, SyncLevel, SyncStats, Syncer},
+    file_swap::{recover_swap, recover_swap_all, swap_in, swap_in_all},
+    index_journal::IndexJournal,
+    migration::{
+        MigrationRegistry, MigrationReport, SchemaHeader, BASELINE_SCHEMA_VERSION,
+        SCHEMA_HEADER_LEN,
+    },
+    model::Entry,
+    secondary_index::{domain_of, SecondaryIndexes},
+    store_error::StoreError,
 };
-use log::{debug, error, info};
+use crate::secret::file_set_mac::FileSetMac;
+use bincode::Options;
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
-    fs::{remove_file, rename, File, OpenOptions},
+    fs::{self, File, OpenOptions},
     io::{Read, Seek, SeekFrom, Write},
     path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-// 36 (id: string representation of uuid v4) + 8 (offset) + 8 (length) = 52 bytes
+// A record is the id followed by 8 (offset) + 8 (length) bytes. A uuid id
+// takes 8 (marker) + 16, other ids their bincode encoding, which for a
+// string is 8 (length) + its bytes, so ids of up to 28 bytes fit in 52.
+// Saving a longer id fails with `IdTooLong`.
 const INDEX_RECORD_SIZE: usize = 52;
+// An index record plus the tag of the optional position
+const JOURNAL_RECORD_SIZE: usize = INDEX_RECORD_SIZE + 1;
+// Journal records merged into the index file by a commit
+const JOURNAL_MERGE_THRESHOLD: usize = 1024;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 struct Position {
@@ -20,22 +44,197 @@ struct Position {
     length: usize,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-pub struct IndexEntry {
-    id: String,
-    position: Position,
+// The MAC file: how long the files were when last committed, and the MAC
+// over them up to there. Saves appended to the data file and the journal
+// since lie past those lengths, so a crash before the next commit leaves
+// the committed part verifiable.
+#[derive(Debug, Serialize, Deserialize)]
+struct CommittedMac {
+    data_len: u64,
+    index_len: u64,
+    journal_len: u64,
+    tag: Vec<u8>,
+}
+
+impl CommittedMac {
+    fn new(mac: &FileSetMac, files: [(&str, u64); 3]) -> Result<Self, StoreError> {
+        let [(_, data_len), (_, index_len), (_, journal_len)] = files;
+        Ok(CommittedMac {
+            data_len,
+            index_len,
+            journal_len,
+            tag: mac.compute_prefixes(&files)?,
+        })
+    }
+
+    fn write(&self, file_path: &str) -> Result<(), StoreError> {
+        fs::write(file_path, bincode::serialize(self)?)?;
+        Ok(())
+    }
+}
+
+// How long each step of opening a store took, so a slow open can be traced
+// to a large index (shard it), a long journal (commit more often) or the
+// integrity check. There is no key derivation step, the key is passed in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpenReport {
+    // Recovering interrupted swaps and creating missing files
+    pub recovery: Duration,
+    pub integrity_check: Duration,
+    pub index_load: Duration,
+    pub journal_replay: Duration,
+    pub secondary_load: Duration,
+    pub total: Duration,
+    pub entries: usize,
+    pub journal_records: usize,
+    pub secondary_rebuilt: bool,
+    pub data_file_len: u64,
+    pub index_file_len: u64,
+    // From the consistency check, see `ConsistencyReport`
+    pub out_of_bounds: usize,
+    pub overlapping: usize,
+    pub trimmed: usize,
+    // The last session ended with `shut_down`, so the index was trusted
+    pub clean_shutdown: bool,
+}
+
+// What opening a store does about index positions the data file can't back
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConsistencyRepair {
+    // Log them and leave the index alone, loading those entries will fail
+    #[default]
+    Report,
+    // Drop them from the index, as `trim_inconsistent` does
+    Trim,
+}
+
+// Index positions that can't be right, typically a truncated data file
+// with a stale index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsistencyReport<K = String> {
+    pub data_file_len: u64,
+    // Ids whose record would run past the end of the data file
+    pub out_of_bounds: Vec<K>,
+    // Pairs of ids whose records share bytes, the earlier record first
+    pub overlapping: Vec<(K, K)>,
+    // Ids dropped from the index by a trim
+    pub trimmed: Vec<K>,
+}
+
+impl<K> ConsistencyReport<K> {
+    pub fn is_consistent(&self) -> bool {
+        self.out_of_bounds.is_empty() && self.overlapping.is_empty()
+    }
 }
 
-pub struct IndexedBinaryFileEntryStore {
+// A record of the data file the index doesn't point to, though it is the
+// latest one with its id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrphanRecord {
+    pub id: String,
+    pub offset: u64,
+    pub length: usize,
+    // The index has the id, but at an older record
+    pub newer_than_indexed: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OrphanReport {
+    pub records_scanned: usize,
+    // In the order they were written
+    pub orphans: Vec<OrphanRecord>,
+    // Bytes no record could be read from, e.g. a torn write
+    pub unreadable_bytes: u64,
+    // Where the last readable record of the data file ends
+    pub readable_len: u64,
+}
+
+// What `repair` found wrong with a store, before or after repairing it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairState {
+    pub entries: usize,
+    pub integrity_ok: bool,
+    // See `ConsistencyReport`
+    pub out_of_bounds: usize,
+    pub overlapping: usize,
+    // Ids the index points at an older record of, e.g. after losing the
+    // journal
+    pub lost_updates: usize,
+    // Records of ids missing from the index, which may be lost or deleted
+    pub unindexed: usize,
+    // Bytes after the last readable record, e.g. a torn append
+    pub torn_bytes: u64,
+}
+
+impl RepairState {
+    // Unindexed records aren't repaired, they can't be told apart from
+    // deleted entries. Neither is a failed integrity check on its own.
+    pub fn needs_repair(&self) -> bool {
+        self.out_of_bounds > 0
+            || self.overlapping > 0
+            || self.lost_updates > 0
+            || self.torn_bytes > 0
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    pub before: RepairState,
+    // None on a dry run or when nothing needed repairing
+    pub after: Option<RepairState>,
+    pub trimmed: Vec<String>,
+    // Trimming wasn't enough and the index was rebuilt from the data file
+    pub rebuilt: bool,
+    pub recovered: Vec<OrphanRecord>,
+    // The length the data file was cut to
+    pub truncated_to: Option<u64>,
+    // Copies of the files as they were before repairing
+    pub backups: Vec<String>,
+    pub dry_run: bool,
+}
+
+pub struct IndexedBinaryFileEntryStore<K: StoreKey = String> {
     data_file_path: String,
     index_file_path: String,
-    index: HashMap<String, Position>,
+    index: CompactIndex<K, Position>,
     needs_index_rewrite: bool,
     needs_data_rewrite: bool,
+    durability: Durability,
+    syncer: Syncer,
+    pending_writes: usize,
+    last_commit: Instant,
+    integrity: Option<FileSetMac>,
+    journal: IndexJournal,
+    secondary: Option<SecondaryIndexes<K>>,
+    consistency_repair: ConsistencyRepair,
+    shutdown_marker: bool,
+    // Of the data file as of the last `reload_index`
+    schema: SchemaHeader,
+    #[cfg(feature = "watch")]
+    watcher: Option<StoreWatcher>,
 }
 
-impl IndexedBinaryFileEntryStore {
+impl<K: StoreKey> IndexedBinaryFileEntryStore<K> {
     pub fn new(data_file_path: String, index_file_path: String) -> Self {
+        let mac_file_path = Self::mac_file_path(&index_file_path);
+        let secondary_file_path = Self::secondary_file_path(&index_file_path);
+        // The data file is only swapped in together with its index, and the
+        // index with the MAC of both, though either may be left out
+        let temp_data_file_path = Self::temp_file_path(&data_file_path);
+        let temp_index_file_path = Self::temp_file_path(&index_file_path);
+        let temp_mac_file_path = Self::temp_file_path(&mac_file_path);
+        if let Err(e) = recover_swap_all(&[
+            (&temp_data_file_path, &data_file_path),
+            (&temp_index_file_path, &index_file_path),
+            (&temp_mac_file_path, &mac_file_path),
+        ]) {
+            error!("Recovering {} failed! {}", temp_data_file_path, e);
+        }
+        let temp_secondary_file_path = Self::temp_file_path(&secondary_file_path);
+        if let Err(e) = recover_swap(&temp_secondary_file_path, &secondary_file_path) {
+            error!("Recovering {} failed! {}", temp_secondary_file_path, e);
+        }
+
         let check_files = (
             Self::file_exists(&data_file_path),
             Self::file_exists(&index_file_path),
@@ -77,291 +276,1514 @@ impl IndexedBinaryFileEntryStore {
             }
         }
 
+        Self::with_files(data_file_path, index_file_path)
+    }
+
+    // Opens an existing store only to read it. Unlike `open` nothing is
+    // created, recovered, repaired or migrated, the files are left as they
+    // are.
+    pub fn open_read_only(
+        data_file_path: String,
+        index_file_path: String,
+    ) -> Result<Self, StoreError> {
+        for file_path in [&data_file_path, &index_file_path] {
+            File::open(file_path)?;
+        }
+        let mut store = Self::with_files(data_file_path, index_file_path);
+        store.read_index()?;
+        Ok(store)
+    }
+
+    fn with_files(data_file_path: String, index_file_path: String) -> Self {
+        let journal = IndexJournal::new(
+            Self::journal_file_path(&index_file_path),
+            JOURNAL_RECORD_SIZE,
+        );
+        // Until it can be read nothing is trusted to be in the current layout
+        let schema = Self::read_schema(&data_file_path).unwrap_or_else(|e| {
+            error!("Reading the header of {} failed! {}", data_file_path, e);
+            SchemaHeader {
+                version: BASELINE_SCHEMA_VERSION,
+                len: 0,
+            }
+        });
+
         Self {
             data_file_path,
             index_file_path,
-            index: HashMap::new(),
+            index: CompactIndex::new(),
             needs_index_rewrite: false,
             needs_data_rewrite: false,
+            durability: Durability::default(),
+            syncer: Syncer::default(),
+            pending_writes: 0,
+            last_commit: Instant::now(),
+            integrity: None,
+            journal,
+            secondary: None,
+            consistency_repair: ConsistencyRepair::default(),
+            shutdown_marker: false,
+            schema,
+            #[cfg(feature = "watch")]
+            watcher: None,
         }
     }
 
-    fn file_exists(file_path: &str) -> bool {
-        let path = Path::new(file_path);
+    // Opens a store whose files are authenticated with `key`, failing if
+    // they were modified by someone who doesn't hold it.
+    pub fn open(
+        data_file_path: String,
+        index_file_path: String,
+        key: [u8; 32],
+    ) -> Result<Self, StoreError> {
+        Self::initialise_mac(&data_file_path, &index_file_path, key)?;
+        let mut store = Self::new(data_file_path, index_file_path).with_integrity_key(key);
+        store.recover_committed()?;
+        store.read_index()?;
+        Ok(store)
+    }
 
-        if path.exists() {
-            true
-        } else {
-            false
+    // Like `open`, timing every step. Without a key nothing is verified.
+    pub fn open_with_report(
+        data_file_path: String,
+        index_file_path: String,
+        key: Option<[u8; 32]>,
+    ) -> Result<(Self, OpenReport), StoreError> {
+        let started = Instant::now();
+        let mut report = OpenReport::default();
+
+        if let Some(key) = key {
+            Self::initialise_mac(&data_file_path, &index_file_path, key)?;
+        }
+        let mut store = Self::new(data_file_path, index_file_path);
+        report.recovery = started.elapsed();
+
+        if let Some(key) = key {
+            let step = Instant::now();
+            store = store.with_integrity_key(key);
+            store.recover_committed()?;
+            report.integrity_check = step.elapsed();
         }
+
+        store.read_index_timed(&mut report)?;
+        report.data_file_len = Path::new(&store.data_file_path).metadata()?.len();
+        report.index_file_len = Path::new(&store.index_file_path).metadata()?.len();
+        report.total = started.elapsed();
+        info!("Opened {} in {:?}", store.data_file_path, report.total);
+
+        Ok((store, report))
     }
 
-    pub fn reload_index(&mut self) {
-        match Self::load_index(&self.index_file_path) {
-            Ok(map) => self.index = map,
-            Err(e) => error!(
-                "Reloading index failed. Index file: {} - error: {}",
-                self.index_file_path, e
-            ),
+    // A new store gets the MAC of its empty files before they are created,
+    // so from then on a missing MAC means it was removed
+    fn initialise_mac(
+        data_file_path: &str,
+        index_file_path: &str,
+        key: [u8; 32],
+    ) -> Result<(), StoreError> {
+        let exists = [data_file_path, index_file_path].iter().any(|file_path| {
+            Self::file_exists(file_path) || Self::file_exists(&Self::temp_file_path(file_path))
+        });
+        if exists {
+            return Ok(());
         }
+        let journal_file_path = Self::journal_file_path(index_file_path);
+        CommittedMac::new(
+            &FileSetMac::new(key),
+            [
+                (data_file_path, 0),
+                (index_file_path, 0),
+                (&journal_file_path, 0),
+            ],
+        )?
+        .write(&Self::mac_file_path(index_file_path))
     }
 
-    pub fn rewrite_index(&mut self) -> Result<(), BinaryStoreError> {
-        let temp_index_file = format!("temp_{}", self.index_file_path);
+    pub fn with_integrity_key(mut self, key: [u8; 32]) -> Self {
+        self.integrity = Some(FileSetMac::new(key));
+        self
+    }
 
-        match Self::write_index(&temp_index_file, &self.index) {
-            Ok(_) => {
-                remove_file(&self.index_file_path)?;
-                rename(&temp_index_file, &self.index_file_path)?;
-                self.needs_index_rewrite = false;
-                Ok(())
-            }
-            Err(e) => {
-                error!("Writing index file failed!, {}", e);
-                Err(e)
-            }
-        }
+    // Takes effect with the next `reload_index`
+    pub fn with_consistency_repair(mut self, repair: ConsistencyRepair) -> Self {
+        self.consistency_repair = repair;
+        self
     }
 
-    pub fn needs_index_rewrite(&self) -> bool {
-        self.needs_index_rewrite
+    fn mac_file_path(index_file_path: &str) -> String {
+        format!("{}.mac", index_file_path)
     }
 
-    pub fn needs_data_rewrite(&self) -> bool {
-        self.needs_data_rewrite
+    fn marker_file_path(index_file_path: &str) -> String {
+        format!("{}.clean", index_file_path)
     }
 
-    fn write_index<P: AsRef<Path>>(
-        index_file: P,
-        index: &HashMap<String, Position>,
-    ) -> Result<(), BinaryStoreError> {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(index_file)?;
+    fn journal_file_path(index_file_path: &str) -> String {
+        format!("{}.journal", index_file_path)
+    }
+
+    fn secondary_file_path(index_file_path: &str) -> String {
+        format!("{}.secondary", index_file_path)
+    }
+
+    // Maintains url domain and username indexes next to the primary index.
+    // Takes effect with the next `reload_index`.
+    pub fn with_secondary_indexes(mut self) -> Self {
+        self.secondary = Some(SecondaryIndexes::default());
+        self
+    }
 
-        for (id, position) in index {
-            let serialized: &Vec<u8> = &bincode::serialize(&(id, position))?;
+    // Returns whether the indexes had to be rebuilt from the data file
+    fn read_secondary(&mut self) -> Result<bool, StoreError> {
+        if self.secondary.is_none() {
+            return Ok(false);
+        }
 
-            // Ensure the serialized data is exactly INDEX_RECORD_SIZE bytes
-            if serialized.len() > INDEX_RECORD_SIZE {
-                return Err(BinaryStoreError::IndexRecordTooLarge);
+        let secondary_file_path = Self::secondary_file_path(&self.index_file_path);
+        let mut rebuilt = false;
+        let secondary = match SecondaryIndexes::load(&secondary_file_path)? {
+            Some(secondary) if secondary.is_current(self.journal.len()) => secondary,
+            _ => {
+                rebuilt = true;
+                info!("Rebuilding secondary indexes for {}", self.data_file_path);
+                let mut secondary = SecondaryIndexes::default();
+                for (id, position) in self.index.iter() {
+                    secondary.insert(&id, &self.get(position)?);
+                }
+                secondary
             }
+        };
 
-            let mut record = vec![0; INDEX_RECORD_SIZE];
-            record[..serialized.len()].copy_from_slice(&serialized);
+        self.secondary = Some(secondary);
+        Ok(rebuilt)
+    }
 
-            file.write_all(&record)?;
+    fn write_secondary(&mut self) -> Result<(), StoreError> {
+        if let Some(secondary) = self.secondary.as_mut() {
+            let secondary_file_path = Self::secondary_file_path(&self.index_file_path);
+            let temp_file_path = Self::temp_file_path(&secondary_file_path);
+            secondary.write(
+                &secondary_file_path,
+                &temp_file_path,
+                self.journal.len(),
+                &self.syncer,
+            )?;
         }
-
         Ok(())
     }
 
-    fn load_index<P: AsRef<Path>>(
-        index_file: P,
-    ) -> Result<HashMap<String, Position>, BinaryStoreError> {
-        let file = OpenOptions::new().read(true).open(index_file)?;
+    fn lookup(
+        &self,
+        ids: Vec<K>,
+        matches: impl Fn(&Entry) -> bool,
+    ) -> Result<Vec<Entry>, StoreError> {
+        // The secondary indexes aren't loaded before migrating
+        self.schema.check()?;
+        let mut entries = Vec::new();
+        for id in ids {
+            // The secondary file isn't authenticated, so confirm each hit
+            if let Some(entry) = self.load(&id)?.filter(|entry| matches(entry)) {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
 
-        let mut result = HashMap::new();
+    // Entries whose url is on the same domain as `url`. Like the username
+    // lookup it backs autofill, so archived entries are left out.
+    pub fn entries_for_url(&self, url: &str) -> Result<Vec<Entry>, StoreError> {
+        let domain = domain_of(url);
+        let matches = |entry: &Entry| {
+            !entry.archived
+                && domain.is_some()
+                && entry.url.as_deref().and_then(domain_of) == domain
+        };
 
-        for record in BinaryIndexIterator::new(file, INDEX_RECORD_SIZE) {
-            let index = record?;
-            result.insert(index.id, index.position);
+        match &self.secondary {
+            Some(secondary) => self.lookup(secondary.ids_for_url(url), matches),
+            None => self.search(&filter_fn(matches)),
         }
+    }
 
-        Ok(result)
+    // Entries with `username`, ignoring case
+    pub fn entries_for_username(&self, username: &str) -> Result<Vec<Entry>, StoreError> {
+        let username = username.trim().to_lowercase();
+        let matches = |entry: &Entry| {
+            !entry.archived
+                && !username.is_empty()
+                && entry
+                    .username
+                    .as_deref()
+                    .map(|u| u.trim().to_lowercase())
+                    .as_ref()
+                    == Some(&username)
+        };
+
+        match &self.secondary {
+            Some(secondary) => self.lookup(secondary.ids_for_username(&username), matches),
+            None => self.search(&filter_fn(matches)),
+        }
     }
 
-    fn update_index_entry(&mut self, id: &String, position: Position) {
-        self.index.insert(id.to_string(), position);
-        self.needs_index_rewrite = true;
+    // Answers from the secondary indexes, or from ones built by a scan when
+    // they are turned off
+    fn with_secondary<T>(
+        &self,
+        answer: impl FnOnce(&SecondaryIndexes<K>) -> T,
+    ) -> Result<T, StoreError> {
+        self.schema.check()?;
+        if let Some(secondary) = &self.secondary {
+            return Ok(answer(secondary));
+        }
+
+        let mut secondary = SecondaryIndexes::default();
+        for (id, position) in self.index.iter() {
+            secondary.insert(&id, &self.get(position)?);
+        }
+        Ok(answer(&secondary))
     }
 
-    fn get(&self, position: &Position) -> Result<Entry, BinaryStoreError> {
-        let mut file = OpenOptions::new().read(true).open(&self.data_file_path)?;
+    // All tags with the number of entries that have each
+    pub fn tags(&self) -> Result<Vec<(String, usize)>, StoreError> {
+        self.with_secondary(SecondaryIndexes::tags)
+    }
 
-        file.seek(SeekFrom::Start(position.offset))?;
+    // Up to `limit` tags starting with `prefix`, for autocompletion
+    pub fn suggest_tags(&self, prefix: &str, limit: usize) -> Result<Vec<String>, StoreError> {
+        self.with_secondary(|secondary| secondary.suggest_tags(prefix, limit))
+    }
 
-        let mut buf = vec![0; position.length];
-        file.read_exact(&mut buf)?;
-        bincode::deserialize(&buf).map_err(|e| BinaryStoreError::from(e))
+    // The files the MAC covers, the journal only exists while it has records
+    fn authenticated_files(&self) -> Vec<&str> {
+        let mut files = vec![self.data_file_path.as_str(), self.index_file_path.as_str()];
+        if self.journal.exists() {
+            files.push(self.journal.file_path());
+        }
+        files
     }
 
-    fn write_data(&mut self) -> Result<(), BinaryStoreError> {
-        let temp_file = format!("temp_{}", self.data_file_path);
+    fn journal_len(&self) -> Result<u64, StoreError> {
+        if !self.journal.exists() {
+            return Ok(0);
+        }
+        Ok(Path::new(self.journal.file_path()).metadata()?.len())
+    }
 
-        let mut new_file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&temp_file)?;
+    // The MAC is refreshed by every commit and rewrite, so it covers the
+    // files as of the last of those, see `CommittedMac`.
+    pub fn verify_integrity(&self) -> Result<(), StoreError> {
+        self.verified_commit().map(|_| ())
+    }
 
-        let mut new_index: HashMap<String, Position> = HashMap::new();
+    fn verified_commit(&self) -> Result<Option<CommittedMac>, StoreError> {
+        let mac = match &self.integrity {
+            Some(mac) => mac,
+            None => return Ok(None),
+        };
+        let mac_file_path = Self::mac_file_path(&self.index_file_path);
 
-        for (key, pos) in &self.index {
-            let entry = self.get(pos)?;
-            let new_pos = Self::write_entry(&entry, &mut new_file)?;
-            new_index.insert(key.to_string(), new_pos);
+        // Stores opened with a key have their MAC from the start
+        let committed = match fs::read(&mac_file_path) {
+            Ok(bytes) => bincode::deserialize::<CommittedMac>(&bytes).ok(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let verified = match &committed {
+            // Only the data file and the journal are appended to
+            Some(committed) => {
+                Path::new(&self.index_file_path).metadata()?.len() == committed.index_len
+                    && mac.verify_prefixes(
+                        &[
+                            (self.data_file_path.as_str(), committed.data_len),
+                            (&self.index_file_path, committed.index_len),
+                            (self.journal.file_path(), committed.journal_len),
+                        ],
+                        &committed.tag,
+                    )?
+            }
+            None => false,
+        };
+
+        if verified {
+            Ok(committed)
+        } else {
+            error!("Integrity check failed for {}", self.data_file_path);
+            Err(StoreError::IntegrityMismatch)
         }
+    }
 
-        self.index = new_index;
+    // Verifies the files and cuts off the saves appended since the last
+    // commit, left by a process that died before committing them. The MAC
+    // doesn't cover them, so they can't be told apart from bytes appended by
+    // someone without the key.
+    fn recover_committed(&mut self) -> Result<(), StoreError> {
+        let committed = match self.verified_commit()? {
+            Some(committed) => committed,
+            None => return Ok(()),
+        };
 
-        remove_file(&self.data_file_path)?;
-        rename(&temp_file, &self.data_file_path)?;
+        let mut data_file = OpenOptions::new().write(true).open(&self.data_file_path)?;
+        if data_file.metadata()?.len() > committed.data_len {
+            warn!(
+                "Dropping saves to {} made after the last commit",
+                self.data_file_path
+            );
+            data_file.set_len(committed.data_len)?;
+            self.syncer.saved(&mut data_file)?;
+        }
+        self.journal.truncate(committed.journal_len, &self.syncer)
+    }
 
-        self.needs_data_rewrite = false;
+    // Writes the MAC of the files as they are now to its temp file, for
+    // swapping in together with them when `data_file_path` or
+    // `index_file_path` is a temp file too. The journal is covered up to
+    // `journal_len`.
+    fn write_temp_mac(
+        &self,
+        data_file_path: &str,
+        index_file_path: &str,
+        journal_len: u64,
+    ) -> Result<Option<String>, StoreError> {
+        let mac = match &self.integrity {
+            Some(mac) => mac,
+            None => return Ok(None),
+        };
+        let temp_mac_file = Self::temp_file_path(&Self::mac_file_path(&self.index_file_path));
+        CommittedMac::new(
+            mac,
+            [
+                (data_file_path, Path::new(data_file_path).metadata()?.len()),
+                (
+                    index_file_path,
+                    Path::new(index_file_path).metadata()?.len(),
+                ),
+                (self.journal.file_path(), journal_len),
+            ],
+        )?
+        .write(&temp_mac_file)?;
+        Ok(Some(temp_mac_file))
+    }
 
+    fn write_mac(&self) -> Result<(), StoreError> {
+        let journal_len = self.journal_len()?;
+        if let Some(temp_mac_file) =
+            self.write_temp_mac(&self.data_file_path, &self.index_file_path, journal_len)?
+        {
+            let mac_file_path = Self::mac_file_path(&self.index_file_path);
+            swap_in(&temp_mac_file, &mac_file_path)?;
+            self.syncer.renamed(&mac_file_path)?;
+        }
         Ok(())
     }
 
-    fn write_entry<W: Write + Seek>(
-        value: &Entry,
-        file: &mut W,
-    ) -> Result<Position, BinaryStoreError> {
-        // Serialize data
-        let serialized: &Vec<u8> = &bincode::serialize(value)?;
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
 
-        // Position
-        let offset = file.seek(SeekFrom::End(0))?;
-        let length = serialized.len();
-        let pos = Position { length, offset };
+    // What a commit does to the files, see `SyncLevel`
+    pub fn with_sync_level(mut self, level: SyncLevel) -> Self {
+        self.syncer = Syncer::new(level);
+        self
+    }
 
-        // Write data
-        file.write_all(&serialized)?;
+    pub fn sync_stats(&self) -> SyncStats {
+        self.syncer.stats()
+    }
 
-        Ok(pos)
+    // Watches the store files so writes from other processes are noticed by
+    // `reload_if_changed`
+    #[cfg(feature = "watch")]
+    pub fn with_watcher(mut self) -> Result<Self, StoreError> {
+        let mac_file_path = Self::mac_file_path(&self.index_file_path);
+        self.watcher = Some(StoreWatcher::new(&[
+            self.data_file_path.as_str(),
+            self.index_file_path.as_str(),
+            self.journal.file_path(),
+            mac_file_path.as_str(),
+        ])?);
+        Ok(self)
     }
-}
 
-impl DataStore<String, Entry, BinaryStoreError> for IndexedBinaryFileEntryStore {
-    fn save(&mut self, id: &String, value: &Entry) -> Result<(), BinaryStoreError> {
-        // Open file
-        let mut file = OpenOptions::new()
-            .write(true)
-            .append(true)
-            .open(&self.data_file_path)?;
+    // Reloads the index when the store files were changed externally, instead
+    // of serving entries from stale positions
+    #[cfg(feature = "watch")]
+    pub fn reload_if_changed(&mut self) -> Result<Option<StoreEvent>, StoreError> {
+        let event = match self.watcher.as_mut().and_then(StoreWatcher::check) {
+            Some(event) => event,
+            None => return Ok(None),
+        };
 
-        let pos = Self::write_entry(value, &mut file)?;
+        info!(
+            "Store {} changed externally, reloading",
+            self.data_file_path
+        );
+        self.verify_integrity()?;
+        self.read_index()?;
+        Ok(Some(event))
+    }
 
-        // Update index (not index file)
-        self.update_index_entry(id, pos);
+    // Brackets writes made by this store so the watcher doesn't report them
+    fn watched<T>(
+        &mut self,
+        write: impl FnOnce(&mut Self) -> Result<T, StoreError>,
+    ) -> Result<T, StoreError> {
+        #[cfg(feature = "watch")]
+        if let Some(watcher) = self.watcher.as_mut() {
+            watcher.begin_write();
+        }
 
-        Ok(())
-    }
+        let result = write(self);
 
-    fn load(&self, key: &String) -> Result<Option<Entry>, BinaryStoreError> {
-        match self.index.get(key) {
-            Some(pos) => self.get(pos).map(Some),
-            None => Ok(None),
+        #[cfg(feature = "watch")]
+        if let Some(watcher) = self.watcher.as_mut() {
+            watcher.end_write();
         }
+        result
     }
 
-    fn delete(&mut self, id: &String) -> Result<(), BinaryStoreError> {
-        self.index.remove(id);
-        self.needs_data_rewrite = true;
+    pub fn pending_writes(&self) -> usize {
+        self.pending_writes
+    }
 
-        Ok(())
+    // Persists the index in full when the store is dropped and leaves a
+    // marker saying so. The next open trusts an index with a marker that
+    // matches the files and skips the consistency check. Without one the
+    // last session crashed: the journal is replayed as always and whatever
+    // the data file can't back is trimmed, whatever `ConsistencyRepair`
+    // says. `rewrite_index` no longer has to be called before dropping.
+    pub fn with_shutdown_marker(mut self) -> Self {
+        self.shutdown_marker = true;
+        self
     }
 
-    fn search(
-        &self,
-        filter: &dyn super::data_store::Filter<Entry>,
-    ) -> Result<Vec<Entry>, BinaryStoreError> {
-        let mut file = OpenOptions::new().read(true).open(&self.data_file_path)?;
+    // Commits, merges the journal into the index file and writes the
+    // marker, as dropping a store `with_shutdown_marker` does. Writes made
+    // afterwards only go to the journal, which the next open replays.
+    pub fn shut_down(&mut self) -> Result<(), StoreError> {
+        self.commit()?;
+        self.rewrite_index()?;
 
-        // sort index entries
-        let mut sorted_index_entries: Vec<_> = self.index.iter().collect();
-        sorted_index_entries.sort_by_key(|(_, position)| position.offset);
+        let mut file = File::create(Self::marker_file_path(&self.index_file_path))?;
+        file.write_all(&bincode::serialize(&self.file_lengths()?)?)?;
+        self.syncer.saved(&mut file)?;
+        Ok(())
+    }
 
-        // result to return
-        let mut result: Vec<Entry> = vec![];
+    fn file_lengths(&self) -> Result<(u64, u64), StoreError> {
+        Ok((
+            Path::new(&self.data_file_path).metadata()?.len(),
+            Path::new(&self.index_file_path).metadata()?.len(),
+        ))
+    }
 
-        for (_, pos) in sorted_index_entries {
-            // Seek to the correct offset
-            file.seek(SeekFrom::Start(pos.offset))?;
+    // Whether the last session shut down cleanly and nothing changed the
+    // files since. The marker is removed either way, so a crash from here on
+    // is caught by the next open.
+    fn take_shutdown_marker(&self) -> Result<bool, StoreError> {
+        let marker_file_path = Self::marker_file_path(&self.index_file_path);
+        let Ok(bytes) = fs::read(&marker_file_path) else {
+            return Ok(false);
+        };
+        fs::remove_file(&marker_file_path)?;
+        let lengths: Option<(u64, u64)> = bincode::deserialize(&bytes).ok();
+        Ok(lengths == Some(self.file_lengths()?))
+    }
 
-            let mut buf = vec![0; pos.length];
-            file.read_exact(&mut buf)?;
-            let entry: Entry = bincode::deserialize(&buf)?;
+    // Syncs the data file and the index journal for all pending writes. The
+    // journal is merged into the index file once it grows large enough.
+    pub fn commit(&mut self) -> Result<(), StoreError> {
+        self.watched(Self::commit_pending)
+    }
 
-            if filter.pass(&entry) {
-                result.push(entry);
-            }
+    fn commit_pending(&mut self) -> Result<(), StoreError> {
+        if self.pending_writes > 0 {
+            self.syncer
+                .saved(&mut OpenOptions::new().write(true).open(&self.data_file_path)?)?;
+            self.journal.sync(&self.syncer)?;
+        }
+        if self.journal.len() >= JOURNAL_MERGE_THRESHOLD {
+            self.rewrite_index()?;
+        } else if self.pending_writes > 0 {
+            self.write_secondary()?;
+            self.write_mac()?;
         }
 
-        Ok(result)
+        self.pending_writes = 0;
+        self.last_commit = Instant::now();
+        Ok(())
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use crate::data::data_store::Filter;
 
-    use super::*;
-    use std::fs::{self, File};
-    use std::io::{self, Read, Write};
-    use std::path::Path;
+    fn write_committed(&mut self) -> Result<(), StoreError> {
+        self.pending_writes += 1;
 
-    // Helper function to create a temporary file and return its path
-    fn create_temp_file(file_path: &str) -> io::Result<()> {
-        let mut file = File::create(file_path)?;
-        file.write_all(b"")?; // Create an empty file
-        Ok(())
+        match self.durability {
+            Durability::Manual => Ok(()),
+            Durability::GroupCommit(interval) if self.last_commit.elapsed() < interval => Ok(()),
+            Durability::GroupCommit(_) | Durability::Immediate => self.commit(),
+        }
     }
 
-    // Helper function to clean up temporary files after tests
-    fn cleanup_temp_file(file_path: &str) {
-        if Path::new(file_path).exists() {
-            fs::remove_file(file_path).unwrap();
+    fn file_exists(file_path: &str) -> bool {
+        let path = Path::new(file_path);
+
+        if path.exists() {
+            true
+        } else {
+            false
         }
     }
 
-    #[test]
-    fn test_save_new_entry() {
-        // Create temporary files for data and index
-        let data_file_path = "test_data1.bin";
-        let index_file_path = "test_index1.bin";
+    fn temp_file_path(file_path: &str) -> String {
+        format!("{}.tmp", file_path)
+    }
 
-        create_temp_file(data_file_path).unwrap();
-        create_temp_file(index_file_path).unwrap();
+    fn read_index(&mut self) -> Result<(), StoreError> {
+        self.read_index_timed(&mut OpenReport::default())
+    }
 
-        // Initialize the store
-        let mut store = IndexedBinaryFileEntryStore::new(
-            data_file_path.to_string(),
-            index_file_path.to_string(),
-        );
+    fn read_schema(data_file_path: &str) -> Result<SchemaHeader, StoreError> {
+        SchemaHeader::read(&mut File::open(data_file_path)?)
+    }
 
-        // Create a test entry
-        let entry = Entry {
-            id: "test_id".to_string(),
-            title: "Test Title".to_string(),
-            username: Some("test_user".to_string()),
-            password: Some("test_password".to_string()),
-            url: Some("https://example.com".to_string()),
-            note: Some("This is a test entry".to_string()),
-        };
+    fn read_index_timed(&mut self, report: &mut OpenReport) -> Result<(), StoreError> {
+        self.schema = Self::read_schema(&self.data_file_path)?;
+        report.clean_shutdown = self.shutdown_marker && self.take_shutdown_marker()?;
 
-        // Save the entry
-        let id = entry.id.clone();
-        store.save(&id, &entry).unwrap();
+        let step = Instant::now();
+        let mut index = Self::load_index(&self.index_file_path)?;
+        report.index_load = step.elapsed();
 
-        // Verify that the index was updated
-        assert!(store.index.contains_key(&id));
-        let position = store.index.get(&id).unwrap();
-        assert_eq!(position.length, bincode::serialize(&entry).unwrap().len());
+        let step = Instant::now();
+        self.journal.replay(&mut index)?;
+        report.journal_replay = step.elapsed();
 
-        // Verify that the data file contains the serialized entry
-        let data_file_content = fs::read(&data_file_path).unwrap();
-        let serialized_entry = bincode::serialize(&entry).unwrap();
-        assert_eq!(data_file_content, serialized_entry);
+        self.index = index;
+        self.needs_index_rewrite = !self.journal.is_empty();
+        report.journal_records = self.journal.len();
 
-        // Clean up temporary files
-        cleanup_temp_file(&data_file_path);
-        cleanup_temp_file(&index_file_path);
-    }
+        // Left for `migrate`, the entries can't be read until then
+        if let Err(e) = self.schema.check() {
+            warn!("{}: {}", self.data_file_path, e);
+            report.entries = self.index.len();
+            return Ok(());
+        }
 
-    #[test]
+        // Before anything reads records through the index
+        let repair = match self.consistency_repair {
+            _ if report.clean_shutdown => None,
+            _ if self.shutdown_marker => Some(ConsistencyRepair::Trim),
+            repair => Some(repair),
+        };
+        let consistency = match repair {
+            None => ConsistencyReport {
+                data_file_len: Path::new(&self.data_file_path).metadata()?.len(),
+                out_of_bounds: Vec::new(),
+                overlapping: Vec::new(),
+                trimmed: Vec::new(),
+            },
+            Some(ConsistencyRepair::Report) => self.check_consistency()?,
+            Some(ConsistencyRepair::Trim) => {
+                // The secondary indexes aren't loaded yet, see `migrate_data`
+                let secondary = self.secondary.take();
+                let trimmed = self.trim_index();
+                self.secondary = secondary;
+                trimmed?
+            }
+        };
+        if !consistency.is_consistent() {
+            warn!(
+                "Index of {} has {} positions past the end of the data file and {} overlapping, {} trimmed",
+                self.data_file_path,
+                consistency.out_of_bounds.len(),
+                consistency.overlapping.len(),
+                consistency.trimmed.len()
+            );
+        }
+        report.out_of_bounds = consistency.out_of_bounds.len();
+        report.overlapping = consistency.overlapping.len();
+        report.trimmed = consistency.trimmed.len();
+        report.entries = self.index.len();
+
+        let step = Instant::now();
+        report.secondary_rebuilt = self.read_secondary()?;
+        report.secondary_load = step.elapsed();
+        Ok(())
+    }
+
+    pub fn reload_index(&mut self) {
+        match self.read_index() {
+            Ok(_) => (),
+            Err(e) => error!(
+                "Reloading index failed. Index file: {} - error: {}",
+                self.index_file_path, e
+            ),
+        }
+    }
+
+    pub fn rewrite_index(&mut self) -> Result<(), StoreError> {
+        self.watched(Self::merge_index)
+    }
+
+    fn merge_index(&mut self) -> Result<(), StoreError> {
+        self.merge_journal()?;
+        self.write_secondary()
+    }
+
+    // The journal left until it is cleared is past what the new MAC covers
+    fn merge_journal(&mut self) -> Result<(), StoreError> {
+        let temp_index_file = Self::temp_file_path(&self.index_file_path);
+
+        match Self::write_index(&temp_index_file, &self.index, &self.syncer) {
+            Ok(_) => {
+                let mac_file_path = Self::mac_file_path(&self.index_file_path);
+                let temp_mac_file =
+                    self.write_temp_mac(&self.data_file_path, &temp_index_file, 0)?;
+                let mut pairs = vec![(temp_index_file.as_str(), self.index_file_path.as_str())];
+                pairs.extend(
+                    temp_mac_file
+                        .as_deref()
+                        .map(|temp| (temp, mac_file_path.as_str())),
+                );
+                swap_in_all(&pairs)?;
+                self.syncer.renamed(&self.index_file_path)?;
+                if temp_mac_file.is_some() {
+                    self.syncer.renamed(&mac_file_path)?;
+                }
+                self.journal.clear()?;
+                self.needs_index_rewrite = false;
+                Ok(())
+            }
+            Err(e) => {
+                error!("Writing index file failed!, {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    // Swaps in a rewritten data file, in the current layout, together with
+    // the index of it, so the index never points into the other data file.
+    // Journal records are positions in the old data file, so the journal is
+    // merged beforehand.
+    fn replace_data(
+        &mut self,
+        temp_data_file: &str,
+        new_index: CompactIndex<K, Position>,
+    ) -> Result<(), StoreError> {
+        if self.journal.exists() {
+            self.merge_journal()?;
+        }
+        let temp_index_file = Self::temp_file_path(&self.index_file_path);
+        Self::write_index(&temp_index_file, &new_index, &self.syncer)?;
+        let mac_file_path = Self::mac_file_path(&self.index_file_path);
+        let temp_mac_file = self.write_temp_mac(temp_data_file, &temp_index_file, 0)?;
+
+        let mut pairs = vec![
+            (temp_data_file, self.data_file_path.as_str()),
+            (&temp_index_file, &self.index_file_path),
+        ];
+        pairs.extend(
+            temp_mac_file
+                .as_deref()
+                .map(|temp| (temp, mac_file_path.as_str())),
+        );
+        swap_in_all(&pairs)?;
+        self.syncer.renamed(&self.data_file_path)?;
+        self.syncer.renamed(&self.index_file_path)?;
+        if temp_mac_file.is_some() {
+            self.syncer.renamed(&mac_file_path)?;
+        }
+        self.index = new_index;
+        self.schema = Self::read_schema(&self.data_file_path)?;
+        self.needs_data_rewrite = false;
+        self.needs_index_rewrite = false;
+        Ok(())
+    }
+
+    // Checks every indexed record lies within the data file and that no two
+    // of them overlap
+    pub fn check_consistency(&self) -> Result<ConsistencyReport<K>, StoreError> {
+        let data_file_len = Path::new(&self.data_file_path).metadata()?.len();
+        let mut positions: Vec<_> = self.index.iter().collect();
+        positions.sort_by_key(|(_, position)| position.offset);
+
+        let mut report = ConsistencyReport {
+            data_file_len,
+            out_of_bounds: Vec::new(),
+            overlapping: Vec::new(),
+            trimmed: Vec::new(),
+        };
+        // The record reaching furthest so far, which any later one overlaps
+        // if it starts before that end
+        let mut furthest: Option<(K, u64)> = None;
+        for (id, position) in positions {
+            let end = position.offset + position.length as u64;
+            if end > data_file_len {
+                report.out_of_bounds.push(id.clone());
+            }
+            match &furthest {
+                Some((other, other_end)) if position.offset < *other_end => {
+                    report.overlapping.push((other.clone(), id.clone()));
+                    if end > *other_end {
+                        furthest = Some((id, end));
+                    }
+                }
+                _ => furthest = Some((id, end)),
+            }
+        }
+
+        Ok(report)
+    }
+
+    // Checks what saves, overwrites and deletes must keep true: the index
+    // is consistent and the secondary indexes hold exactly the entries it
+    // points at. Returns what is wrong otherwise.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn check_invariants(&self) -> Result<(), String> {
+        let consistency = self.check_consistency().map_err(|e| e.to_string())?;
+        if !consistency.is_consistent() {
+            return Err(format!(
+                "{} index positions are out of bounds and {} overlap",
+                consistency.out_of_bounds.len(),
+                consistency.overlapping.len()
+            ));
+        }
+
+        if let Some(secondary) = &self.secondary {
+            let mut expected = SecondaryIndexes::default();
+            for (id, position) in self.index.iter() {
+                expected.insert(&id, &self.get(position).map_err(|e| e.to_string())?);
+            }
+            if !secondary.same_entries(&expected) {
+                return Err("the secondary indexes differ from the entries".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    // Drops the entries `check_consistency` finds fault with from the index:
+    // those past the end of the data file, and of overlapping ones those
+    // that don't read back as an entry. The data file isn't changed.
+    pub fn trim_inconsistent(&mut self) -> Result<ConsistencyReport<K>, StoreError> {
+        self.watched(Self::trim_index)
+    }
+
+    fn trim_index(&mut self) -> Result<ConsistencyReport<K>, StoreError> {
+        let mut report = self.check_consistency()?;
+        if report.is_consistent() {
+            return Ok(report);
+        }
+
+        let mut file = OpenOptions::new().read(true).open(&self.data_file_path)?;
+        let mut trimmed = report.out_of_bounds.clone();
+        for (first, second) in &report.overlapping {
+            for id in [first, second] {
+                if trimmed.contains(id) {
+                    continue;
+                }
+                let Some(position) = self.index.get(id) else {
+                    continue;
+                };
+                let readable = Self::read_bytes(&mut file, position)
+                    .ok()
+                    .and_then(|bytes| bincode::deserialize::<Entry>(&bytes).ok())
+                    .is_some();
+                if !readable {
+                    trimmed.push(id.clone());
+                }
+            }
+        }
+        if trimmed.is_empty() {
+            return Ok(report);
+        }
+
+        for id in &trimmed {
+            self.index.remove(id);
+            if let Some(secondary) = self.secondary.as_mut() {
+                secondary.remove(id);
+            }
+        }
+        // The trimmed records may still take up space in the data file
+        self.needs_data_rewrite = true;
+        self.merge_index()?;
+        info!(
+            "Trimmed {} entries from the index of {}",
+            trimmed.len(),
+            self.data_file_path
+        );
+
+        report.trimmed = trimmed;
+        Ok(report)
+    }
+
+    pub fn needs_index_rewrite(&self) -> bool {
+        self.needs_index_rewrite
+    }
+
+    pub fn needs_data_rewrite(&self) -> bool {
+        self.needs_data_rewrite
+    }
+
+    fn write_index<P: AsRef<Path>>(
+        index_file: P,
+        index: &CompactIndex<K, Position>,
+        syncer: &Syncer,
+    ) -> Result<(), StoreError> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(index_file)?;
+
+        for (id, position) in index.iter() {
+            file.write_all(&encode_record(&id, position, INDEX_RECORD_SIZE)?)?;
+        }
+
+        syncer.rewritten(&mut file)?;
+        Ok(())
+    }
+
+    fn load_index<P: AsRef<Path>>(index_file: P) -> Result<CompactIndex<K, Position>, StoreError> {
+        let file = OpenOptions::new().read(true).open(index_file)?;
+
+        let mut result = CompactIndex::new();
+
+        for record in BinaryIndexIterator::new(file, INDEX_RECORD_SIZE) {
+            let (id, position) = record?;
+            result.insert(id, position);
+        }
+
+        Ok(result)
+    }
+
+    // Every entry with the key it is saved under, in key order
+    pub fn entries(&self) -> Result<Vec<(K, Entry)>, StoreError> {
+        self.schema.check()?;
+        let mut file = OpenOptions::new().read(true).open(&self.data_file_path)?;
+        let mut positions: Vec<_> = self.index.iter().collect();
+        positions.sort_by_key(|(_, position)| position.offset);
+
+        let mut entries = Vec::with_capacity(positions.len());
+        for (key, position) in positions {
+            let bytes = Self::read_bytes(&mut file, position)?;
+            entries.push((key, bincode::deserialize(&bytes)?));
+        }
+        entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(entries)
+    }
+
+    fn update_index_entry(&mut self, id: &K, position: Position) {
+        self.index.insert(id.clone(), position);
+        self.needs_index_rewrite = true;
+    }
+
+    fn get(&self, position: &Position) -> Result<Entry, StoreError> {
+        self.schema.check()?;
+        let mut file = OpenOptions::new().read(true).open(&self.data_file_path)?;
+
+        file.seek(SeekFrom::Start(position.offset))?;
+
+        let mut buf = vec![0; position.length];
+        file.read_exact(&mut buf)?;
+        bincode::deserialize(&buf).map_err(StoreError::from)
+    }
+
+    fn read_bytes(file: &mut File, position: &Position) -> Result<Vec<u8>, StoreError> {
+        file.seek(SeekFrom::Start(position.offset))?;
+        let mut buf = vec![0; position.length];
+        file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    // Upgrades entries written with an older layout of `Entry`, after backing
+    // up the store files. A dry run only reports what would be migrated.
+    // Nothing migrates a store on its own, until this is called a store in
+    // an older layout fails with `MigrationRequired`.
+    pub fn migrate(
+        &mut self,
+        registry: &MigrationRegistry,
+        dry_run: bool,
+    ) -> Result<MigrationReport, StoreError> {
+        self.watched(|store| store.migrate_data(registry, dry_run))
+    }
+
+    fn migrate_data(
+        &mut self,
+        registry: &MigrationRegistry,
+        dry_run: bool,
+    ) -> Result<MigrationReport, StoreError> {
+        let mut file = OpenOptions::new().read(true).open(&self.data_file_path)?;
+        let mut positions: Vec<_> = self.index.iter().collect();
+        positions.sort_by_key(|(_, position)| position.offset);
+
+        let mut report = MigrationReport::new(dry_run);
+        let mut upgraded = Vec::with_capacity(positions.len());
+        for (key, position) in positions {
+            let bytes = Self::read_bytes(&mut file, position)?;
+            let entry = registry.upgrade(self.schema.version, &bytes)?;
+            report.add(&entry);
+            upgraded.push((key, entry.bytes));
+        }
+
+        if dry_run || report.migrated() == 0 {
+            return Ok(report);
+        }
+        let mac_file_path = Self::mac_file_path(&self.index_file_path);
+        let mut files = self.authenticated_files();
+        if Self::file_exists(&mac_file_path) {
+            files.push(&mac_file_path);
+        }
+        report.back_up(&files)?;
+
+        let temp_file = Self::temp_file_path(&self.data_file_path);
+        let mut new_file = File::create(&temp_file)?;
+        SchemaHeader::write_current(&mut new_file)?;
+        let mut new_index = CompactIndex::new();
+        let mut offset = SCHEMA_HEADER_LEN;
+        for (key, bytes) in upgraded {
+            new_file.write_all(&bytes)?;
+            let length = bytes.len();
+            new_index.insert(key, Position { offset, length });
+            offset += length as u64;
+        }
+        self.syncer.rewritten(&mut new_file)?;
+        self.replace_data(&temp_file, new_index)?;
+        // Opening skipped them, as the entries couldn't be read yet
+        self.read_secondary()?;
+
+        Ok(report)
+    }
+
+    pub fn write_data(&mut self) -> Result<(), StoreError> {
+        self.watched(|store| store.compact_data(&mut |_, _| {}))
+    }
+
+    fn compact_data(&mut self, progress: &mut dyn FnMut(usize, usize)) -> Result<(), StoreError> {
+        let temp_file = Self::temp_file_path(&self.data_file_path);
+
+        let mut new_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&temp_file)?;
+        SchemaHeader::write_current(&mut new_file)?;
+
+        let mut new_index = CompactIndex::new();
+
+        for (written, (key, pos)) in self.index.iter().enumerate() {
+            let entry = self.get(pos)?;
+            let new_pos = Self::write_entry(&entry, &mut new_file)?;
+            new_index.insert(key, new_pos);
+            progress(written + 1, self.index.len());
+        }
+
+        self.syncer.rewritten(&mut new_file)?;
+        self.replace_data(&temp_file, new_index)?;
+        self.write_secondary()
+    }
+
+    fn append_entry(&mut self, id: &K, value: &Entry) -> Result<(), StoreError> {
+        // Before anything is written, so a long id doesn't leave a record
+        // behind that nothing indexes
+        let position = Some(Position {
+            offset: 0,
+            length: 0,
+        });
+        if let Err(StoreError::IndexRecordTooLarge) =
+            encode_record(id, &position, JOURNAL_RECORD_SIZE)
+        {
+            return Err(StoreError::IdTooLong);
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&self.data_file_path)?;
+        self.schema = SchemaHeader::for_append(&mut file)?;
+
+        let pos = Self::write_entry(value, &mut file)?;
+
+        // Update index (journal only, not index file)
+        self.journal.append(id, Some(&pos))?;
+        self.update_index_entry(id, pos);
+        if let Some(secondary) = self.secondary.as_mut() {
+            secondary.insert(id, value);
+        }
+
+        self.write_committed()
+    }
+
+    fn remove_entry(&mut self, id: &K) -> Result<(), StoreError> {
+        self.schema.check()?;
+        if self.index.remove(id).is_some() {
+            self.journal.append(id, None::<&Position>)?;
+            self.needs_index_rewrite = true;
+            if let Some(secondary) = self.secondary.as_mut() {
+                secondary.remove(id);
+            }
+        }
+        self.needs_data_rewrite = true;
+
+        self.write_committed()
+    }
+
+    fn write_entry<W: Write + Seek>(value: &Entry, file: &mut W) -> Result<Position, StoreError> {
+        // Serialize data
+        let serialized: &Vec<u8> = &bincode::serialize(value)?;
+
+        // Position
+        let offset = file.seek(SeekFrom::End(0))?;
+        let length = serialized.len();
+        let pos = Position { length, offset };
+
+        // Write data
+        file.write_all(&serialized)?;
+
+        Ok(pos)
+    }
+}
+
+// Orphans are re-added under the id embedded in the entry, so this needs a
+// store keyed by those ids
+impl IndexedBinaryFileEntryStore<String> {
+    // Scans the data file for records missing from the index, e.g. after
+    // the journal was lost. Entries deleted since the data file was last
+    // rewritten are still in it and can't be told apart from lost ones, so
+    // look at what this finds before calling `recover_orphans`.
+    pub fn find_orphans(&self) -> Result<OrphanReport, StoreError> {
+        let (report, latest) = self.scan_records()?;
+        Ok(self.with_orphans(report, &latest))
+    }
+
+    fn with_orphans(
+        &self,
+        mut report: OrphanReport,
+        latest: &HashMap<String, Position>,
+    ) -> OrphanReport {
+        for (id, position) in latest {
+            let newer_than_indexed = match self.index.get(id) {
+                None => false,
+                Some(indexed) if indexed.offset < position.offset => true,
+                Some(_) => continue,
+            };
+            report.orphans.push(OrphanRecord {
+                id: id.clone(),
+                offset: position.offset,
+                length: position.length,
+                newer_than_indexed,
+            });
+        }
+        report.orphans.sort_by_key(|orphan| orphan.offset);
+
+        report
+    }
+
+    // Reads the data file record by record, returning the latest position
+    // of every id in it
+    fn scan_records(&self) -> Result<(OrphanReport, HashMap<String, Position>), StoreError> {
+        let data = fs::read(&self.data_file_path)?;
+        let header = SchemaHeader::read(&mut &data[..])?;
+        header.check()?;
+        let mut report = OrphanReport {
+            readable_len: header.len,
+            ..OrphanReport::default()
+        };
+        let mut latest: HashMap<String, Position> = HashMap::new();
+
+        let mut offset = header.len as usize;
+        while offset < data.len() {
+            let mut rest = &data[offset..];
+            // Past a torn record the bytes read as lengths can be anything,
+            // so nothing may be allocated beyond what is left of the file
+            let decoded = bincode::DefaultOptions::new()
+                .with_fixint_encoding()
+                .allow_trailing_bytes()
+                .with_limit(rest.len() as u64)
+                .deserialize_from::<_, Entry>(&mut rest);
+            match decoded {
+                Ok(entry) => {
+                    let length = data.len() - offset - rest.len();
+                    let position = Position {
+                        offset: offset as u64,
+                        length,
+                    };
+                    report.records_scanned += 1;
+                    latest.insert(entry.id, position);
+                    offset += length;
+                    report.readable_len = offset as u64;
+                }
+                // Resynchronize on the next byte a record can be read from
+                Err(_) => {
+                    report.unreadable_bytes += 1;
+                    offset += 1;
+                }
+            }
+        }
+
+        Ok((report, latest))
+    }
+
+    // Replaces the index with one built from the data file alone, for when
+    // `check_consistency` finds more wrong than trimming would fix. Like
+    // `recover_orphans` it brings back entries deleted since the data file
+    // was last rewritten.
+    pub fn rebuild_index(&mut self) -> Result<OrphanReport, StoreError> {
+        let (report, latest) = self.scan_records()?;
+        let report = self.with_orphans(report, &latest);
+
+        self.watched(|store| {
+            store.index = latest.into_iter().collect();
+            if store.secondary.is_some() {
+                let mut secondary = SecondaryIndexes::default();
+                for (id, position) in store.index.iter() {
+                    secondary.insert(&id, &store.get(position)?);
+                }
+                store.secondary = Some(secondary);
+            }
+            store.merge_index()
+        })?;
+        info!(
+            "Rebuilt the index of {} with {} entries",
+            self.data_file_path,
+            self.index.len()
+        );
+
+        Ok(report)
+    }
+
+    // Points the index at every orphan found by `find_orphans` and writes it
+    // out in full. The data file isn't changed.
+    pub fn recover_orphans(&mut self) -> Result<OrphanReport, StoreError> {
+        let report = self.find_orphans()?;
+        if report.orphans.is_empty() {
+            return Ok(report);
+        }
+
+        self.watched(|store| {
+            for orphan in &report.orphans {
+                let position = Position {
+                    offset: orphan.offset,
+                    length: orphan.length,
+                };
+                let entry = store.get(&position)?;
+                if let Some(secondary) = store.secondary.as_mut() {
+                    secondary.insert(&orphan.id, &entry);
+                }
+                store.update_index_entry(&orphan.id, position);
+            }
+            store.merge_index()
+        })?;
+        info!(
+            "Recovered {} orphaned records of {}",
+            report.orphans.len(),
+            self.data_file_path
+        );
+
+        Ok(report)
+    }
+
+    fn repair_state(&self) -> Result<RepairState, StoreError> {
+        let integrity_ok = match self.verify_integrity() {
+            Ok(()) => true,
+            Err(StoreError::IntegrityMismatch) => false,
+            Err(e) => return Err(e),
+        };
+        let consistency = self.check_consistency()?;
+        let orphans = self.find_orphans()?;
+        let lost_updates = orphans
+            .orphans
+            .iter()
+            .filter(|orphan| orphan.newer_than_indexed)
+            .count();
+
+        Ok(RepairState {
+            entries: self.index.len(),
+            integrity_ok,
+            out_of_bounds: consistency.out_of_bounds.len(),
+            overlapping: consistency.overlapping.len(),
+            lost_updates,
+            unindexed: orphans.orphans.len() - lost_updates,
+            torn_bytes: consistency.data_file_len - orphans.readable_len,
+        })
+    }
+
+    // Checks the store and fixes what it can in one go, after backing up
+    // its files next to themselves as `{file}.repair-{millis}.bak`:
+    // index positions the data file can't back are trimmed, or the index
+    // is rebuilt when that isn't enough (bringing back entries deleted
+    // since the data file was rewritten), lost updates are recovered and a
+    // torn record at the end of the data file is cut off. Repairing
+    // authenticates the files again, so check `before.integrity_ok` first
+    // when the store has an integrity key. A dry run only reports.
+    pub fn repair(&mut self, dry_run: bool) -> Result<RepairReport, StoreError> {
+        let before = self.repair_state()?;
+        let mut report = RepairReport {
+            before,
+            dry_run,
+            ..RepairReport::default()
+        };
+        if dry_run || !report.before.needs_repair() {
+            return Ok(report);
+        }
+
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis());
+        let mac_file_path = Self::mac_file_path(&self.index_file_path);
+        let mut files: Vec<String> = self
+            .authenticated_files()
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        if Self::file_exists(&mac_file_path) {
+            files.push(mac_file_path);
+        }
+        for file in files {
+            let backup = format!("{}.repair-{}.bak", file, millis);
+            fs::copy(&file, &backup)?;
+            report.backups.push(backup);
+        }
+
+        report.trimmed = self.trim_inconsistent()?.trimmed;
+        if !self.check_consistency()?.is_consistent() {
+            self.rebuild_index()?;
+            report.rebuilt = true;
+        }
+
+        let lost: Vec<OrphanRecord> = self
+            .find_orphans()?
+            .orphans
+            .into_iter()
+            .filter(|orphan| orphan.newer_than_indexed)
+            .collect();
+        if !lost.is_empty() {
+            self.watched(|store| {
+                for orphan in &lost {
+                    let position = Position {
+                        offset: orphan.offset,
+                        length: orphan.length,
+                    };
+                    let entry = store.get(&position)?;
+                    if let Some(secondary) = store.secondary.as_mut() {
+                        secondary.insert(&orphan.id, &entry);
+                    }
+                    store.update_index_entry(&orphan.id, position);
+                }
+                store.merge_index()
+            })?;
+        }
+        report.recovered = lost;
+
+        // Only cut when the index points at nothing past the readable records
+        let readable_len = self.scan_records()?.0.readable_len;
+        let indexed_len = self
+            .index
+            .values()
+            .map(|position| position.offset + position.length as u64)
+            .max()
+            .unwrap_or(0);
+        let data_file_len = Path::new(&self.data_file_path).metadata()?.len();
+        if readable_len < data_file_len && indexed_len <= readable_len {
+            self.watched(|store| {
+                let mut file = OpenOptions::new().write(true).open(&store.data_file_path)?;
+                file.set_len(readable_len)?;
+                store.syncer.saved(&mut file)?;
+                store.merge_index()
+            })?;
+            report.truncated_to = Some(readable_len);
+        }
+
+        info!(
+            "Repaired {}: trimmed {}, recovered {}{}",
+            self.data_file_path,
+            report.trimmed.len(),
+            report.recovered.len(),
+            if report.rebuilt {
+                ", rebuilt the index"
+            } else {
+                ""
+            }
+        );
+        report.after = Some(self.repair_state()?);
+        Ok(report)
+    }
+}
+
+impl<K: StoreKey> DataStore<K, Entry, StoreError> for IndexedBinaryFileEntryStore<K> {
+    fn save(&mut self, id: &K, value: &Entry) -> Result<(), StoreError> {
+        self.watched(|store| store.append_entry(id, value))
+    }
+
+    fn load(&self, key: &K) -> Result<Option<Entry>, StoreError> {
+        match self.index.get(key) {
+            Some(pos) => self.get(pos).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn delete(&mut self, id: &K) -> Result<(), StoreError> {
+        self.watched(|store| store.remove_entry(id))
+    }
+
+    fn search(
+        &self,
+        filter: &dyn super::data_store::Filter<Entry>,
+    ) -> Result<Vec<Entry>, StoreError> {
+        self.schema.check()?;
+        let mut file = OpenOptions::new().read(true).open(&self.data_file_path)?;
+
+        // sort index entries
+        let mut sorted_index_entries: Vec<_> = self.index.iter().collect();
+        sorted_index_entries.sort_by_key(|(_, position)| position.offset);
+
+        // result to return
+        let mut result: Vec<(K, Entry)> = vec![];
+
+        for (id, pos) in sorted_index_entries {
+            // Seek to the correct offset
+            file.seek(SeekFrom::Start(pos.offset))?;
+
+            let mut buf = vec![0; pos.length];
+            file.read_exact(&mut buf)?;
+            let entry: Entry = bincode::deserialize(&buf)?;
+
+            if filter.pass(&entry) {
+                result.push((id, entry));
+            }
+        }
+
+        // Read in file order, returned in id order like the other stores
+        result.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(result.into_iter().map(|(_, entry)| entry).collect())
+    }
+}
+
+impl<K: StoreKey> Compactable for IndexedBinaryFileEntryStore<K> {
+    fn fragmentation(&self) -> Result<f32, StoreError> {
+        let live_bytes = self
+            .index
+            .values()
+            .map(|position| position.length as u64)
+            .sum();
+        let total_bytes = Path::new(&self.data_file_path).metadata()?.len();
+        let total_bytes = total_bytes.saturating_sub(self.schema.len);
+        Ok(fragmentation_of(live_bytes, total_bytes))
+    }
+
+    fn compact_with_progress(
+        &mut self,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<CompactionStats, StoreError> {
+        let started = Instant::now();
+        let bytes_before = Path::new(&self.data_file_path).metadata()?.len();
+        self.watched(|store| store.compact_data(progress))?;
+
+        Ok(CompactionStats {
+            bytes_before,
+            bytes_after: Path::new(&self.data_file_path).metadata()?.len(),
+            entries: self.index.len(),
+            duration: started.elapsed(),
+        })
+    }
+}
+
+impl<K: StoreKey> Drop for IndexedBinaryFileEntryStore<K> {
+    fn drop(&mut self) {
+        if self.shutdown_marker {
+            if let Err(e) = self.shut_down() {
+                error!("Shutting down {} failed! {}", self.data_file_path, e);
+            }
+            return;
+        }
+        // Don't lose the tail of a group commit
+        if self.durability != Durability::Manual && self.pending_writes > 0 {
+            if let Err(e) = self.commit() {
+                error!("Committing pending writes failed! {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data::data_store::Filter;
+
+    use super::*;
+    use std::fs::{self, File};
+    use std::io::{self, Read, Write};
+    use std::path::Path;
+    use std::time::Duration;
+
+    // Helper function to create a temporary file and return its path
+    fn create_temp_file(file_path: &str) -> io::Result<()> {
+        let mut file = File::create(file_path)?;
+        file.write_all(b"")?; // Create an empty file
+        Ok(())
+    }
+
+    // Helper function to clean up temporary files after tests
+    fn cleanup_temp_file(file_path: &str) {
+        if Path::new(file_path).exists() {
+            fs::remove_file(file_path).unwrap();
+        }
+
+        for suffix in ["journal", "secondary"] {
+            let extra_file_path = format!("{}.{}", file_path, suffix);
+            if Path::new(&extra_file_path).exists() {
+                fs::remove_file(extra_file_path).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_save_new_entry() {
+        // Create temporary files for data and index
+        let data_file_path = "test_data1.bin";
+        let index_file_path = "test_index1.bin";
+
+        create_temp_file(data_file_path).unwrap();
+        create_temp_file(index_file_path).unwrap();
+
+        // Initialize the store
+        let mut store = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+
+        // Create a test entry
+        let entry = Entry {
+            id: "test_id".to_string(),
+            title: "Test Title".to_string(),
+            username: Some("test_user".to_string()),
+            password: Some("test_password".to_string()),
+            url: Some("https://example.com".to_string()),
+            note: Some("This is a test entry".to_string()),
+            ..Default::default()
+        };
+
+        // Save the entry
+        let id = entry.id.clone();
+        store.save(&id, &entry).unwrap();
+
+        // Verify that the index was updated
+        assert!(store.index.contains_key(&id));
+        let position = store.index.get(&id).unwrap();
+        assert_eq!(position.length, bincode::serialize(&entry).unwrap().len());
+
+        // Verify that the data file contains the serialized entry
+        let data_file_content = fs::read(&data_file_path).unwrap();
+        let serialized_entry = bincode::serialize(&entry).unwrap();
+        assert_eq!(
+            &data_file_content[SCHEMA_HEADER_LEN as usize..],
+            serialized_entry
+        );
+
+        // Clean up temporary files
+        cleanup_temp_file(&data_file_path);
+        cleanup_temp_file(&index_file_path);
+    }
+
+    #[test]
     fn test_save_multiple_entries() {
         // Create temporary files for data and index
         let data_file_path = "test_data2.bin";
@@ -370,13 +1792,472 @@ mod tests {
         create_temp_file(data_file_path).unwrap();
         create_temp_file(index_file_path).unwrap();
 
-        // Initialize the store
+        // Initialize the store
+        let mut store = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+
+        // Create and save multiple entries
+        let entry1 = Entry {
+            id: "id1".to_string(),
+            title: "First Entry".to_string(),
+            username: Some("user1".to_string()),
+            password: Some("password1".to_string()),
+            url: Some("https://example.com/1".to_string()),
+            note: Some("First test entry".to_string()),
+            ..Default::default()
+        };
+        let id1 = entry1.id.clone();
+        store.save(&id1, &entry1).unwrap();
+
+        let entry2 = Entry {
+            id: "id2".to_string(),
+            title: "Second Entry".to_string(),
+            username: Some("user2".to_string()),
+            password: Some("password2".to_string()),
+            url: Some("https://example.com/2".to_string()),
+            note: Some("Second test entry".to_string()),
+            ..Default::default()
+        };
+        let id2 = entry2.id.clone();
+        store.save(&id2, &entry2).unwrap();
+
+        // Verify that the index contains both entries
+        assert!(store.index.contains_key(&id1));
+        assert!(store.index.contains_key(&id2));
+
+        // Verify that the data file contains both serialized entries
+        let data_file_content = fs::read(&data_file_path).unwrap();
+        let serialized_entry1 = bincode::serialize(&entry1).unwrap();
+        let serialized_entry2 = bincode::serialize(&entry2).unwrap();
+
+        assert!(data_file_content[SCHEMA_HEADER_LEN as usize..].starts_with(&serialized_entry1));
+        assert!(data_file_content.ends_with(&serialized_entry2));
+
+        // Clean up temporary files
+        cleanup_temp_file(&data_file_path);
+        cleanup_temp_file(&index_file_path);
+    }
+
+    #[test]
+    fn test_save_and_persist_index() {
+        // Create temporary files for data and index
+        let data_file_path = "test_data3.bin";
+        let index_file_path = "test_index3.bin";
+
+        create_temp_file(data_file_path).unwrap();
+        create_temp_file(index_file_path).unwrap();
+
+        // Initialize the store
+        let mut store = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+
+        // Create and save an entry
+        let entry = Entry {
+            id: "test_id".to_string(),
+            title: "Test Title".to_string(),
+            username: Some("test_user".to_string()),
+            password: Some("test_password".to_string()),
+            url: Some("https://example.com".to_string()),
+            note: Some("This is a test entry".to_string()),
+            ..Default::default()
+        };
+        let id = entry.id.clone();
+        store.save(&id, &entry).unwrap();
+
+        // Persist the index to the index file
+        store.rewrite_index().unwrap();
+
+        // Verify that the index file contains the updated index
+        let index_content = fs::read(&index_file_path).unwrap();
+        assert!(!index_content.is_empty()); // Ensure the index file is not empty
+
+        // Clean up temporary files
+        cleanup_temp_file(&data_file_path);
+        cleanup_temp_file(&index_file_path);
+    }
+
+    #[test]
+    fn test_uuid_ids_persist_in_index() {
+        let data_file_path = "test_uuid_ids_data.bin";
+        let index_file_path = "test_uuid_ids_index.bin";
+        create_temp_file(data_file_path).unwrap();
+        create_temp_file(index_file_path).unwrap();
+
+        let mut store: IndexedBinaryFileEntryStore = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+        let ids = [uuid::Uuid::new_v4().to_string(), "short".to_string()];
+        for id in &ids {
+            let entry = Entry {
+                id: id.clone(),
+                title: "Test Title".to_string(),
+                ..Default::default()
+            };
+            store.save(id, &entry).unwrap();
+        }
+        // A uuid id as a string didn't fit in an index record
+        store.rewrite_index().unwrap();
+        assert_eq!(
+            fs::metadata(index_file_path).unwrap().len(),
+            2 * INDEX_RECORD_SIZE as u64
+        );
+
+        store.reload_index();
+        for id in &ids {
+            assert_eq!(store.load(id).unwrap().unwrap().id, *id);
+        }
+
+        drop(store);
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
+    }
+
+    #[test]
+    fn test_save_existing_entry_updates_index() {
+        // Create temporary files for data and index
+        let data_file_path = "test_data4.bin";
+        let index_file_path = "test_index4.bin";
+
+        create_temp_file(data_file_path).unwrap();
+        create_temp_file(index_file_path).unwrap();
+
+        // Initialize the store
+        let mut store = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+
+        // Create and save an initial entry
+        let entry1 = Entry {
+            id: "test_id".to_string(),
+            title: "Initial Title".to_string(),
+            username: Some("initial_user".to_string()),
+            password: Some("initial_password".to_string()),
+            url: Some("https://example.com/initial".to_string()),
+            note: Some("Initial test entry".to_string()),
+            ..Default::default()
+        };
+        let id = entry1.id.clone();
+        store.save(&id, &entry1).unwrap();
+
+        // Save a new entry with the same ID (should overwrite the index entry)
+        let entry2 = Entry {
+            id: "test_id".to_string(),
+            title: "Updated Title".to_string(),
+            username: Some("updated_user".to_string()),
+            password: Some("updated_password".to_string()),
+            url: Some("https://example.com/updated".to_string()),
+            note: Some("Updated test entry".to_string()),
+            ..Default::default()
+        };
+        store.save(&id, &entry2).unwrap();
+
+        // Verify that the index was updated with the new position
+        let position = store.index.get(&id).unwrap();
+        assert_eq!(position.length, bincode::serialize(&entry2).unwrap().len());
+
+        // Verify that the data file contains the new serialized entry
+        let mut file = OpenOptions::new().read(true).open(data_file_path).unwrap();
+        file.seek(SeekFrom::Start(position.offset)).unwrap();
+        let mut data_file_content = vec![0; position.length as usize];
+        file.read_exact(&mut data_file_content).unwrap();
+        let serialized_entry2 = bincode::serialize(&entry2).unwrap();
+        assert_eq!(data_file_content, serialized_entry2);
+
+        // Clean up temporary files
+        cleanup_temp_file(&data_file_path);
+        cleanup_temp_file(&index_file_path);
+    }
+
+    // --- new tests
+
+    #[test]
+    fn test_load_non_existent_entry() {
+        let data_file_path = "test_data5.bin";
+        let index_file_path = "test_index5.bin";
+
+        create_temp_file(data_file_path).unwrap();
+        create_temp_file(index_file_path).unwrap();
+
+        let mut store = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+
+        let non_existent_id = "non_existent_id".to_string();
+        let result = store.load(&non_existent_id).unwrap();
+
+        assert!(result.is_none());
+
+        cleanup_temp_file(&data_file_path);
+        cleanup_temp_file(&index_file_path);
+    }
+
+    #[test]
+    fn test_delete_non_existent_entry() {
+        let data_file_path = "test_data6.bin";
+        let index_file_path = "test_index6.bin";
+
+        create_temp_file(data_file_path).unwrap();
+        create_temp_file(index_file_path).unwrap();
+
+        let mut store = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+
+        let non_existent_id = "non_existent_id".to_string();
+        store.delete(&non_existent_id).unwrap();
+
+        assert!(!store.index.contains_key(&non_existent_id));
+
+        cleanup_temp_file(&data_file_path);
+        cleanup_temp_file(&index_file_path);
+    }
+
+    #[test]
+    fn test_rewrite_data_file_after_deletion() {
+        let data_file_path = "test_data7.bin";
+        let index_file_path = "test_index7.bin";
+
+        create_temp_file(data_file_path).unwrap();
+        create_temp_file(index_file_path).unwrap();
+
+        let mut store = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+
+        let entry = Entry {
+            id: "test_id".to_string(),
+            title: "Test Title".to_string(),
+            username: Some("test_user".to_string()),
+            password: Some("test_password".to_string()),
+            url: Some("https://example.com".to_string()),
+            note: Some("This is a test entry".to_string()),
+            ..Default::default()
+        };
+        let id = entry.id.clone();
+        store.save(&id, &entry).unwrap();
+
+        store.delete(&id).unwrap();
+        store.write_data().unwrap();
+
+        // Only the header is left
+        let data_file_content = fs::read(&data_file_path).unwrap();
+        assert_eq!(data_file_content.len() as u64, SCHEMA_HEADER_LEN);
+
+        cleanup_temp_file(&data_file_path);
+        cleanup_temp_file(&index_file_path);
+    }
+
+    #[test]
+    fn test_reload_index() {
+        let data_file_path = "test_data8.bin";
+        let index_file_path = "test_index8.bin";
+
+        create_temp_file(data_file_path).unwrap();
+        create_temp_file(index_file_path).unwrap();
+
+        let mut store = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+
+        let entry = Entry {
+            id: "test_id".to_string(),
+            title: "Test Title".to_string(),
+            username: Some("test_user".to_string()),
+            password: Some("test_password".to_string()),
+            url: Some("https://example.com".to_string()),
+            note: Some("This is a test entry".to_string()),
+            ..Default::default()
+        };
+        let id = &entry.id;
+        store.save(id, &entry).unwrap();
+
+        store.rewrite_index().unwrap();
+        store.reload_index();
+
+        assert!(store.index.contains_key(id));
+
+        cleanup_temp_file(&data_file_path);
+        cleanup_temp_file(&index_file_path);
+    }
+
+    #[test]
+    fn test_error_handling_for_file_operations() {
+        let data_file_path = "test_data9.bin";
+        let index_file_path = "test_index9.bin";
+
+        create_temp_file(data_file_path).unwrap();
+        create_temp_file(index_file_path).unwrap();
+
+        let mut store = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+
+        // Simulate a file operation error by deleting the data file
+        fs::remove_file(&data_file_path).unwrap();
+
+        let entry = Entry {
+            id: "test_id".to_string(),
+            title: "Test Title".to_string(),
+            username: Some("test_user".to_string()),
+            password: Some("test_password".to_string()),
+            url: Some("https://example.com".to_string()),
+            note: Some("This is a test entry".to_string()),
+            ..Default::default()
+        };
+        let id = entry.id.clone();
+
+        let result = store.save(&id, &entry);
+        assert!(result.is_err());
+
+        cleanup_temp_file(&data_file_path);
+        cleanup_temp_file(&index_file_path);
+    }
+
+    #[test]
+    fn test_save_entry_with_existing_id() {
+        let data_file_path = "test_data10.bin";
+        let index_file_path = "test_index10.bin";
+
+        create_temp_file(data_file_path).unwrap();
+        create_temp_file(index_file_path).unwrap();
+
+        let mut store = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+
+        let entry1 = Entry {
+            id: "test_id".to_string(),
+            title: "Initial Title".to_string(),
+            username: Some("initial_user".to_string()),
+            password: Some("initial_password".to_string()),
+            url: Some("https://example.com/initial".to_string()),
+            note: Some("Initial test entry".to_string()),
+            ..Default::default()
+        };
+        let id = entry1.id.clone();
+        store.save(&id, &entry1).unwrap();
+
+        let entry2 = Entry {
+            id: "test_id".to_string(),
+            title: "Updated Title".to_string(),
+            username: Some("updated_user".to_string()),
+            password: Some("updated_password".to_string()),
+            url: Some("https://example.com/updated".to_string()),
+            note: Some("Updated test entry".to_string()),
+            ..Default::default()
+        };
+        store.save(&id, &entry2).unwrap();
+
+        let loaded_entry = store.load(&id).unwrap().unwrap();
+        assert_eq!(loaded_entry.title, "Updated Title");
+
+        cleanup_temp_file(&data_file_path);
+        cleanup_temp_file(&index_file_path);
+    }
+
+    // Search tests
+
+    // Define a filter that matches all entries
+    struct MatchAllFilter;
+    impl Filter<Entry> for MatchAllFilter {
+        fn pass(&self, _: &Entry) -> bool {
+            true
+        }
+    }
+
+    // Define a filter that matches no entries
+    struct MatchNoneFilter;
+    impl Filter<Entry> for MatchNoneFilter {
+        fn pass(&self, _: &Entry) -> bool {
+            false
+        }
+    }
+
+    // Define a filter that matches entries with a specific title
+    struct TitleFilter {
+        title: String,
+    }
+    impl Filter<Entry> for TitleFilter {
+        fn pass(&self, entry: &Entry) -> bool {
+            entry.title == self.title
+        }
+    }
+
+    #[test]
+    fn test_search_match_all() {
+        let data_file_path = "test_search_match_all_data.bin";
+        let index_file_path = "test_search_match_all_index.bin";
+
+        create_temp_file(data_file_path).unwrap();
+        create_temp_file(index_file_path).unwrap();
+
+        let mut store = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+
+        // Add some entries
+        let entry1 = Entry {
+            id: "id1".to_string(),
+            title: "First Entry".to_string(),
+            username: Some("user1".to_string()),
+            password: Some("password1".to_string()),
+            url: Some("https://example.com/1".to_string()),
+            note: Some("First test entry".to_string()),
+            ..Default::default()
+        };
+        let entry2 = Entry {
+            id: "id2".to_string(),
+            title: "Second Entry".to_string(),
+            username: Some("user2".to_string()),
+            password: Some("password2".to_string()),
+            url: Some("https://example.com/2".to_string()),
+            note: Some("Second test entry".to_string()),
+            ..Default::default()
+        };
+
+        store.save(&entry1.id, &entry1).unwrap();
+        store.save(&entry2.id, &entry2).unwrap();
+
+        // Search for entries with a filter that matches all
+        let filter = MatchAllFilter;
+        let results = store.search(&filter).unwrap();
+
+        // Verify the results
+        assert_eq!(results.len(), 2);
+        assert!(results.contains(&entry1));
+        assert!(results.contains(&entry2));
+
+        cleanup_temp_file(&data_file_path);
+        cleanup_temp_file(&index_file_path);
+    }
+
+    #[test]
+    fn test_search_match_none() {
+        let data_file_path = "test_search_match_none_data.bin";
+        let index_file_path = "test_search_match_none_index.bin";
+
+        create_temp_file(data_file_path).unwrap();
+        create_temp_file(index_file_path).unwrap();
+
         let mut store = IndexedBinaryFileEntryStore::new(
             data_file_path.to_string(),
             index_file_path.to_string(),
         );
 
-        // Create and save multiple entries
+        // Add some entries
         let entry1 = Entry {
             id: "id1".to_string(),
             title: "First Entry".to_string(),
@@ -384,10 +2265,8 @@ mod tests {
             password: Some("password1".to_string()),
             url: Some("https://example.com/1".to_string()),
             note: Some("First test entry".to_string()),
+            ..Default::default()
         };
-        let id1 = entry1.id.clone();
-        store.save(&id1, &entry1).unwrap();
-
         let entry2 = Entry {
             id: "id2".to_string(),
             title: "Second Entry".to_string(),
@@ -395,127 +2274,134 @@ mod tests {
             password: Some("password2".to_string()),
             url: Some("https://example.com/2".to_string()),
             note: Some("Second test entry".to_string()),
+            ..Default::default()
         };
-        let id2 = entry2.id.clone();
-        store.save(&id2, &entry2).unwrap();
 
-        // Verify that the index contains both entries
-        assert!(store.index.contains_key(&id1));
-        assert!(store.index.contains_key(&id2));
+        store.save(&entry1.id, &entry1).unwrap();
+        store.save(&entry2.id, &entry2).unwrap();
 
-        // Verify that the data file contains both serialized entries
-        let data_file_content = fs::read(&data_file_path).unwrap();
-        let serialized_entry1 = bincode::serialize(&entry1).unwrap();
-        let serialized_entry2 = bincode::serialize(&entry2).unwrap();
+        // Search for entries with a filter that matches none
+        let filter = MatchNoneFilter;
+        let results = store.search(&filter).unwrap();
 
-        assert!(data_file_content.starts_with(&serialized_entry1));
-        assert!(data_file_content.ends_with(&serialized_entry2));
+        // Verify the results
+        assert!(results.is_empty());
 
-        // Clean up temporary files
         cleanup_temp_file(&data_file_path);
         cleanup_temp_file(&index_file_path);
     }
 
     #[test]
-    fn test_save_and_persist_index() {
-        // Create temporary files for data and index
-        let data_file_path = "test_data3.bin";
-        let index_file_path = "test_index3.bin";
+    fn test_search_match_specific_title() {
+        let data_file_path = "test_search_match_specific_title_data.bin";
+        let index_file_path = "test_search_match_specific_title_index.bin";
 
         create_temp_file(data_file_path).unwrap();
         create_temp_file(index_file_path).unwrap();
 
-        // Initialize the store
         let mut store = IndexedBinaryFileEntryStore::new(
             data_file_path.to_string(),
             index_file_path.to_string(),
         );
 
-        // Create and save an entry
-        let entry = Entry {
-            id: "test_id".to_string(),
-            title: "Test Title".to_string(),
-            username: Some("test_user".to_string()),
-            password: Some("test_password".to_string()),
-            url: Some("https://example.com".to_string()),
-            note: Some("This is a test entry".to_string()),
+        // Add some entries
+        let entry1 = Entry {
+            id: "id1".to_string(),
+            title: "First Entry".to_string(),
+            username: Some("user1".to_string()),
+            password: Some("password1".to_string()),
+            url: Some("https://example.com/1".to_string()),
+            note: Some("First test entry".to_string()),
+            ..Default::default()
+        };
+        let entry2 = Entry {
+            id: "id2".to_string(),
+            title: "Second Entry".to_string(),
+            username: Some("user2".to_string()),
+            password: Some("password2".to_string()),
+            url: Some("https://example.com/2".to_string()),
+            note: Some("Second test entry".to_string()),
+            ..Default::default()
         };
-        let id = entry.id.clone();
-        store.save(&id, &entry).unwrap();
 
-        // Persist the index to the index file
-        store.rewrite_index().unwrap();
+        store.save(&entry1.id, &entry1).unwrap();
+        store.save(&entry2.id, &entry2).unwrap();
 
-        // Verify that the index file contains the updated index
-        let index_content = fs::read(&index_file_path).unwrap();
-        assert!(!index_content.is_empty()); // Ensure the index file is not empty
+        // Search for entries with a specific title
+        let filter = TitleFilter {
+            title: "First Entry".to_string(),
+        };
+        let results = store.search(&filter).unwrap();
+
+        // Verify the results
+        assert_eq!(results.len(), 1);
+        assert!(results.contains(&entry1));
 
-        // Clean up temporary files
         cleanup_temp_file(&data_file_path);
         cleanup_temp_file(&index_file_path);
     }
 
     #[test]
-    fn test_save_existing_entry_updates_index() {
-        // Create temporary files for data and index
-        let data_file_path = "test_data4.bin";
-        let index_file_path = "test_index4.bin";
+    fn test_search_empty_store() {
+        let data_file_path = "test_search_empty_store_data.bin";
+        let index_file_path = "test_search_empty_store_index.bin";
 
         create_temp_file(data_file_path).unwrap();
         create_temp_file(index_file_path).unwrap();
 
-        // Initialize the store
-        let mut store = IndexedBinaryFileEntryStore::new(
+        let store: IndexedBinaryFileEntryStore = IndexedBinaryFileEntryStore::new(
             data_file_path.to_string(),
             index_file_path.to_string(),
         );
 
-        // Create and save an initial entry
-        let entry1 = Entry {
-            id: "test_id".to_string(),
-            title: "Initial Title".to_string(),
-            username: Some("initial_user".to_string()),
-            password: Some("initial_password".to_string()),
-            url: Some("https://example.com/initial".to_string()),
-            note: Some("Initial test entry".to_string()),
-        };
-        let id = entry1.id.clone();
-        store.save(&id, &entry1).unwrap();
+        // Search for entries in an empty store
+        let filter = MatchAllFilter;
+        let results = store.search(&filter).unwrap();
 
-        // Save a new entry with the same ID (should overwrite the index entry)
-        let entry2 = Entry {
+        // Verify the results
+        assert!(results.is_empty());
+
+        cleanup_temp_file(&data_file_path);
+        cleanup_temp_file(&index_file_path);
+    }
+
+    #[test]
+    fn test_needs_index_rewrite_after_save() {
+        let data_file_path = "test_needs_index_rewrite_after_save_data.bin";
+        let index_file_path = "test_needs_index_rewrite_after_save_index.bin";
+
+        create_temp_file(data_file_path).unwrap();
+        create_temp_file(index_file_path).unwrap();
+
+        let mut store = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+
+        let entry = Entry {
             id: "test_id".to_string(),
-            title: "Updated Title".to_string(),
-            username: Some("updated_user".to_string()),
-            password: Some("updated_password".to_string()),
-            url: Some("https://example.com/updated".to_string()),
-            note: Some("Updated test entry".to_string()),
+            title: "Test Title".to_string(),
+            username: Some("test_user".to_string()),
+            password: Some("test_password".to_string()),
+            url: Some("https://example.com".to_string()),
+            note: Some("This is a test entry".to_string()),
+            ..Default::default()
         };
-        store.save(&id, &entry2).unwrap();
 
-        // Verify that the index was updated with the new position
-        let position = store.index.get(&id).unwrap();
-        assert_eq!(position.length, bincode::serialize(&entry2).unwrap().len());
+        // Save the entry
+        store.save(&entry.id, &entry).unwrap();
 
-        // Verify that the data file contains the new serialized entry
-        let mut file = OpenOptions::new().read(true).open(data_file_path).unwrap();
-        file.seek(SeekFrom::Start(position.offset)).unwrap();
-        let mut data_file_content = vec![0; position.length as usize];
-        file.read_exact(&mut data_file_content).unwrap();
-        let serialized_entry2 = bincode::serialize(&entry2).unwrap();
-        assert_eq!(data_file_content, serialized_entry2);
+        // Verify that the index rewrite flag is set
+        assert!(store.needs_index_rewrite());
 
-        // Clean up temporary files
         cleanup_temp_file(&data_file_path);
         cleanup_temp_file(&index_file_path);
     }
 
-    // --- new tests
-
     #[test]
-    fn test_load_non_existent_entry() {
-        let data_file_path = "test_data5.bin";
-        let index_file_path = "test_index5.bin";
+    fn test_needs_index_rewrite_cleared_after_rewrite() {
+        let data_file_path = "test_needs_index_rewrite_cleared_after_rewrite_data.bin";
+        let index_file_path = "test_needs_index_rewrite_cleared_after_rewrite_index.bin";
 
         create_temp_file(data_file_path).unwrap();
         create_temp_file(index_file_path).unwrap();
@@ -525,19 +2411,33 @@ mod tests {
             index_file_path.to_string(),
         );
 
-        let non_existent_id = "non_existent_id".to_string();
-        let result = store.load(&non_existent_id).unwrap();
+        let entry = Entry {
+            id: "test_id".to_string(),
+            title: "Test Title".to_string(),
+            username: Some("test_user".to_string()),
+            password: Some("test_password".to_string()),
+            url: Some("https://example.com".to_string()),
+            note: Some("This is a test entry".to_string()),
+            ..Default::default()
+        };
 
-        assert!(result.is_none());
+        // Save the entry (sets needs_index_rewrite to true)
+        store.save(&entry.id, &entry).unwrap();
+
+        // Rewrite the index (clears needs_index_rewrite)
+        store.rewrite_index().unwrap();
+
+        // Verify that the index rewrite flag is cleared
+        assert!(!store.needs_index_rewrite());
 
         cleanup_temp_file(&data_file_path);
         cleanup_temp_file(&index_file_path);
     }
 
     #[test]
-    fn test_delete_non_existent_entry() {
-        let data_file_path = "test_data6.bin";
-        let index_file_path = "test_index6.bin";
+    fn test_needs_data_rewrite_after_delete() {
+        let data_file_path = "test_needs_data_rewrite_after_delete_data.bin";
+        let index_file_path = "test_needs_data_rewrite_after_delete_index.bin";
 
         create_temp_file(data_file_path).unwrap();
         create_temp_file(index_file_path).unwrap();
@@ -547,19 +2447,33 @@ mod tests {
             index_file_path.to_string(),
         );
 
-        let non_existent_id = "non_existent_id".to_string();
-        store.delete(&non_existent_id).unwrap();
+        let entry = Entry {
+            id: "test_id".to_string(),
+            title: "Test Title".to_string(),
+            username: Some("test_user".to_string()),
+            password: Some("test_password".to_string()),
+            url: Some("https://example.com".to_string()),
+            note: Some("This is a test entry".to_string()),
+            ..Default::default()
+        };
 
-        assert!(!store.index.contains_key(&non_existent_id));
+        // Save the entry
+        store.save(&entry.id, &entry).unwrap();
+
+        // Delete the entry
+        store.delete(&entry.id).unwrap();
+
+        // Verify that the data rewrite flag is set
+        assert!(store.needs_data_rewrite());
 
         cleanup_temp_file(&data_file_path);
         cleanup_temp_file(&index_file_path);
     }
 
     #[test]
-    fn test_rewrite_data_file_after_deletion() {
-        let data_file_path = "test_data7.bin";
-        let index_file_path = "test_index7.bin";
+    fn test_needs_data_rewrite_cleared_after_rewrite() {
+        let data_file_path = "test_needs_data_rewrite_cleared_after_rewrite_data.bin";
+        let index_file_path = "test_needs_data_rewrite_cleared_after_rewrite_index.bin";
 
         create_temp_file(data_file_path).unwrap();
         create_temp_file(index_file_path).unwrap();
@@ -576,24 +2490,49 @@ mod tests {
             password: Some("test_password".to_string()),
             url: Some("https://example.com".to_string()),
             note: Some("This is a test entry".to_string()),
+            ..Default::default()
         };
-        let id = entry.id.clone();
-        store.save(&id, &entry).unwrap();
 
-        store.delete(&id).unwrap();
+        // Save the entry
+        store.save(&entry.id, &entry).unwrap();
+
+        // Delete the entry (sets needs_data_rewrite to true)
+        store.delete(&entry.id).unwrap();
+
+        // Rewrite the data file (clears needs_data_rewrite)
         store.write_data().unwrap();
 
-        let data_file_content = fs::read(&data_file_path).unwrap();
-        assert!(data_file_content.is_empty());
+        // Verify that the data rewrite flag is cleared
+        assert!(!store.needs_data_rewrite());
 
         cleanup_temp_file(&data_file_path);
         cleanup_temp_file(&index_file_path);
     }
 
     #[test]
-    fn test_reload_index() {
-        let data_file_path = "test_data8.bin";
-        let index_file_path = "test_index8.bin";
+    fn test_needs_index_rewrite_unchanged_without_modifications() {
+        let data_file_path = "test_needs_index_rewrite_unchanged_data.bin";
+        let index_file_path = "test_needs_index_rewrite_unchanged_index.bin";
+
+        create_temp_file(data_file_path).unwrap();
+        create_temp_file(index_file_path).unwrap();
+
+        let store: IndexedBinaryFileEntryStore = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+
+        // Verify that the index rewrite flag is initially false
+        assert!(!store.needs_index_rewrite());
+
+        cleanup_temp_file(&data_file_path);
+        cleanup_temp_file(&index_file_path);
+    }
+
+    #[test]
+    fn test_needs_data_rewrite_unchanged_without_deletions() {
+        let data_file_path = "test_needs_data_rewrite_unchanged_data.bin";
+        let index_file_path = "test_needs_data_rewrite_unchanged_index.bin";
 
         create_temp_file(data_file_path).unwrap();
         create_temp_file(index_file_path).unwrap();
@@ -610,23 +2549,31 @@ mod tests {
             password: Some("test_password".to_string()),
             url: Some("https://example.com".to_string()),
             note: Some("This is a test entry".to_string()),
+            ..Default::default()
         };
-        let id = &entry.id;
-        store.save(id, &entry).unwrap();
 
-        store.rewrite_index().unwrap();
-        store.reload_index();
+        // Save the entry
+        store.save(&entry.id, &entry).unwrap();
 
-        assert!(store.index.contains_key(id));
+        // Verify that the data rewrite flag is still false (no deletions)
+        assert!(!store.needs_data_rewrite());
 
         cleanup_temp_file(&data_file_path);
         cleanup_temp_file(&index_file_path);
     }
 
+    fn durability_test_entry(id: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: "Test Title".to_string(),
+            ..Default::default()
+        }
+    }
+
     #[test]
-    fn test_error_handling_for_file_operations() {
-        let data_file_path = "test_data9.bin";
-        let index_file_path = "test_index9.bin";
+    fn test_immediate_durability_persists_entry_on_save() {
+        let data_file_path = "test_immediate_durability_data.bin";
+        let index_file_path = "test_immediate_durability_index.bin";
 
         create_temp_file(data_file_path).unwrap();
         create_temp_file(index_file_path).unwrap();
@@ -634,32 +2581,31 @@ mod tests {
         let mut store = IndexedBinaryFileEntryStore::new(
             data_file_path.to_string(),
             index_file_path.to_string(),
-        );
+        )
+        .with_durability(Durability::Immediate);
 
-        // Simulate a file operation error by deleting the data file
-        fs::remove_file(&data_file_path).unwrap();
+        let entry = durability_test_entry("test_id");
+        store.save(&entry.id, &entry).unwrap();
 
-        let entry = Entry {
-            id: "test_id".to_string(),
-            title: "Test Title".to_string(),
-            username: Some("test_user".to_string()),
-            password: Some("test_password".to_string()),
-            url: Some("https://example.com".to_string()),
-            note: Some("This is a test entry".to_string()),
-        };
-        let id = entry.id.clone();
+        assert_eq!(store.pending_writes(), 0);
 
-        let result = store.save(&id, &entry);
-        assert!(result.is_err());
+        // A fresh store finds the entry through the journal
+        let mut reopened = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+        reopened.reload_index();
+        assert_eq!(reopened.load(&entry.id).unwrap(), Some(entry));
 
-        cleanup_temp_file(&data_file_path);
-        cleanup_temp_file(&index_file_path);
+        drop(store);
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
     }
 
     #[test]
-    fn test_save_entry_with_existing_id() {
-        let data_file_path = "test_data10.bin";
-        let index_file_path = "test_index10.bin";
+    fn test_group_commit_batches_saves() {
+        let data_file_path = "test_group_commit_data.bin";
+        let index_file_path = "test_group_commit_index.bin";
 
         create_temp_file(data_file_path).unwrap();
         create_temp_file(index_file_path).unwrap();
@@ -667,160 +2613,272 @@ mod tests {
         let mut store = IndexedBinaryFileEntryStore::new(
             data_file_path.to_string(),
             index_file_path.to_string(),
-        );
+        )
+        .with_durability(Durability::GroupCommit(Duration::from_secs(3600)));
 
-        let entry1 = Entry {
-            id: "test_id".to_string(),
-            title: "Initial Title".to_string(),
-            username: Some("initial_user".to_string()),
-            password: Some("initial_password".to_string()),
-            url: Some("https://example.com/initial".to_string()),
-            note: Some("Initial test entry".to_string()),
-        };
-        let id = entry1.id.clone();
-        store.save(&id, &entry1).unwrap();
+        for id in ["id1", "id2", "id3"] {
+            let entry = durability_test_entry(id);
+            store.save(&entry.id, &entry).unwrap();
+        }
 
-        let entry2 = Entry {
-            id: "test_id".to_string(),
-            title: "Updated Title".to_string(),
-            username: Some("updated_user".to_string()),
-            password: Some("updated_password".to_string()),
-            url: Some("https://example.com/updated".to_string()),
-            note: Some("Updated test entry".to_string()),
-        };
-        store.save(&id, &entry2).unwrap();
+        // Nothing is committed until the interval passes
+        assert_eq!(store.pending_writes(), 3);
+        assert!(store.needs_index_rewrite());
+        assert!(fs::read(index_file_path).unwrap().is_empty());
 
-        let loaded_entry = store.load(&id).unwrap().unwrap();
-        assert_eq!(loaded_entry.title, "Updated Title");
+        store.commit().unwrap();
 
-        cleanup_temp_file(&data_file_path);
-        cleanup_temp_file(&index_file_path);
+        assert_eq!(store.pending_writes(), 0);
+        store.reload_index();
+        assert_eq!(store.index.len(), 3);
+
+        drop(store);
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
     }
 
-    // Search tests
+    #[test]
+    fn test_group_commit_commits_when_interval_elapsed() {
+        let data_file_path = "test_group_commit_elapsed_data.bin";
+        let index_file_path = "test_group_commit_elapsed_index.bin";
 
-    // Define a filter that matches all entries
-    struct MatchAllFilter;
-    impl Filter<Entry> for MatchAllFilter {
-        fn pass(&self, _: &Entry) -> bool {
-            true
-        }
-    }
+        create_temp_file(data_file_path).unwrap();
+        create_temp_file(index_file_path).unwrap();
 
-    // Define a filter that matches no entries
-    struct MatchNoneFilter;
-    impl Filter<Entry> for MatchNoneFilter {
-        fn pass(&self, _: &Entry) -> bool {
-            false
-        }
-    }
+        let mut store = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        )
+        .with_durability(Durability::GroupCommit(Duration::ZERO));
 
-    // Define a filter that matches entries with a specific title
-    struct TitleFilter {
-        title: String,
-    }
-    impl Filter<Entry> for TitleFilter {
-        fn pass(&self, entry: &Entry) -> bool {
-            entry.title == self.title
-        }
+        let entry = durability_test_entry("test_id");
+        store.save(&entry.id, &entry).unwrap();
+        store.delete(&entry.id).unwrap();
+
+        assert_eq!(store.pending_writes(), 0);
+        store.reload_index();
+        assert!(store.index.is_empty());
+
+        drop(store);
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
     }
 
     #[test]
-    fn test_search_match_all() {
-        let data_file_path = "test_search_match_all_data.bin";
-        let index_file_path = "test_search_match_all_index.bin";
+    fn test_drop_commits_pending_group() {
+        let data_file_path = "test_group_commit_drop_data.bin";
+        let index_file_path = "test_group_commit_drop_index.bin";
 
         create_temp_file(data_file_path).unwrap();
         create_temp_file(index_file_path).unwrap();
 
+        let mut store = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        )
+        .with_durability(Durability::GroupCommit(Duration::from_secs(3600)));
+
+        let entry = durability_test_entry("test_id");
+        store.save(&entry.id, &entry).unwrap();
+        drop(store);
+
         let mut store = IndexedBinaryFileEntryStore::new(
             data_file_path.to_string(),
             index_file_path.to_string(),
         );
+        store.reload_index();
+        assert_eq!(store.load(&entry.id).unwrap(), Some(entry));
 
-        // Add some entries
-        let entry1 = Entry {
-            id: "id1".to_string(),
-            title: "First Entry".to_string(),
-            username: Some("user1".to_string()),
-            password: Some("password1".to_string()),
-            url: Some("https://example.com/1".to_string()),
-            note: Some("First test entry".to_string()),
-        };
-        let entry2 = Entry {
-            id: "id2".to_string(),
-            title: "Second Entry".to_string(),
-            username: Some("user2".to_string()),
-            password: Some("password2".to_string()),
-            url: Some("https://example.com/2".to_string()),
-            note: Some("Second test entry".to_string()),
-        };
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
+    }
 
-        store.save(&entry1.id, &entry1).unwrap();
-        store.save(&entry2.id, &entry2).unwrap();
+    #[test]
+    fn test_open_verifies_integrity() {
+        let data_file_path = "test_integrity_data.bin";
+        let index_file_path = "test_integrity_index.bin";
+        let mac_file_path = "test_integrity_index.bin.mac";
+        let key = [3u8; 32];
 
-        // Search for entries with a filter that matches all
-        let filter = MatchAllFilter;
-        let results = store.search(&filter).unwrap();
+        let mut store = IndexedBinaryFileEntryStore::open(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+            key,
+        )
+        .unwrap();
 
-        // Verify the results
-        assert_eq!(results.len(), 2);
-        assert!(results.contains(&entry1));
-        assert!(results.contains(&entry2));
+        let entry = durability_test_entry("test_id");
+        store.save(&entry.id, &entry).unwrap();
+        store.rewrite_index().unwrap();
+        drop(store);
 
-        cleanup_temp_file(&data_file_path);
-        cleanup_temp_file(&index_file_path);
+        // Reopening with the same key loads the index
+        let store = IndexedBinaryFileEntryStore::open(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+            key,
+        )
+        .unwrap();
+        assert_eq!(store.load(&entry.id).unwrap(), Some(entry));
+        drop(store);
+
+        // A different key is rejected
+        let result = IndexedBinaryFileEntryStore::<String>::open(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+            [4u8; 32],
+        );
+        assert!(matches!(result, Err(StoreError::IntegrityMismatch)));
+
+        // So is a modified data file
+        let mut data = fs::read(data_file_path).unwrap();
+        data[0] ^= 0xff;
+        fs::write(data_file_path, data).unwrap();
+        let result = IndexedBinaryFileEntryStore::<String>::open(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+            key,
+        );
+        assert!(matches!(result, Err(StoreError::IntegrityMismatch)));
+
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
+        cleanup_temp_file(mac_file_path);
     }
 
     #[test]
-    fn test_search_match_none() {
-        let data_file_path = "test_search_match_none_data.bin";
-        let index_file_path = "test_search_match_none_index.bin";
+    fn test_open_drops_saves_made_after_the_last_commit() {
+        let data_file_path = "test_uncommitted_data.bin";
+        let index_file_path = "test_uncommitted_index.bin";
+        let mac_file_path = "test_uncommitted_index.bin.mac";
+        let key = [3u8; 32];
 
-        create_temp_file(data_file_path).unwrap();
-        create_temp_file(index_file_path).unwrap();
+        let mut store = IndexedBinaryFileEntryStore::open(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+            key,
+        )
+        .unwrap();
+        let committed = durability_test_entry("committed");
+        store.save(&committed.id, &committed).unwrap();
+        store.commit().unwrap();
+        let committed_len = fs::metadata(data_file_path).unwrap().len();
+
+        // The process dies before committing the second save
+        let uncommitted = durability_test_entry("uncommitted");
+        store.save(&uncommitted.id, &uncommitted).unwrap();
+        drop(store);
+
+        let store = IndexedBinaryFileEntryStore::open(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+            key,
+        )
+        .unwrap();
+        assert_eq!(store.load(&committed.id).unwrap(), Some(committed));
+        assert_eq!(store.load(&uncommitted.id).unwrap(), None);
+        assert_eq!(fs::metadata(data_file_path).unwrap().len(), committed_len);
+        store.verify_integrity().unwrap();
+        drop(store);
+
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
+        cleanup_temp_file(mac_file_path);
+    }
 
-        let mut store = IndexedBinaryFileEntryStore::new(
+    #[test]
+    fn test_open_fails_without_the_mac_of_an_initialised_store() {
+        let data_file_path = "test_missing_mac_data.bin";
+        let index_file_path = "test_missing_mac_index.bin";
+        let mac_file_path = "test_missing_mac_index.bin.mac";
+        let journal_file_path = "test_missing_mac_index.bin.journal";
+        let key = [3u8; 32];
+
+        let mut store = IndexedBinaryFileEntryStore::open(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+            key,
+        )
+        .unwrap();
+        // A new store has its MAC before anything is saved
+        assert!(Path::new(mac_file_path).exists());
+        let entry = durability_test_entry("test_id");
+        store.save(&entry.id, &entry).unwrap();
+        store.rewrite_index().unwrap();
+        drop(store);
+
+        // Emptying the files and removing the MAC doesn't pass for a new store
+        for file_path in [data_file_path, index_file_path] {
+            fs::write(file_path, b"").unwrap();
+        }
+        cleanup_temp_file(journal_file_path);
+        fs::remove_file(mac_file_path).unwrap();
+        let result = IndexedBinaryFileEntryStore::<String>::open(
             data_file_path.to_string(),
             index_file_path.to_string(),
+            key,
         );
+        assert!(matches!(result, Err(StoreError::IntegrityMismatch)));
 
-        // Add some entries
-        let entry1 = Entry {
-            id: "id1".to_string(),
-            title: "First Entry".to_string(),
-            username: Some("user1".to_string()),
-            password: Some("password1".to_string()),
-            url: Some("https://example.com/1".to_string()),
-            note: Some("First test entry".to_string()),
-        };
-        let entry2 = Entry {
-            id: "id2".to_string(),
-            title: "Second Entry".to_string(),
-            username: Some("user2".to_string()),
-            password: Some("password2".to_string()),
-            url: Some("https://example.com/2".to_string()),
-            note: Some("Second test entry".to_string()),
-        };
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
+        cleanup_temp_file(mac_file_path);
+    }
 
-        store.save(&entry1.id, &entry1).unwrap();
-        store.save(&entry2.id, &entry2).unwrap();
+    #[test]
+    fn test_open_with_report() {
+        let data_file_path = "test_open_report_data.bin";
+        let index_file_path = "test_open_report_index.bin";
+        let mac_file_path = "test_open_report_index.bin.mac";
+        let key = [5u8; 32];
 
-        // Search for entries with a filter that matches none
-        let filter = MatchNoneFilter;
-        let results = store.search(&filter).unwrap();
+        let (mut store, report) = IndexedBinaryFileEntryStore::open_with_report(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+            Some(key),
+        )
+        .unwrap();
+        assert_eq!(report.entries, 0);
+        assert_eq!(report.data_file_len, 0);
+
+        for id in ["a", "b"] {
+            let entry = durability_test_entry(id);
+            store.save(&entry.id, &entry).unwrap();
+        }
+        store.rewrite_index().unwrap();
+        drop(store);
 
-        // Verify the results
-        assert!(results.is_empty());
+        let (store, report) = IndexedBinaryFileEntryStore::<String>::open_with_report(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+            Some(key),
+        )
+        .unwrap();
+        assert_eq!(store.load(&"a".to_string()).unwrap().unwrap().id, "a");
+        assert_eq!(report.entries, 2);
+        assert_eq!(report.journal_records, 0);
+        assert!(!report.secondary_rebuilt);
+        assert!(report.data_file_len > 0);
+        assert_eq!(report.index_file_len, 2 * INDEX_RECORD_SIZE as u64);
+        assert!(
+            report.total
+                >= report.recovery
+                    + report.integrity_check
+                    + report.index_load
+                    + report.journal_replay
+                    + report.secondary_load
+        );
+        drop(store);
 
-        cleanup_temp_file(&data_file_path);
-        cleanup_temp_file(&index_file_path);
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
+        cleanup_temp_file(mac_file_path);
     }
 
     #[test]
-    fn test_search_match_specific_title() {
-        let data_file_path = "test_search_match_specific_title_data.bin";
-        let index_file_path = "test_search_match_specific_title_index.bin";
+    fn test_journal_replayed_on_reload() {
+        let data_file_path = "test_journal_replay_data.bin";
+        let index_file_path = "test_journal_replay_index.bin";
 
         create_temp_file(data_file_path).unwrap();
         create_temp_file(index_file_path).unwrap();
@@ -830,69 +2888,157 @@ mod tests {
             index_file_path.to_string(),
         );
 
-        // Add some entries
-        let entry1 = Entry {
-            id: "id1".to_string(),
-            title: "First Entry".to_string(),
-            username: Some("user1".to_string()),
-            password: Some("password1".to_string()),
-            url: Some("https://example.com/1".to_string()),
-            note: Some("First test entry".to_string()),
-        };
-        let entry2 = Entry {
-            id: "id2".to_string(),
-            title: "Second Entry".to_string(),
-            username: Some("user2".to_string()),
-            password: Some("password2".to_string()),
-            url: Some("https://example.com/2".to_string()),
-            note: Some("Second test entry".to_string()),
-        };
+        let kept = durability_test_entry("kept");
+        let deleted = durability_test_entry("deleted");
+        store.save(&kept.id, &kept).unwrap();
+        store.rewrite_index().unwrap();
+        store.save(&deleted.id, &deleted).unwrap();
+        store.delete(&deleted.id).unwrap();
+        let mut updated = kept.clone();
+        updated.title = "Updated".to_string();
+        store.save(&updated.id, &updated).unwrap();
+        drop(store);
 
-        store.save(&entry1.id, &entry1).unwrap();
-        store.save(&entry2.id, &entry2).unwrap();
+        let mut store = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+        store.reload_index();
 
-        // Search for entries with a specific title
-        let filter = TitleFilter {
-            title: "First Entry".to_string(),
+        assert_eq!(store.load(&kept.id).unwrap(), Some(updated));
+        assert_eq!(store.load(&deleted.id).unwrap(), None);
+        assert!(store.needs_index_rewrite());
+
+        // Rewriting the index merges and removes the journal
+        store.rewrite_index().unwrap();
+        assert!(!Path::new("test_journal_replay_index.bin.journal").exists());
+        assert!(!store.needs_index_rewrite());
+
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
+    }
+
+    fn lookup_test_entry(id: &str, url: &str, username: &str) -> Entry {
+        Entry {
+            url: Some(url.to_string()),
+            username: Some(username.to_string()),
+            ..durability_test_entry(id)
+        }
+    }
+
+    #[test]
+    fn test_secondary_indexes_survive_reload() {
+        let data_file_path = "test_secondary_data.bin";
+        let index_file_path = "test_secondary_index.bin";
+
+        create_temp_file(data_file_path).unwrap();
+        create_temp_file(index_file_path).unwrap();
+
+        let open = || {
+            let mut store = IndexedBinaryFileEntryStore::new(
+                data_file_path.to_string(),
+                index_file_path.to_string(),
+            )
+            .with_secondary_indexes();
+            store.reload_index();
+            store
         };
-        let results = store.search(&filter).unwrap();
 
-        // Verify the results
-        assert_eq!(results.len(), 1);
-        assert!(results.contains(&entry1));
+        let mut store = open();
+        let a = lookup_test_entry("a", "https://example.com/login", "Alice");
+        let b = lookup_test_entry("b", "https://www.example.com", "bob");
+        let c = lookup_test_entry("c", "https://other.com", "alice");
+        for entry in [&a, &b, &c] {
+            store.save(&entry.id, entry).unwrap();
+        }
+        store.delete(&b.id).unwrap();
 
-        cleanup_temp_file(&data_file_path);
-        cleanup_temp_file(&index_file_path);
+        assert_eq!(
+            store.entries_for_url("example.com").unwrap(),
+            vec![a.clone()]
+        );
+        drop(store);
+
+        // Rebuilt from the data file, the journal has changes it hasn't seen
+        let mut store = open();
+        let mut by_username = store.entries_for_username("ALICE").unwrap();
+        by_username.sort_by(|x, y| x.id.cmp(&y.id));
+        assert_eq!(by_username, vec![a.clone(), c.clone()]);
+
+        // Loaded from the file written with the index
+        store.rewrite_index().unwrap();
+        drop(store);
+        let store = open();
+        assert_eq!(
+            store.entries_for_url("http://example.com").unwrap(),
+            vec![a]
+        );
+        assert_eq!(store.entries_for_username("bob").unwrap(), vec![]);
+
+        drop(store);
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
     }
 
     #[test]
-    fn test_search_empty_store() {
-        let data_file_path = "test_search_empty_store_data.bin";
-        let index_file_path = "test_search_empty_store_index.bin";
+    fn test_overwrites_and_deletes_update_secondary_indexes() {
+        let data_file_path = "test_secondary_invariants_data.bin";
+        let index_file_path = "test_secondary_invariants_index.bin";
 
         create_temp_file(data_file_path).unwrap();
         create_temp_file(index_file_path).unwrap();
 
-        let store = IndexedBinaryFileEntryStore::new(
+        let mut store = IndexedBinaryFileEntryStore::<String>::new(
             data_file_path.to_string(),
             index_file_path.to_string(),
+        )
+        .with_secondary_indexes();
+        store.reload_index();
+
+        let a = lookup_test_entry("a", "https://example.com", "alice");
+        let b = Entry {
+            tags: vec!["work".to_string()],
+            ..lookup_test_entry("b", "https://example.com", "bob")
+        };
+        for entry in [&a, &b] {
+            store.save(&entry.id, entry).unwrap();
+        }
+        store.check_invariants().unwrap();
+
+        // An overwrite moves the entry off its old url, username and tags
+        let moved = lookup_test_entry("b", "https://other.com", "carol");
+        store.save(&moved.id, &moved).unwrap();
+        store.check_invariants().unwrap();
+        assert_eq!(
+            store.entries_for_url("example.com").unwrap(),
+            vec![a.clone()]
         );
+        assert_eq!(store.entries_for_username("bob").unwrap(), vec![]);
 
-        // Search for entries in an empty store
-        let filter = MatchAllFilter;
-        let results = store.search(&filter).unwrap();
+        store.delete(&a.id).unwrap();
+        store.check_invariants().unwrap();
+        assert_eq!(store.entries_for_url("example.com").unwrap(), vec![]);
 
-        // Verify the results
-        assert!(results.is_empty());
+        store.commit().unwrap();
+        drop(store);
+        let mut store = IndexedBinaryFileEntryStore::<String>::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        )
+        .with_secondary_indexes();
+        store.reload_index();
+        store.check_invariants().unwrap();
+        assert_eq!(store.entries_for_username("carol").unwrap(), vec![moved]);
 
-        cleanup_temp_file(&data_file_path);
-        cleanup_temp_file(&index_file_path);
+        drop(store);
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
     }
 
     #[test]
-    fn test_needs_index_rewrite_after_save() {
-        let data_file_path = "test_needs_index_rewrite_after_save_data.bin";
-        let index_file_path = "test_needs_index_rewrite_after_save_index.bin";
+    fn test_lookups_scan_without_secondary_indexes() {
+        let data_file_path = "test_lookup_scan_data.bin";
+        let index_file_path = "test_lookup_scan_index.bin";
 
         create_temp_file(data_file_path).unwrap();
         create_temp_file(index_file_path).unwrap();
@@ -901,30 +3047,35 @@ mod tests {
             data_file_path.to_string(),
             index_file_path.to_string(),
         );
+        let a = lookup_test_entry("a", "https://example.com", "alice");
+        store.save(&a.id, &a).unwrap();
 
-        let entry = Entry {
-            id: "test_id".to_string(),
-            title: "Test Title".to_string(),
-            username: Some("test_user".to_string()),
-            password: Some("test_password".to_string()),
-            url: Some("https://example.com".to_string()),
-            note: Some("This is a test entry".to_string()),
-        };
-
-        // Save the entry
-        store.save(&entry.id, &entry).unwrap();
+        assert_eq!(
+            store.entries_for_url("https://example.com/x").unwrap(),
+            vec![a.clone()]
+        );
+        assert_eq!(
+            store.entries_for_username("Alice").unwrap(),
+            vec![a.clone()]
+        );
 
-        // Verify that the index rewrite flag is set
-        assert!(store.needs_index_rewrite());
+        // Archived entries aren't offered for autofill
+        let archived = Entry {
+            archived: true,
+            ..lookup_test_entry("b", "https://example.com", "alice")
+        };
+        store.save(&archived.id, &archived).unwrap();
+        assert_eq!(store.entries_for_url("example.com").unwrap(), vec![a]);
 
-        cleanup_temp_file(&data_file_path);
-        cleanup_temp_file(&index_file_path);
+        drop(store);
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
     }
 
     #[test]
-    fn test_needs_index_rewrite_cleared_after_rewrite() {
-        let data_file_path = "test_needs_index_rewrite_cleared_after_rewrite_data.bin";
-        let index_file_path = "test_needs_index_rewrite_cleared_after_rewrite_index.bin";
+    fn test_tags_with_and_without_secondary_indexes() {
+        let data_file_path = "test_tags_data.bin";
+        let index_file_path = "test_tags_index.bin";
 
         create_temp_file(data_file_path).unwrap();
         create_temp_file(index_file_path).unwrap();
@@ -932,34 +3083,45 @@ mod tests {
         let mut store = IndexedBinaryFileEntryStore::new(
             data_file_path.to_string(),
             index_file_path.to_string(),
-        );
-
-        let entry = Entry {
-            id: "test_id".to_string(),
-            title: "Test Title".to_string(),
-            username: Some("test_user".to_string()),
-            password: Some("test_password".to_string()),
-            url: Some("https://example.com".to_string()),
-            note: Some("This is a test entry".to_string()),
-        };
-
-        // Save the entry (sets needs_index_rewrite to true)
-        store.save(&entry.id, &entry).unwrap();
+        )
+        .with_secondary_indexes();
+        for (id, tags) in [
+            ("a", vec!["work", "mail"]),
+            ("b", vec!["Work"]),
+            ("c", vec![]),
+        ] {
+            let entry = Entry {
+                tags: tags.into_iter().map(String::from).collect(),
+                ..durability_test_entry(id)
+            };
+            store.save(&entry.id, &entry).unwrap();
+        }
+        store.delete(&"a".to_string()).unwrap();
 
-        // Rewrite the index (clears needs_index_rewrite)
-        store.rewrite_index().unwrap();
+        let expected = vec![("work".to_string(), 1)];
+        assert_eq!(store.tags().unwrap(), expected);
+        assert_eq!(store.suggest_tags("wo", 5).unwrap(), vec!["work"]);
+        drop(store);
 
-        // Verify that the index rewrite flag is cleared
-        assert!(!store.needs_index_rewrite());
+        let mut store: IndexedBinaryFileEntryStore = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+        store.reload_index();
+        assert_eq!(store.tags().unwrap(), expected);
 
-        cleanup_temp_file(&data_file_path);
-        cleanup_temp_file(&index_file_path);
+        drop(store);
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
     }
 
+    #[cfg(feature = "watch")]
     #[test]
-    fn test_needs_data_rewrite_after_delete() {
-        let data_file_path = "test_needs_data_rewrite_after_delete_data.bin";
-        let index_file_path = "test_needs_data_rewrite_after_delete_index.bin";
+    fn test_reload_if_changed_sees_external_save() {
+        use std::{thread, time::Duration};
+
+        let data_file_path = "test_watch_store_data.bin";
+        let index_file_path = "test_watch_store_index.bin";
 
         create_temp_file(data_file_path).unwrap();
         create_temp_file(index_file_path).unwrap();
@@ -967,117 +3129,563 @@ mod tests {
         let mut store = IndexedBinaryFileEntryStore::new(
             data_file_path.to_string(),
             index_file_path.to_string(),
-        );
+        )
+        .with_durability(Durability::Immediate)
+        .with_watcher()
+        .unwrap();
+
+        // Our own writes are not reported
+        let own = durability_test_entry("own");
+        store.save(&own.id, &own).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(store.reload_if_changed().unwrap(), None);
+
+        let mut other = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        )
+        .with_durability(Durability::Immediate);
+        other.reload_index();
+        let external = durability_test_entry("external");
+        other.save(&external.id, &external).unwrap();
+        drop(other);
+
+        let mut event = None;
+        for _ in 0..100 {
+            event = store.reload_if_changed().unwrap();
+            if event.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        assert!(matches!(
+            event,
+            Some(StoreEvent::VaultChangedExternally { .. })
+        ));
+        assert_eq!(store.load(&external.id).unwrap(), Some(external));
+
+        drop(store);
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
+    }
+
+    #[test]
+    fn test_integer_keys() {
+        let data_file_path = "test_integer_keys_data.bin";
+        let index_file_path = "test_integer_keys_index.bin";
+
+        let mut store: IndexedBinaryFileEntryStore<u64> = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        )
+        .with_secondary_indexes();
 
         let entry = Entry {
-            id: "test_id".to_string(),
-            title: "Test Title".to_string(),
-            username: Some("test_user".to_string()),
-            password: Some("test_password".to_string()),
-            url: Some("https://example.com".to_string()),
-            note: Some("This is a test entry".to_string()),
+            id: "42".to_string(),
+            title: "Numbered".to_string(),
+            username: Some("alice".to_string()),
+            ..Default::default()
         };
+        store.save(&42, &entry).unwrap();
+        store.save(&7, &entry).unwrap();
+        store.delete(&7).unwrap();
 
-        // Save the entry
-        store.save(&entry.id, &entry).unwrap();
-
-        // Delete the entry
-        store.delete(&entry.id).unwrap();
+        let mut store: IndexedBinaryFileEntryStore<u64> = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        )
+        .with_secondary_indexes();
+        store.reload_index();
 
-        // Verify that the data rewrite flag is set
-        assert!(store.needs_data_rewrite());
+        assert_eq!(store.load(&42).unwrap(), Some(entry.clone()));
+        assert_eq!(store.load(&7).unwrap(), None);
+        assert_eq!(store.entries_for_username("alice").unwrap(), vec![entry]);
 
-        cleanup_temp_file(&data_file_path);
-        cleanup_temp_file(&index_file_path);
+        drop(store);
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
     }
 
     #[test]
-    fn test_needs_data_rewrite_cleared_after_rewrite() {
-        let data_file_path = "test_needs_data_rewrite_cleared_after_rewrite_data.bin";
-        let index_file_path = "test_needs_data_rewrite_cleared_after_rewrite_index.bin";
+    fn test_migrates_legacy_entries_when_asked() {
+        use crate::data::migration::tests::{entry, v1_bytes};
 
+        let data_file_path = "test_migrate_data.bin";
+        let index_file_path = "test_migrate_index.bin";
         create_temp_file(data_file_path).unwrap();
         create_temp_file(index_file_path).unwrap();
 
-        let mut store = IndexedBinaryFileEntryStore::new(
+        let legacy = v1_bytes();
+        fs::write(data_file_path, &legacy).unwrap();
+        let position = Position {
+            offset: 0,
+            length: legacy.len(),
+        };
+        let index = CompactIndex::from_iter([("1".to_string(), position)]);
+        IndexedBinaryFileEntryStore::write_index(index_file_path, &index, &Syncer::default())
+            .unwrap();
+
+        // Opening doesn't migrate, the store refuses to be used instead
+        let (mut store, report): (IndexedBinaryFileEntryStore, _) =
+            IndexedBinaryFileEntryStore::open_with_report(
+                data_file_path.to_string(),
+                index_file_path.to_string(),
+                None,
+            )
+            .unwrap();
+        assert_eq!(report.entries, 1);
+        assert!(matches!(
+            store.load(&"1".to_string()),
+            Err(StoreError::MigrationRequired { version: 1 })
+        ));
+        assert!(matches!(
+            store.save(&"2".to_string(), &entry()),
+            Err(StoreError::MigrationRequired { version: 1 })
+        ));
+        assert_eq!(fs::read(data_file_path).unwrap(), legacy);
+
+        let report = store.migrate(&MigrationRegistry::default(), false).unwrap();
+        assert_eq!(report.versions, std::collections::BTreeMap::from([(1, 1)]));
+        assert_eq!(store.load(&"1".to_string()).unwrap(), Some(entry()));
+        let backup = format!("{}.v1.bak", data_file_path);
+        assert_eq!(fs::read(&backup).unwrap(), legacy);
+
+        // Already current, so it reopens as it is
+        drop(store);
+        let mut store: IndexedBinaryFileEntryStore = IndexedBinaryFileEntryStore::new(
             data_file_path.to_string(),
             index_file_path.to_string(),
         );
+        store.reload_index();
+        assert_eq!(store.load(&"1".to_string()).unwrap(), Some(entry()));
+        assert_eq!(
+            store
+                .migrate(&MigrationRegistry::default(), true)
+                .unwrap()
+                .migrated(),
+            0
+        );
 
-        let entry = Entry {
-            id: "test_id".to_string(),
-            title: "Test Title".to_string(),
-            username: Some("test_user".to_string()),
-            password: Some("test_password".to_string()),
-            url: Some("https://example.com".to_string()),
-            note: Some("This is a test entry".to_string()),
+        drop(store);
+        fs::remove_file(backup).unwrap();
+        fs::remove_file(format!("{}.v1.bak", index_file_path)).unwrap();
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
+    }
+
+    #[test]
+    fn test_recover_orphans_after_losing_the_journal() {
+        let data_file_path = "test_orphans_data.bin";
+        let index_file_path = "test_orphans_index.bin";
+        let entry = |id: &str, title: &str| Entry {
+            id: id.to_string(),
+            title: title.to_string(),
+            ..Default::default()
         };
 
-        // Save the entry
-        store.save(&entry.id, &entry).unwrap();
+        let mut store: IndexedBinaryFileEntryStore = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+        store.save(&"a".to_string(), &entry("a", "First")).unwrap();
+        store.save(&"b".to_string(), &entry("b", "Second")).unwrap();
+        store.rewrite_index().unwrap();
+        // Only in the journal
+        store
+            .save(&"b".to_string(), &entry("b", "Renamed"))
+            .unwrap();
+        store.save(&"c".to_string(), &entry("c", "Third")).unwrap();
+        drop(store);
+        fs::remove_file(format!("{}.journal", index_file_path)).unwrap();
+
+        let mut store: IndexedBinaryFileEntryStore = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+        store.reload_index();
+        assert_eq!(store.load(&"c".to_string()).unwrap(), None);
+
+        let report = store.recover_orphans().unwrap();
+        assert_eq!(report.records_scanned, 4);
+        assert_eq!(report.unreadable_bytes, 0);
+        let orphans: Vec<_> = report
+            .orphans
+            .iter()
+            .map(|orphan| (orphan.id.as_str(), orphan.newer_than_indexed))
+            .collect();
+        assert_eq!(orphans, vec![("b", true), ("c", false)]);
+
+        assert_eq!(
+            store.load(&"b".to_string()).unwrap(),
+            Some(entry("b", "Renamed"))
+        );
+        assert_eq!(
+            store.load(&"c".to_string()).unwrap(),
+            Some(entry("c", "Third"))
+        );
+        assert!(store.find_orphans().unwrap().orphans.is_empty());
 
-        // Delete the entry (sets needs_data_rewrite to true)
-        store.delete(&entry.id).unwrap();
+        drop(store);
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
+    }
 
-        // Rewrite the data file (clears needs_data_rewrite)
-        store.write_data().unwrap();
+    #[test]
+    fn test_consistency_check_after_truncation() {
+        let data_file_path = "test_consistency_data.bin";
+        let index_file_path = "test_consistency_index.bin";
+        let entry = |id: &str| Entry {
+            id: id.to_string(),
+            title: format!("Title {}", id),
+            ..Default::default()
+        };
 
-        // Verify that the data rewrite flag is cleared
-        assert!(!store.needs_data_rewrite());
+        let mut store: IndexedBinaryFileEntryStore = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+        store.save(&"a".to_string(), &entry("a")).unwrap();
+        store.save(&"b".to_string(), &entry("b")).unwrap();
+        let truncated_len = fs::metadata(data_file_path).unwrap().len() + 1;
+        store.save(&"c".to_string(), &entry("c")).unwrap();
+        store.rewrite_index().unwrap();
+        drop(store);
+        // Cut into "c", leaving the index pointing past the end
+        OpenOptions::new()
+            .write(true)
+            .open(data_file_path)
+            .unwrap()
+            .set_len(truncated_len)
+            .unwrap();
 
-        cleanup_temp_file(&data_file_path);
-        cleanup_temp_file(&index_file_path);
+        let mut store: IndexedBinaryFileEntryStore = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+        store.reload_index();
+        let report = store.check_consistency().unwrap();
+        assert_eq!(report.data_file_len, truncated_len);
+        assert_eq!(report.out_of_bounds, vec!["c".to_string()]);
+        assert!(report.overlapping.is_empty());
+
+        // Appended over where "c" was
+        store.save(&"d".to_string(), &entry("d")).unwrap();
+        let report = store.trim_inconsistent().unwrap();
+        assert_eq!(report.overlapping, vec![("c".to_string(), "d".to_string())]);
+        assert_eq!(report.trimmed, vec!["c".to_string()]);
+        assert_eq!(store.load(&"c".to_string()).unwrap(), None);
+        assert_eq!(store.load(&"d".to_string()).unwrap(), Some(entry("d")));
+        assert!(store.check_consistency().unwrap().is_consistent());
+
+        // Rebuilding skips the torn byte of "c" and finds the rest
+        let report = store.rebuild_index().unwrap();
+        assert_eq!(report.records_scanned, 3);
+        assert_eq!(report.unreadable_bytes, 1);
+        assert!(report.orphans.is_empty());
+        for id in ["a", "b", "d"] {
+            assert_eq!(store.load(&id.to_string()).unwrap(), Some(entry(id)));
+        }
+        drop(store);
+
+        let mut store: IndexedBinaryFileEntryStore = IndexedBinaryFileEntryStore::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        )
+        .with_consistency_repair(ConsistencyRepair::Trim);
+        store.reload_index();
+        assert!(store.check_consistency().unwrap().is_consistent());
+
+        drop(store);
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
     }
 
     #[test]
-    fn test_needs_index_rewrite_unchanged_without_modifications() {
-        let data_file_path = "test_needs_index_rewrite_unchanged_data.bin";
-        let index_file_path = "test_needs_index_rewrite_unchanged_index.bin";
+    fn test_scan_survives_a_torn_record() {
+        let data_file_path = "test_torn_scan_data.bin";
+        let index_file_path = "test_torn_scan_index.bin";
 
-        create_temp_file(data_file_path).unwrap();
-        create_temp_file(index_file_path).unwrap();
+        let mut store = IndexedBinaryFileEntryStore::<String>::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+        for id in ["a", "b", "c"] {
+            store
+                .save(&id.to_string(), &durability_test_entry(id))
+                .unwrap();
+        }
+        store.rewrite_index().unwrap();
+        drop(store);
+        // Cut "c" off in the middle of its fields
+        let data_file_len = fs::metadata(data_file_path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(data_file_path)
+            .unwrap()
+            .set_len(data_file_len - 5)
+            .unwrap();
 
-        let mut store = IndexedBinaryFileEntryStore::new(
+        let mut store = IndexedBinaryFileEntryStore::<String>::new(
             data_file_path.to_string(),
             index_file_path.to_string(),
         );
+        store.reload_index();
+        let report = store.find_orphans().unwrap();
+        assert_eq!(report.records_scanned, 2);
+        assert!(report.unreadable_bytes > 0);
+
+        store.rebuild_index().unwrap();
+        for id in ["a", "b"] {
+            assert_eq!(
+                store.load(&id.to_string()).unwrap(),
+                Some(durability_test_entry(id))
+            );
+        }
+        assert_eq!(store.load(&"c".to_string()).unwrap(), None);
 
-        // Verify that the index rewrite flag is initially false
-        assert!(!store.needs_index_rewrite());
+        drop(store);
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
+    }
 
-        cleanup_temp_file(&data_file_path);
-        cleanup_temp_file(&index_file_path);
+    #[test]
+    fn test_repair() {
+        let data_file_path = "test_repair_data.bin";
+        let index_file_path = "test_repair_index.bin";
+        let entry = |id: &str, title: &str| Entry {
+            title: title.to_string(),
+            ..durability_test_entry(id)
+        };
+
+        let mut store = IndexedBinaryFileEntryStore::<String>::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+        store.save(&"a".to_string(), &entry("a", "First")).unwrap();
+        store.save(&"b".to_string(), &entry("b", "Second")).unwrap();
+        store.rewrite_index().unwrap();
+        // Only in the journal, which is lost
+        store
+            .save(&"b".to_string(), &entry("b", "Renamed"))
+            .unwrap();
+        store.save(&"c".to_string(), &entry("c", "Third")).unwrap();
+        drop(store);
+        fs::remove_file(format!("{}.journal", index_file_path)).unwrap();
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(data_file_path)
+            .unwrap();
+        file.write_all(&[0xff; 5]).unwrap();
+        drop(file);
+
+        let mut store = IndexedBinaryFileEntryStore::<String>::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+        store.reload_index();
+        let expected = RepairState {
+            entries: 2,
+            integrity_ok: true,
+            out_of_bounds: 0,
+            overlapping: 0,
+            lost_updates: 1,
+            unindexed: 1,
+            torn_bytes: 5,
+        };
+        let dry_run = store.repair(true).unwrap();
+        assert_eq!(dry_run.before, expected);
+        assert!(dry_run.after.is_none() && dry_run.backups.is_empty());
+
+        let report = store.repair(false).unwrap();
+        assert_eq!(report.before, expected);
+        assert!(report.trimmed.is_empty() && !report.rebuilt);
+        assert_eq!(report.recovered.len(), 1);
+        let data_file_len = fs::metadata(data_file_path).unwrap().len();
+        assert_eq!(report.truncated_to, Some(data_file_len));
+        let after = report.after.unwrap();
+        assert!(!after.needs_repair());
+        // "c" may have been deleted, so it is left for `recover_orphans`
+        assert_eq!(after.unindexed, 1);
+        assert_eq!(
+            store.load(&"b".to_string()).unwrap(),
+            Some(entry("b", "Renamed"))
+        );
+        assert_eq!(store.load(&"c".to_string()).unwrap(), None);
+        assert_eq!(
+            fs::read(&report.backups[0]).unwrap().len() as u64,
+            data_file_len + 5
+        );
+        assert!(!store.repair(false).unwrap().before.needs_repair());
+
+        drop(store);
+        for backup in &report.backups {
+            fs::remove_file(backup).unwrap();
+        }
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
     }
 
     #[test]
-    fn test_needs_data_rewrite_unchanged_without_deletions() {
-        let data_file_path = "test_needs_data_rewrite_unchanged_data.bin";
-        let index_file_path = "test_needs_data_rewrite_unchanged_index.bin";
+    fn test_shutdown_marker() {
+        let data_file_path = "test_shutdown_data.bin";
+        let index_file_path = "test_shutdown_index.bin";
+        let marker_file_path = format!("{}.clean", index_file_path);
+        let open = || {
+            IndexedBinaryFileEntryStore::<String>::open_with_report(
+                data_file_path.to_string(),
+                index_file_path.to_string(),
+                None,
+            )
+        };
 
-        create_temp_file(data_file_path).unwrap();
-        create_temp_file(index_file_path).unwrap();
+        let mut store = IndexedBinaryFileEntryStore::<String>::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        )
+        .with_shutdown_marker();
+        store.reload_index();
+        for id in ["a", "b"] {
+            store
+                .save(&id.to_string(), &durability_test_entry(id))
+                .unwrap();
+        }
+        // No `rewrite_index`, dropping persists the index
+        drop(store);
+        assert!(Path::new(&marker_file_path).exists());
+        assert_eq!(
+            fs::metadata(format!("{}.journal", index_file_path))
+                .ok()
+                .map(|m| m.len()),
+            None
+        );
 
-        let mut store = IndexedBinaryFileEntryStore::new(
+        let (store, report) = open().unwrap();
+        let mut store = store.with_shutdown_marker();
+        assert_eq!(report.entries, 2);
+        // Only a store `with_shutdown_marker` reads the marker
+        assert!(!report.clean_shutdown);
+        store.reload_index();
+        assert!(!Path::new(&marker_file_path).exists());
+        store
+            .save(&"c".to_string(), &durability_test_entry("c"))
+            .unwrap();
+        store.shut_down().unwrap();
+        // A crash after shutting down: the data file grows past the marker
+        OpenOptions::new()
+            .append(true)
+            .open(data_file_path)
+            .unwrap()
+            .write_all(&[0xff; 3])
+            .unwrap();
+        std::mem::forget(store);
+
+        let mut store = IndexedBinaryFileEntryStore::<String>::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        )
+        .with_shutdown_marker();
+        let mut report = OpenReport::default();
+        store.read_index_timed(&mut report).unwrap();
+        assert!(!report.clean_shutdown);
+        assert_eq!(report.entries, 3);
+        drop(store);
+
+        let mut store = IndexedBinaryFileEntryStore::<String>::new(
             data_file_path.to_string(),
             index_file_path.to_string(),
+        )
+        .with_shutdown_marker();
+        let mut report = OpenReport::default();
+        store.read_index_timed(&mut report).unwrap();
+        assert!(report.clean_shutdown);
+        assert_eq!(
+            store.load(&"c".to_string()).unwrap(),
+            Some(durability_test_entry("c"))
         );
 
-        let entry = Entry {
-            id: "test_id".to_string(),
-            title: "Test Title".to_string(),
-            username: Some("test_user".to_string()),
-            password: Some("test_password".to_string()),
-            url: Some("https://example.com".to_string()),
-            note: Some("This is a test entry".to_string()),
+        drop(store);
+        fs::remove_file(&marker_file_path).unwrap();
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
+    }
+
+    #[test]
+    fn test_compaction_swaps_data_and_index_as_one() {
+        let dir = format!("test_compaction_dir_{}", uuid::Uuid::new_v4());
+        fs::create_dir(&dir).unwrap();
+        let data_file_path = format!("{}/data.bin", dir);
+        let index_file_path = format!("{}/index.bin", dir);
+        let open = || {
+            let mut store = IndexedBinaryFileEntryStore::<String>::new(
+                data_file_path.clone(),
+                index_file_path.clone(),
+            );
+            store.reload_index();
+            store
         };
 
-        // Save the entry
-        store.save(&entry.id, &entry).unwrap();
+        let mut store = open();
+        for id in ["a", "b"] {
+            store
+                .save(&id.to_string(), &durability_test_entry(id))
+                .unwrap();
+        }
+        store.rewrite_index().unwrap();
+        drop(store);
+        let old_data = fs::read(&data_file_path).unwrap();
+        let old_index = fs::read(&index_file_path).unwrap();
 
-        // Verify that the data rewrite flag is still false (no deletions)
-        assert!(!store.needs_data_rewrite());
+        let mut store = open();
+        store.delete(&"a".to_string()).unwrap();
+        store.write_data().unwrap();
+        drop(store);
 
-        cleanup_temp_file(&data_file_path);
-        cleanup_temp_file(&index_file_path);
+        // A crash once the data file was swapped in, before its index was
+        for (file_path, old) in [(&data_file_path, &old_data), (&index_file_path, &old_index)] {
+            fs::rename(file_path, format!("{}.tmp", file_path)).unwrap();
+            fs::write(file_path, old).unwrap();
+        }
+        File::create(format!("{}.tmp.complete", data_file_path)).unwrap();
+
+        let store = open();
+        assert_eq!(store.load(&"a".to_string()).unwrap(), None);
+        assert_eq!(
+            store.load(&"b".to_string()).unwrap(),
+            Some(durability_test_entry("b"))
+        );
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        drop(store);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_rejects_ids_too_long_to_index() {
+        let data_file_path = "test_data_long_id.bin";
+        let index_file_path = "test_index_long_id.bin";
+        let mut store = IndexedBinaryFileEntryStore::<String>::new(
+            data_file_path.to_string(),
+            index_file_path.to_string(),
+        );
+
+        let fits = "x".repeat(28);
+        store.save(&fits, &durability_test_entry(&fits)).unwrap();
+        // Not held as a UUID, so it takes as long as any other string
+        let uuid = "67E55044-10B1-426F-9247-BB680E5FE0C8".to_string();
+        let data_len = fs::metadata(data_file_path).unwrap().len();
+        assert!(matches!(
+            store.save(&uuid, &durability_test_entry(&uuid)),
+            Err(StoreError::IdTooLong)
+        ));
+        assert_eq!(fs::metadata(data_file_path).unwrap().len(), data_len);
+        assert_eq!(
+            store.load(&fits).unwrap(),
+            Some(durability_test_entry(&fits))
+        );
+
+        drop(store);
+        cleanup_temp_file(data_file_path);
+        cleanup_temp_file(index_file_path);
     }
 }
@@ -0,0 +1,345 @@
+use bincode::Options;
+use log::info;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{Read, Write},
+};
+
+use super::model::Entry;
+use super::store_error::StoreError;
+
+// Version of the bincode layout of `Entry`. Bump it and register a migration
+// from the previous version whenever the layout changes.
+pub const STORE_SCHEMA_VERSION: u32 = 2;
+// The layout of the first release, whose files have no `SchemaHeader`
+pub const BASELINE_SCHEMA_VERSION: u32 = 1;
+
+// Files written since the layout was versioned start with the magic and the
+// version as a little-endian u32. A file of the first release starts with a
+// record, whose length or magic never spells `SCHEMA_MAGIC`.
+const SCHEMA_MAGIC: &[u8; 8] = b"TUGSTORE";
+pub const SCHEMA_HEADER_LEN: u64 = SCHEMA_MAGIC.len() as u64 + 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchemaHeader {
+    pub version: u32,
+    // Where the records start
+    pub len: u64,
+}
+
+impl SchemaHeader {
+    // Reads the header at the start of `reader`. A file holding no more than
+    // part of a header, e.g. an empty one, has no records to misread, so it
+    // counts as current.
+    pub fn read<R: Read>(reader: &mut R) -> Result<Self, StoreError> {
+        let mut bytes = Vec::with_capacity(SCHEMA_HEADER_LEN as usize);
+        reader.take(SCHEMA_HEADER_LEN).read_to_end(&mut bytes)?;
+
+        if let Some(version) = bytes.strip_prefix(&SCHEMA_MAGIC[..]) {
+            if let Ok(version) = version.try_into() {
+                return Ok(Self {
+                    version: u32::from_le_bytes(version),
+                    len: SCHEMA_HEADER_LEN,
+                });
+            }
+        }
+        if SCHEMA_MAGIC.starts_with(&bytes) {
+            return Ok(Self {
+                version: STORE_SCHEMA_VERSION,
+                len: bytes.len() as u64,
+            });
+        }
+        Ok(Self {
+            version: BASELINE_SCHEMA_VERSION,
+            len: 0,
+        })
+    }
+
+    // Starts a file with the header of `STORE_SCHEMA_VERSION`
+    pub fn write_current<W: Write>(writer: &mut W) -> Result<(), StoreError> {
+        writer.write_all(SCHEMA_MAGIC)?;
+        writer.write_all(&STORE_SCHEMA_VERSION.to_le_bytes())?;
+        Ok(())
+    }
+
+    // Reads the header of a file about to be appended to, opened for
+    // reading and appending. A file holding no more than part of a header
+    // is started over with a current one, for the records to follow.
+    pub fn for_append(file: &mut File) -> Result<Self, StoreError> {
+        let header = Self::read(file)?;
+        if header.is_written() || header.len != file.metadata()?.len() {
+            header.check()?;
+            return Ok(header);
+        }
+
+        file.set_len(0)?;
+        Self::write_current(file)?;
+        Ok(Self {
+            version: STORE_SCHEMA_VERSION,
+            len: SCHEMA_HEADER_LEN,
+        })
+    }
+
+    // Whether the header is complete, rather than missing or torn
+    pub fn is_written(&self) -> bool {
+        self.len == SCHEMA_HEADER_LEN
+    }
+
+    // Fails unless the records are in the current layout. Older ones have
+    // to be upgraded with `migrate` before the store can be used.
+    pub fn check(&self) -> Result<(), StoreError> {
+        match self.version {
+            STORE_SCHEMA_VERSION => Ok(()),
+            version if version < STORE_SCHEMA_VERSION => {
+                Err(StoreError::MigrationRequired { version })
+            }
+            _ => Err(StoreError::UnknownRecordLayout),
+        }
+    }
+}
+
+// Decodes a payload only if it has exactly the layout asked for, so a
+// record that isn't in the version its file says fails instead of being
+// misread
+fn strict() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, StoreError> {
+    strict()
+        .deserialize(bytes)
+        .map_err(|_| StoreError::UnknownRecordLayout)
+}
+
+// Decodes a payload in one layout and encodes it in the next
+fn upgrade<A: DeserializeOwned, B: Serialize>(
+    bytes: &[u8],
+    convert: impl FnOnce(A) -> B,
+) -> Result<Vec<u8>, StoreError> {
+    Ok(bincode::serialize(&convert(decode(bytes)?))?)
+}
+
+#[derive(Serialize, Deserialize)]
+struct EntryV1 {
+    id: String,
+    title: String,
+    username: Option<String>,
+    password: Option<String>,
+    url: Option<String>,
+    note: Option<String>,
+}
+
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    // Decodes a payload in the `from` layout, failing on any other layout,
+    // and encodes it in the layout of `from + 1`
+    pub upgrade: fn(&[u8]) -> Result<Vec<u8>, StoreError>,
+}
+
+// Migrations keyed by the version they upgrade from, run in order
+pub struct MigrationRegistry {
+    migrations: BTreeMap<u32, Migration>,
+}
+
+impl Default for MigrationRegistry {
+    fn default() -> Self {
+        let mut registry = Self {
+            migrations: BTreeMap::new(),
+        };
+        registry.register(Migration {
+            from: BASELINE_SCHEMA_VERSION,
+            description: "add the fields added since the first release",
+            upgrade: |bytes| {
+                upgrade(bytes, |v1: EntryV1| Entry {
+                    id: v1.id,
+                    title: v1.title,
+                    username: v1.username,
+                    password: v1.password,
+                    url: v1.url,
+                    note: v1.note,
+                    ..Entry::default()
+                })
+            },
+        });
+        registry
+    }
+}
+
+impl MigrationRegistry {
+    pub fn register(&mut self, migration: Migration) {
+        self.migrations.insert(migration.from, migration);
+    }
+
+    // Brings an entry encoded in layout `from`, as read from its file's
+    // header, up to `STORE_SCHEMA_VERSION`. Every step decodes exactly the
+    // layout it upgrades from, so an entry in any other layout fails with
+    // `UnknownRecordLayout`.
+    pub fn upgrade(&self, from: u32, bytes: &[u8]) -> Result<Upgraded, StoreError> {
+        if from > STORE_SCHEMA_VERSION {
+            return Err(StoreError::UnknownRecordLayout);
+        }
+
+        let mut bytes = bytes.to_vec();
+        let mut steps = Vec::new();
+        for version in from..STORE_SCHEMA_VERSION {
+            let migration = self
+                .migrations
+                .get(&version)
+                .ok_or(StoreError::UnknownRecordLayout)?;
+            bytes = (migration.upgrade)(&bytes)?;
+            steps.push(migration.description);
+        }
+        Ok(Upgraded { from, bytes, steps })
+    }
+}
+
+pub struct Upgraded {
+    pub from: u32,
+    pub bytes: Vec<u8>,
+    pub steps: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationReport {
+    // Records found per schema version
+    pub versions: BTreeMap<u32, usize>,
+    // The migrations that ran on at least one record, in order
+    pub steps: Vec<&'static str>,
+    // Copies of the files as they were before migrating
+    pub backups: Vec<String>,
+    pub dry_run: bool,
+}
+
+impl MigrationReport {
+    pub fn new(dry_run: bool) -> Self {
+        Self {
+            dry_run,
+            ..Self::default()
+        }
+    }
+
+    pub fn add(&mut self, upgraded: &Upgraded) {
+        *self.versions.entry(upgraded.from).or_default() += 1;
+        for step in &upgraded.steps {
+            if !self.steps.contains(step) {
+                self.steps.push(step);
+            }
+        }
+    }
+
+    // Records that had to be upgraded
+    pub fn migrated(&self) -> usize {
+        self.versions
+            .iter()
+            .filter(|(version, _)| **version != STORE_SCHEMA_VERSION)
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    pub fn oldest_version(&self) -> Option<u32> {
+        self.versions.keys().next().copied()
+    }
+
+    // Copies `files` next to themselves as `{file}.v{version}.bak`
+    pub fn back_up(&mut self, files: &[&str]) -> Result<(), StoreError> {
+        let version = self.oldest_version().unwrap_or(STORE_SCHEMA_VERSION);
+        for file in files {
+            let backup = format!("{}.v{}.bak", file, version);
+            fs::copy(file, &backup)?;
+            info!("Backed up {} to {}", file, backup);
+            self.backups.push(backup);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn entry() -> Entry {
+        Entry {
+            id: "1".to_string(),
+            title: "Example".to_string(),
+            username: Some("alice".to_string()),
+            password: Some("hunter2".to_string()),
+            note: Some("note".to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn v1_bytes() -> Vec<u8> {
+        bincode::serialize(&EntryV1 {
+            id: "1".to_string(),
+            title: "Example".to_string(),
+            username: Some("alice".to_string()),
+            password: Some("hunter2".to_string()),
+            url: None,
+            note: Some("note".to_string()),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_upgrades_baseline() {
+        let registry = MigrationRegistry::default();
+
+        let upgraded = registry
+            .upgrade(BASELINE_SCHEMA_VERSION, &v1_bytes())
+            .unwrap();
+        assert_eq!(upgraded.steps.len(), 1);
+        assert_eq!(
+            bincode::deserialize::<Entry>(&upgraded.bytes).unwrap(),
+            entry()
+        );
+
+        let current = bincode::serialize(&entry()).unwrap();
+        let upgraded = registry.upgrade(STORE_SCHEMA_VERSION, &current).unwrap();
+        assert!(upgraded.steps.is_empty());
+        assert_eq!(upgraded.bytes, current);
+    }
+
+    #[test]
+    fn test_unknown_layout() {
+        let registry = MigrationRegistry::default();
+        let current = bincode::serialize(&entry()).unwrap();
+
+        // Only the layout the header names is tried
+        for bytes in [&b"not an entry"[..], &current] {
+            let result = registry.upgrade(BASELINE_SCHEMA_VERSION, bytes);
+            assert!(matches!(result, Err(StoreError::UnknownRecordLayout)));
+        }
+        let result = registry.upgrade(STORE_SCHEMA_VERSION + 1, &current);
+        assert!(matches!(result, Err(StoreError::UnknownRecordLayout)));
+    }
+
+    #[test]
+    fn test_schema_header() {
+        let mut current = Vec::new();
+        SchemaHeader::write_current(&mut current).unwrap();
+        current.extend(v1_bytes());
+
+        let header = SchemaHeader::read(&mut &current[..]).unwrap();
+        assert_eq!(header.version, STORE_SCHEMA_VERSION);
+        assert!(header.is_written());
+        assert!(header.check().is_ok());
+
+        let header = SchemaHeader::read(&mut &v1_bytes()[..]).unwrap();
+        assert_eq!(header.version, BASELINE_SCHEMA_VERSION);
+        assert_eq!(header.len, 0);
+        assert!(matches!(
+            header.check(),
+            Err(StoreError::MigrationRequired { version: 1 })
+        ));
+
+        // A header cut off by a crash, with nothing after it
+        let header = SchemaHeader::read(&mut &current[..5]).unwrap();
+        assert_eq!((header.version, header.len), (STORE_SCHEMA_VERSION, 5));
+        assert!(!header.is_written());
+    }
+}
@@ -1,7 +1,42 @@
+pub mod access_log;
+pub mod activity;
+pub mod archive;
+pub mod backup;
 pub mod binary_file_entry_store;
 pub mod binary_index_iterator;
 pub mod binary_record_iterator;
-pub mod binary_store_error;
+pub mod bloom_filter;
+pub mod compact_index;
+pub mod compaction;
 pub mod data_store;
+pub mod durability;
+pub mod email_alias;
+pub mod encrypted_store;
+pub mod entry_diff;
+pub mod favorites;
+pub mod file_swap;
+pub mod fs;
+pub mod generations;
+#[cfg(any(test, feature = "testing"))]
+pub mod generator;
+pub mod hooked_store;
+pub mod index_journal;
 pub mod indexed_binary_file_entry_store;
+pub mod migration;
 pub mod model;
+pub mod multi_vault;
+pub mod policy_store;
+pub mod quota_store;
+pub mod retry;
+pub mod sanitize;
+pub mod search_cache;
+pub mod secondary_index;
+pub mod share;
+pub mod sort;
+pub mod store_backend;
+pub mod store_error;
+#[cfg(feature = "watch")]
+pub mod store_watcher;
+pub mod sync;
+pub mod text_search;
+pub mod vault_metadata;
@@ -1,11 +1,396 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+use super::{data_store::Versioned, entry_diff::EntryField};
+
+const REDACTED: &str = "***";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum Label {
+    Red,
+    Orange,
+    Yellow,
+    Green,
+    Blue,
+    Purple,
+    Gray,
+}
+
+// What an entry holds, which decides the fields it uses. Kinds other than
+// logins keep their details, such as a card's number and expiry, in the
+// note rather than in the username and password.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum EntryKind {
+    #[default]
+    Login,
+    SecureNote,
+    Card,
+    Identity,
+    // An API key or access token, the secret kept as the password
+    Token,
+}
+
+impl EntryKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EntryKind::Login => "login",
+            EntryKind::SecureNote => "secure note",
+            EntryKind::Card => "card",
+            EntryKind::Identity => "identity",
+            EntryKind::Token => "token",
+        }
+    }
+
+    // The fields shown for an entry of this kind, in order
+    pub fn display_fields(&self) -> &'static [EntryField] {
+        match self {
+            EntryKind::Login => &[
+                EntryField::Title,
+                EntryField::Username,
+                EntryField::Password,
+                EntryField::Url,
+                EntryField::Note,
+            ],
+            EntryKind::SecureNote | EntryKind::Identity => &[EntryField::Title, EntryField::Note],
+            EntryKind::Card => &[EntryField::Title, EntryField::Url, EntryField::Note],
+            EntryKind::Token => &[
+                EntryField::Title,
+                EntryField::Token,
+                EntryField::Password,
+                EntryField::Url,
+                EntryField::Note,
+            ],
+        }
+    }
+
+    // Checks `entry` only sets fields its kind uses, and has what it needs
+    pub fn validate(&self, entry: &Entry) -> Result<(), KindViolation> {
+        let fields = self.display_fields();
+        let set = [
+            (EntryField::Username, entry.username.is_some()),
+            (EntryField::Password, entry.password.is_some()),
+            (EntryField::Url, entry.url.is_some()),
+            (EntryField::Token, entry.token.is_some()),
+        ];
+        if let Some((field, _)) = set
+            .iter()
+            .find(|(field, is_set)| *is_set && !fields.contains(field))
+        {
+            return Err(KindViolation::UnusedField(*self, *field));
+        }
+        match self {
+            EntryKind::Login => {}
+            EntryKind::Token => {
+                if entry.password.as_deref().is_none_or(str::is_empty) {
+                    return Err(KindViolation::MissingField(*self, EntryField::Password));
+                }
+            }
+            _ => {
+                if entry.note.as_deref().is_none_or(str::is_empty) {
+                    return Err(KindViolation::MissingField(*self, EntryField::Note));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KindViolation {
+    // A field set that entries of the kind don't use
+    UnusedField(EntryKind, EntryField),
+    MissingField(EntryKind, EntryField),
+}
+
+impl fmt::Display for KindViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            KindViolation::UnusedField(kind, field) => {
+                write!(f, "A {} has no {}", kind.name(), field.name())
+            }
+            KindViolation::MissingField(kind, field) => {
+                write!(f, "A {} needs a {}", kind.name(), field.name())
+            }
+        }
+    }
+}
+
+impl std::error::Error for KindViolation {}
+
+// What is known about a token besides the secret itself
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct TokenInfo {
+    // The public start of the token, e.g. "ghp_", to tell tokens apart
+    // without revealing them
+    pub prefix: Option<String>,
+    pub scopes: Vec<String>,
+    // Unix time in milliseconds, 0 when it doesn't expire
+    pub expires_at: u64,
+}
+
+impl TokenInfo {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at != 0 && self.expires_at <= now
+    }
+
+    // One line for diffs and listings
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(prefix) = &self.prefix {
+            parts.push(format!("prefix {}", prefix));
+        }
+        if !self.scopes.is_empty() {
+            parts.push(format!("scopes {}", self.scopes.join(" ")));
+        }
+        if self.expires_at != 0 {
+            parts.push(format!("expires at {}", self.expires_at));
+        }
+        parts.join(", ")
+    }
+}
+
+// `Debug` is implemented by hand so secrets never end up in logs. The
+// default is an untitled login with nothing else set.
+#[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Entry {
     pub id: String,
     pub title: String,
+    pub kind: EntryKind,
+    // Only on tokens
+    pub token: Option<TokenInfo>,
     pub username: Option<String>,
     pub password: Option<String>,
     pub url: Option<String>,
     pub note: Option<String>,
+    pub favorite: bool,
+    pub label: Option<Label>,
+    pub tags: Vec<String>,
+    // Hidden from default listings, searches and autofill, but kept
+    pub archived: bool,
+    // Position in a manually ordered list, see `SortBy::Manual`. Keys are
+    // compared as strings, so one can always be made between two others
+    // without renumbering the rest.
+    pub sort_key: Option<String>,
+    // Unix time in milliseconds of the last versioned save, 0 when unknown
+    pub updated_at: u64,
+    // Unix time in milliseconds of the versioned save that last changed the
+    // password, 0 when unknown or there is no password
+    pub password_changed_at: u64,
+}
+
+impl Entry {
+    // Opt in to printing the protected fields as well
+    pub fn reveal(&self) -> RevealedEntry<'_> {
+        RevealedEntry(self)
+    }
+
+    fn debug_struct(&self, f: &mut fmt::Formatter<'_>, reveal: bool) -> fmt::Result {
+        let password = if reveal {
+            self.password.as_deref()
+        } else {
+            self.password.as_ref().map(|_| REDACTED)
+        };
+
+        f.debug_struct("Entry")
+            .field("id", &self.id)
+            .field("title", &self.title)
+            .field("kind", &self.kind)
+            .field("token", &self.token)
+            .field("username", &self.username)
+            .field("password", &password)
+            .field("url", &self.url)
+            .field("note", &self.note)
+            .field("favorite", &self.favorite)
+            .field("label", &self.label)
+            .field("tags", &self.tags)
+            .field("archived", &self.archived)
+            .field("sort_key", &self.sort_key)
+            .field("updated_at", &self.updated_at)
+            .field("password_changed_at", &self.password_changed_at)
+            .finish()
+    }
+}
+
+impl Versioned for Entry {
+    fn updated_at(&self) -> u64 {
+        self.updated_at
+    }
+
+    fn set_updated_at(&mut self, updated_at: u64) {
+        self.updated_at = updated_at;
+    }
+
+    // Other edits, e.g. a new title or tag, don't make the password newer
+    fn replacing(&mut self, previous: Option<&Self>) {
+        self.password_changed_at = match previous {
+            Some(previous) if previous.password == self.password => previous.password_changed_at,
+            _ if self.password.is_some() => self.updated_at,
+            _ => 0,
+        };
+    }
+}
+
+impl fmt::Debug for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.debug_struct(f, false)
+    }
+}
+
+pub struct RevealedEntry<'a>(pub &'a Entry);
+
+impl fmt::Debug for RevealedEntry<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.debug_struct(f, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry {
+            id: "1".to_string(),
+            title: "Example".to_string(),
+            username: Some("user1".to_string()),
+            password: Some("hunter2".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_debug_redacts_password() {
+        let output = format!("{:?}", entry());
+
+        assert!(!output.contains("hunter2"));
+        assert!(output.contains("password: Some(\"***\")"));
+        assert!(output.contains("user1"));
+    }
+
+    #[test]
+    fn test_debug_without_password() {
+        let mut entry = entry();
+        entry.password = None;
+
+        assert!(format!("{:?}", entry).contains("password: None"));
+    }
+
+    #[test]
+    fn test_revealed_entry_shows_password() {
+        let entry = entry();
+
+        assert!(format!("{:?}", entry.reveal()).contains("hunter2"));
+    }
+
+    #[test]
+    fn test_kind_validation() {
+        let login = entry();
+        assert_eq!(EntryKind::Login.validate(&login), Ok(()));
+        assert_eq!(
+            EntryKind::SecureNote.validate(&login),
+            Err(KindViolation::UnusedField(
+                EntryKind::SecureNote,
+                EntryField::Username
+            ))
+        );
+
+        let card = Entry {
+            kind: EntryKind::Card,
+            username: None,
+            password: None,
+            url: Some("https://bank.example".to_string()),
+            ..entry()
+        };
+        assert_eq!(
+            card.kind.validate(&card).unwrap_err().to_string(),
+            "A card needs a note"
+        );
+        let card = Entry {
+            note: Some("4111 1111 1111 1111, 12/30".to_string()),
+            ..card
+        };
+        assert_eq!(card.kind.validate(&card), Ok(()));
+        assert_eq!(
+            EntryKind::Identity.validate(&card),
+            Err(KindViolation::UnusedField(
+                EntryKind::Identity,
+                EntryField::Url
+            ))
+        );
+        assert!(!EntryKind::SecureNote
+            .display_fields()
+            .contains(&EntryField::Password));
+    }
+
+    #[test]
+    fn test_password_change_time_survives_other_edits() {
+        let mut created = entry();
+        created.set_updated_at(1);
+        created.replacing(None);
+        assert_eq!(created.password_changed_at, 1);
+
+        let mut renamed = Entry {
+            title: "Renamed".to_string(),
+            tags: vec!["work".to_string()],
+            ..created.clone()
+        };
+        renamed.set_updated_at(2);
+        renamed.replacing(Some(&created));
+        assert_eq!(renamed.password_changed_at, 1);
+
+        let mut rotated = Entry {
+            password: Some("correct horse".to_string()),
+            ..renamed.clone()
+        };
+        rotated.set_updated_at(3);
+        rotated.replacing(Some(&renamed));
+        assert_eq!(rotated.password_changed_at, 3);
+
+        let mut cleared = Entry {
+            password: None,
+            ..rotated.clone()
+        };
+        cleared.set_updated_at(4);
+        cleared.replacing(Some(&rotated));
+        assert_eq!(cleared.password_changed_at, 0);
+    }
+
+    #[test]
+    fn test_token_validation() {
+        let token = Entry {
+            kind: EntryKind::Token,
+            token: Some(TokenInfo {
+                prefix: Some("ghp_".to_string()),
+                scopes: vec!["repo".to_string(), "read:org".to_string()],
+                expires_at: 1_000,
+            }),
+            username: None,
+            ..entry()
+        };
+        assert_eq!(token.kind.validate(&token), Ok(()));
+        assert!(token.token.as_ref().unwrap().is_expired(1_000));
+        assert_eq!(
+            token.token.as_ref().unwrap().summary(),
+            "prefix ghp_, scopes repo read:org, expires at 1000"
+        );
+
+        let without_secret = Entry {
+            password: None,
+            ..token.clone()
+        };
+        assert_eq!(
+            EntryKind::Token.validate(&without_secret),
+            Err(KindViolation::MissingField(
+                EntryKind::Token,
+                EntryField::Password
+            ))
+        );
+        assert_eq!(
+            EntryKind::Login.validate(&token),
+            Err(KindViolation::UnusedField(
+                EntryKind::Login,
+                EntryField::Token
+            ))
+        );
+    }
 }
@@ -0,0 +1,142 @@
+use super::{
+    data_store::Filter,
+    model::Entry,
+    store_backend::{EntryStore, StoreBackend},
+    store_error::StoreError,
+};
+
+// An entry found by a search across vaults, labeled with its vault
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VaultHit {
+    pub vault: String,
+    pub entry: Entry,
+}
+
+#[derive(Debug, Default)]
+pub struct MultiVaultResults {
+    // By vault in the order they were added, then by id within a vault
+    pub hits: Vec<VaultHit>,
+    // Vaults that couldn't be searched. The others still are, so one broken
+    // vault doesn't hide the results of the rest.
+    pub failures: Vec<(String, StoreError)>,
+}
+
+// Runs one search over several open vaults, e.g. a personal and a work one.
+// There is no vault config yet, so callers add the vaults to search.
+#[derive(Default)]
+pub struct MultiVaultSearcher {
+    vaults: Vec<(String, EntryStore)>,
+}
+
+impl MultiVaultSearcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A vault added under a name already in use replaces the earlier one
+    pub fn with_vault(mut self, name: &str, store: EntryStore) -> Self {
+        self.vaults.retain(|(vault, _)| vault != name);
+        self.vaults.push((name.to_string(), store));
+        self
+    }
+
+    pub fn open<I>(backends: I) -> Result<Self, StoreError>
+    where
+        I: IntoIterator<Item = (String, StoreBackend)>,
+    {
+        backends
+            .into_iter()
+            .try_fold(Self::new(), |searcher, (name, backend)| {
+                Ok(searcher.with_vault(&name, backend.open()?))
+            })
+    }
+
+    pub fn vaults(&self) -> impl Iterator<Item = &str> {
+        self.vaults.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn search(&self, filter: &dyn Filter<Entry>) -> MultiVaultResults {
+        let mut results = MultiVaultResults::default();
+
+        for (vault, store) in &self.vaults {
+            match store.search(filter) {
+                Ok(entries) => results
+                    .hits
+                    .extend(entries.into_iter().map(|entry| VaultHit {
+                        vault: vault.clone(),
+                        entry,
+                    })),
+                Err(e) => results.failures.push((vault.clone(), e)),
+            }
+        }
+
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{durability::SyncLevel, text_search::TextFilter};
+    use std::fs;
+    use uuid::Uuid;
+
+    fn entry(id: &str, title: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: title.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_search_labels_hits_by_vault() {
+        let id = Uuid::new_v4();
+        let personal_path = format!("test_multi_vault_personal_{}.bin", id);
+        let work_path = format!("test_multi_vault_work_{}.bin", id);
+        let backend = |file_path: &String| StoreBackend::Binary {
+            file_path: file_path.clone(),
+            sync_level: SyncLevel::FlushOnSave,
+        };
+
+        let mut personal = backend(&personal_path).open().unwrap();
+        personal
+            .save(&"1".to_string(), &entry("1", "Mail"))
+            .unwrap();
+        personal
+            .save(&"2".to_string(), &entry("2", "Bank"))
+            .unwrap();
+        let mut work = backend(&work_path).open().unwrap();
+        work.save(&"1".to_string(), &entry("1", "Work mail"))
+            .unwrap();
+
+        let searcher = MultiVaultSearcher::open([
+            ("personal".to_string(), backend(&personal_path)),
+            ("work".to_string(), backend(&work_path)),
+        ])
+        .unwrap();
+        assert_eq!(
+            searcher.vaults().collect::<Vec<_>>(),
+            vec!["personal", "work"]
+        );
+
+        let results = searcher.search(&TextFilter::new("mail"));
+        assert!(results.failures.is_empty());
+        assert_eq!(
+            results.hits,
+            vec![
+                VaultHit {
+                    vault: "personal".to_string(),
+                    entry: entry("1", "Mail"),
+                },
+                VaultHit {
+                    vault: "work".to_string(),
+                    entry: entry("1", "Work mail"),
+                },
+            ]
+        );
+
+        fs::remove_file(personal_path).unwrap();
+        fs::remove_file(work_path).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+use super::{
+    data_store::{DataStore, Filter},
+    model::Entry,
+    store_error::StoreError,
+};
+use crate::secret::password_policy::PasswordPolicy;
+
+// Wraps a store so passwords are checked against a `PasswordPolicy` before
+// they are saved. Only new or changed passwords are checked, so entries
+// saved under an older policy can still be edited.
+pub struct PolicyStore<S> {
+    store: S,
+    policy: PasswordPolicy,
+}
+
+impl<S: DataStore<String, Entry, StoreError>> PolicyStore<S> {
+    pub fn new(store: S, policy: PasswordPolicy) -> Self {
+        PolicyStore { store, policy }
+    }
+
+    pub fn policy(&self) -> &PasswordPolicy {
+        &self.policy
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    // Saves without checking the policy, for when the user explicitly
+    // accepts a weak password
+    pub fn save_overriding_policy(&mut self, id: &String, entry: &Entry) -> Result<(), StoreError> {
+        self.store.save(id, entry)
+    }
+
+    fn check(&self, id: &String, entry: &Entry) -> Result<(), StoreError> {
+        let password = match entry.password.as_deref() {
+            Some(password) => password,
+            None => return Ok(()),
+        };
+
+        let unchanged = self
+            .store
+            .load(id)?
+            .is_some_and(|existing| existing.password.as_deref() == Some(password));
+        if !unchanged {
+            self.policy.check(password)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<S: DataStore<String, Entry, StoreError>> DataStore<String, Entry, StoreError>
+    for PolicyStore<S>
+{
+    fn save(&mut self, id: &String, entry: &Entry) -> Result<(), StoreError> {
+        self.check(id, entry)?;
+        self.store.save(id, entry)
+    }
+
+    fn load(&self, id: &String) -> Result<Option<Entry>, StoreError> {
+        self.store.load(id)
+    }
+
+    fn delete(&mut self, id: &String) -> Result<(), StoreError> {
+        self.store.delete(id)
+    }
+
+    fn search(&self, filter: &dyn Filter<Entry>) -> Result<Vec<Entry>, StoreError> {
+        self.store.search(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secret::password_policy::PolicyViolation;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct MemoryStore(HashMap<String, Entry>);
+
+    impl DataStore<String, Entry, StoreError> for MemoryStore {
+        fn save(&mut self, id: &String, entry: &Entry) -> Result<(), StoreError> {
+            self.0.insert(id.clone(), entry.clone());
+            Ok(())
+        }
+
+        fn load(&self, id: &String) -> Result<Option<Entry>, StoreError> {
+            Ok(self.0.get(id).cloned())
+        }
+
+        fn delete(&mut self, id: &String) -> Result<(), StoreError> {
+            self.0.remove(id);
+            Ok(())
+        }
+
+        fn search(&self, filter: &dyn Filter<Entry>) -> Result<Vec<Entry>, StoreError> {
+            Ok(self
+                .0
+                .values()
+                .filter(|e| filter.pass(e))
+                .cloned()
+                .collect())
+        }
+    }
+
+    fn entry(password: Option<&str>) -> Entry {
+        Entry {
+            id: "1".to_string(),
+            title: "Example".to_string(),
+            password: password.map(|p| p.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn store() -> PolicyStore<MemoryStore> {
+        PolicyStore::new(
+            MemoryStore::default(),
+            PasswordPolicy::default().with_min_length(8),
+        )
+    }
+
+    #[test]
+    fn test_rejects_weak_password() {
+        let mut store = store();
+        let weak = entry(Some("short"));
+
+        let result = store.save(&weak.id, &weak);
+
+        assert!(matches!(
+            result,
+            Err(StoreError::PolicyViolation(
+                PolicyViolation::TooShort { .. }
+            ))
+        ));
+        assert_eq!(store.load(&weak.id).unwrap(), None);
+    }
+
+    #[test]
+    fn test_accepts_strong_or_missing_password() {
+        let mut store = store();
+
+        store
+            .save(&"1".to_string(), &entry(Some("long enough")))
+            .unwrap();
+        store.save(&"1".to_string(), &entry(None)).unwrap();
+    }
+
+    #[test]
+    fn test_override_saves_weak_password() {
+        let mut store = store();
+        let weak = entry(Some("short"));
+
+        store.save_overriding_policy(&weak.id, &weak).unwrap();
+
+        assert_eq!(store.load(&weak.id).unwrap(), Some(weak));
+    }
+
+    #[test]
+    fn test_unchanged_password_is_not_rechecked() {
+        let mut store = store();
+        let mut weak = entry(Some("short"));
+        store.save_overriding_policy(&weak.id, &weak).unwrap();
+
+        weak.title = "Renamed".to_string();
+        store.save(&weak.id, &weak).unwrap();
+
+        assert_eq!(store.load(&weak.id).unwrap(), Some(weak));
+    }
+}
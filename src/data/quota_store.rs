@@ -0,0 +1,235 @@
+use std::fmt;
+
+use super::{
+    data_store::{filter_fn, DataStore, Filter},
+    model::Entry,
+    store_error::StoreError,
+};
+
+// Caps on what a store may hold, each unlimited unless set. They keep the
+// single file formats from growing without bound, e.g. for mobile bundles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StoreLimits {
+    max_entries: Option<usize>,
+    // In characters
+    max_note_len: Option<usize>,
+    // Of the encoded entry, which bounds every record written for it
+    max_entry_size: Option<usize>,
+}
+
+impl StoreLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    pub fn with_max_note_len(mut self, max_note_len: usize) -> Self {
+        self.max_note_len = Some(max_note_len);
+        self
+    }
+
+    pub fn with_max_entry_size(mut self, max_entry_size: usize) -> Self {
+        self.max_entry_size = Some(max_entry_size);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    Entries { max: usize },
+    NoteLength { length: usize, max: usize },
+    EntrySize { size: usize, max: usize },
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            LimitExceeded::Entries { max } => write!(f, "The store is full at {} entries", max),
+            LimitExceeded::NoteLength { length, max } => write!(
+                f,
+                "The note is {} characters, at most {} are allowed",
+                length, max
+            ),
+            LimitExceeded::EntrySize { size, max } => write!(
+                f,
+                "The entry takes {} bytes, at most {} are allowed",
+                size, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+// Wraps a store to enforce `StoreLimits` when saving. The limits are soft
+// towards what is already stored: an entry over a limit set later can
+// still be saved as long as it doesn't grow past what it was.
+pub struct QuotaStore<S> {
+    store: S,
+    limits: StoreLimits,
+    // Counted on the first save that needs it, then kept up to date
+    entries: Option<usize>,
+}
+
+impl<S: DataStore<String, Entry, StoreError>> QuotaStore<S> {
+    pub fn new(store: S, limits: StoreLimits) -> Self {
+        QuotaStore {
+            store,
+            limits,
+            entries: None,
+        }
+    }
+
+    pub fn limits(&self) -> &StoreLimits {
+        &self.limits
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    fn entry_count(&mut self) -> Result<usize, StoreError> {
+        match self.entries {
+            Some(entries) => Ok(entries),
+            None => {
+                let entries = self.store.search(&filter_fn(|_: &Entry| true))?.len();
+                self.entries = Some(entries);
+                Ok(entries)
+            }
+        }
+    }
+
+    fn check(&mut self, entry: &Entry, existing: Option<&Entry>) -> Result<(), StoreError> {
+        if let (Some(max), None) = (self.limits.max_entries, existing) {
+            if self.entry_count()? >= max {
+                return Err(LimitExceeded::Entries { max }.into());
+            }
+        }
+
+        if let Some(max) = self.limits.max_note_len {
+            let note_len = |entry: &Entry| entry.note.as_deref().map_or(0, |n| n.chars().count());
+            let length = note_len(entry);
+            let allowed = existing.map_or(max, |existing| max.max(note_len(existing)));
+            if length > allowed {
+                return Err(LimitExceeded::NoteLength { length, max }.into());
+            }
+        }
+
+        if let Some(max) = self.limits.max_entry_size {
+            let size = bincode::serialized_size(entry)? as usize;
+            let allowed = match existing {
+                Some(existing) => max.max(bincode::serialized_size(existing)? as usize),
+                None => max,
+            };
+            if size > allowed {
+                return Err(LimitExceeded::EntrySize { size, max }.into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<S: DataStore<String, Entry, StoreError>> DataStore<String, Entry, StoreError>
+    for QuotaStore<S>
+{
+    fn save(&mut self, id: &String, entry: &Entry) -> Result<(), StoreError> {
+        let existing = self.store.load(id)?;
+        self.check(entry, existing.as_ref())?;
+        self.store.save(id, entry)?;
+        if let (Some(entries), None) = (self.entries.as_mut(), existing) {
+            *entries += 1;
+        }
+        Ok(())
+    }
+
+    fn load(&self, id: &String) -> Result<Option<Entry>, StoreError> {
+        self.store.load(id)
+    }
+
+    fn delete(&mut self, id: &String) -> Result<(), StoreError> {
+        let existed = self.entries.is_some() && self.store.load(id)?.is_some();
+        self.store.delete(id)?;
+        if let (Some(entries), true) = (self.entries.as_mut(), existed) {
+            *entries -= 1;
+        }
+        Ok(())
+    }
+
+    fn search(&self, filter: &dyn Filter<Entry>) -> Result<Vec<Entry>, StoreError> {
+        self.store.search(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::binary_file_entry_store::BinaryFileEntryStore;
+    use std::fs;
+    use uuid::Uuid;
+
+    fn entry(id: &str, note: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: format!("Entry {}", id),
+            note: Some(note.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_limits_are_enforced_on_save() {
+        let path = format!("test_quota_{}.bin", Uuid::new_v4());
+        let mut store = BinaryFileEntryStore::new(path.clone());
+        store
+            .save(&"big".to_string(), &entry("big", &"x".repeat(20)))
+            .unwrap();
+        let limits = StoreLimits::new()
+            .with_max_entries(2)
+            .with_max_note_len(10)
+            .with_max_entry_size(200);
+        let mut store = QuotaStore::new(store, limits);
+
+        assert!(matches!(
+            store.save(&"1".to_string(), &entry("1", &"y".repeat(11))),
+            Err(StoreError::LimitExceeded(LimitExceeded::NoteLength {
+                length: 11,
+                max: 10
+            }))
+        ));
+        store.save(&"1".to_string(), &entry("1", "short")).unwrap();
+        assert!(matches!(
+            store.save(&"2".to_string(), &entry("2", "")),
+            Err(StoreError::LimitExceeded(LimitExceeded::Entries { max: 2 }))
+        ));
+
+        // Already over the note limit, but it may shrink or stay the same
+        store
+            .save(&"big".to_string(), &entry("big", &"z".repeat(20)))
+            .unwrap();
+        assert!(store
+            .save(&"big".to_string(), &entry("big", &"z".repeat(21)))
+            .is_err());
+
+        // Deleting makes room again
+        store.delete(&"big".to_string()).unwrap();
+        store.save(&"2".to_string(), &entry("2", "")).unwrap();
+
+        let mut huge = entry("2", "");
+        huge.tags = vec!["tag".repeat(100)];
+        assert!(matches!(
+            store.save(&"2".to_string(), &huge),
+            Err(StoreError::LimitExceeded(LimitExceeded::EntrySize {
+                max: 200,
+                ..
+            }))
+        ));
+
+        drop(store);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,222 @@
+use log::warn;
+use rand::Rng;
+use std::{error::Error, fmt, io, thread, time::Duration};
+
+// EBUSY and ESTALE, seen on NFS and with files held open by scanners
+const TRANSIENT_OS_ERRORS: [i32; 2] = [16, 116];
+
+// Retries file operations that fail for reasons that usually go away on their
+// own, e.g. a rename on Windows while an antivirus scanner has the file open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // A single attempt, for callers that handle failures themselves
+    pub fn none() -> Self {
+        Self::default().with_max_attempts(1)
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn with_base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub fn is_transient(error: &io::Error) -> bool {
+        match error.kind() {
+            io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::ResourceBusy => true,
+            // What Windows reports for a sharing violation on rename
+            io::ErrorKind::PermissionDenied if cfg!(windows) => true,
+            _ => error
+                .raw_os_error()
+                .is_some_and(|code| TRANSIENT_OS_ERRORS.contains(&code)),
+        }
+    }
+
+    // Exponential backoff with half of each delay jittered, so processes
+    // that collided don't retry in lockstep
+    fn delay(&self, retry: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+        let half = delay / 2;
+        let jitter = rand::rng().random_range(0..=half.as_micros() as u64);
+        half + Duration::from_micros(jitter)
+    }
+
+    // Runs `attempt` until it succeeds, fails with an error that isn't
+    // transient, or runs out of attempts.
+    pub fn run<T>(
+        &self,
+        operation: &str,
+        mut attempt: impl FnMut() -> io::Result<T>,
+    ) -> Result<T, RetryError> {
+        let mut errors = Vec::new();
+
+        loop {
+            match attempt() {
+                Ok(value) => return Ok(value),
+                Err(e) => {
+                    let transient = Self::is_transient(&e);
+                    errors.push(e);
+                    if !transient || errors.len() as u32 >= self.max_attempts {
+                        return Err(RetryError {
+                            operation: operation.to_string(),
+                            errors,
+                        });
+                    }
+                }
+            }
+
+            let delay = self.delay(errors.len() as u32 - 1);
+            warn!(
+                "{} failed ({}), retrying in {:?}",
+                operation,
+                errors[errors.len() - 1],
+                delay
+            );
+            thread::sleep(delay);
+        }
+    }
+}
+
+// The errors of every attempt at `operation`, oldest first
+#[derive(Debug)]
+pub struct RetryError {
+    pub operation: String,
+    pub errors: Vec<io::Error>,
+}
+
+impl RetryError {
+    pub fn last(&self) -> &io::Error {
+        // `run` never returns an error without at least one attempt
+        &self.errors[self.errors.len() - 1]
+    }
+
+    pub fn into_last(mut self) -> io::Error {
+        self.errors.pop().expect("no attempts were made")
+    }
+}
+
+impl fmt::Display for RetryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} failed after {} attempt(s): {}",
+            self.operation,
+            self.errors.len(),
+            self.last()
+        )
+    }
+}
+
+impl Error for RetryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.last())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::default()
+            .with_max_attempts(3)
+            .with_base_delay(Duration::ZERO)
+    }
+
+    fn busy() -> io::Error {
+        io::Error::from(io::ErrorKind::ResourceBusy)
+    }
+
+    #[test]
+    fn test_retries_transient_errors() {
+        let mut calls = 0;
+
+        let result = policy().run("rename", || {
+            calls += 1;
+            if calls < 3 {
+                Err(busy())
+            } else {
+                Ok(calls)
+            }
+        });
+
+        assert_eq!(result.unwrap(), 3);
+    }
+
+    #[test]
+    fn test_gives_up_with_history() {
+        let mut calls = 0;
+
+        let error = policy()
+            .run("rename", || -> io::Result<()> {
+                calls += 1;
+                Err(busy())
+            })
+            .unwrap_err();
+
+        assert_eq!(calls, 3);
+        assert_eq!(error.errors.len(), 3);
+        assert_eq!(error.operation, "rename");
+        assert!(error
+            .to_string()
+            .starts_with("rename failed after 3 attempt(s)"));
+    }
+
+    #[test]
+    fn test_does_not_retry_permanent_errors() {
+        let mut calls = 0;
+
+        let error = policy()
+            .run("remove", || -> io::Result<()> {
+                calls += 1;
+                Err(io::Error::from(io::ErrorKind::NotFound))
+            })
+            .unwrap_err();
+
+        assert_eq!(calls, 1);
+        assert_eq!(error.into_last().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_delay_is_capped() {
+        let policy = RetryPolicy::default()
+            .with_base_delay(Duration::from_millis(10))
+            .with_max_delay(Duration::from_millis(40));
+
+        for retry in 0..10 {
+            let delay = policy.delay(retry);
+            assert!(delay <= Duration::from_millis(40));
+            assert!(delay >= Duration::from_millis(5));
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::{io, path::Path};
+
+use super::{
+    binary_file_entry_store::BinaryFileEntryStore,
+    data_store::{filter_fn, DataStore},
+    model::Entry,
+    store_error::StoreError,
+};
+
+// What a sanitized copy keeps in place of a password
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum SecretHandling {
+    #[default]
+    Remove,
+    // Keeps the field set, e.g. for demos where empty passwords look broken
+    Placeholder(String),
+}
+
+// Copies entries without their secrets, so a vault's structure can be shared
+// for debugging, demos or as a template
+#[derive(Debug, Clone, Default)]
+pub struct Sanitizer {
+    secrets: SecretHandling,
+    strip_notes: bool,
+}
+
+impl Sanitizer {
+    pub fn with_placeholder(mut self, placeholder: &str) -> Self {
+        self.secrets = SecretHandling::Placeholder(placeholder.to_string());
+        self
+    }
+
+    // Notes are free text and often hold recovery codes or PINs
+    pub fn with_notes_stripped(mut self) -> Self {
+        self.strip_notes = true;
+        self
+    }
+
+    pub fn sanitize(&self, entry: &Entry) -> Entry {
+        let mut sanitized = entry.clone();
+        sanitized.password = match &self.secrets {
+            SecretHandling::Remove => None,
+            SecretHandling::Placeholder(placeholder) => {
+                entry.password.as_ref().map(|_| placeholder.clone())
+            }
+        };
+        if self.strip_notes {
+            sanitized.note = None;
+        }
+        sanitized
+    }
+
+    // Saves a sanitized copy of every entry of `source` into `target` and
+    // returns how many were copied
+    pub fn copy<S, T>(&self, source: &S, target: &mut T) -> Result<usize, StoreError>
+    where
+        S: DataStore<String, Entry, StoreError> + ?Sized,
+        T: DataStore<String, Entry, StoreError> + ?Sized,
+    {
+        let entries = source.search(&filter_fn(|_: &Entry| true))?;
+        for entry in &entries {
+            target.save(&entry.id, &self.sanitize(entry))?;
+        }
+        Ok(entries.len())
+    }
+
+    // Creates a new store at `file_path` holding a sanitized copy of `source`.
+    // Refuses to write into an existing file, which may hold real secrets.
+    pub fn clone_sanitized<S>(
+        &self,
+        source: &S,
+        file_path: String,
+    ) -> Result<BinaryFileEntryStore, StoreError>
+    where
+        S: DataStore<String, Entry, StoreError> + ?Sized,
+    {
+        if Path::new(&file_path).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", file_path),
+            )
+            .into());
+        }
+
+        let mut target = BinaryFileEntryStore::new(file_path);
+        self.copy(source, &mut target)?;
+        Ok(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use uuid::Uuid;
+
+    fn entry(id: &str, password: Option<&str>) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: format!("Entry {}", id),
+            username: Some("alice".to_string()),
+            password: password.map(str::to_string),
+            note: Some("backup codes: 1234".to_string()),
+            tags: vec!["work".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let with_password = entry("1", Some("hunter2"));
+        let without_password = entry("2", None);
+
+        let removed = Sanitizer::default().sanitize(&with_password);
+        assert_eq!(removed.password, None);
+        assert_eq!(removed.note, with_password.note);
+        assert_eq!(removed.tags, with_password.tags);
+
+        let sanitizer = Sanitizer::default()
+            .with_placeholder("changeme")
+            .with_notes_stripped();
+        let replaced = sanitizer.sanitize(&with_password);
+        assert_eq!(replaced.password, Some("changeme".to_string()));
+        assert_eq!(replaced.note, None);
+        assert_eq!(sanitizer.sanitize(&without_password).password, None);
+    }
+
+    #[test]
+    fn test_clone_sanitized() {
+        let source_path = format!("test_sanitize_source_{}.bin", Uuid::new_v4());
+        let target_path = format!("test_sanitize_target_{}.bin", Uuid::new_v4());
+        let mut source = BinaryFileEntryStore::new(source_path.clone());
+        for entry in [entry("1", Some("hunter2")), entry("2", None)] {
+            source.save(&entry.id, &entry).unwrap();
+        }
+
+        let sanitizer = Sanitizer::default();
+        let target = sanitizer
+            .clone_sanitized(&source, target_path.clone())
+            .unwrap();
+        let copied = target.load(&"1".to_string()).unwrap().unwrap();
+        assert_eq!(copied.password, None);
+        assert_eq!(copied.title, "Entry 1");
+        assert!(target.load(&"2".to_string()).unwrap().is_some());
+        assert!(!fs::read(&target_path)
+            .unwrap()
+            .windows(7)
+            .any(|w| w == b"hunter2"));
+
+        // The source is left alone and an existing file is never reused
+        assert_eq!(
+            source.load(&"1".to_string()).unwrap().unwrap().password,
+            Some("hunter2".to_string())
+        );
+        assert!(sanitizer
+            .clone_sanitized(&source, target_path.clone())
+            .is_err());
+
+        fs::remove_file(source_path).unwrap();
+        fs::remove_file(target_path).unwrap();
+    }
+}
@@ -0,0 +1,304 @@
+use std::{
+    collections::{hash_map::DefaultHasher, VecDeque},
+    hash::{Hash, Hasher},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use super::{
+    data_store::{DataStore, Filter},
+    model::Entry,
+    store_error::StoreError,
+    text_search::TextFilter,
+};
+
+// Searches kept by default, the least recently used is dropped first
+const DEFAULT_CAPACITY: usize = 32;
+
+// Wraps a store to keep the results of recent searches, so search as you
+// type doesn't read the whole store on every key. Results are kept whole
+// rather than as ids, which a store without an index would have to scan
+// for one by one. Any save or delete through the wrapper empties the
+// cache, changes made around it aren't noticed.
+pub struct CachedSearchStore<S> {
+    store: S,
+    capacity: usize,
+    cache: Mutex<SearchCache>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    // Text searches answered from the results of a shorter query
+    pub narrowed: usize,
+    pub misses: usize,
+}
+
+struct CachedSearch {
+    key: u64,
+    // The query of a text search, which longer queries can narrow down
+    text: Option<String>,
+    entries: Vec<Entry>,
+}
+
+#[derive(Default)]
+struct SearchCache {
+    // Most recently used last
+    searches: VecDeque<CachedSearch>,
+    stats: CacheStats,
+}
+
+impl SearchCache {
+    fn get(&mut self, key: u64) -> Option<Vec<Entry>> {
+        let position = self.searches.iter().position(|search| search.key == key)?;
+        let search = self.searches.remove(position)?;
+        let entries = search.entries.clone();
+        self.searches.push_back(search);
+        Some(entries)
+    }
+
+    fn insert(&mut self, search: CachedSearch, capacity: usize) {
+        self.searches.push_back(search);
+        while self.searches.len() > capacity {
+            self.searches.pop_front();
+        }
+    }
+}
+
+fn key_of<Q: Hash + ?Sized>(kind: &str, query: &Q) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    kind.hash(&mut hasher);
+    query.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<S: DataStore<String, Entry, StoreError>> CachedSearchStore<S> {
+    pub fn new(store: S) -> Self {
+        CachedSearchStore {
+            store,
+            capacity: DEFAULT_CAPACITY,
+            cache: Mutex::new(SearchCache::default()),
+        }
+    }
+
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    // Searches with `filter`, cached under `key`. The key has to describe
+    // the filter fully, e.g. the query and options it was built from, as
+    // filters themselves can't be compared.
+    pub fn search_keyed<Q: Hash + ?Sized>(
+        &self,
+        key: &Q,
+        filter: &dyn Filter<Entry>,
+    ) -> Result<Vec<Entry>, StoreError> {
+        let key = key_of("keyed", key);
+        let mut cache = self.cache();
+        if let Some(entries) = cache.get(key) {
+            cache.stats.hits += 1;
+            return Ok(entries);
+        }
+        // The lock isn't held while the store is read
+        drop(cache);
+
+        let entries = self.store.search(filter)?;
+        let mut cache = self.cache();
+        cache.stats.misses += 1;
+        cache.insert(
+            CachedSearch {
+                key,
+                text: None,
+                entries: entries.clone(),
+            },
+            self.capacity,
+        );
+        Ok(entries)
+    }
+
+    // A `TextFilter` search. A query that extends a cached one, as the next
+    // key typed does, can only match fewer entries, so it is run over the
+    // cached results instead of the store.
+    pub fn search_text(&self, query: &str) -> Result<Vec<Entry>, StoreError> {
+        let key = key_of("text", query);
+        let filter = TextFilter::new(query);
+        let mut cache = self.cache();
+        if let Some(entries) = cache.get(key) {
+            cache.stats.hits += 1;
+            return Ok(entries);
+        }
+
+        let narrowed = cache
+            .searches
+            .iter()
+            .filter(|search| {
+                search
+                    .text
+                    .as_deref()
+                    .is_some_and(|text| query.starts_with(text))
+            })
+            .max_by_key(|search| search.text.as_ref().map_or(0, String::len))
+            .map(|search| {
+                search
+                    .entries
+                    .iter()
+                    .filter(|entry| filter.pass(entry))
+                    .cloned()
+                    .collect::<Vec<_>>()
+            });
+        let entries = match narrowed {
+            Some(entries) => {
+                cache.stats.narrowed += 1;
+                entries
+            }
+            None => {
+                cache.stats.misses += 1;
+                // The lock isn't held while the store is read
+                drop(cache);
+                let entries = self.store.search(&filter)?;
+                cache = self.cache();
+                entries
+            }
+        };
+
+        cache.insert(
+            CachedSearch {
+                key,
+                text: Some(query.to_string()),
+                entries: entries.clone(),
+            },
+            self.capacity,
+        );
+        Ok(entries)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache().stats
+    }
+
+    pub fn clear_cache(&self) {
+        self.cache().searches.clear();
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    fn cache(&self) -> MutexGuard<'_, SearchCache> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<S: DataStore<String, Entry, StoreError>> DataStore<String, Entry, StoreError>
+    for CachedSearchStore<S>
+{
+    fn save(&mut self, id: &String, entry: &Entry) -> Result<(), StoreError> {
+        self.clear_cache();
+        self.store.save(id, entry)
+    }
+
+    fn load(&self, id: &String) -> Result<Option<Entry>, StoreError> {
+        self.store.load(id)
+    }
+
+    fn delete(&mut self, id: &String) -> Result<(), StoreError> {
+        self.clear_cache();
+        self.store.delete(id)
+    }
+
+    fn search(&self, filter: &dyn Filter<Entry>) -> Result<Vec<Entry>, StoreError> {
+        self.store.search(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{binary_file_entry_store::BinaryFileEntryStore, data_store::filter_fn};
+    use std::fs;
+    use uuid::Uuid;
+
+    fn entry(id: &str, title: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: title.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn titles(entries: &[Entry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.title.as_str()).collect()
+    }
+
+    #[test]
+    fn test_search_as_you_type() {
+        let file_path = format!("test_search_cache_{}.bin", Uuid::new_v4());
+        let mut store = CachedSearchStore::new(BinaryFileEntryStore::new(file_path.clone()));
+        for (id, title) in [("1", "Bank"), ("2", "Bandcamp"), ("3", "Mail")] {
+            store.save(&id.to_string(), &entry(id, title)).unwrap();
+        }
+
+        assert_eq!(
+            titles(&store.search_text("ba").unwrap()),
+            ["Bank", "Bandcamp"]
+        );
+        assert_eq!(
+            titles(&store.search_text("ban").unwrap()),
+            ["Bank", "Bandcamp"]
+        );
+        assert_eq!(titles(&store.search_text("bank").unwrap()), ["Bank"]);
+        assert_eq!(
+            titles(&store.search_text("ban").unwrap()),
+            ["Bank", "Bandcamp"]
+        );
+        assert_eq!(
+            store.stats(),
+            CacheStats {
+                hits: 1,
+                narrowed: 2,
+                misses: 1
+            }
+        );
+
+        // A write empties the cache
+        store.save(&"4".to_string(), &entry("4", "Banjo")).unwrap();
+        assert_eq!(store.search_text("ban").unwrap().len(), 3);
+        assert_eq!(store.stats().misses, 2);
+
+        drop(store);
+        fs::remove_file(file_path).unwrap();
+    }
+
+    #[test]
+    fn test_keyed_search_and_capacity() {
+        let file_path = format!("test_search_cache_{}.bin", Uuid::new_v4());
+        let mut store =
+            CachedSearchStore::new(BinaryFileEntryStore::new(file_path.clone())).with_capacity(1);
+        store.save(&"1".to_string(), &entry("1", "Bank")).unwrap();
+        let favorites = filter_fn(|entry: &Entry| entry.favorite);
+        let all = filter_fn(|_: &Entry| true);
+
+        assert!(store
+            .search_keyed("favorites", &favorites)
+            .unwrap()
+            .is_empty());
+        assert!(store
+            .search_keyed("favorites", &favorites)
+            .unwrap()
+            .is_empty());
+        assert_eq!(store.search_keyed("all", &all).unwrap().len(), 1);
+        // Pushed out by "all"
+        store.search_keyed("favorites", &favorites).unwrap();
+        assert_eq!(
+            store.stats(),
+            CacheStats {
+                hits: 1,
+                narrowed: 0,
+                misses: 3
+            }
+        );
+
+        drop(store);
+        fs::remove_file(file_path).unwrap();
+    }
+}
@@ -0,0 +1,312 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+};
+
+use super::{
+    data_store::StoreKey, durability::Syncer, file_swap::swap_in, model::Entry,
+    store_error::StoreError,
+};
+
+// "https://user@www.Example.com:8443/login" -> "example.com"
+pub fn domain_of(url: &str) -> Option<String> {
+    let rest = url.trim();
+    let rest = rest.split_once("://").map_or(rest, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = host.split(':').next()?.trim_end_matches('.').to_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+
+    (!host.is_empty()).then(|| host.to_string())
+}
+
+// Usernames are looked up ignoring case and surrounding whitespace
+pub fn username_key(username: &str) -> Option<String> {
+    let username = username.trim().to_lowercase();
+    (!username.is_empty()).then_some(username)
+}
+
+// Tags are counted and suggested ignoring case and surrounding whitespace
+pub fn tag_key(tag: &str) -> Option<String> {
+    let tag = tag.trim().to_lowercase();
+    (!tag.is_empty()).then_some(tag)
+}
+
+// The keys an entry was indexed under, to unindex them on update
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+struct IndexedKeys {
+    domain: Option<String>,
+    username: Option<String>,
+    tags: BTreeSet<String>,
+}
+
+// Lookups by url domain, username and tag, kept next to the primary index so
+// they don't need a scan of the data file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "K: StoreKey")]
+pub struct SecondaryIndexes<K = String> {
+    by_domain: HashMap<String, BTreeSet<K>>,
+    by_username: HashMap<String, BTreeSet<K>>,
+    // Ordered so tags can be suggested by prefix
+    by_tag: BTreeMap<String, BTreeSet<K>>,
+    keys: HashMap<K, IndexedKeys>,
+    // Number of journal records already reflected, see `is_current`
+    journal_len: usize,
+}
+
+// Not derived, those would require `K: Default` and miss `K: Hash`
+impl<K: StoreKey> PartialEq for SecondaryIndexes<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.by_domain == other.by_domain
+            && self.by_username == other.by_username
+            && self.by_tag == other.by_tag
+            && self.keys == other.keys
+            && self.journal_len == other.journal_len
+    }
+}
+
+impl<K: StoreKey> Eq for SecondaryIndexes<K> {}
+
+impl<K> Default for SecondaryIndexes<K> {
+    fn default() -> Self {
+        SecondaryIndexes {
+            by_domain: HashMap::new(),
+            by_username: HashMap::new(),
+            by_tag: BTreeMap::new(),
+            keys: HashMap::new(),
+            journal_len: 0,
+        }
+    }
+}
+
+impl<K: StoreKey> SecondaryIndexes<K> {
+    pub fn insert(&mut self, id: &K, entry: &Entry) {
+        self.remove(id);
+
+        let keys = IndexedKeys {
+            domain: entry.url.as_deref().and_then(domain_of),
+            username: entry.username.as_deref().and_then(username_key),
+            tags: entry.tags.iter().filter_map(|tag| tag_key(tag)).collect(),
+        };
+        if let Some(domain) = &keys.domain {
+            self.by_domain
+                .entry(domain.clone())
+                .or_default()
+                .insert(id.clone());
+        }
+        if let Some(username) = &keys.username {
+            self.by_username
+                .entry(username.clone())
+                .or_default()
+                .insert(id.clone());
+        }
+        for tag in &keys.tags {
+            self.by_tag
+                .entry(tag.clone())
+                .or_default()
+                .insert(id.clone());
+        }
+        self.keys.insert(id.clone(), keys);
+    }
+
+    pub fn remove(&mut self, id: &K) {
+        let unindex = |ids: Option<&mut BTreeSet<K>>| {
+            ids.map(|ids| {
+                ids.remove(id);
+                ids.is_empty()
+            })
+            .unwrap_or(false)
+        };
+
+        if let Some(keys) = self.keys.remove(id) {
+            if let Some(domain) = keys.domain {
+                if unindex(self.by_domain.get_mut(&domain)) {
+                    self.by_domain.remove(&domain);
+                }
+            }
+            if let Some(username) = keys.username {
+                if unindex(self.by_username.get_mut(&username)) {
+                    self.by_username.remove(&username);
+                }
+            }
+            for tag in keys.tags {
+                if unindex(self.by_tag.get_mut(&tag)) {
+                    self.by_tag.remove(&tag);
+                }
+            }
+        }
+    }
+
+    pub fn ids_for_url(&self, url: &str) -> Vec<K> {
+        domain_of(url)
+            .and_then(|domain| self.by_domain.get(&domain))
+            .map(|ids| ids.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn ids_for_username(&self, username: &str) -> Vec<K> {
+        username_key(username)
+            .and_then(|username| self.by_username.get(&username))
+            .map(|ids| ids.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    // Every tag with the number of entries that have it, by name
+    pub fn tags(&self) -> Vec<(String, usize)> {
+        self.by_tag
+            .iter()
+            .map(|(tag, ids)| (tag.clone(), ids.len()))
+            .collect()
+    }
+
+    // Tags starting with `prefix`, most used first
+    pub fn suggest_tags(&self, prefix: &str, limit: usize) -> Vec<String> {
+        let prefix = prefix.trim().to_lowercase();
+        let mut matches: Vec<(&String, usize)> = self
+            .by_tag
+            .range(prefix.clone()..)
+            .take_while(|(tag, _)| tag.starts_with(&prefix))
+            .map(|(tag, ids)| (tag, ids.len()))
+            .collect();
+        matches.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+
+        matches
+            .into_iter()
+            .take(limit)
+            .map(|(tag, _)| tag.clone())
+            .collect()
+    }
+
+    // Whether both index the same entries under the same keys, however
+    // many journal records each has seen
+    #[cfg(any(test, feature = "testing"))]
+    pub fn same_entries(&self, other: &Self) -> bool {
+        self.by_domain == other.by_domain
+            && self.by_username == other.by_username
+            && self.by_tag == other.by_tag
+            && self.keys == other.keys
+    }
+
+    // The file is only rewritten on commits, so it is stale when the index
+    // journal has records it hasn't seen
+    pub fn is_current(&self, journal_len: usize) -> bool {
+        self.journal_len == journal_len
+    }
+
+    // A file in an older layout is treated like a missing one and rebuilt
+    pub fn load(file_path: &str) -> Result<Option<Self>, StoreError> {
+        match fs::read(file_path) {
+            Ok(bytes) => Ok(bincode::deserialize(&bytes).ok()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write(
+        &mut self,
+        file_path: &str,
+        temp_file_path: &str,
+        journal_len: usize,
+        syncer: &Syncer,
+    ) -> Result<(), StoreError> {
+        self.journal_len = journal_len;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(temp_file_path)?;
+        file.write_all(&bincode::serialize(self)?)?;
+        syncer.rewritten(&mut file)?;
+
+        swap_in(temp_file_path, file_path)?;
+        Ok(syncer.renamed(file_path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tagged(tags: &[&str]) -> Entry {
+        let mut entry = entry("https://example.com", "alice");
+        entry.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        entry
+    }
+
+    fn entry(url: &str, username: &str) -> Entry {
+        Entry {
+            id: String::new(),
+            title: "Title".to_string(),
+            username: Some(username.to_string()),
+            url: Some(url.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_domain_of() {
+        assert_eq!(
+            domain_of("https://user@www.Example.com:8443/login?next=/"),
+            Some("example.com".to_string())
+        );
+        assert_eq!(
+            domain_of("example.com/path"),
+            Some("example.com".to_string())
+        );
+        assert_eq!(domain_of("https://"), None);
+    }
+
+    #[test]
+    fn test_insert_and_lookup() {
+        let mut indexes: SecondaryIndexes = SecondaryIndexes::default();
+        indexes.insert(&"1".to_string(), &entry("https://example.com/a", "Alice"));
+        indexes.insert(&"2".to_string(), &entry("https://www.example.com/b", "bob"));
+
+        assert_eq!(indexes.ids_for_url("http://example.com"), vec!["1", "2"]);
+        assert_eq!(indexes.ids_for_username("alice"), vec!["1"]);
+        assert!(indexes.ids_for_url("https://other.com").is_empty());
+    }
+
+    #[test]
+    fn test_update_and_remove_unindex_old_values() {
+        let mut indexes: SecondaryIndexes = SecondaryIndexes::default();
+        indexes.insert(&"1".to_string(), &entry("https://example.com", "alice"));
+        indexes.insert(&"1".to_string(), &entry("https://other.com", "alice"));
+
+        assert!(indexes.ids_for_url("https://example.com").is_empty());
+        assert_eq!(indexes.ids_for_url("https://other.com"), vec!["1"]);
+
+        indexes.remove(&"1".to_string());
+
+        assert_eq!(indexes, SecondaryIndexes::default());
+    }
+
+    #[test]
+    fn test_tag_counts_and_suggestions() {
+        let mut indexes: SecondaryIndexes = SecondaryIndexes::default();
+        indexes.insert(&"1".to_string(), &tagged(&["Work", "email"]));
+        indexes.insert(&"2".to_string(), &tagged(&["work ", "wifi"]));
+        indexes.insert(&"3".to_string(), &tagged(&["web"]));
+
+        assert_eq!(
+            indexes.tags(),
+            vec![
+                ("email".to_string(), 1),
+                ("web".to_string(), 1),
+                ("wifi".to_string(), 1),
+                ("work".to_string(), 2)
+            ]
+        );
+        assert_eq!(indexes.suggest_tags("W", 2), vec!["work", "web"]);
+        assert!(indexes.suggest_tags("x", 10).is_empty());
+
+        indexes.insert(&"2".to_string(), &tagged(&[]));
+        indexes.remove(&"1".to_string());
+        assert_eq!(indexes.tags(), vec![("web".to_string(), 1)]);
+    }
+}
@@ -0,0 +1,286 @@
+use rand::RngCore;
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    time::Duration,
+};
+
+use super::{
+    data_store::DataStore, durability::Syncer, file_swap::swap_in, model::Entry,
+    store_error::StoreError,
+};
+use crate::secret::sealed::{self, Tampered, NONCE_SIZE};
+
+// Hands a single entry to someone else as an encrypted blob, with the key
+// sent separately (e.g. blob by mail, key by phone). The ledger of the store
+// it was shared from holds the nonce of every share not yet redeemed, so a
+// share opens once and not after its expiry, even with the right key.
+
+const MAGIC: &[u8; 6] = b"TUGSHR";
+const SHARE_VERSION: u8 = 1;
+// The magic, version and expiry, in the clear but authenticated
+const HEADER_SIZE: usize = MAGIC.len() + 1 + 8;
+
+#[derive(Debug)]
+pub enum ShareError {
+    StoreError(StoreError),
+    NotFound(String),
+    NotAShare,
+    // Wrong key or a modified blob
+    Tampered,
+    Expired,
+    // Redeemed before, or not shared from this ledger
+    AlreadyUsed,
+}
+
+impl From<StoreError> for ShareError {
+    fn from(error: StoreError) -> Self {
+        ShareError::StoreError(error)
+    }
+}
+
+impl From<Tampered> for ShareError {
+    fn from(_: Tampered) -> Self {
+        ShareError::Tampered
+    }
+}
+
+impl fmt::Display for ShareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ShareError::StoreError(ref err) => write!(f, "Store error: {}", err),
+            ShareError::NotFound(ref id) => write!(f, "No entry with id {}", id),
+            ShareError::NotAShare => write!(f, "Not a share"),
+            ShareError::Tampered => write!(f, "Wrong key or modified share"),
+            ShareError::Expired => write!(f, "Share has expired"),
+            ShareError::AlreadyUsed => write!(f, "Share was already used"),
+        }
+    }
+}
+
+impl std::error::Error for ShareError {}
+
+// What to hand over, each half by a different channel
+#[derive(Clone, PartialEq, Eq)]
+pub struct OneTimeShare {
+    pub blob: Vec<u8>,
+    pub key: [u8; 32],
+    // Unix milliseconds
+    pub expires_at: u64,
+}
+
+// The key is left out like passwords are in `Entry`
+impl fmt::Debug for OneTimeShare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OneTimeShare")
+            .field("blob", &self.blob.len())
+            .field("key", &"***")
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+pub struct ShareLedger {
+    file_path: String,
+    // Nonce of every outstanding share to its expiry in unix milliseconds
+    outstanding: HashMap<[u8; NONCE_SIZE], u64>,
+    syncer: Syncer,
+}
+
+impl ShareLedger {
+    // A missing file is an empty ledger
+    pub fn open(file_path: String) -> Result<Self, ShareError> {
+        let outstanding = match fs::read(&file_path) {
+            Ok(bytes) => bincode::deserialize(&bytes).map_err(StoreError::from)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(StoreError::from(e).into()),
+        };
+        Ok(Self {
+            file_path,
+            outstanding,
+            syncer: Syncer::default(),
+        })
+    }
+
+    pub fn outstanding(&self) -> usize {
+        self.outstanding.len()
+    }
+
+    // Encrypts entry `id` of `store` under a new random key, valid until
+    // `ttl` after `now` (unix milliseconds)
+    pub fn share_once<S>(
+        &mut self,
+        store: &S,
+        id: &String,
+        ttl: Duration,
+        now: u64,
+    ) -> Result<OneTimeShare, ShareError>
+    where
+        S: DataStore<String, Entry, StoreError> + ?Sized,
+    {
+        let entry = store
+            .load(id)?
+            .ok_or_else(|| ShareError::NotFound(id.clone()))?;
+        let expires_at = now.saturating_add(ttl.as_millis() as u64);
+
+        let mut key = [0; 32];
+        rand::rng().fill_bytes(&mut key);
+        let header = [&MAGIC[..], &[SHARE_VERSION], &expires_at.to_le_bytes()].concat();
+        let blob = sealed::seal(
+            &key,
+            &header,
+            &bincode::serialize(&entry).map_err(StoreError::from)?,
+        );
+        let nonce: [u8; NONCE_SIZE] = blob[HEADER_SIZE..HEADER_SIZE + NONCE_SIZE]
+            .try_into()
+            .expect("the nonce follows the header");
+
+        self.outstanding.insert(nonce, expires_at);
+        self.write()?;
+        Ok(OneTimeShare {
+            blob,
+            key,
+            expires_at,
+        })
+    }
+
+    // Decrypts a share and strikes it from the ledger, so it can't be
+    // redeemed again. A blob that doesn't decrypt leaves the share open.
+    pub fn redeem(&mut self, blob: &[u8], key: &[u8; 32], now: u64) -> Result<Entry, ShareError> {
+        if blob.len() < HEADER_SIZE
+            || !blob.starts_with(MAGIC)
+            || blob[MAGIC.len()] != SHARE_VERSION
+        {
+            return Err(ShareError::NotAShare);
+        }
+        let opened = sealed::open(key, HEADER_SIZE, blob)?;
+
+        let expires_at = self
+            .outstanding
+            .remove(&opened.nonce)
+            .ok_or(ShareError::AlreadyUsed)?;
+        self.write()?;
+        if now > expires_at {
+            return Err(ShareError::Expired);
+        }
+        Ok(bincode::deserialize(&opened.plaintext).map_err(StoreError::from)?)
+    }
+
+    // Forgets shares that expired unredeemed, returning how many
+    pub fn purge_expired(&mut self, now: u64) -> Result<usize, ShareError> {
+        let before = self.outstanding.len();
+        self.outstanding.retain(|_, expires_at| *expires_at >= now);
+        let purged = before - self.outstanding.len();
+        if purged > 0 {
+            self.write()?;
+        }
+        Ok(purged)
+    }
+
+    fn write(&self) -> Result<(), StoreError> {
+        let temp_file_path = format!("{}.tmp", self.file_path);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&temp_file_path)?;
+        file.write_all(&bincode::serialize(&self.outstanding)?)?;
+        self.syncer.rewritten(&mut file)?;
+
+        swap_in(&temp_file_path, &self.file_path)?;
+        Ok(self.syncer.renamed(&self.file_path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::binary_file_entry_store::BinaryFileEntryStore;
+    use uuid::Uuid;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    fn entry(id: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: "Wifi".to_string(),
+            password: Some("correct horse".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_share_redeems_once() {
+        let id = Uuid::new_v4();
+        let store_path = format!("test_share_store_{}.bin", id);
+        // In a directory, as the temp file has to go next to it
+        let ledger_dir = format!("test_share_ledger_{}", id);
+        fs::create_dir(&ledger_dir).unwrap();
+        let ledger_path = format!("{}/ledger.bin", ledger_dir);
+        let mut store = BinaryFileEntryStore::new(store_path.clone());
+        store.save(&"1".to_string(), &entry("1")).unwrap();
+        let mut ledger = ShareLedger::open(ledger_path.clone()).unwrap();
+
+        let share = ledger
+            .share_once(&store, &"1".to_string(), HOUR, 1_000)
+            .unwrap();
+        let debug = format!("{:?}", share);
+        assert!(debug.contains("***"));
+        assert!(!debug.contains(&format!("{:?}", share.key)));
+        assert!(matches!(
+            ledger.share_once(&store, &"2".to_string(), HOUR, 1_000),
+            Err(ShareError::NotFound(_))
+        ));
+        assert!(matches!(
+            ledger.redeem(&share.blob, &[0; 32], 2_000),
+            Err(ShareError::Tampered)
+        ));
+
+        // The ledger survives reopening, e.g. the recipient redeems later
+        let mut ledger = ShareLedger::open(ledger_path.clone()).unwrap();
+        assert_eq!(ledger.outstanding(), 1);
+        assert_eq!(
+            ledger.redeem(&share.blob, &share.key, 2_000).unwrap(),
+            entry("1")
+        );
+        assert!(matches!(
+            ledger.redeem(&share.blob, &share.key, 2_000),
+            Err(ShareError::AlreadyUsed)
+        ));
+
+        drop(store);
+        fs::remove_file(store_path).unwrap();
+        fs::remove_dir_all(ledger_dir).unwrap();
+    }
+
+    #[test]
+    fn test_expired_share() {
+        let id = Uuid::new_v4();
+        let store_path = format!("test_share_store_{}.bin", id);
+        let ledger_path = format!("test_share_ledger_{}.bin", id);
+        let mut store = BinaryFileEntryStore::new(store_path.clone());
+        store.save(&"1".to_string(), &entry("1")).unwrap();
+        let mut ledger = ShareLedger::open(ledger_path.clone()).unwrap();
+
+        let late = ledger
+            .share_once(&store, &"1".to_string(), HOUR, 0)
+            .unwrap();
+        ledger
+            .share_once(&store, &"1".to_string(), Duration::ZERO, 0)
+            .unwrap();
+        let after_expiry = late.expires_at + 1;
+
+        assert!(matches!(
+            ledger.redeem(&late.blob, &late.key, after_expiry),
+            Err(ShareError::Expired)
+        ));
+        assert_eq!(ledger.purge_expired(after_expiry).unwrap(), 1);
+        assert_eq!(ledger.outstanding(), 0);
+
+        drop(store);
+        fs::remove_file(store_path).unwrap();
+        fs::remove_file(ledger_path).unwrap();
+    }
+}
@@ -0,0 +1,367 @@
+use std::cmp::{Ordering, Reverse};
+
+use super::{
+    data_store::{DataStore, Filter},
+    model::Entry,
+};
+
+// Orders for listing entries. Ties are always broken by id, so the order is
+// total and the same on every backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortBy {
+    #[default]
+    Id,
+    // Ignoring case
+    Title,
+    // Newest first
+    RecentlyUpdated,
+    // By `sort_key`, entries without one last. Within a tag the keys of its
+    // entries keep their relative order, so one key serves every list.
+    Manual,
+}
+
+// The digits of a sort key, in ascending order
+const RANK_DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+// Where a page ended, to continue after it. Unlike an offset it stays
+// correct when entries are saved or deleted between the two queries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    sort: SortBy,
+    key: SortKey,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Primary {
+    None,
+    Text(String),
+    Newest(Reverse<u64>),
+    // Unranked entries compare as `(true, "")`
+    Manual(bool, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct SortKey {
+    primary: Primary,
+    id: String,
+}
+
+impl SortBy {
+    fn key(&self, entry: &Entry) -> SortKey {
+        let primary = match self {
+            SortBy::Id => Primary::None,
+            SortBy::Title => Primary::Text(entry.title.to_lowercase()),
+            SortBy::RecentlyUpdated => Primary::Newest(Reverse(entry.updated_at)),
+            SortBy::Manual => Primary::Manual(
+                entry.sort_key.is_none(),
+                entry.sort_key.clone().unwrap_or_default(),
+            ),
+        };
+        SortKey {
+            primary,
+            id: entry.id.clone(),
+        }
+    }
+
+    pub fn compare(&self, a: &Entry, b: &Entry) -> Ordering {
+        self.key(a).cmp(&self.key(b))
+    }
+
+    pub fn sort(&self, entries: &mut [Entry]) {
+        entries.sort_by_cached_key(|entry| self.key(entry));
+    }
+
+    pub fn cursor_after(&self, entry: &Entry) -> Cursor {
+        Cursor {
+            sort: *self,
+            key: self.key(entry),
+        }
+    }
+}
+
+fn rank_digit(digit: u8) -> Option<usize> {
+    RANK_DIGITS.iter().position(|d| *d == digit)
+}
+
+// A key of digits with no trailing zero, so there is always room after it
+fn is_rank(key: &str) -> bool {
+    !key.is_empty() && !key.ends_with('0') && key.bytes().all(|d| rank_digit(d).is_some())
+}
+
+// Digits strictly between `a` and `b`, read as fractions, or above `a` when
+// there is no `b`. Missing digits of a shorter `a` count as zeros.
+fn midpoint(a: &[usize], b: Option<&[usize]>) -> Vec<usize> {
+    let base = RANK_DIGITS.len();
+    if let Some(b) = b {
+        let common = b
+            .iter()
+            .enumerate()
+            .take_while(|(i, digit)| a.get(*i).copied().unwrap_or(0) == **digit)
+            .count();
+        if common > 0 {
+            let mut rank = b[..common].to_vec();
+            rank.extend(midpoint(a.get(common..).unwrap_or(&[]), Some(&b[common..])));
+            return rank;
+        }
+    }
+
+    let low = a.first().copied().unwrap_or(0);
+    let high = b.map_or(base, |b| b[0]);
+    if high - low > 1 {
+        vec![(low + high) / 2]
+    } else if b.is_some_and(|b| b.len() > 1) {
+        // `b` cut short sorts before `b` and after anything starting with `low`
+        vec![high]
+    } else {
+        let mut rank = vec![low];
+        rank.extend(midpoint(a.get(1..).unwrap_or(&[]), None));
+        rank
+    }
+}
+
+// A sort key between `before` and `after`, either of which may be missing
+// at the ends of a list. None when the keys aren't valid or in order.
+pub fn rank_between(before: Option<&str>, after: Option<&str>) -> Option<String> {
+    let digits = |key: &str| -> Option<Vec<usize>> {
+        is_rank(key).then(|| key.bytes().filter_map(rank_digit).collect())
+    };
+    let before = match before {
+        Some(key) => digits(key)?,
+        None => Vec::new(),
+    };
+    let after = match after {
+        Some(key) => Some(digits(key)?),
+        None => None,
+    };
+    if after.as_ref().is_some_and(|after| *after <= before) {
+        return None;
+    }
+
+    let rank = midpoint(&before, after.as_deref());
+    Some(rank.into_iter().map(|d| RANK_DIGITS[d] as char).collect())
+}
+
+// Gives `entries` evenly spaced sort keys in their current order, to start
+// ordering a list by hand or to shorten keys grown by many moves
+pub fn assign_ranks(entries: &mut [Entry]) {
+    let base = RANK_DIGITS.len() as u128;
+    let slots = entries.len() as u128 + 1;
+    let mut width = 1;
+    let mut span = base;
+    while span < slots {
+        width += 1;
+        span *= base;
+    }
+
+    for (i, entry) in entries.iter_mut().enumerate() {
+        let mut value = (i as u128 + 1) * span / slots;
+        let mut digits = vec![b'0'; width];
+        for digit in digits.iter_mut().rev() {
+            *digit = RANK_DIGITS[(value % base) as usize];
+            value /= base;
+        }
+        let key = String::from_utf8(digits).unwrap_or_default();
+        entry.sort_key = Some(key.trim_end_matches('0').to_string());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+    pub entries: Vec<Entry>,
+    // None on the last page
+    pub next: Option<Cursor>,
+}
+
+pub fn search_sorted<S, E>(
+    store: &S,
+    filter: &dyn Filter<Entry>,
+    sort: SortBy,
+) -> Result<Vec<Entry>, E>
+where
+    S: DataStore<String, Entry, E> + ?Sized,
+{
+    let mut entries = store.search(filter)?;
+    sort.sort(&mut entries);
+    Ok(entries)
+}
+
+// Up to `limit` entries following `after`, or from the start without it. A
+// cursor from a different order starts over rather than skipping entries.
+pub fn search_page<S, E>(
+    store: &S,
+    filter: &dyn Filter<Entry>,
+    sort: SortBy,
+    after: Option<&Cursor>,
+    limit: usize,
+) -> Result<Page, E>
+where
+    S: DataStore<String, Entry, E> + ?Sized,
+{
+    let after = after.filter(|cursor| cursor.sort == sort);
+
+    let mut entries: Vec<Entry> = search_sorted(store, filter, sort)?
+        .into_iter()
+        .filter(|entry| after.is_none_or(|cursor| sort.key(entry) > cursor.key))
+        .collect();
+
+    let next = if entries.len() > limit {
+        entries.truncate(limit);
+        entries.last().map(|last| sort.cursor_after(last))
+    } else {
+        None
+    };
+
+    Ok(Page { entries, next })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{
+        binary_file_entry_store::BinaryFileEntryStore, data_store::filter_fn,
+        indexed_binary_file_entry_store::IndexedBinaryFileEntryStore,
+    };
+    use std::fs;
+    use uuid::Uuid;
+
+    fn entry(id: &str, title: &str, updated_at: u64) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: title.to_string(),
+            updated_at,
+            ..Default::default()
+        }
+    }
+
+    fn entries() -> Vec<Entry> {
+        vec![
+            entry("c", "bank", 5),
+            entry("a", "Bank", 5),
+            entry("d", "Mail", 9),
+            entry("b", "apple", 1),
+        ]
+    }
+
+    fn ids(entries: &[Entry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_ties_are_broken_by_id() {
+        let mut sorted = entries();
+
+        SortBy::Title.sort(&mut sorted);
+        assert_eq!(ids(&sorted), vec!["b", "a", "c", "d"]);
+
+        SortBy::RecentlyUpdated.sort(&mut sorted);
+        assert_eq!(ids(&sorted), vec!["d", "a", "c", "b"]);
+
+        SortBy::Id.sort(&mut sorted);
+        assert_eq!(ids(&sorted), vec!["a", "b", "c", "d"]);
+    }
+
+    // Every backend returns the same order, before and after updates
+    #[test]
+    fn test_backends_agree() {
+        let id = Uuid::new_v4();
+        let binary_path = format!("test_sort_binary_{}.bin", id);
+        let data_path = format!("test_sort_data_{}.bin", id);
+        let index_path = format!("test_sort_index_{}.bin", id);
+        let generational_path = format!("test_sort_generations_{}.bin", id);
+
+        let mut stores: Vec<Box<dyn DataStore<String, Entry, _>>> = vec![
+            Box::new(BinaryFileEntryStore::new(binary_path.clone())),
+            Box::new(BinaryFileEntryStore::generational(
+                generational_path.clone(),
+            )),
+            Box::new(IndexedBinaryFileEntryStore::<String>::new(
+                data_path.clone(),
+                index_path.clone(),
+            )),
+        ];
+        let all = filter_fn(|_: &Entry| true);
+
+        for store in stores.iter_mut() {
+            for entry in entries() {
+                store.save(&entry.id, &entry).unwrap();
+            }
+            // Saving again moves the record to the end of the file
+            store
+                .save(&"a".to_string(), &entry("a", "Bank", 5))
+                .unwrap();
+
+            assert_eq!(ids(&store.search(&all).unwrap()), vec!["a", "b", "c", "d"]);
+            let by_title = search_sorted(store.as_ref(), &all, SortBy::Title).unwrap();
+            assert_eq!(ids(&by_title), vec!["b", "a", "c", "d"]);
+        }
+
+        drop(stores);
+        let journal_path = format!("{}.journal", index_path);
+        for path in [&binary_path, &data_path, &index_path, &journal_path] {
+            fs::remove_file(path).unwrap();
+        }
+        fs::remove_file(format!("{}.0001", generational_path)).unwrap();
+    }
+
+    #[test]
+    fn test_pages_neither_skip_nor_repeat() {
+        let path = format!("test_sort_pages_{}.bin", Uuid::new_v4());
+        let mut store = BinaryFileEntryStore::new(path.clone());
+        for entry in entries() {
+            store.save(&entry.id, &entry).unwrap();
+        }
+        let all = filter_fn(|_: &Entry| true);
+
+        let first = search_page(&store, &all, SortBy::Title, None, 2).unwrap();
+        assert_eq!(ids(&first.entries), vec!["b", "a"]);
+
+        // An entry sorting before the cursor is added between the queries
+        let added = entry("e", "Aardvark", 0);
+        store.save(&added.id, &added).unwrap();
+
+        let second = search_page(&store, &all, SortBy::Title, first.next.as_ref(), 2).unwrap();
+        assert_eq!(ids(&second.entries), vec!["c", "d"]);
+        assert_eq!(second.next, None);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_manual_order() {
+        let mut sorted = entries();
+        assign_ranks(&mut sorted);
+        SortBy::Manual.sort(&mut sorted);
+        assert_eq!(ids(&sorted), vec!["c", "a", "d", "b"]);
+
+        // "b" is dragged between "c" and "a"
+        let key = rank_between(sorted[0].sort_key.as_deref(), sorted[1].sort_key.as_deref());
+        sorted[3].sort_key = key;
+        // Unranked entries come last
+        sorted.push(entry("e", "Unranked", 0));
+        SortBy::Manual.sort(&mut sorted);
+        assert_eq!(ids(&sorted), vec!["c", "b", "a", "d", "e"]);
+    }
+
+    #[test]
+    fn test_rank_between() {
+        assert_eq!(rank_between(None, None).as_deref(), Some("i"));
+        assert_eq!(rank_between(Some("a"), Some("c")).as_deref(), Some("b"));
+        assert_eq!(rank_between(Some("a"), Some("b")).as_deref(), Some("ai"));
+        assert_eq!(rank_between(Some("a"), Some("b1")).as_deref(), Some("b"));
+        assert_eq!(rank_between(Some("z"), None).as_deref(), Some("zi"));
+        assert_eq!(rank_between(None, Some("01")).as_deref(), Some("00i"));
+
+        // Repeatedly inserting at the same place always finds room
+        let mut after = "1".to_string();
+        for _ in 0..100 {
+            let key = rank_between(Some("0001"), Some(&after)).unwrap();
+            assert!(*key > *"0001" && key < after, "{}", key);
+            after = key;
+        }
+
+        assert_eq!(rank_between(Some("b"), Some("a")), None);
+        assert_eq!(rank_between(Some("a"), Some("a")), None);
+        assert_eq!(rank_between(Some("a0"), None), None);
+        assert_eq!(rank_between(Some("A"), None), None);
+    }
+}
@@ -0,0 +1,466 @@
+use sha2::{Digest, Sha256};
+use std::{fs, io, path::Path};
+
+use super::{
+    binary_file_entry_store::BinaryFileEntryStore,
+    data_store::DataStore,
+    durability::{Durability, SyncLevel},
+    file_swap::{recover_group_swap, swap_in_all},
+    indexed_binary_file_entry_store::IndexedBinaryFileEntryStore,
+    model::Entry,
+    store_error::StoreError,
+    vault_metadata::VaultMetadata,
+};
+
+pub type EntryStore = Box<dyn DataStore<String, Entry, StoreError>>;
+
+// Suffix of the files a migration writes before they are activated
+const STAGING_SUFFIX: &str = ".migrating";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackendMigration {
+    pub entries: usize,
+    // Hex SHA-256 of the entries and their keys in key order, equal in
+    // source and target
+    pub checksum: String,
+}
+
+// Describes which backend to use, so it can be chosen at runtime (e.g. from config)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreBackend {
+    Binary {
+        file_path: String,
+        sync_level: SyncLevel,
+    },
+    IndexedBinary {
+        data_file_path: String,
+        index_file_path: String,
+        // Callers of a boxed store can't reach `rewrite_index`, so the
+        // durability decides when the index is persisted.
+        durability: Durability,
+        sync_level: SyncLevel,
+    },
+}
+
+impl StoreBackend {
+    pub fn open(self) -> Result<EntryStore, StoreError> {
+        Ok(match self {
+            StoreBackend::Binary {
+                file_path,
+                sync_level,
+            } => Box::new(BinaryFileEntryStore::open(file_path)?.with_sync_level(sync_level)),
+            StoreBackend::IndexedBinary {
+                data_file_path,
+                index_file_path,
+                durability,
+                sync_level,
+            } => {
+                let (store, _) = IndexedBinaryFileEntryStore::open_with_report(
+                    data_file_path,
+                    index_file_path,
+                    None,
+                )?;
+                Box::new(
+                    store
+                        .with_durability(durability)
+                        .with_sync_level(sync_level),
+                )
+            }
+        })
+    }
+
+    // Every file the backend may write, present or not. The files of two
+    // backends of the same kind line up, e.g. both journals are third.
+    pub fn files(&self) -> Vec<String> {
+        match self {
+            StoreBackend::Binary { file_path, .. } => vec![file_path.clone()],
+            StoreBackend::IndexedBinary {
+                data_file_path,
+                index_file_path,
+                ..
+            } => vec![
+                data_file_path.clone(),
+                index_file_path.clone(),
+                format!("{}.journal", index_file_path),
+                format!("{}.mac", index_file_path),
+                format!("{}.secondary", index_file_path),
+            ],
+        }
+    }
+
+    // Where the vault's name, description and icon are kept, next to the
+    // store's data file. It isn't one of `files`, as the store never writes it.
+    pub fn metadata_file_path(&self) -> String {
+        match self {
+            StoreBackend::Binary { file_path, .. } => format!("{}.meta", file_path),
+            StoreBackend::IndexedBinary { data_file_path, .. } => {
+                format!("{}.meta", data_file_path)
+            }
+        }
+    }
+
+    pub fn metadata(&self) -> Result<Option<VaultMetadata>, StoreError> {
+        VaultMetadata::load(&self.metadata_file_path())
+    }
+
+    pub fn save_metadata(&self, metadata: &VaultMetadata) -> Result<(), StoreError> {
+        metadata.save(&self.metadata_file_path())
+    }
+
+    // Copies every entry into `target`, which must not exist yet, under the
+    // key it is stored under. The copy and the vault's metadata are written
+    // under temporary names and the copy is read back. Only when it holds
+    // the same entries are they all swapped into place as one. On failure
+    // the temporary files are removed. The source is only read, never
+    // recovered or migrated, so one in an older schema has to be migrated
+    // first. A migration that died is finished, or its leftovers removed,
+    // before anything else.
+    pub fn migrate_to(&self, target: &StoreBackend) -> Result<BackendMigration, StoreError> {
+        let staged_files = target.staged().migrated_files();
+        let target_files = target.migrated_files();
+        let pairs: Vec<(&str, &str)> = staged_files
+            .iter()
+            .map(String::as_str)
+            .zip(target_files.iter().map(String::as_str))
+            .collect();
+        recover_group_swap(&pairs)?;
+
+        if let Some(existing) = target_files.iter().find(|file| Path::new(file).exists()) {
+            return Err(StoreError::IoError(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", existing),
+            )));
+        }
+
+        let migration = match stage_copy(self, &target.staged()) {
+            Ok(migration) => migration,
+            Err(e) => {
+                remove_files(&staged_files);
+                return Err(e);
+            }
+        };
+
+        // The staged data file always exists, so the group keeps its marker
+        let pairs: Vec<(&str, &str)> = pairs
+            .into_iter()
+            .filter(|(staged_file, _)| Path::new(staged_file).exists())
+            .collect();
+        swap_in_all(&pairs)?;
+        Ok(migration)
+    }
+
+    // The store's files and its metadata, everything a migration activates
+    fn migrated_files(&self) -> Vec<String> {
+        let mut files = self.files();
+        files.push(self.metadata_file_path());
+        files
+    }
+
+    // Every entry with the key it is stored under, in key order. The store
+    // is opened read-only, so nothing is created, recovered or migrated.
+    fn read_entries(&self) -> Result<Vec<(String, Entry)>, StoreError> {
+        match self {
+            StoreBackend::Binary { file_path, .. } => {
+                BinaryFileEntryStore::open_read_only(file_path.clone())?.entries()
+            }
+            StoreBackend::IndexedBinary {
+                data_file_path,
+                index_file_path,
+                ..
+            } => IndexedBinaryFileEntryStore::open_read_only(
+                data_file_path.clone(),
+                index_file_path.clone(),
+            )?
+            .entries(),
+        }
+    }
+
+    // The same backend under the temporary names of a migration. The index
+    // is persisted on every save, a boxed store has no other way to.
+    fn staged(&self) -> StoreBackend {
+        let staged = |file_path: &str| format!("{}{}", file_path, STAGING_SUFFIX);
+        match self {
+            StoreBackend::Binary {
+                file_path,
+                sync_level,
+            } => StoreBackend::Binary {
+                file_path: staged(file_path),
+                sync_level: *sync_level,
+            },
+            StoreBackend::IndexedBinary {
+                data_file_path,
+                index_file_path,
+                sync_level,
+                ..
+            } => StoreBackend::IndexedBinary {
+                data_file_path: staged(data_file_path),
+                index_file_path: staged(index_file_path),
+                durability: Durability::Immediate,
+                sync_level: *sync_level,
+            },
+        }
+    }
+}
+
+fn remove_files(files: &[String]) {
+    for file in files {
+        let _ = fs::remove_file(file);
+    }
+}
+
+fn checksum(entries: &[(String, Entry)]) -> Result<String, StoreError> {
+    let mut hasher = Sha256::new();
+    for entry in entries {
+        hasher.update(bincode::serialize(entry)?);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+// Writes the entries of `source`, under their keys, and its metadata to
+// `staged`, then reads it back to check the entries all arrived unchanged
+fn stage_copy(
+    source: &StoreBackend,
+    staged: &StoreBackend,
+) -> Result<BackendMigration, StoreError> {
+    let entries = source.read_entries()?;
+    let mut store = staged.clone().open()?;
+    for (key, entry) in &entries {
+        store.save(key, entry)?;
+    }
+    drop(store);
+    if let Some(metadata) = source.metadata()? {
+        staged.save_metadata(&metadata)?;
+    }
+
+    let copied = staged.read_entries()?;
+    let expected = checksum(&entries)?;
+    if copied.len() != entries.len() || checksum(&copied)? != expected {
+        return Err(StoreError::IntegrityMismatch);
+    }
+    Ok(BackendMigration {
+        entries: entries.len(),
+        checksum: expected,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::data_store::filter_fn;
+    use uuid::Uuid;
+
+    fn entry(id: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: format!("Entry {}", id),
+            ..Default::default()
+        }
+    }
+
+    fn exercise(mut store: EntryStore) {
+        store.save(&"1".to_string(), &entry("1")).unwrap();
+        store.save(&"2".to_string(), &entry("2")).unwrap();
+        store.delete(&"1".to_string()).unwrap();
+
+        assert_eq!(store.load(&"1".to_string()).unwrap(), None);
+        assert_eq!(store.load(&"2".to_string()).unwrap(), Some(entry("2")));
+        let found = store
+            .search(&filter_fn(|e: &Entry| e.title.ends_with('2')))
+            .unwrap();
+        assert_eq!(found, vec![entry("2")]);
+    }
+
+    fn cleanup(paths: &[&str]) {
+        for path in paths {
+            if Path::new(path).exists() {
+                fs::remove_file(path).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_binary_backend() {
+        let file_path = format!("test_backend_{}.bin", Uuid::new_v4());
+
+        exercise(
+            StoreBackend::Binary {
+                file_path: file_path.clone(),
+                sync_level: SyncLevel::FlushOnSave,
+            }
+            .open()
+            .unwrap(),
+        );
+
+        cleanup(&[&file_path]);
+    }
+
+    #[test]
+    fn test_indexed_binary_backend() {
+        let id = Uuid::new_v4();
+        let data_file_path = format!("test_backend_data_{}.bin", id);
+        let index_file_path = format!("test_backend_index_{}.bin", id);
+        let backend = StoreBackend::IndexedBinary {
+            data_file_path: data_file_path.clone(),
+            index_file_path: index_file_path.clone(),
+            durability: Durability::Immediate,
+            sync_level: SyncLevel::FsyncEverything,
+        };
+
+        exercise(backend.clone().open().unwrap());
+
+        // The index was persisted, so a fresh store sees the surviving entry
+        let store = backend.open().unwrap();
+        assert_eq!(store.load(&"2".to_string()).unwrap(), Some(entry("2")));
+        drop(store);
+
+        let journal_file_path = format!("{}.journal", index_file_path);
+        cleanup(&[&data_file_path, &index_file_path, &journal_file_path]);
+    }
+
+    #[test]
+    fn test_open_fails_when_files_cant_be_created() {
+        let missing_dir = format!("test_backend_missing_{}", Uuid::new_v4());
+        let backends = [
+            StoreBackend::Binary {
+                file_path: format!("{}/vault.bin", missing_dir),
+                sync_level: SyncLevel::FlushOnSave,
+            },
+            StoreBackend::IndexedBinary {
+                data_file_path: format!("{}/data.bin", missing_dir),
+                index_file_path: format!("{}/index.bin", missing_dir),
+                durability: Durability::Immediate,
+                sync_level: SyncLevel::FlushOnSave,
+            },
+        ];
+
+        for backend in backends {
+            assert!(matches!(backend.open(), Err(StoreError::IoError(_))));
+        }
+        assert!(!Path::new(&missing_dir).exists());
+    }
+
+    #[test]
+    fn test_migrate_between_backends() {
+        let id = Uuid::new_v4();
+        let source = StoreBackend::Binary {
+            file_path: format!("test_migrate_source_{}.bin", id),
+            sync_level: SyncLevel::FlushOnSave,
+        };
+        let target = StoreBackend::IndexedBinary {
+            data_file_path: format!("test_migrate_data_{}.bin", id),
+            index_file_path: format!("test_migrate_index_{}.bin", id),
+            durability: Durability::Immediate,
+            sync_level: SyncLevel::FlushOnSave,
+        };
+        // Keys needn't be the entries' ids, the copy keeps them
+        let mut store = source.clone().open().unwrap();
+        for id in ["1", "2", "3"] {
+            store.save(&format!("key-{}", id), &entry(id)).unwrap();
+        }
+        drop(store);
+        let source_file = fs::read(&source.files()[0]).unwrap();
+        // Left over from a migration that died
+        fs::write(
+            format!("{}{}", target.files()[0], STAGING_SUFFIX),
+            b"partial",
+        )
+        .unwrap();
+
+        let metadata = VaultMetadata::new("Source").with_icon("📦");
+        source.save_metadata(&metadata).unwrap();
+
+        let migration = source.migrate_to(&target).unwrap();
+        assert_eq!(migration.entries, 3);
+        assert_eq!(target.metadata().unwrap(), Some(metadata));
+        let store = target.clone().open().unwrap();
+        assert_eq!(store.load(&"key-2".to_string()).unwrap(), Some(entry("2")));
+        assert_eq!(store.load(&"2".to_string()).unwrap(), None);
+        drop(store);
+        assert_eq!(fs::read(&source.files()[0]).unwrap(), source_file);
+        for file in target.staged().migrated_files() {
+            assert!(!Path::new(&file).exists(), "{}", file);
+        }
+
+        // An existing target is never replaced
+        assert!(matches!(
+            source.migrate_to(&target),
+            Err(StoreError::IoError(ref e)) if e.kind() == io::ErrorKind::AlreadyExists
+        ));
+
+        for file in source.files().iter().chain(&target.files()) {
+            cleanup(&[file]);
+        }
+        cleanup(&[&source.metadata_file_path(), &target.metadata_file_path()]);
+    }
+
+    #[test]
+    fn test_failed_migration_leaves_nothing_behind() {
+        let id = Uuid::new_v4();
+        let source = StoreBackend::Binary {
+            file_path: format!("test_migrate_source_{}.bin", id),
+            sync_level: SyncLevel::FlushOnSave,
+        };
+        let mut store = source.clone().open().unwrap();
+        store.save(&"1".to_string(), &entry("1")).unwrap();
+        drop(store);
+
+        // The staged index can't be written, so the copy fails part way
+        let blocked = format!("test_migrate_blocked_{}", id);
+        fs::create_dir(format!("{}{}", blocked, STAGING_SUFFIX)).unwrap();
+        let target = StoreBackend::IndexedBinary {
+            data_file_path: format!("test_migrate_data_{}.bin", id),
+            index_file_path: blocked.clone(),
+            durability: Durability::Immediate,
+            sync_level: SyncLevel::FlushOnSave,
+        };
+
+        assert!(source.migrate_to(&target).is_err());
+        for file in target.files() {
+            assert!(!Path::new(&file).exists(), "{}", file);
+        }
+        assert!(!Path::new(&target.staged().files()[0]).exists());
+        let store = source.clone().open().unwrap();
+        assert_eq!(store.load(&"1".to_string()).unwrap(), Some(entry("1")));
+        drop(store);
+
+        fs::remove_dir(format!("{}{}", blocked, STAGING_SUFFIX)).unwrap();
+        cleanup(&[&source.files()[0]]);
+    }
+
+    #[test]
+    fn test_migration_leaves_an_old_schema_source_alone() {
+        use crate::data::binary_record_iterator::{write_raw, RawRecord};
+        use crate::data::migration::tests::v1_bytes;
+
+        let id = Uuid::new_v4();
+        let source = StoreBackend::Binary {
+            file_path: format!("test_migrate_source_{}.bin", id),
+            sync_level: SyncLevel::FlushOnSave,
+        };
+        let target = StoreBackend::Binary {
+            file_path: format!("test_migrate_target_{}.bin", id),
+            sync_level: SyncLevel::FlushOnSave,
+        };
+        let record = RawRecord {
+            tombstone: false,
+            payload: [bincode::serialize("1").unwrap(), v1_bytes()].concat(),
+        };
+        write_raw(&mut fs::File::create(&source.files()[0]).unwrap(), &record).unwrap();
+        let legacy = fs::read(&source.files()[0]).unwrap();
+
+        assert!(matches!(
+            source.migrate_to(&target),
+            Err(StoreError::MigrationRequired { version: 1 })
+        ));
+        assert_eq!(fs::read(&source.files()[0]).unwrap(), legacy);
+        for file in target
+            .migrated_files()
+            .iter()
+            .chain(&target.staged().migrated_files())
+        {
+            assert!(!Path::new(file).exists(), "{}", file);
+        }
+
+        cleanup(&[&source.files()[0]]);
+    }
+}
@@ -0,0 +1,131 @@
+use std::{error::Error, fmt, io};
+
+use bincode::Error as BincodeError;
+
+use super::{quota_store::LimitExceeded, retry::RetryError};
+use crate::secret::password_policy::PolicyViolation;
+
+#[derive(Debug)]
+pub enum StoreError {
+    IoError(io::Error),
+    SerializationError(BincodeError),
+    IndexRecordTooLarge,
+    // The id doesn't fit an index record, see `IndexedBinaryFileEntryStore`
+    IdTooLong,
+    IntegrityMismatch,
+    // A record ends early, e.g. the process died while appending it
+    TruncatedRecord { offset: u64 },
+    // A record is complete but its checksum doesn't match
+    CorruptRecord { offset: u64 },
+    // A record that matches none of the known layouts of `Entry`
+    UnknownRecordLayout,
+    // The store's records are in an older layout, see `migrate`
+    MigrationRequired { version: u32 },
+    // A record or store header that doesn't match the encryption mode the
+    // store was created with
+    EncryptionMismatch,
+    PolicyViolation(PolicyViolation),
+    // Saving would take the store past one of its `StoreLimits`
+    LimitExceeded(LimitExceeded),
+    // A transient I/O failure kept happening, with the error of every attempt
+    RetriesExhausted(RetryError),
+}
+
+impl From<io::Error> for StoreError {
+    fn from(error: io::Error) -> Self {
+        StoreError::IoError(error)
+    }
+}
+
+impl From<BincodeError> for StoreError {
+    fn from(error: BincodeError) -> Self {
+        StoreError::SerializationError(error)
+    }
+}
+
+impl From<RetryError> for StoreError {
+    fn from(error: RetryError) -> Self {
+        // A permanent failure isn't a retry problem
+        if error.errors.len() == 1 {
+            StoreError::IoError(error.into_last())
+        } else {
+            StoreError::RetriesExhausted(error)
+        }
+    }
+}
+
+impl From<PolicyViolation> for StoreError {
+    fn from(violation: PolicyViolation) -> Self {
+        StoreError::PolicyViolation(violation)
+    }
+}
+
+impl From<LimitExceeded> for StoreError {
+    fn from(limit: LimitExceeded) -> Self {
+        StoreError::LimitExceeded(limit)
+    }
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            StoreError::IoError(ref err) => {
+                write!(f, "I/O error: {}", err)
+            }
+            StoreError::SerializationError(ref err) => {
+                write!(f, "Serialization error: {}", err)
+            }
+            StoreError::IndexRecordTooLarge => {
+                write!(f, "Index record is too large: ")
+            }
+            StoreError::IdTooLong => write!(f, "Id is too long for the index"),
+            StoreError::IntegrityMismatch => {
+                write!(f, "Store files failed integrity verification")
+            }
+            StoreError::TruncatedRecord { offset } => {
+                write!(f, "Record at offset {} is truncated", offset)
+            }
+            StoreError::CorruptRecord { offset } => {
+                write!(f, "Record at offset {} is corrupt", offset)
+            }
+            StoreError::UnknownRecordLayout => {
+                write!(f, "Record has an unknown layout")
+            }
+            StoreError::MigrationRequired { version } => {
+                write!(
+                    f,
+                    "Store is in schema version {} and needs migrating",
+                    version
+                )
+            }
+            StoreError::EncryptionMismatch => {
+                write!(f, "Record doesn't match the store's encryption mode")
+            }
+            StoreError::PolicyViolation(ref violation) => {
+                write!(f, "Password policy violation: {}", violation)
+            }
+            StoreError::LimitExceeded(ref limit) => write!(f, "Limit exceeded: {}", limit),
+            StoreError::RetriesExhausted(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for StoreError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            StoreError::IoError(ref err) => Some(err),
+            StoreError::SerializationError(ref err) => Some(err),
+            StoreError::PolicyViolation(ref violation) => Some(violation),
+            StoreError::LimitExceeded(ref limit) => Some(limit),
+            StoreError::RetriesExhausted(ref err) => Some(err),
+            StoreError::IndexRecordTooLarge
+            | StoreError::IdTooLong
+            | StoreError::IntegrityMismatch
+            | StoreError::TruncatedRecord { .. }
+            | StoreError::CorruptRecord { .. }
+            | StoreError::UnknownRecordLayout
+            | StoreError::MigrationRequired { .. }
+            | StoreError::EncryptionMismatch => None,
+        }
+    }
+}
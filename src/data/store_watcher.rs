@@ -0,0 +1,177 @@
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver},
+    time::SystemTime,
+};
+
+use super::store_error::StoreError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreEvent {
+    // Another process (e.g. a file sync service) wrote these store files
+    VaultChangedExternally { paths: Vec<PathBuf> },
+}
+
+// Enough to tell a file apart from the version this process last wrote
+type Fingerprint = Option<(u64, SystemTime)>;
+
+fn fingerprint(path: &Path) -> Fingerprint {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+// Watches the files of a store and reports writes that didn't come from
+// this process. Own writes are bracketed by `begin_write` and `end_write`,
+// anything else that changes a file is reported by `check`.
+pub struct StoreWatcher {
+    // Dropping the watcher stops the notifications
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    fingerprints: HashMap<PathBuf, Fingerprint>,
+    changed: HashSet<PathBuf>,
+    write_depth: usize,
+}
+
+impl StoreWatcher {
+    pub fn new<P: AsRef<Path>>(files: &[P]) -> Result<Self, StoreError> {
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(io::Error::other)?;
+
+        let mut fingerprints = HashMap::new();
+        for file in files {
+            let file = file.as_ref();
+            // Stores replace files by renaming, so the directory is watched
+            // rather than the file itself
+            let dir = match file.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            let dir = dir.canonicalize()?;
+            let name = file
+                .file_name()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+            watcher
+                .watch(&dir, RecursiveMode::NonRecursive)
+                .map_err(io::Error::other)?;
+
+            let path = dir.join(name);
+            fingerprints.insert(path.clone(), fingerprint(&path));
+        }
+
+        Ok(StoreWatcher {
+            _watcher: watcher,
+            events,
+            fingerprints,
+            changed: HashSet::new(),
+            write_depth: 0,
+        })
+    }
+
+    // Collects the watched files that changed since they were last seen
+    fn collect_changes(&mut self) {
+        let touched: HashSet<PathBuf> = self
+            .events
+            .try_iter()
+            .filter_map(Result::ok)
+            .flat_map(|event| event.paths)
+            .filter(|path| self.fingerprints.contains_key(path))
+            .collect();
+
+        for path in touched {
+            let current = fingerprint(&path);
+            if self.fingerprints.get(&path) != Some(&current) {
+                self.fingerprints.insert(path.clone(), current);
+                self.changed.insert(path);
+            }
+        }
+    }
+
+    pub fn begin_write(&mut self) {
+        if self.write_depth == 0 {
+            self.collect_changes();
+        }
+        self.write_depth += 1;
+    }
+
+    pub fn end_write(&mut self) {
+        self.write_depth = self.write_depth.saturating_sub(1);
+        if self.write_depth == 0 {
+            // Drop the notifications for our own writes
+            self.events.try_iter().for_each(drop);
+            for (path, known) in self.fingerprints.iter_mut() {
+                *known = fingerprint(path);
+            }
+        }
+    }
+
+    // Reports the files changed by someone else since the last check
+    pub fn check(&mut self) -> Option<StoreEvent> {
+        self.collect_changes();
+        if self.changed.is_empty() {
+            return None;
+        }
+
+        let mut paths: Vec<PathBuf> = self.changed.drain().collect();
+        paths.sort();
+        Some(StoreEvent::VaultChangedExternally { paths })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+    use uuid::Uuid;
+
+    // Notifications arrive asynchronously
+    fn wait_for_change(watcher: &mut StoreWatcher) -> Option<StoreEvent> {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(2) {
+            if let Some(event) = watcher.check() {
+                return Some(event);
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        None
+    }
+
+    #[test]
+    fn test_reports_external_write() {
+        let path = format!("test_watch_{}.bin", Uuid::new_v4());
+        fs::write(&path, b"one").unwrap();
+        let mut watcher = StoreWatcher::new(&[&path]).unwrap();
+
+        fs::write(&path, b"external").unwrap();
+
+        match wait_for_change(&mut watcher) {
+            Some(StoreEvent::VaultChangedExternally { paths }) => {
+                assert_eq!(paths, vec![Path::new(&path).canonicalize().unwrap()]);
+            }
+            None => panic!("external write was not reported"),
+        }
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_ignores_own_write() {
+        let path = format!("test_watch_{}.bin", Uuid::new_v4());
+        fs::write(&path, b"one").unwrap();
+        let mut watcher = StoreWatcher::new(&[&path]).unwrap();
+
+        watcher.begin_write();
+        fs::write(&path, b"own write").unwrap();
+        thread::sleep(Duration::from_millis(100));
+        watcher.end_write();
+
+        assert_eq!(wait_for_change(&mut watcher), None);
+
+        fs::remove_file(path).unwrap();
+    }
+}
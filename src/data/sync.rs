@@ -0,0 +1,235 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{
+    data_store::{filter_fn, DataStore},
+    entry_diff::FieldChange,
+    model::Entry,
+    store_error::StoreError,
+};
+
+// Two-way sync of two stores, e.g. a laptop's and a copy on a shared drive.
+// An entry only one side has is copied to the other. An entry both have
+// with different fields is a conflict for the `ConflictResolver` to settle,
+// as there is no revision history to tell an edit from a stale copy.
+// Deletions aren't synced, a deleted entry comes back from the other side.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    KeepLocal,
+    KeepRemote,
+    // Saved to both sides, e.g. the fields of each that were edited
+    Merged(Box<Entry>),
+}
+
+pub trait ConflictResolver {
+    // Called with the two versions of an entry whose fields differ
+    fn resolve(&mut self, local: &Entry, remote: &Entry) -> Resolution;
+}
+
+// The version saved last by `updated_at`, the local one on a tie
+pub struct NewestWins;
+
+impl ConflictResolver for NewestWins {
+    fn resolve(&mut self, local: &Entry, remote: &Entry) -> Resolution {
+        if remote.updated_at > local.updated_at {
+            Resolution::KeepRemote
+        } else {
+            Resolution::KeepLocal
+        }
+    }
+}
+
+pub struct LocalWins;
+
+impl ConflictResolver for LocalWins {
+    fn resolve(&mut self, _: &Entry, _: &Entry) -> Resolution {
+        Resolution::KeepLocal
+    }
+}
+
+// Asks the user, through `ask`, with what changed from the local version to
+// the remote one. Secret values in the changes only show as changed.
+pub struct InteractiveResolver<F> {
+    ask: F,
+}
+
+impl<F: FnMut(&Entry, &Entry, &[FieldChange]) -> Resolution> InteractiveResolver<F> {
+    pub fn new(ask: F) -> Self {
+        InteractiveResolver { ask }
+    }
+}
+
+impl<F: FnMut(&Entry, &Entry, &[FieldChange]) -> Resolution> ConflictResolver
+    for InteractiveResolver<F>
+{
+    fn resolve(&mut self, local: &Entry, remote: &Entry) -> Resolution {
+        (self.ask)(local, remote, &local.diff(remote))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    // Ids copied from remote to local
+    pub pulled: Vec<String>,
+    // Ids copied from local to remote
+    pub pushed: Vec<String>,
+    pub conflicts: Vec<(String, Resolution)>,
+}
+
+// Brings `local` and `remote` to the same entries. Ids are handled in order,
+// an error stops the sync with the ids before it done.
+pub fn sync<L, R>(
+    local: &mut L,
+    remote: &mut R,
+    resolver: &mut dyn ConflictResolver,
+) -> Result<SyncReport, StoreError>
+where
+    L: DataStore<String, Entry, StoreError> + ?Sized,
+    R: DataStore<String, Entry, StoreError> + ?Sized,
+{
+    let all = filter_fn(|_: &Entry| true);
+    let by_id = |entries: Vec<Entry>| -> BTreeMap<String, Entry> {
+        entries.into_iter().map(|e| (e.id.clone(), e)).collect()
+    };
+    let mut local_entries = by_id(local.search(&all)?);
+    let mut remote_entries = by_id(remote.search(&all)?);
+
+    let ids: BTreeSet<String> = local_entries
+        .keys()
+        .chain(remote_entries.keys())
+        .cloned()
+        .collect();
+
+    let mut report = SyncReport::default();
+    for id in ids {
+        match (local_entries.remove(&id), remote_entries.remove(&id)) {
+            (Some(entry), None) => {
+                remote.save(&id, &entry)?;
+                report.pushed.push(id);
+            }
+            (None, Some(entry)) => {
+                local.save(&id, &entry)?;
+                report.pulled.push(id);
+            }
+            (Some(mine), Some(theirs)) => {
+                if mine.diff(&theirs).is_empty() {
+                    continue;
+                }
+                let resolution = resolver.resolve(&mine, &theirs);
+                match &resolution {
+                    Resolution::KeepLocal => remote.save(&id, &mine)?,
+                    Resolution::KeepRemote => local.save(&id, &theirs)?,
+                    Resolution::Merged(merged) => {
+                        local.save(&id, merged)?;
+                        remote.save(&id, merged)?;
+                    }
+                }
+                report.conflicts.push((id, resolution));
+            }
+            (None, None) => unreachable!("ids come from one side or the other"),
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{binary_file_entry_store::BinaryFileEntryStore, entry_diff::EntryField};
+    use std::fs;
+    use uuid::Uuid;
+
+    fn entry(id: &str, title: &str, updated_at: u64) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: title.to_string(),
+            updated_at,
+            ..Default::default()
+        }
+    }
+
+    fn stores(
+        local: &[Entry],
+        remote: &[Entry],
+    ) -> (BinaryFileEntryStore, BinaryFileEntryStore, [String; 2]) {
+        let id = Uuid::new_v4();
+        let paths = [
+            format!("test_sync_local_{}.bin", id),
+            format!("test_sync_remote_{}.bin", id),
+        ];
+        let mut local_store = BinaryFileEntryStore::new(paths[0].clone());
+        let mut remote_store = BinaryFileEntryStore::new(paths[1].clone());
+        for entry in local {
+            local_store.save(&entry.id, entry).unwrap();
+        }
+        for entry in remote {
+            remote_store.save(&entry.id, entry).unwrap();
+        }
+        (local_store, remote_store, paths)
+    }
+
+    #[test]
+    fn test_newest_wins() {
+        let (mut local, mut remote, paths) = stores(
+            &[entry("1", "Only local", 1), entry("3", "Old", 1)],
+            &[
+                entry("2", "Only remote", 1),
+                entry("3", "New", 2),
+                entry("4", "Same", 5),
+            ],
+        );
+        local
+            .save(&"4".to_string(), &entry("4", "Same", 4))
+            .unwrap();
+
+        let report = sync(&mut local, &mut remote, &mut NewestWins).unwrap();
+
+        assert_eq!(report.pushed, vec!["1"]);
+        assert_eq!(report.pulled, vec!["2"]);
+        assert_eq!(
+            report.conflicts,
+            vec![("3".to_string(), Resolution::KeepRemote)]
+        );
+        for store in [&local, &remote] {
+            let all = store.search(&filter_fn(|_: &Entry| true)).unwrap();
+            let titles: Vec<_> = all.iter().map(|e| e.title.as_str()).collect();
+            assert_eq!(titles, vec!["Only local", "Only remote", "New", "Same"]);
+        }
+
+        drop((local, remote));
+        paths.iter().for_each(|path| fs::remove_file(path).unwrap());
+    }
+
+    #[test]
+    fn test_local_wins_and_interactive() {
+        let (mut local, mut remote, paths) =
+            stores(&[entry("1", "Mine", 1)], &[entry("1", "Theirs", 2)]);
+
+        let report = sync(&mut local, &mut remote, &mut LocalWins).unwrap();
+        assert_eq!(report.conflicts[0].1, Resolution::KeepLocal);
+        assert_eq!(
+            remote.load(&"1".to_string()).unwrap().unwrap().title,
+            "Mine"
+        );
+
+        remote
+            .save(&"1".to_string(), &entry("1", "Theirs", 3))
+            .unwrap();
+        let mut asked = Vec::new();
+        let mut resolver =
+            InteractiveResolver::new(|_: &Entry, _: &Entry, changes: &[FieldChange]| {
+                asked.extend(changes.iter().map(|change| change.field));
+                Resolution::Merged(Box::new(entry("1", "Both", 4)))
+            });
+        sync(&mut local, &mut remote, &mut resolver).unwrap();
+
+        assert_eq!(asked, vec![EntryField::Title]);
+        for store in [&local, &remote] {
+            assert_eq!(store.load(&"1".to_string()).unwrap().unwrap().title, "Both");
+        }
+
+        drop((local, remote));
+        paths.iter().for_each(|path| fs::remove_file(path).unwrap());
+    }
+}
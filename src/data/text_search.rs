@@ -0,0 +1,207 @@
+use std::ops::Range;
+
+use super::{
+    data_store::{DataStore, Filter},
+    model::Entry,
+};
+use crate::output::markdown::plain_text;
+
+// Full-text search over the title, username, url and note of an entry. Every
+// word of the query has to appear, ignoring case. The note is matched
+// without its markdown syntax, so "**wifi**" is found by "wifi" and a
+// search for "*" doesn't match every note with a list.
+pub struct TextFilter {
+    terms: Vec<String>,
+}
+
+impl TextFilter {
+    pub fn new(query: &str) -> Self {
+        Self {
+            terms: query.split_whitespace().map(str::to_lowercase).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SearchField {
+    Title,
+    Username,
+    Url,
+    // Ranges are into the plain text of the note, see `plain_text`
+    Note,
+}
+
+// Where a query term was found, as a byte range into the field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchSpan {
+    pub field: SearchField,
+    pub range: Range<usize>,
+}
+
+// An entry that matched a search, with the spans that made it match so a UI
+// can highlight them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchHit {
+    pub entry: Entry,
+    pub matches: Vec<MatchSpan>,
+}
+
+impl TextFilter {
+    // The spans of every term in `entry`, ordered by field and position, or
+    // None when the entry doesn't pass the filter
+    pub fn matches(&self, entry: &Entry) -> Option<Vec<MatchSpan>> {
+        let note = entry.note.as_deref().map(plain_text);
+        let fields = [
+            (SearchField::Title, Some(entry.title.as_str())),
+            (SearchField::Username, entry.username.as_deref()),
+            (SearchField::Url, entry.url.as_deref()),
+            (SearchField::Note, note.as_deref()),
+        ];
+
+        let mut matches = Vec::new();
+        for term in &self.terms {
+            let found = matches.len();
+            for (field, text) in fields {
+                if let Some(text) = text {
+                    matches.extend(
+                        find_ignoring_case(text, term)
+                            .into_iter()
+                            .map(|range| MatchSpan { field, range }),
+                    );
+                }
+            }
+            if matches.len() == found {
+                return None;
+            }
+        }
+
+        matches.sort_by_key(|span| (span.field as u8, span.range.start, span.range.end));
+        matches.dedup();
+        Some(matches)
+    }
+}
+
+// Byte ranges of `term` (already lowercase) in `text`, mapped back onto the
+// original text since lowercasing can change the length of a character
+fn find_ignoring_case(text: &str, term: &str) -> Vec<Range<usize>> {
+    let mut lower = String::with_capacity(text.len());
+    // The original character of every byte of `lower`
+    let mut origins = Vec::with_capacity(text.len());
+    for (start, c) in text.char_indices() {
+        lower.extend(c.to_lowercase());
+        origins.resize(lower.len(), start..start + c.len_utf8());
+    }
+
+    lower
+        .match_indices(term)
+        .map(|(start, found)| origins[start].start..origins[start + found.len() - 1].end)
+        .collect()
+}
+
+// Runs `filter` against `store` and returns the entries with their matches
+pub fn search_hits<S, E>(store: &S, filter: &TextFilter) -> Result<Vec<SearchHit>, E>
+where
+    S: DataStore<String, Entry, E> + ?Sized,
+{
+    Ok(store
+        .search(filter)?
+        .into_iter()
+        .filter_map(|entry| {
+            let matches = filter.matches(&entry)?;
+            Some(SearchHit { entry, matches })
+        })
+        .collect())
+}
+
+pub fn searchable_text(entry: &Entry) -> String {
+    [
+        Some(entry.title.clone()),
+        entry.username.clone(),
+        entry.url.clone(),
+        entry.note.as_deref().map(plain_text),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join("\n")
+    .to_lowercase()
+}
+
+impl Filter<Entry> for TextFilter {
+    fn pass(&self, entry: &Entry) -> bool {
+        let text = searchable_text(entry);
+        self.terms.iter().all(|term| text.contains(term))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(title: &str, note: &str) -> Entry {
+        Entry {
+            id: "1".to_string(),
+            title: title.to_string(),
+            username: Some("alice".to_string()),
+            password: Some("hunter2".to_string()),
+            note: Some(note.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_matches_note_without_markdown() {
+        let entry = entry(
+            "Router",
+            "The **admin** pass is in the [drawer](https://x.test)",
+        );
+
+        assert!(TextFilter::new("admin pass").pass(&entry));
+        assert!(TextFilter::new("ROUTER alice").pass(&entry));
+        assert!(!TextFilter::new("**admin**").pass(&entry));
+        assert!(!TextFilter::new("x.test").pass(&entry));
+    }
+
+    #[test]
+    fn test_password_is_not_searched() {
+        assert!(!TextFilter::new("hunter2").pass(&entry("Router", "")));
+    }
+
+    #[test]
+    fn test_matches_report_spans() {
+        let entry = entry("Home Router", "The **router** admin");
+
+        let matches = TextFilter::new("router ALICE").matches(&entry).unwrap();
+        assert_eq!(
+            matches,
+            vec![
+                MatchSpan {
+                    field: SearchField::Title,
+                    range: 5..11
+                },
+                MatchSpan {
+                    field: SearchField::Username,
+                    range: 0..5
+                },
+                MatchSpan {
+                    field: SearchField::Note,
+                    range: 4..10
+                },
+            ]
+        );
+        assert_eq!(TextFilter::new("router bob").matches(&entry), None);
+    }
+
+    #[test]
+    fn test_spans_are_byte_ranges_of_the_original() {
+        // 'İ' lowercases to two characters, so offsets shift
+        let entry = entry("İstanbul Öffice", "");
+
+        let matches = TextFilter::new("öffice").matches(&entry).unwrap();
+        let range = matches[0].range.clone();
+        assert_eq!(&entry.title[range], "Öffice");
+
+        let matches = TextFilter::new("i̇stan").matches(&entry).unwrap();
+        assert_eq!(&entry.title[matches[0].range.clone()], "İstan");
+    }
+}
@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    file_swap::{recover_swap, swap_in},
+    fs::{Fs, OsFs},
+    store_error::StoreError,
+};
+
+// What a vault is called and shown as, for listing several vaults by more
+// than their file paths. The header of the entry files only holds the
+// schema version, so it is kept in a small file next to the store, see
+// `StoreBackend::metadata_file_path`. It isn't encrypted: like a file name,
+// it is readable without the key.
+
+const MAGIC: &[u8; 7] = b"TUGMETA";
+const METADATA_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VaultMetadata {
+    name: String,
+    description: Option<String>,
+    // E.g. an emoji or the name of an icon the UI ships with
+    icon: Option<String>,
+    // Milliseconds since the epoch
+    created_at: u64,
+}
+
+impl VaultMetadata {
+    pub fn new(name: &str) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        VaultMetadata {
+            name: name.to_string(),
+            description: None,
+            icon: None,
+            created_at: now,
+        }
+    }
+
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub fn with_icon(mut self, icon: &str) -> Self {
+        self.icon = Some(icon.to_string());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn set_description(&mut self, description: Option<&str>) {
+        self.description = description.map(str::to_string);
+    }
+
+    pub fn icon(&self) -> Option<&str> {
+        self.icon.as_deref()
+    }
+
+    pub fn set_icon(&mut self, icon: Option<&str>) {
+        self.icon = icon.map(str::to_string);
+    }
+
+    pub fn created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    // `None` for a vault that never had metadata saved
+    pub fn load(file_path: &str) -> Result<Option<Self>, StoreError> {
+        recover_swap(&temp_file_path(file_path), file_path)?;
+        if !OsFs.exists(file_path) {
+            return Ok(None);
+        }
+
+        let bytes = fs::read(file_path)?;
+        let body = bytes
+            .strip_prefix(&MAGIC[..])
+            .and_then(|rest| rest.strip_prefix(&[METADATA_VERSION]))
+            .ok_or(StoreError::UnknownRecordLayout)?;
+        Ok(Some(bincode::deserialize(body)?))
+    }
+
+    // Replaces the file as a whole, so a crash leaves the old metadata or
+    // the new, never a mix
+    pub fn save(&self, file_path: &str) -> Result<(), StoreError> {
+        let contents = [&MAGIC[..], &[METADATA_VERSION], &bincode::serialize(self)?].concat();
+        let temp_file_path = temp_file_path(file_path);
+        OsFs.write_file(&temp_file_path, &contents)?;
+        swap_in(&temp_file_path, file_path)
+    }
+}
+
+fn temp_file_path(file_path: &str) -> String {
+    format!("{}.tmp", file_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_save_and_load() {
+        let file_path = format!("test_vault_metadata_{}.meta", Uuid::new_v4());
+        assert_eq!(VaultMetadata::load(&file_path).unwrap(), None);
+
+        let mut metadata = VaultMetadata::new("Personal")
+            .with_description("Everything outside work")
+            .with_icon("🏠");
+        assert!(metadata.created_at() > 0);
+        metadata.save(&file_path).unwrap();
+        assert_eq!(
+            VaultMetadata::load(&file_path).unwrap(),
+            Some(metadata.clone())
+        );
+
+        metadata.set_name("Home");
+        metadata.set_icon(None);
+        metadata.save(&file_path).unwrap();
+        let loaded = VaultMetadata::load(&file_path).unwrap().unwrap();
+        assert_eq!(loaded.name(), "Home");
+        assert_eq!(loaded.description(), Some("Everything outside work"));
+        assert_eq!(loaded.icon(), None);
+        assert_eq!(loaded.created_at(), metadata.created_at());
+
+        fs::write(&file_path, b"not metadata").unwrap();
+        assert!(matches!(
+            VaultMetadata::load(&file_path),
+            Err(StoreError::UnknownRecordLayout)
+        ));
+
+        fs::remove_file(file_path).unwrap();
+    }
+}
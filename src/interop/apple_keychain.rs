@@ -0,0 +1,185 @@
+use csv::{ReaderBuilder, StringRecord};
+use log::warn;
+use std::io::Read;
+use uuid::Uuid;
+
+use super::{import_error::ImportError, import_report::ImportReport, otpauth};
+use crate::data::model::{Entry, EntryKind};
+
+struct Columns {
+    title: usize,
+    url: usize,
+    username: usize,
+    password: usize,
+    notes: usize,
+    otp_auth: Option<usize>,
+}
+
+impl Columns {
+    fn from_headers(headers: &StringRecord) -> Result<Self, ImportError> {
+        let find = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header.trim().eq_ignore_ascii_case(name))
+                .ok_or_else(|| ImportError::MissingColumn(name.to_string()))
+        };
+
+        Ok(Columns {
+            title: find("Title")?,
+            url: find("URL")?,
+            username: find("Username")?,
+            password: find("Password")?,
+            notes: find("Notes")?,
+            otp_auth: find("OTPAuth").ok(),
+        })
+    }
+}
+
+fn field(record: &StringRecord, column: usize) -> Option<String> {
+    record
+        .get(column)
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+}
+
+// Imports the Passwords.csv written by Safari and iCloud Keychain
+pub fn import<R: Read>(reader: R) -> Result<ImportReport, ImportError> {
+    let mut csv_reader = ReaderBuilder::new().flexible(true).from_reader(reader);
+    let columns = Columns::from_headers(csv_reader.headers()?)?;
+
+    let mut report = ImportReport::default();
+
+    for result in csv_reader.records() {
+        let record = match result {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map(|p| p.line()).unwrap_or(0);
+                report.skip(line, e.to_string());
+                continue;
+            }
+        };
+        let line = record.position().map(|p| p.line()).unwrap_or(0);
+
+        let url = field(&record, columns.url);
+        let title = match (field(&record, columns.title), &url) {
+            (Some(title), _) => title,
+            (None, Some(url)) => url.clone(),
+            (None, None) => {
+                report.skip(line, "record has neither a title nor a url".to_string());
+                continue;
+            }
+        };
+
+        let mut entry = Entry {
+            id: Uuid::new_v4().to_string(),
+            title,
+            kind: EntryKind::Login,
+            token: None,
+            username: field(&record, columns.username),
+            password: field(&record, columns.password),
+            url,
+            note: field(&record, columns.notes),
+            favorite: false,
+            label: None,
+            tags: Vec::new(),
+            archived: false,
+            sort_key: None,
+            updated_at: 0,
+            password_changed_at: 0,
+        };
+
+        // Entries have no field for the second factor yet, so the URI is kept
+        // in the note either way rather than lost when the entry is saved
+        let otp_auth = columns.otp_auth.and_then(|column| field(&record, column));
+        if let Some(uri) = otp_auth {
+            match otpauth::parse(&uri) {
+                Ok(totp) => {
+                    report.totp.insert(entry.id.clone(), totp);
+                }
+                Err(e) => warn!("Line {}: {}, keeping OTPAuth in the note", line, e),
+            }
+            entry.note = Some(match entry.note {
+                Some(note) => format!("{}\n{}", note, uri),
+                None => uri,
+            });
+        }
+
+        report.add(entry, None);
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "Title,URL,Username,Password,Notes,OTPAuth\n";
+
+    fn import_str(rows: &str) -> ImportReport {
+        import(format!("{}{}", HEADER, rows).as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_import_login() {
+        let report = import_str(
+            "example.com (alice),https://example.com/,alice,secret,\"line 1\nline 2\",\n",
+        );
+
+        assert_eq!(report.entries.len(), 1);
+        let entry = &report.entries[0];
+        assert_eq!(entry.title, "example.com (alice)");
+        assert_eq!(entry.url, Some("https://example.com/".to_string()));
+        assert_eq!(entry.username, Some("alice".to_string()));
+        assert_eq!(entry.password, Some("secret".to_string()));
+        assert_eq!(entry.note, Some("line 1\nline 2".to_string()));
+        assert!(report.totp.is_empty());
+        assert!(report.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_import_otp_auth() {
+        let report = import_str(
+            "Example,https://example.com,alice,secret,,\
+             otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&issuer=Example\n",
+        );
+
+        let totp = &report.totp[&report.entries[0].id];
+        assert_eq!(totp.secret, "JBSWY3DPEHPK3PXP");
+        assert_eq!(totp.issuer, Some("Example".to_string()));
+        assert_eq!(
+            report.entries[0].note.as_deref(),
+            Some("otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&issuer=Example")
+        );
+    }
+
+    #[test]
+    fn test_import_keeps_invalid_otp_auth_in_note() {
+        let report = import_str("Example,https://example.com,alice,secret,note,otpauth://hotp/x\n");
+
+        assert!(report.totp.is_empty());
+        assert_eq!(
+            report.entries[0].note,
+            Some("note\notpauth://hotp/x".to_string())
+        );
+    }
+
+    #[test]
+    fn test_import_without_otp_auth_column() {
+        let report = import(
+            "Title,URL,Username,Password,Notes\n,https://example.com,alice,secret,\n".as_bytes(),
+        )
+        .unwrap();
+
+        assert_eq!(report.entries[0].title, "https://example.com");
+    }
+
+    #[test]
+    fn test_import_skips_empty_record() {
+        let report = import_str(",,alice,secret,,\nA,https://a.com,a,a,,\n");
+
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].line, 2);
+    }
+}
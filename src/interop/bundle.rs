@@ -0,0 +1,248 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+use crate::data::{
+    data_store::{DataStore, Filter},
+    model::Entry,
+    store_error::StoreError,
+};
+use crate::secret::sealed::{self, Tampered, NONCE_SIZE, TAG_SIZE};
+
+// A read-only copy of some entries in one encrypted file, for viewers such
+// as a phone app that shouldn't need the stores to show them. The layout is
+// the magic and version, then the contents sealed with `secret::sealed`, so
+// a wrong key shows as `Tampered`.
+
+const MAGIC: &[u8; 7] = b"TUGBNDL";
+const BUNDLE_VERSION: u8 = 1;
+const HEADER_SIZE: usize = MAGIC.len() + 1;
+
+#[derive(Debug)]
+pub enum BundleError {
+    StoreError(StoreError),
+    IoError(io::Error),
+    EncodingError(bincode::Error),
+    NotABundle,
+    UnsupportedVersion(u8),
+    // The MAC didn't match, the key is wrong or the file was changed
+    Tampered,
+}
+
+impl From<StoreError> for BundleError {
+    fn from(error: StoreError) -> Self {
+        BundleError::StoreError(error)
+    }
+}
+
+impl From<io::Error> for BundleError {
+    fn from(error: io::Error) -> Self {
+        BundleError::IoError(error)
+    }
+}
+
+impl From<bincode::Error> for BundleError {
+    fn from(error: bincode::Error) -> Self {
+        BundleError::EncodingError(error)
+    }
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            BundleError::StoreError(ref err) => write!(f, "Store error: {}", err),
+            BundleError::IoError(ref err) => write!(f, "IO error: {}", err),
+            BundleError::EncodingError(ref err) => write!(f, "Encoding error: {}", err),
+            BundleError::NotABundle => write!(f, "Not a bundle"),
+            BundleError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported bundle version: {}", version)
+            }
+            BundleError::Tampered => write!(f, "Wrong key or modified bundle"),
+        }
+    }
+}
+
+impl std::error::Error for BundleError {}
+
+#[derive(Serialize, Deserialize)]
+struct BundleContents {
+    // Sorted by id
+    entries: Vec<Entry>,
+    // Positions in `entries` ordered by lowercased title, for listing
+    by_title: Vec<u32>,
+}
+
+impl From<Tampered> for BundleError {
+    fn from(_: Tampered) -> Self {
+        BundleError::Tampered
+    }
+}
+
+// Writes the entries of `store` that pass `filter` as a bundle encrypted
+// with `key`, and returns how many were written.
+pub fn export_bundle<S, W>(
+    store: &S,
+    filter: &dyn Filter<Entry>,
+    key: &[u8; 32],
+    mut writer: W,
+) -> Result<usize, BundleError>
+where
+    S: DataStore<String, Entry, StoreError> + ?Sized,
+    W: Write,
+{
+    let mut entries = store.search(filter)?;
+    entries.sort_by(|a, b| a.id.cmp(&b.id));
+    let mut by_title: Vec<u32> = (0..entries.len() as u32).collect();
+    by_title.sort_by_cached_key(|&i| entries[i as usize].title.to_lowercase());
+    let count = entries.len();
+
+    let contents = bincode::serialize(&BundleContents { entries, by_title })?;
+    let header = [&MAGIC[..], &[BUNDLE_VERSION]].concat();
+
+    writer.write_all(&sealed::seal(key, &header, &contents))?;
+    writer.flush()?;
+
+    Ok(count)
+}
+
+// The reading side of a bundle. It is decrypted and checked as a whole when
+// opened, after that every lookup is in memory.
+pub struct BundleReader {
+    contents: BundleContents,
+}
+
+impl BundleReader {
+    pub fn open<R: Read>(mut reader: R, key: &[u8; 32]) -> Result<Self, BundleError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        if bytes.len() < HEADER_SIZE + NONCE_SIZE + TAG_SIZE || !bytes.starts_with(MAGIC) {
+            return Err(BundleError::NotABundle);
+        }
+        let version = bytes[MAGIC.len()];
+        if version != BUNDLE_VERSION {
+            return Err(BundleError::UnsupportedVersion(version));
+        }
+
+        let opened = sealed::open(key, HEADER_SIZE, &bytes)?;
+        Ok(Self {
+            contents: bincode::deserialize(&opened.plaintext)?,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.contents.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contents.entries.is_empty()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Entry> {
+        let entries = &self.contents.entries;
+        entries
+            .binary_search_by(|entry| entry.id.as_str().cmp(id))
+            .ok()
+            .map(|i| &entries[i])
+    }
+
+    // By id
+    pub fn entries(&self) -> &[Entry] {
+        &self.contents.entries
+    }
+
+    // By title, ignoring case
+    pub fn by_title(&self) -> impl Iterator<Item = &Entry> {
+        self.contents
+            .by_title
+            .iter()
+            .filter_map(|&i| self.contents.entries.get(i as usize))
+    }
+
+    pub fn search(&self, filter: &dyn Filter<Entry>) -> Vec<&Entry> {
+        self.contents
+            .entries
+            .iter()
+            .filter(|entry| filter.pass(entry))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{
+        binary_file_entry_store::BinaryFileEntryStore, data_store::filter_fn,
+        text_search::TextFilter,
+    };
+    use std::fs;
+    use uuid::Uuid;
+
+    fn entry(id: &str, title: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: title.to_string(),
+            username: Some("alice".to_string()),
+            password: Some("s3cret".to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn bundle(key: &[u8; 32]) -> Vec<u8> {
+        let file_path = format!("test_bundle_{}.bin", Uuid::new_v4());
+        let mut store = BinaryFileEntryStore::new(file_path.clone());
+        store.save(&"2".to_string(), &entry("2", "bank")).unwrap();
+        store.save(&"1".to_string(), &entry("1", "Mail")).unwrap();
+        store.save(&"3".to_string(), &entry("3", "Alarm")).unwrap();
+
+        let mut bytes = Vec::new();
+        let count = export_bundle(&store, &filter_fn(|_: &Entry| true), key, &mut bytes).unwrap();
+        assert_eq!(count, 3);
+
+        drop(store);
+        fs::remove_file(file_path).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let key = [7; 32];
+        let bytes = bundle(&key);
+        assert!(!bytes
+            .windows(b"s3cret".len())
+            .any(|window| window == b"s3cret"));
+
+        let reader = BundleReader::open(bytes.as_slice(), &key).unwrap();
+
+        assert_eq!(reader.len(), 3);
+        assert_eq!(reader.get("2"), Some(&entry("2", "bank")));
+        assert_eq!(reader.get("4"), None);
+        let titles: Vec<_> = reader.by_title().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, vec!["Alarm", "bank", "Mail"]);
+        assert_eq!(
+            reader.search(&TextFilter::new("mail")),
+            vec![&entry("1", "Mail")]
+        );
+    }
+
+    #[test]
+    fn test_rejects_wrong_key_and_changes() {
+        let key = [7; 32];
+        let mut bytes = bundle(&key);
+
+        assert!(matches!(
+            BundleReader::open(bytes.as_slice(), &[8; 32]),
+            Err(BundleError::Tampered)
+        ));
+        bytes[HEADER_SIZE + NONCE_SIZE] ^= 1;
+        assert!(matches!(
+            BundleReader::open(bytes.as_slice(), &key),
+            Err(BundleError::Tampered)
+        ));
+        assert!(matches!(
+            BundleReader::open(&b"not a bundle at all, but long enough to pass"[..], &key),
+            Err(BundleError::NotABundle)
+        ));
+    }
+}
@@ -0,0 +1,333 @@
+use csv::{ReaderBuilder, StringRecord};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, io, io::Read};
+use uuid::Uuid;
+
+use super::{import_error::ImportError, import_report::ImportReport};
+use crate::data::model::{Entry, EntryKind};
+
+// Imports CSV files from sources without an importer of their own, using a
+// profile that says which column goes where. Profiles are saved under a
+// name, so the next export from the same source is imported with just that
+// name.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    Title,
+    Username,
+    Password,
+    Url,
+    // Values of several columns are joined line by line
+    Note,
+    // Adds the value as a tag, several columns may do so
+    Tag,
+    // A folder path such as "Work/Email"
+    Folder,
+    // "1", "true" or "yes" in any case
+    Favorite,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Transform {
+    Copy {
+        target: Target,
+    },
+    // Splits the value at `separator` into as many parts as there are
+    // targets, e.g. "alice @ example.com" into a username and a url. The
+    // last target gets the rest, missing parts are left empty.
+    Split {
+        separator: String,
+        targets: Vec<Target>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnRule {
+    // Header of the column, matched ignoring case and surrounding spaces
+    pub column: String,
+    pub transform: Transform,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MappingProfile {
+    pub name: String,
+    pub rules: Vec<ColumnRule>,
+}
+
+impl MappingProfile {
+    pub fn new(name: &str) -> Self {
+        MappingProfile {
+            name: name.to_string(),
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn with_column(mut self, column: &str, target: Target) -> Self {
+        self.rules.push(ColumnRule {
+            column: column.to_string(),
+            transform: Transform::Copy { target },
+        });
+        self
+    }
+
+    pub fn with_split(mut self, column: &str, separator: &str, targets: &[Target]) -> Self {
+        self.rules.push(ColumnRule {
+            column: column.to_string(),
+            transform: Transform::Split {
+                separator: separator.to_string(),
+                targets: targets.to_vec(),
+            },
+        });
+        self
+    }
+
+    // Imports `reader` with this profile. Columns it doesn't name are
+    // ignored. Records with neither a title nor a url are skipped, as by
+    // the other importers.
+    pub fn import<R: Read>(&self, reader: R) -> Result<ImportReport, ImportError> {
+        let mut csv_reader = ReaderBuilder::new().flexible(true).from_reader(reader);
+        let headers = csv_reader.headers()?;
+        let columns = self
+            .rules
+            .iter()
+            .map(|rule| {
+                headers
+                    .iter()
+                    .position(|header| header.trim().eq_ignore_ascii_case(rule.column.trim()))
+                    .ok_or_else(|| ImportError::MissingColumn(rule.column.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut report = ImportReport::default();
+
+        for result in csv_reader.records() {
+            let record = match result {
+                Ok(record) => record,
+                Err(e) => {
+                    let line = e.position().map(|p| p.line()).unwrap_or(0);
+                    report.skip(line, e.to_string());
+                    continue;
+                }
+            };
+            let line = record.position().map(|p| p.line()).unwrap_or(0);
+
+            let mut mapped = Mapped::default();
+            for (rule, &column) in self.rules.iter().zip(&columns) {
+                mapped.apply(&rule.transform, field(&record, column));
+            }
+
+            let title = match (mapped.title, &mapped.url) {
+                (Some(title), _) => title,
+                (None, Some(url)) => url.clone(),
+                (None, None) => {
+                    report.skip(line, "record has neither a title nor a url".to_string());
+                    continue;
+                }
+            };
+
+            let entry = Entry {
+                id: Uuid::new_v4().to_string(),
+                title,
+                kind: EntryKind::Login,
+                token: None,
+                username: mapped.username,
+                password: mapped.password,
+                url: mapped.url,
+                note: mapped.note,
+                favorite: mapped.favorite,
+                label: None,
+                tags: mapped.tags,
+                archived: false,
+                sort_key: None,
+                updated_at: 0,
+                password_changed_at: 0,
+            };
+            report.add(entry, mapped.folder);
+        }
+
+        Ok(report)
+    }
+}
+
+fn field(record: &StringRecord, column: usize) -> Option<&str> {
+    record
+        .get(column)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+// The fields of one record as the rules fill them in
+#[derive(Default)]
+struct Mapped {
+    title: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    url: Option<String>,
+    note: Option<String>,
+    tags: Vec<String>,
+    folder: Option<String>,
+    favorite: bool,
+}
+
+impl Mapped {
+    fn apply(&mut self, transform: &Transform, value: Option<&str>) {
+        let value = match value {
+            Some(value) => value,
+            None => return,
+        };
+        match transform {
+            Transform::Copy { target } => self.set(*target, value),
+            Transform::Split { separator, targets } => {
+                for (target, part) in targets
+                    .iter()
+                    .zip(value.splitn(targets.len(), &**separator))
+                {
+                    let part = part.trim();
+                    if !part.is_empty() {
+                        self.set(*target, part);
+                    }
+                }
+            }
+        }
+    }
+
+    fn set(&mut self, target: Target, value: &str) {
+        let value = value.to_string();
+        match target {
+            Target::Title => self.title = Some(value),
+            Target::Username => self.username = Some(value),
+            Target::Password => self.password = Some(value),
+            Target::Url => self.url = Some(value),
+            Target::Note => {
+                self.note = Some(match self.note.take() {
+                    Some(note) => format!("{}\n{}", note, value),
+                    None => value,
+                })
+            }
+            Target::Tag => self.tags.push(value),
+            Target::Folder => self.folder = Some(value),
+            Target::Favorite => {
+                self.favorite = ["1", "true", "yes"]
+                    .iter()
+                    .any(|yes| value.eq_ignore_ascii_case(yes))
+            }
+        }
+    }
+}
+
+// The saved profiles, kept as JSON in the config directory
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MappingProfiles {
+    profiles: BTreeMap<String, MappingProfile>,
+}
+
+impl MappingProfiles {
+    // No profiles when the file doesn't exist yet
+    pub fn load(file_path: &str) -> Result<Self, ImportError> {
+        match fs::read(file_path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Written next to the file first, so a crash leaves the old profiles
+    pub fn save(&self, file_path: &str) -> Result<(), ImportError> {
+        let temp_file_path = format!("{}.tmp", file_path);
+        fs::write(&temp_file_path, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(temp_file_path, file_path)?;
+        Ok(())
+    }
+
+    // Replaces a profile of the same name
+    pub fn insert(&mut self, profile: MappingProfile) {
+        self.profiles.insert(profile.name.clone(), profile);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<MappingProfile> {
+        self.profiles.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MappingProfile> {
+        self.profiles.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    pub fn import<R: Read>(&self, name: &str, reader: R) -> Result<ImportReport, ImportError> {
+        self.get(name)
+            .ok_or_else(|| ImportError::UnknownProfile(name.to_string()))?
+            .import(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile() -> MappingProfile {
+        MappingProfile::new("bank")
+            .with_split("Account", "@", &[Target::Username, Target::Url])
+            .with_column("Secret", Target::Password)
+            .with_column("Memo", Target::Note)
+            .with_column("Extra", Target::Note)
+            .with_column("Group", Target::Folder)
+            .with_column("Starred", Target::Favorite)
+    }
+
+    #[test]
+    fn test_import_with_profile() {
+        let csv = "account,Secret,Memo,Extra,Group,Starred\n\
+                   alice@bank.example,pw1,first,second,Money,Yes\n\
+                   bob,pw2,,,,\n\
+                   alice@ other.example,pw3,,,,0\n";
+        let report = profile().import(csv.as_bytes()).unwrap();
+
+        assert_eq!(report.entries.len(), 2);
+        let entry = &report.entries[0];
+        assert_eq!(entry.title, "bank.example");
+        assert_eq!(entry.username.as_deref(), Some("alice"));
+        assert_eq!(entry.url.as_deref(), Some("bank.example"));
+        assert_eq!(entry.password.as_deref(), Some("pw1"));
+        assert_eq!(entry.note.as_deref(), Some("first\nsecond"));
+        assert!(entry.favorite);
+        assert_eq!(report.folders["Money"], vec![entry.id.clone()]);
+        assert_eq!(report.entries[1].url.as_deref(), Some("other.example"));
+        // "bob" has no url and no title
+        assert_eq!(report.skipped[0].line, 3);
+
+        assert!(matches!(
+            profile().import("Secret\n".as_bytes()),
+            Err(ImportError::MissingColumn(ref c)) if c == "Account"
+        ));
+    }
+
+    #[test]
+    fn test_profiles_persist() {
+        let file_path = format!("test_mapping_profiles_{}.json", Uuid::new_v4());
+        let mut profiles = MappingProfiles::load(&file_path).unwrap();
+        assert_eq!(profiles.names().count(), 0);
+        profiles.insert(profile());
+        profiles.save(&file_path).unwrap();
+
+        let profiles = MappingProfiles::load(&file_path).unwrap();
+        assert_eq!(profiles.get("bank"), Some(&profile()));
+        let report = profiles
+            .import(
+                "bank",
+                "Account,Secret,Memo,Extra,Group,Starred\na@b.c,x,,,,\n".as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(report.entries[0].username.as_deref(), Some("a"));
+        assert!(matches!(
+            profiles.import("other", "".as_bytes()),
+            Err(ImportError::UnknownProfile(_))
+        ));
+
+        fs::remove_file(file_path).unwrap();
+    }
+}
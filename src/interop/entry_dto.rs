@@ -0,0 +1,375 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::timestamp;
+use crate::data::model::{Entry, EntryKind, Label, TokenInfo};
+
+// Bump when the JSON shape changes; older documents must keep loading.
+// 2: `updated_at` is an RFC 3339 string or null instead of milliseconds
+pub const ENTRY_SCHEMA_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelDto {
+    Red,
+    Orange,
+    Yellow,
+    Green,
+    Blue,
+    Purple,
+    Gray,
+}
+
+impl From<Label> for LabelDto {
+    fn from(label: Label) -> Self {
+        match label {
+            Label::Red => LabelDto::Red,
+            Label::Orange => LabelDto::Orange,
+            Label::Yellow => LabelDto::Yellow,
+            Label::Green => LabelDto::Green,
+            Label::Blue => LabelDto::Blue,
+            Label::Purple => LabelDto::Purple,
+            Label::Gray => LabelDto::Gray,
+        }
+    }
+}
+
+impl From<LabelDto> for Label {
+    fn from(label: LabelDto) -> Self {
+        match label {
+            LabelDto::Red => Label::Red,
+            LabelDto::Orange => Label::Orange,
+            LabelDto::Yellow => Label::Yellow,
+            LabelDto::Green => Label::Green,
+            LabelDto::Blue => Label::Blue,
+            LabelDto::Purple => Label::Purple,
+            LabelDto::Gray => Label::Gray,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKindDto {
+    #[default]
+    Login,
+    SecureNote,
+    Card,
+    Identity,
+    Token,
+}
+
+impl From<EntryKind> for EntryKindDto {
+    fn from(kind: EntryKind) -> Self {
+        match kind {
+            EntryKind::Login => EntryKindDto::Login,
+            EntryKind::SecureNote => EntryKindDto::SecureNote,
+            EntryKind::Card => EntryKindDto::Card,
+            EntryKind::Identity => EntryKindDto::Identity,
+            EntryKind::Token => EntryKindDto::Token,
+        }
+    }
+}
+
+impl From<EntryKindDto> for EntryKind {
+    fn from(kind: EntryKindDto) -> Self {
+        match kind {
+            EntryKindDto::Login => EntryKind::Login,
+            EntryKindDto::SecureNote => EntryKind::SecureNote,
+            EntryKindDto::Card => EntryKind::Card,
+            EntryKindDto::Identity => EntryKind::Identity,
+            EntryKindDto::Token => EntryKind::Token,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TokenDto {
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    // Null when the token doesn't expire
+    #[serde(default, with = "timestamp::rfc3339_millis")]
+    pub expires_at: u64,
+}
+
+impl From<&TokenInfo> for TokenDto {
+    fn from(token: &TokenInfo) -> Self {
+        TokenDto {
+            prefix: token.prefix.clone(),
+            scopes: token.scopes.clone(),
+            expires_at: token.expires_at,
+        }
+    }
+}
+
+impl From<TokenDto> for TokenInfo {
+    fn from(token: TokenDto) -> Self {
+        TokenInfo {
+            prefix: token.prefix,
+            scopes: token.scopes,
+            expires_at: token.expires_at,
+        }
+    }
+}
+
+// The stable, external JSON representation of an `Entry`. It is decoupled
+// from the bincode layout so the on-disk format can change freely.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EntryDto {
+    pub schema: u32,
+    pub id: String,
+    pub title: String,
+    // Documents from before kinds are logins
+    #[serde(default)]
+    pub kind: EntryKindDto,
+    #[serde(default)]
+    pub token: Option<TokenDto>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub url: Option<String>,
+    pub note: Option<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub label: Option<LabelDto>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub sort_key: Option<String>,
+    // Unix milliseconds, see `timestamp::rfc3339_millis`
+    #[serde(default, with = "timestamp::rfc3339_millis")]
+    pub updated_at: u64,
+    #[serde(default, with = "timestamp::rfc3339_millis")]
+    pub password_changed_at: u64,
+}
+
+impl fmt::Debug for EntryDto {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EntryDto")
+            .field("schema", &self.schema)
+            .field("id", &self.id)
+            .field("title", &self.title)
+            .field("kind", &self.kind)
+            .field("token", &self.token)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .field("url", &self.url)
+            .field("note", &self.note)
+            .field("favorite", &self.favorite)
+            .field("label", &self.label)
+            .field("tags", &self.tags)
+            .field("archived", &self.archived)
+            .field("sort_key", &self.sort_key)
+            .field("updated_at", &self.updated_at)
+            .field("password_changed_at", &self.password_changed_at)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub enum EntryDtoError {
+    JsonError(serde_json::Error),
+    UnsupportedSchema(u32),
+}
+
+impl From<serde_json::Error> for EntryDtoError {
+    fn from(error: serde_json::Error) -> Self {
+        EntryDtoError::JsonError(error)
+    }
+}
+
+impl fmt::Display for EntryDtoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            EntryDtoError::JsonError(ref err) => write!(f, "JSON error: {}", err),
+            EntryDtoError::UnsupportedSchema(schema) => {
+                write!(f, "Unsupported entry schema: {}", schema)
+            }
+        }
+    }
+}
+
+impl From<&Entry> for EntryDto {
+    fn from(entry: &Entry) -> Self {
+        EntryDto {
+            schema: ENTRY_SCHEMA_VERSION,
+            id: entry.id.clone(),
+            title: entry.title.clone(),
+            kind: entry.kind.into(),
+            token: entry.token.as_ref().map(TokenDto::from),
+            username: entry.username.clone(),
+            password: entry.password.clone(),
+            url: entry.url.clone(),
+            note: entry.note.clone(),
+            favorite: entry.favorite,
+            label: entry.label.map(LabelDto::from),
+            tags: entry.tags.clone(),
+            archived: entry.archived,
+            sort_key: entry.sort_key.clone(),
+            updated_at: entry.updated_at,
+            password_changed_at: entry.password_changed_at,
+        }
+    }
+}
+
+impl TryFrom<EntryDto> for Entry {
+    type Error = EntryDtoError;
+
+    fn try_from(dto: EntryDto) -> Result<Self, Self::Error> {
+        if dto.schema == 0 || dto.schema > ENTRY_SCHEMA_VERSION {
+            return Err(EntryDtoError::UnsupportedSchema(dto.schema));
+        }
+
+        Ok(Entry {
+            id: dto.id,
+            title: dto.title,
+            kind: dto.kind.into(),
+            token: dto.token.map(TokenInfo::from),
+            username: dto.username,
+            password: dto.password,
+            url: dto.url,
+            note: dto.note,
+            favorite: dto.favorite,
+            label: dto.label.map(Label::from),
+            tags: dto.tags,
+            archived: dto.archived,
+            sort_key: dto.sort_key,
+            updated_at: dto.updated_at,
+            password_changed_at: dto.password_changed_at,
+        })
+    }
+}
+
+pub fn to_json(entry: &Entry) -> Result<String, EntryDtoError> {
+    Ok(serde_json::to_string(&EntryDto::from(entry))?)
+}
+
+pub fn from_json(json: &str) -> Result<Entry, EntryDtoError> {
+    let dto: EntryDto = serde_json::from_str(json)?;
+    Entry::try_from(dto)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn entry() -> Entry {
+        Entry {
+            id: "1".to_string(),
+            title: "Example".to_string(),
+            username: Some("user1".to_string()),
+            password: Some("pass1".to_string()),
+            url: Some("https://example.com".to_string()),
+            favorite: true,
+            label: Some(Label::Blue),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let entry = entry();
+
+        let json = to_json(&entry).unwrap();
+
+        assert_eq!(from_json(&json).unwrap(), entry);
+    }
+
+    #[test]
+    fn test_json_shape() {
+        let value: Value = serde_json::from_str(&to_json(&entry()).unwrap()).unwrap();
+
+        assert_eq!(
+            value,
+            json!({
+                "schema": 2,
+                "id": "1",
+                "title": "Example",
+                "kind": "login",
+                "token": null,
+                "username": "user1",
+                "password": "pass1",
+                "url": "https://example.com",
+                "note": null,
+                "favorite": true,
+                "label": "blue",
+                "tags": [],
+                "archived": false,
+                "sort_key": null,
+                "updated_at": null,
+                "password_changed_at": null
+            })
+        );
+    }
+
+    #[test]
+    fn test_optional_fields_default() {
+        let json = r#"{"schema":1,"id":"1","title":"t","username":null,"password":null,"url":null,"note":null}"#;
+
+        let entry = from_json(json).unwrap();
+
+        assert!(!entry.favorite);
+        assert_eq!(entry.label, None);
+        assert!(entry.tags.is_empty());
+        assert_eq!(entry.kind, EntryKind::Login);
+        assert!(!entry.archived);
+        assert_eq!(entry.updated_at, 0);
+    }
+
+    #[test]
+    fn test_debug_redacts_password() {
+        let output = format!("{:?}", EntryDto::from(&entry()));
+
+        assert!(!output.contains("pass1"));
+    }
+
+    #[test]
+    fn test_unsupported_schema() {
+        let mut value = serde_json::to_value(EntryDto::from(&entry())).unwrap();
+        value["schema"] = json!(ENTRY_SCHEMA_VERSION + 1);
+
+        let result = from_json(&value.to_string());
+
+        assert!(matches!(result, Err(EntryDtoError::UnsupportedSchema(3))));
+    }
+
+    #[test]
+    fn test_updated_at_is_rfc3339() {
+        let mut entry = entry();
+        entry.updated_at = 1_709_251_198_123;
+
+        let value = serde_json::to_value(EntryDto::from(&entry)).unwrap();
+        assert_eq!(value["updated_at"], json!("2024-02-29T23:59:58.123Z"));
+        assert_eq!(from_json(&value.to_string()).unwrap(), entry);
+
+        // Schema 1 wrote milliseconds
+        let json = r#"{"schema":1,"id":"1","title":"t","username":null,"password":null,"url":null,"note":null,"updated_at":1709251198123}"#;
+        assert_eq!(from_json(json).unwrap().updated_at, entry.updated_at);
+    }
+
+    #[test]
+    fn test_token_round_trip() {
+        let entry = Entry {
+            kind: EntryKind::Token,
+            token: Some(TokenInfo {
+                prefix: Some("ghp_".to_string()),
+                scopes: vec!["repo".to_string()],
+                expires_at: 1_709_251_198_000,
+            }),
+            ..entry()
+        };
+
+        let value = serde_json::to_value(EntryDto::from(&entry)).unwrap();
+        assert_eq!(value["kind"], json!("token"));
+        assert_eq!(
+            value["token"]["expires_at"],
+            json!("2024-02-29T23:59:58.000Z")
+        );
+        assert_eq!(from_json(&value.to_string()).unwrap(), entry);
+    }
+}
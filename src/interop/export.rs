@@ -0,0 +1,373 @@
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use std::{collections::HashMap, fmt, io::Write};
+
+#[cfg(feature = "csv")]
+use super::timestamp::to_rfc3339;
+use super::{entry_dto::EntryDto, jsonl};
+use crate::data::{
+    data_store::{DataStore, Filter},
+    entry_diff::EntryField,
+    model::Entry,
+    store_error::StoreError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[cfg(feature = "csv")]
+    Csv,
+    // An array of `EntryDto`s
+    Json,
+    // One `EntryDto` per line, see `jsonl`
+    JsonLines,
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    StoreError(StoreError),
+    #[cfg(feature = "csv")]
+    CsvError(csv::Error),
+    JsonError(serde_json::Error),
+}
+
+impl From<StoreError> for ExportError {
+    fn from(error: StoreError) -> Self {
+        ExportError::StoreError(error)
+    }
+}
+
+#[cfg(feature = "csv")]
+impl From<csv::Error> for ExportError {
+    fn from(error: csv::Error) -> Self {
+        ExportError::CsvError(error)
+    }
+}
+
+impl From<serde_json::Error> for ExportError {
+    fn from(error: serde_json::Error) -> Self {
+        ExportError::JsonError(error)
+    }
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ExportError::StoreError(ref err) => write!(f, "Store error: {}", err),
+            #[cfg(feature = "csv")]
+            ExportError::CsvError(ref err) => write!(f, "CSV error: {}", err),
+            ExportError::JsonError(ref err) => write!(f, "JSON error: {}", err),
+        }
+    }
+}
+
+// What an export writes in place of a field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redaction {
+    Keep,
+    // Written as unset
+    Exclude,
+    // A keyed hash, so equal values (e.g. a reused password) can be spotted
+    // without the value being recoverable from the export
+    Hash,
+}
+
+// How each of the optional text fields is written, for exporting the shape
+// of a vault without its secrets. Everything is kept by default.
+#[derive(Clone)]
+pub struct RedactionPolicy {
+    fields: HashMap<EntryField, Redaction>,
+    // Random per policy, so hashes only compare within one export unless
+    // the caller sets a key to compare across exports
+    hash_key: [u8; 32],
+}
+
+impl Default for RedactionPolicy {
+    fn default() -> Self {
+        let mut hash_key = [0u8; 32];
+        rand::rng().fill_bytes(&mut hash_key);
+        Self {
+            fields: HashMap::new(),
+            hash_key,
+        }
+    }
+}
+
+fn is_redactable(field: EntryField) -> bool {
+    matches!(
+        field,
+        EntryField::Username | EntryField::Password | EntryField::Url | EntryField::Note
+    )
+}
+
+impl RedactionPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Shorthand for hashing or excluding passwords, the secret fields
+    pub fn passwords(redaction: Redaction) -> Self {
+        Self::new().with_field(EntryField::Password, redaction)
+    }
+
+    // Only the username, password, url and note can be redacted, other
+    // fields are always kept
+    pub fn with_field(mut self, field: EntryField, redaction: Redaction) -> Self {
+        if is_redactable(field) {
+            self.fields.insert(field, redaction);
+        }
+        self
+    }
+
+    pub fn with_hash_key(mut self, hash_key: [u8; 32]) -> Self {
+        self.hash_key = hash_key;
+        self
+    }
+
+    pub fn redaction(&self, field: EntryField) -> Redaction {
+        self.fields.get(&field).copied().unwrap_or(Redaction::Keep)
+    }
+
+    fn hash(&self, value: &str) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.hash_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(value.as_bytes());
+        format!("hmac-sha256:{}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    fn apply_to(&self, field: EntryField, value: &mut Option<String>) {
+        match self.redaction(field) {
+            Redaction::Keep => {}
+            Redaction::Exclude => *value = None,
+            Redaction::Hash => *value = value.as_deref().map(|value| self.hash(value)),
+        }
+    }
+
+    pub fn apply(&self, entry: &Entry) -> Entry {
+        let mut entry = entry.clone();
+        self.apply_to(EntryField::Username, &mut entry.username);
+        self.apply_to(EntryField::Password, &mut entry.password);
+        self.apply_to(EntryField::Url, &mut entry.url);
+        self.apply_to(EntryField::Note, &mut entry.note);
+        entry
+    }
+}
+
+// `updated_at` is RFC 3339, empty when unknown
+#[cfg(feature = "csv")]
+const CSV_HEADER: [&str; 9] = [
+    "id",
+    "title",
+    "username",
+    "password",
+    "url",
+    "note",
+    "favorite",
+    "label",
+    "updated_at",
+];
+
+#[cfg(feature = "csv")]
+fn write_csv<W: Write>(entries: &[Entry], writer: W) -> Result<(), ExportError> {
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    csv_writer.write_record(CSV_HEADER)?;
+
+    for entry in entries {
+        let label = entry
+            .label
+            .map(|label| format!("{:?}", label).to_lowercase())
+            .unwrap_or_default();
+        let updated_at = match entry.updated_at {
+            0 => String::new(),
+            millis => to_rfc3339(millis),
+        };
+        csv_writer.write_record([
+            entry.id.as_str(),
+            entry.title.as_str(),
+            entry.username.as_deref().unwrap_or_default(),
+            entry.password.as_deref().unwrap_or_default(),
+            entry.url.as_deref().unwrap_or_default(),
+            entry.note.as_deref().unwrap_or_default(),
+            if entry.favorite { "1" } else { "0" },
+            label.as_str(),
+            updated_at.as_str(),
+        ])?;
+    }
+
+    csv_writer.flush().map_err(csv::Error::from)?;
+    Ok(())
+}
+
+// Writes the entries of `store` that pass `filter`, passwords included, and
+// returns how many were exported.
+pub fn export_where<S, W>(
+    store: &S,
+    filter: &dyn Filter<Entry>,
+    format: ExportFormat,
+    writer: W,
+) -> Result<usize, ExportError>
+where
+    S: DataStore<String, Entry, StoreError> + ?Sized,
+    W: Write,
+{
+    export_redacted(store, filter, format, &RedactionPolicy::default(), writer)
+}
+
+// Like `export_where`, with the fields of every entry redacted by `policy`
+pub fn export_redacted<S, W>(
+    store: &S,
+    filter: &dyn Filter<Entry>,
+    format: ExportFormat,
+    policy: &RedactionPolicy,
+    mut writer: W,
+) -> Result<usize, ExportError>
+where
+    S: DataStore<String, Entry, StoreError> + ?Sized,
+    W: Write,
+{
+    let entries: Vec<Entry> = store
+        .search(filter)?
+        .iter()
+        .map(|entry| policy.apply(entry))
+        .collect();
+
+    match format {
+        #[cfg(feature = "csv")]
+        ExportFormat::Csv => write_csv(&entries, writer)?,
+        ExportFormat::Json => {
+            let dtos: Vec<EntryDto> = entries.iter().map(EntryDto::from).collect();
+            serde_json::to_writer_pretty(&mut writer, &dtos)?;
+        }
+        ExportFormat::JsonLines => {
+            jsonl::export(&entries, writer)?;
+        }
+    }
+
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{
+        binary_file_entry_store::BinaryFileEntryStore, data_store::filter_fn,
+        favorites::FavoriteFilter, model::Label,
+    };
+    use std::fs;
+    use uuid::Uuid;
+
+    fn entry(id: &str, favorite: bool) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: format!("Entry {}", id),
+            username: Some(format!("user{}", id)),
+            password: Some("p,ss\"word".to_string()),
+            favorite,
+            label: Some(Label::Blue),
+            ..Default::default()
+        }
+    }
+
+    fn store(path: &str) -> BinaryFileEntryStore {
+        let mut store = BinaryFileEntryStore::new(path.to_string());
+        for entry in [entry("1", true), entry("2", false), entry("3", true)] {
+            store.save(&entry.id, &entry).unwrap();
+        }
+        store
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_export_csv_where() {
+        let path = format!("test_export_{}.bin", Uuid::new_v4());
+        let store = store(&path);
+        let mut output = Vec::new();
+
+        let exported =
+            export_where(&store, &FavoriteFilter, ExportFormat::Csv, &mut output).unwrap();
+
+        assert_eq!(exported, 2);
+        let mut reader = csv::Reader::from_reader(output.as_slice());
+        assert_eq!(reader.headers().unwrap(), CSV_HEADER.as_slice());
+        let records: Vec<_> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(&records[0][0], "1");
+        assert_eq!(&records[0][3], "p,ss\"word");
+        assert_eq!(&records[0][7], "blue");
+        assert_eq!(&records[0][8], "");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_export_json_where() {
+        let path = format!("test_export_{}.bin", Uuid::new_v4());
+        let store = store(&path);
+        let mut output = Vec::new();
+
+        let filter = filter_fn(|entry: &Entry| entry.id == "2");
+        export_where(&store, &filter, ExportFormat::Json, &mut output).unwrap();
+
+        let dtos: Vec<EntryDto> = serde_json::from_slice(&output).unwrap();
+        assert_eq!(dtos.len(), 1);
+        assert_eq!(Entry::try_from(dtos[0].clone()).unwrap(), entry("2", false));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_export_json_lines_where() {
+        let path = format!("test_export_{}.bin", Uuid::new_v4());
+        let store = store(&path);
+        let mut output = Vec::new();
+
+        let exported = export_where(
+            &store,
+            &FavoriteFilter,
+            ExportFormat::JsonLines,
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(exported, 2);
+        let report = jsonl::import(output.as_slice()).unwrap();
+        assert_eq!(report.entries, vec![entry("1", true), entry("3", true)]);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_export_redacted() {
+        let path = format!("test_export_{}.bin", Uuid::new_v4());
+        let store = store(&path);
+        let all = filter_fn(|_: &Entry| true);
+
+        let policy = RedactionPolicy::passwords(Redaction::Hash)
+            .with_field(EntryField::Username, Redaction::Exclude);
+        let mut output = Vec::new();
+        export_redacted(&store, &all, ExportFormat::Json, &policy, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(!output.contains("p,ss"));
+        assert!(!output.contains("user1"));
+
+        let dtos: Vec<EntryDto> = serde_json::from_str(&output).unwrap();
+        assert_eq!(dtos[0].username, None);
+        assert_eq!(dtos[0].title, "Entry 1");
+        // The same password hashes the same within an export
+        let hash = dtos[0].password.clone().unwrap();
+        assert!(hash.starts_with("hmac-sha256:"));
+        assert_eq!(dtos[1].password.as_ref(), Some(&hash));
+        // But not across exports without a shared key
+        let other = RedactionPolicy::passwords(Redaction::Hash).apply(&entry("1", true));
+        assert_ne!(other.password, Some(hash));
+
+        let policy = RedactionPolicy::passwords(Redaction::Exclude);
+        let mut output = Vec::new();
+        export_redacted(&store, &all, ExportFormat::Json, &policy, &mut output).unwrap();
+        let dtos: Vec<EntryDto> = serde_json::from_slice(&output).unwrap();
+        assert_eq!(dtos[0].username.as_deref(), Some("user1"));
+        assert_eq!(dtos[0].password, None);
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,68 @@
+use std::{fmt, io};
+
+use crate::data::store_error::StoreError;
+
+#[derive(Debug)]
+pub enum ImportError {
+    IoError(io::Error),
+    #[cfg(feature = "csv")]
+    CsvError(csv::Error),
+    MissingColumn(String),
+    // A record that can't be read, the records around it still can
+    InvalidRecord {
+        line: u64,
+        reason: String,
+    },
+    // Saving an imported entry failed
+    StoreError(StoreError),
+    // The saved mapping profiles can't be read
+    JsonError(serde_json::Error),
+    // No mapping profile has this name
+    UnknownProfile(String),
+}
+
+impl From<io::Error> for ImportError {
+    fn from(error: io::Error) -> Self {
+        ImportError::IoError(error)
+    }
+}
+
+#[cfg(feature = "csv")]
+impl From<csv::Error> for ImportError {
+    fn from(error: csv::Error) -> Self {
+        ImportError::CsvError(error)
+    }
+}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(error: serde_json::Error) -> Self {
+        ImportError::JsonError(error)
+    }
+}
+
+impl From<StoreError> for ImportError {
+    fn from(error: StoreError) -> Self {
+        ImportError::StoreError(error)
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ImportError::IoError(ref err) => write!(f, "I/O error: {}", err),
+            #[cfg(feature = "csv")]
+            ImportError::CsvError(ref err) => write!(f, "CSV error: {}", err),
+            ImportError::MissingColumn(ref column) => {
+                write!(f, "Missing column: {}", column)
+            }
+            ImportError::InvalidRecord { line, ref reason } => {
+                write!(f, "Invalid record on line {}: {}", line, reason)
+            }
+            ImportError::StoreError(ref err) => write!(f, "Store error: {}", err),
+            ImportError::JsonError(ref err) => write!(f, "JSON error: {}", err),
+            ImportError::UnknownProfile(ref name) => {
+                write!(f, "No mapping profile named {}", name)
+            }
+        }
+    }
+}
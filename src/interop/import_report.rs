@@ -0,0 +1,37 @@
+use std::collections::HashMap;
+
+use super::otpauth::Totp;
+use crate::data::model::Entry;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedRecord {
+    // 1-based line of the record in the source file
+    pub line: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub entries: Vec<Entry>,
+    // folder path -> ids of the entries placed in it
+    pub folders: HashMap<String, Vec<String>>,
+    // entry id -> TOTP parameters, entries have no field for them yet
+    pub totp: HashMap<String, Totp>,
+    pub skipped: Vec<SkippedRecord>,
+}
+
+impl ImportReport {
+    pub fn add(&mut self, entry: Entry, folder: Option<String>) {
+        if let Some(folder) = folder {
+            self.folders
+                .entry(folder)
+                .or_default()
+                .push(entry.id.clone());
+        }
+        self.entries.push(entry);
+    }
+
+    pub fn skip(&mut self, line: u64, reason: String) {
+        self.skipped.push(SkippedRecord { line, reason });
+    }
+}
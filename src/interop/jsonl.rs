@@ -0,0 +1,167 @@
+use std::io::{BufRead, BufReader, Lines, Read, Write};
+
+use super::{
+    entry_dto::{from_json, EntryDto},
+    import_error::ImportError,
+    import_report::ImportReport,
+};
+use crate::data::model::Entry;
+
+// JSON Lines: one `EntryDto` per line, so a vault can be streamed through
+// unix tools (grep, jq, split) and appended to for incremental backups.
+// Unlike the other imports, ids are kept, so re-importing replaces entries.
+
+pub struct JsonLinesWriter<W: Write> {
+    writer: W,
+    written: usize,
+}
+
+impl<W: Write> JsonLinesWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, written: 0 }
+    }
+
+    pub fn write(&mut self, entry: &Entry) -> Result<(), serde_json::Error> {
+        serde_json::to_writer(&mut self.writer, &EntryDto::from(entry))?;
+        self.writer
+            .write_all(b"\n")
+            .map_err(serde_json::Error::io)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    pub fn written(&self) -> usize {
+        self.written
+    }
+
+    pub fn into_inner(mut self) -> Result<W, serde_json::Error> {
+        self.writer.flush().map_err(serde_json::Error::io)?;
+        Ok(self.writer)
+    }
+}
+
+// Reads entries one line at a time. Blank lines are ignored, a line that
+// isn't a valid entry yields `InvalidRecord` and reading can go on.
+pub struct JsonLinesReader<R: BufRead> {
+    lines: Lines<R>,
+    line: u64,
+}
+
+impl<R: BufRead> JsonLinesReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            line: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for JsonLinesReader<R> {
+    type Item = Result<Entry, ImportError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let text = match self.lines.next()? {
+                Ok(text) => text,
+                Err(e) => return Some(Err(ImportError::IoError(e))),
+            };
+            self.line += 1;
+            if text.trim().is_empty() {
+                continue;
+            }
+
+            return Some(from_json(&text).map_err(|e| ImportError::InvalidRecord {
+                line: self.line,
+                reason: e.to_string(),
+            }));
+        }
+    }
+}
+
+pub fn import<R: Read>(reader: R) -> Result<ImportReport, ImportError> {
+    let mut report = ImportReport::default();
+
+    for result in JsonLinesReader::new(BufReader::new(reader)) {
+        match result {
+            Ok(entry) => report.add(entry, None),
+            Err(ImportError::InvalidRecord { line, reason }) => report.skip(line, reason),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(report)
+}
+
+pub fn export<'a, W: Write>(
+    entries: impl IntoIterator<Item = &'a Entry>,
+    writer: W,
+) -> Result<usize, serde_json::Error> {
+    let mut jsonl_writer = JsonLinesWriter::new(writer);
+    for entry in entries {
+        jsonl_writer.write(entry)?;
+    }
+    let written = jsonl_writer.written();
+    jsonl_writer.into_inner()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::model::Label;
+
+    fn entry(id: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: format!("Entry {}", id),
+            username: Some(format!("user{}", id)),
+            password: Some("pass\nword".to_string()),
+            note: Some("first line\nsecond line".to_string()),
+            favorite: id == "1",
+            label: Some(Label::Green),
+            tags: vec!["work".to_string()],
+            updated_at: 42,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let entries = vec![entry("1"), entry("2")];
+        let mut output = Vec::new();
+
+        assert_eq!(export(&entries, &mut output).unwrap(), 2);
+
+        let text = String::from_utf8(output.clone()).unwrap();
+        assert_eq!(text.lines().count(), 2);
+        let report = import(output.as_slice()).unwrap();
+        assert_eq!(report.entries, entries);
+        assert!(report.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_import_skips_invalid_lines() {
+        let mut output = Vec::new();
+        export(&[entry("1")], &mut output).unwrap();
+        output.extend_from_slice(b"\n{not json}\n");
+        export(&[entry("2")], &mut output).unwrap();
+
+        let report = import(output.as_slice()).unwrap();
+
+        assert_eq!(report.entries, vec![entry("1"), entry("2")]);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].line, 3);
+    }
+
+    #[test]
+    fn test_reader_streams() {
+        let mut output = Vec::new();
+        export(&[entry("1"), entry("2")], &mut output).unwrap();
+
+        let mut reader = JsonLinesReader::new(output.as_slice());
+
+        assert_eq!(reader.next().unwrap().unwrap().id, "1");
+        assert_eq!(reader.next().unwrap().unwrap().id, "2");
+        assert!(reader.next().is_none());
+    }
+}
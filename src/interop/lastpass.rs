@@ -0,0 +1,203 @@
+use csv::{ReaderBuilder, StringRecord};
+use std::io::Read;
+use uuid::Uuid;
+
+use super::{import_error::ImportError, import_report::ImportReport};
+use crate::data::model::{Entry, EntryKind};
+
+// LastPass stores secure notes as sites with this placeholder url
+const SECURE_NOTE_URL: &str = "http://sn";
+
+struct Columns {
+    url: usize,
+    username: usize,
+    password: usize,
+    extra: usize,
+    name: usize,
+    grouping: usize,
+    fav: Option<usize>,
+}
+
+impl Columns {
+    fn from_headers(headers: &StringRecord) -> Result<Self, ImportError> {
+        let find = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header.trim() == name)
+                .ok_or_else(|| ImportError::MissingColumn(name.to_string()))
+        };
+
+        Ok(Columns {
+            url: find("url")?,
+            username: find("username")?,
+            password: find("password")?,
+            extra: find("extra")?,
+            name: find("name")?,
+            grouping: find("grouping")?,
+            fav: find("fav").ok(),
+        })
+    }
+}
+
+fn field(record: &StringRecord, column: usize) -> Option<String> {
+    record
+        .get(column)
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+}
+
+// LastPass nests groups with backslashes, e.g. "Work\Email"
+fn folder(record: &StringRecord, column: usize) -> Option<String> {
+    field(record, column).map(|grouping| {
+        grouping
+            .split('\\')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("/")
+    })
+}
+
+pub fn import<R: Read>(reader: R) -> Result<ImportReport, ImportError> {
+    // Notes may span several lines, so records go through a real CSV parser.
+    // Columns are looked up by header since older exports have no totp column.
+    let mut csv_reader = ReaderBuilder::new().flexible(true).from_reader(reader);
+    let columns = Columns::from_headers(csv_reader.headers()?)?;
+
+    let mut report = ImportReport::default();
+
+    for result in csv_reader.records() {
+        let record = match result {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map(|p| p.line()).unwrap_or(0);
+                report.skip(line, e.to_string());
+                continue;
+            }
+        };
+        let line = record.position().map(|p| p.line()).unwrap_or(0);
+
+        let url = field(&record, columns.url);
+        let title = field(&record, columns.name);
+        let is_secure_note = url.as_deref() == Some(SECURE_NOTE_URL);
+
+        let title = match (title, &url) {
+            (Some(title), _) => title,
+            (None, Some(url)) if !is_secure_note => url.clone(),
+            _ => {
+                report.skip(line, "record has neither a name nor a url".to_string());
+                continue;
+            }
+        };
+
+        let entry = Entry {
+            id: Uuid::new_v4().to_string(),
+            title,
+            kind: EntryKind::Login,
+            token: None,
+            username: field(&record, columns.username),
+            password: field(&record, columns.password),
+            url: if is_secure_note { None } else { url },
+            note: field(&record, columns.extra),
+            favorite: columns
+                .fav
+                .and_then(|column| record.get(column))
+                .is_some_and(|fav| fav.trim() == "1"),
+            label: None,
+            tags: Vec::new(),
+            archived: false,
+            sort_key: None,
+            updated_at: 0,
+            password_changed_at: 0,
+        };
+
+        report.add(entry, folder(&record, columns.grouping));
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "url,username,password,totp,extra,name,grouping,fav\n";
+
+    fn import_str(rows: &str) -> ImportReport {
+        import(format!("{}{}", HEADER, rows).as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_import_site() {
+        let report = import_str("https://example.com,user1,pass1,,,Example,,0\n");
+
+        assert_eq!(report.entries.len(), 1);
+        let entry = &report.entries[0];
+        assert_eq!(entry.title, "Example");
+        assert_eq!(entry.username, Some("user1".to_string()));
+        assert_eq!(entry.password, Some("pass1".to_string()));
+        assert_eq!(entry.url, Some("https://example.com".to_string()));
+        assert_eq!(entry.note, None);
+        assert!(report.folders.is_empty());
+        assert!(report.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_import_secure_note_with_newlines() {
+        let report = import_str("http://sn,,,,\"first line\nsecond line\",Wifi,,0\n");
+
+        assert_eq!(report.entries.len(), 1);
+        let entry = &report.entries[0];
+        assert_eq!(entry.title, "Wifi");
+        assert_eq!(entry.url, None);
+        assert_eq!(entry.note, Some("first line\nsecond line".to_string()));
+    }
+
+    #[test]
+    fn test_import_grouping_to_folders() {
+        let report = import_str(
+            "https://a.com,a,a,,,A,Work\\Email,0\n\
+             https://b.com,b,b,,,B,Work\\Email,0\n\
+             https://c.com,c,c,,,C,Personal,0\n",
+        );
+
+        assert_eq!(report.entries.len(), 3);
+        let work = &report.folders["Work/Email"];
+        assert_eq!(work.len(), 2);
+        assert_eq!(work[0], report.entries[0].id);
+        assert_eq!(
+            report.folders["Personal"],
+            vec![report.entries[2].id.clone()]
+        );
+    }
+
+    #[test]
+    fn test_import_favorite() {
+        let report = import_str("https://a.com,a,a,,,A,,1\nhttps://b.com,b,b,,,B,,0\n");
+
+        assert!(report.entries[0].favorite);
+        assert!(!report.entries[1].favorite);
+    }
+
+    #[test]
+    fn test_import_uses_url_when_name_missing() {
+        let report = import_str("https://example.com,user1,pass1,,,,,0\n");
+
+        assert_eq!(report.entries[0].title, "https://example.com");
+    }
+
+    #[test]
+    fn test_import_skips_empty_record() {
+        let report = import_str(",user1,pass1,,,,,0\nhttps://a.com,a,a,,,A,,0\n");
+
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].line, 2);
+    }
+
+    #[test]
+    fn test_import_missing_column() {
+        let result = import("url,username,password\n".as_bytes());
+
+        assert!(matches!(result, Err(ImportError::MissingColumn(ref c)) if c == "extra"));
+    }
+}
@@ -0,0 +1,17 @@
+#[cfg(feature = "csv")]
+pub mod apple_keychain;
+pub mod bundle;
+#[cfg(feature = "csv")]
+pub mod csv_mapping;
+pub mod entry_dto;
+pub mod export;
+pub mod import_error;
+pub mod import_report;
+pub mod jsonl;
+#[cfg(feature = "csv")]
+pub mod lastpass;
+pub mod otpauth;
+pub mod page_metadata;
+pub mod parallel_import;
+pub mod quick_add;
+pub mod timestamp;
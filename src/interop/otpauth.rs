@@ -0,0 +1,339 @@
+#[cfg(feature = "qr")]
+use qrcode::{
+    render::{svg, unicode},
+    QrCode,
+};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TotpAlgorithm {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl TotpAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TotpAlgorithm::Sha1 => "SHA1",
+            TotpAlgorithm::Sha256 => "SHA256",
+            TotpAlgorithm::Sha512 => "SHA512",
+        }
+    }
+}
+
+// The TOTP parameters carried by an `otpauth://totp/...` URI
+#[derive(Clone, PartialEq, Eq)]
+pub struct Totp {
+    // Base32 as found in the URI, upper-cased and without padding
+    pub secret: String,
+    pub issuer: Option<String>,
+    pub account: Option<String>,
+    pub algorithm: TotpAlgorithm,
+    pub digits: u32,
+    pub period: u64,
+}
+
+// The secret is left out like passwords are in `Entry`
+impl fmt::Debug for Totp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Totp")
+            .field("secret", &"***")
+            .field("issuer", &self.issuer)
+            .field("account", &self.account)
+            .field("algorithm", &self.algorithm)
+            .field("digits", &self.digits)
+            .field("period", &self.period)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OtpAuthError {
+    NotOtpAuth,
+    UnsupportedType(String),
+    MissingSecret,
+    InvalidParameter(String),
+    // The URI doesn't fit in a QR code
+    TooLongForQrCode,
+}
+
+impl fmt::Display for OtpAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            OtpAuthError::NotOtpAuth => write!(f, "Not an otpauth URI"),
+            OtpAuthError::UnsupportedType(ref kind) => {
+                write!(f, "Unsupported OTP type: {}", kind)
+            }
+            OtpAuthError::MissingSecret => write!(f, "Missing secret"),
+            OtpAuthError::InvalidParameter(ref name) => {
+                write!(f, "Invalid parameter: {}", name)
+            }
+            OtpAuthError::TooLongForQrCode => write!(f, "Too long for a QR code"),
+        }
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+impl Totp {
+    // The provisioning URI authenticator apps scan, the inverse of `parse`
+    pub fn to_uri(&self) -> String {
+        let account = self.account.as_deref().unwrap_or_default();
+        let label = match &self.issuer {
+            Some(issuer) => format!("{}:{}", percent_encode(issuer), percent_encode(account)),
+            None => percent_encode(account),
+        };
+
+        let mut uri = format!("otpauth://totp/{}?secret={}", label, self.secret);
+        if let Some(issuer) = &self.issuer {
+            uri.push_str(&format!("&issuer={}", percent_encode(issuer)));
+        }
+        uri.push_str(&format!(
+            "&algorithm={}&digits={}&period={}",
+            self.algorithm.name(),
+            self.digits,
+            self.period
+        ));
+        uri
+    }
+
+    #[cfg(feature = "qr")]
+    fn qr_code(&self) -> Result<QrCode, OtpAuthError> {
+        QrCode::new(self.to_uri()).map_err(|_| OtpAuthError::TooLongForQrCode)
+    }
+
+    // The provisioning URI as an SVG image, e.g. for a web view
+    #[cfg(feature = "qr")]
+    pub fn to_qr_svg(&self) -> Result<String, OtpAuthError> {
+        Ok(self
+            .qr_code()?
+            .render::<svg::Color>()
+            .min_dimensions(200, 200)
+            .build())
+    }
+
+    // The provisioning URI drawn with block characters, to scan off a terminal
+    #[cfg(feature = "qr")]
+    pub fn to_qr_terminal(&self) -> Result<String, OtpAuthError> {
+        Ok(self
+            .qr_code()?
+            .render::<unicode::Dense1x2>()
+            // Terminals are mostly light text on a dark background
+            .dark_color(unicode::Dense1x2::Light)
+            .light_color(unicode::Dense1x2::Dark)
+            .build())
+    }
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+// Parses `otpauth://totp/Issuer:account?secret=...&issuer=...`. Missing
+// algorithm, digits and period fall back to SHA1, 6 and 30 like most
+// authenticator apps do.
+pub fn parse(uri: &str) -> Result<Totp, OtpAuthError> {
+    let rest = uri
+        .trim()
+        .strip_prefix("otpauth://")
+        .ok_or(OtpAuthError::NotOtpAuth)?;
+    let (kind, rest) = rest.split_once('/').ok_or(OtpAuthError::NotOtpAuth)?;
+    if !kind.eq_ignore_ascii_case("totp") {
+        return Err(OtpAuthError::UnsupportedType(kind.to_string()));
+    }
+    let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+    let label = percent_decode(label);
+    let (label_issuer, account) = match label.split_once(':') {
+        Some((issuer, account)) => (non_empty(issuer), non_empty(account)),
+        None => (None, non_empty(&label)),
+    };
+
+    let mut totp = Totp {
+        secret: String::new(),
+        issuer: label_issuer,
+        account,
+        algorithm: TotpAlgorithm::default(),
+        digits: 6,
+        period: 30,
+    };
+
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(value);
+        let invalid = || OtpAuthError::InvalidParameter(name.to_string());
+        match name.to_ascii_lowercase().as_str() {
+            "secret" => {
+                totp.secret = value
+                    .trim_end_matches('=')
+                    .replace(' ', "")
+                    .to_ascii_uppercase()
+            }
+            // The query parameter wins over the label prefix
+            "issuer" => totp.issuer = non_empty(&value).or(totp.issuer),
+            "algorithm" => {
+                totp.algorithm = match value.to_ascii_uppercase().as_str() {
+                    "SHA1" => TotpAlgorithm::Sha1,
+                    "SHA256" => TotpAlgorithm::Sha256,
+                    "SHA512" => TotpAlgorithm::Sha512,
+                    _ => return Err(invalid()),
+                }
+            }
+            "digits" => {
+                totp.digits = value
+                    .parse()
+                    .ok()
+                    .filter(|digits| (6..=8).contains(digits))
+                    .ok_or_else(invalid)?
+            }
+            "period" => {
+                totp.period = value
+                    .parse()
+                    .ok()
+                    .filter(|period| *period > 0)
+                    .ok_or_else(invalid)?
+            }
+            _ => {}
+        }
+    }
+
+    let is_base32 = totp
+        .secret
+        .chars()
+        .all(|c| c.is_ascii_uppercase() || ('2'..='7').contains(&c));
+    if totp.secret.is_empty() {
+        return Err(OtpAuthError::MissingSecret);
+    }
+    if !is_base32 {
+        return Err(OtpAuthError::InvalidParameter("secret".to_string()));
+    }
+
+    Ok(totp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_full_uri() {
+        let totp = parse(
+            "otpauth://totp/Example%3Aalice%40example.com?secret=jbswy3dpehpk3pxp\
+             &issuer=Example&algorithm=SHA256&digits=8&period=60",
+        )
+        .unwrap();
+
+        assert_eq!(totp.secret, "JBSWY3DPEHPK3PXP");
+        assert_eq!(totp.issuer, Some("Example".to_string()));
+        assert_eq!(totp.account, Some("alice@example.com".to_string()));
+        assert_eq!(totp.algorithm, TotpAlgorithm::Sha256);
+        assert_eq!(totp.digits, 8);
+        assert_eq!(totp.period, 60);
+    }
+
+    #[test]
+    fn test_parse_defaults() {
+        let totp = parse("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP").unwrap();
+
+        assert_eq!(totp.issuer, None);
+        assert_eq!(totp.account, Some("alice".to_string()));
+        assert_eq!(totp.algorithm, TotpAlgorithm::Sha1);
+        assert_eq!(totp.digits, 6);
+        assert_eq!(totp.period, 30);
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid() {
+        assert_eq!(parse("https://example.com"), Err(OtpAuthError::NotOtpAuth));
+        assert_eq!(
+            parse("otpauth://hotp/a?secret=JBSWY3DP&counter=1"),
+            Err(OtpAuthError::UnsupportedType("hotp".to_string()))
+        );
+        assert_eq!(
+            parse("otpauth://totp/a?issuer=A"),
+            Err(OtpAuthError::MissingSecret)
+        );
+        assert_eq!(
+            parse("otpauth://totp/a?secret=JBSWY3DP&digits=12"),
+            Err(OtpAuthError::InvalidParameter("digits".to_string()))
+        );
+        assert_eq!(
+            parse("otpauth://totp/a?secret=not-base32"),
+            Err(OtpAuthError::InvalidParameter("secret".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_to_uri_round_trips() {
+        let totp = parse(
+            "otpauth://totp/Ex%20ample:alice%40example.com?secret=JBSWY3DPEHPK3PXP\
+             &issuer=Ex%20ample&algorithm=SHA512&digits=8&period=60",
+        )
+        .unwrap();
+
+        let uri = totp.to_uri();
+
+        assert_eq!(
+            uri,
+            "otpauth://totp/Ex%20ample:alice%40example.com?secret=JBSWY3DPEHPK3PXP\
+             &issuer=Ex%20ample&algorithm=SHA512&digits=8&period=60"
+        );
+        assert_eq!(parse(&uri), Ok(totp));
+    }
+
+    #[cfg(feature = "qr")]
+    #[test]
+    fn test_qr_code() {
+        let totp = parse("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP").unwrap();
+
+        assert!(totp.to_qr_svg().unwrap().starts_with("<?xml"));
+        assert!(totp.to_qr_terminal().unwrap().contains('▀'));
+    }
+
+    #[test]
+    fn test_debug_hides_secret() {
+        let totp = parse("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP").unwrap();
+
+        assert!(!format!("{:?}", totp).contains("JBSWY3DPEHPK3PXP"));
+    }
+}
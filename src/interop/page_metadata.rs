@@ -0,0 +1,316 @@
+use std::fmt;
+use uuid::Uuid;
+
+use crate::data::model::{Entry, EntryKind};
+
+// Only the head of a page is needed, and a hostile server shouldn't be able
+// to make us read forever
+#[cfg(feature = "scrape")]
+const MAX_PAGE_BYTES: u64 = 512 * 1024;
+#[cfg(feature = "scrape")]
+const FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+// What a login page says about itself, used to pre-fill a new entry
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PageMetadata {
+    pub title: Option<String>,
+    // Absolute url of the favicon, `/favicon.ico` when the page names none
+    pub icon_url: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ScrapeError {
+    InvalidUrl(String),
+    #[cfg(feature = "scrape")]
+    HttpError(ureq::Error),
+    #[cfg(feature = "scrape")]
+    IoError(std::io::Error),
+}
+
+#[cfg(feature = "scrape")]
+impl From<ureq::Error> for ScrapeError {
+    fn from(error: ureq::Error) -> Self {
+        ScrapeError::HttpError(error)
+    }
+}
+
+#[cfg(feature = "scrape")]
+impl From<std::io::Error> for ScrapeError {
+    fn from(error: std::io::Error) -> Self {
+        ScrapeError::IoError(error)
+    }
+}
+
+impl fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ScrapeError::InvalidUrl(ref url) => write!(f, "Invalid url: {}", url),
+            #[cfg(feature = "scrape")]
+            ScrapeError::HttpError(ref err) => write!(f, "HTTP error: {}", err),
+            #[cfg(feature = "scrape")]
+            ScrapeError::IoError(ref err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+// (scheme, host) of an http(s) url
+fn split_origin(url: &str) -> Option<(&str, &str)> {
+    let (scheme, rest) = url.split_once("://")?;
+    if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+        return None;
+    }
+    let host = &rest[..rest.find(['/', '?', '#']).unwrap_or(rest.len())];
+    if host.is_empty() {
+        return None;
+    }
+    Some((scheme, host))
+}
+
+fn resolve(base: &str, href: &str) -> Option<String> {
+    if href.contains("://") || href.starts_with("data:") {
+        return Some(href.to_string());
+    }
+    let (scheme, host) = split_origin(base)?;
+    if let Some(rest) = href.strip_prefix("//") {
+        return Some(format!("{}://{}", scheme, rest));
+    }
+    if href.starts_with('/') {
+        return Some(format!("{}://{}{}", scheme, host, href));
+    }
+
+    // Relative to the directory of the page
+    let origin_len = scheme.len() + 3 + host.len();
+    let path = &base[origin_len..];
+    let path = &path[..path.find(['?', '#']).unwrap_or(path.len())];
+    let dir = &path[..path.rfind('/').map_or(0, |slash| slash + 1)];
+    let dir = if dir.is_empty() { "/" } else { dir };
+    Some(format!("{}://{}{}{}", scheme, host, dir, href))
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+// The value of `name` in the attributes of a tag, e.g. `rel="icon" href=/x`
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let mut from = 0;
+
+    while let Some(found) = lower[from..].find(name) {
+        let start = from + found;
+        from = start + name.len();
+        // Has to be a whole attribute name followed by `=`
+        let preceded = lower[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_ascii_whitespace());
+        let rest = lower[from..].trim_start();
+        if !preceded || !rest.starts_with('=') {
+            continue;
+        }
+
+        let value_start = tag.len() - rest.len() + 1;
+        let value = tag[value_start..].trim_start();
+        let value = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or_default(),
+            _ => value
+                .split(|c: char| c.is_ascii_whitespace() || c == '>' || c == '/')
+                .next()
+                .unwrap_or_default(),
+        };
+        return Some(decode_entities(value));
+    }
+    None
+}
+
+impl PageMetadata {
+    // Picks the title and favicon out of `html`, with relative links
+    // resolved against `url`. Good enough for the head of real-world pages,
+    // not a general HTML parser.
+    pub fn parse(url: &str, html: &str) -> Self {
+        let lower = html.to_ascii_lowercase();
+
+        let title = lower.find("<title").and_then(|open| {
+            let start = open + lower[open..].find('>')? + 1;
+            let end = start + lower[start..].find("</title")?;
+            let title = decode_entities(&html[start..end])
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            Some(title).filter(|title| !title.is_empty())
+        });
+
+        let mut icon = None;
+        let mut touch_icon = None;
+        let mut from = 0;
+        while let Some(found) = lower[from..].find("<link") {
+            let start = from + found;
+            let end = start + lower[start..].find('>').unwrap_or(lower.len() - start);
+            from = end;
+            let tag = &html[start..end];
+
+            let (Some(rel), Some(href)) = (attribute(tag, "rel"), attribute(tag, "href")) else {
+                continue;
+            };
+            let rel = rel.to_ascii_lowercase();
+            if rel.split_whitespace().any(|token| token == "icon") {
+                icon = icon.or(Some(href));
+            } else if rel
+                .split_whitespace()
+                .any(|token| token == "apple-touch-icon")
+            {
+                touch_icon = touch_icon.or(Some(href));
+            }
+        }
+
+        let icon_url = icon
+            .or(touch_icon)
+            .and_then(|href| resolve(url, &href))
+            .or_else(|| resolve(url, "/favicon.ico"));
+
+        PageMetadata { title, icon_url }
+    }
+}
+
+// Downloads `url` and reads its metadata. Redirects are followed, and
+// relative links resolved against where they led.
+#[cfg(feature = "scrape")]
+pub fn fetch_metadata(url: &str) -> Result<PageMetadata, ScrapeError> {
+    use std::io::Read;
+    use ureq::ResponseExt;
+
+    if split_origin(url).is_none() {
+        return Err(ScrapeError::InvalidUrl(url.to_string()));
+    }
+
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(FETCH_TIMEOUT))
+        .build()
+        .into();
+    let response = agent.get(url).call()?;
+    let final_url = response.get_uri().to_string();
+
+    let mut page = Vec::new();
+    response
+        .into_body()
+        .into_reader()
+        .take(MAX_PAGE_BYTES)
+        .read_to_end(&mut page)?;
+
+    Ok(PageMetadata::parse(
+        &final_url,
+        &String::from_utf8_lossy(&page),
+    ))
+}
+
+impl Entry {
+    // A new entry for `url`, titled after the page or else its host
+    pub fn from_page(url: &str, metadata: &PageMetadata) -> Self {
+        let title = metadata
+            .title
+            .clone()
+            .or_else(|| split_origin(url).map(|(_, host)| host.to_string()))
+            .unwrap_or_else(|| url.to_string());
+
+        Entry {
+            id: Uuid::new_v4().to_string(),
+            title,
+            kind: EntryKind::Login,
+            token: None,
+            username: None,
+            password: None,
+            url: Some(url.to_string()),
+            note: None,
+            favorite: false,
+            label: None,
+            tags: Vec::new(),
+            archived: false,
+            sort_key: None,
+            updated_at: 0,
+            password_changed_at: 0,
+        }
+    }
+
+    // Entries have no icon yet, callers that want one can use
+    // `fetch_metadata` and `from_page` themselves
+    #[cfg(feature = "scrape")]
+    pub fn from_url(url: &str) -> Result<Self, ScrapeError> {
+        Ok(Entry::from_page(url, &fetch_metadata(url)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://login.example.com/account/signin?next=/";
+
+    #[test]
+    fn test_parse_title_and_icon() {
+        let html = r#"<html><head>
+            <TITLE>
+              Sign in &amp; manage   your account
+            </TITLE>
+            <link rel="apple-touch-icon" href="/touch.png">
+            <link href='icons/fav.png' rel='shortcut icon' />
+            </head></html>"#;
+
+        let metadata = PageMetadata::parse(URL, html);
+
+        assert_eq!(
+            metadata.title,
+            Some("Sign in & manage your account".to_string())
+        );
+        assert_eq!(
+            metadata.icon_url,
+            Some("https://login.example.com/account/icons/fav.png".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_falls_back_to_favicon_ico() {
+        let metadata = PageMetadata::parse(URL, "<title></title><link rel=stylesheet href=a.css>");
+
+        assert_eq!(metadata.title, None);
+        assert_eq!(
+            metadata.icon_url,
+            Some("https://login.example.com/favicon.ico".to_string())
+        );
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(
+            resolve(URL, "//cdn.example.com/i.png").unwrap(),
+            "https://cdn.example.com/i.png"
+        );
+        assert_eq!(
+            resolve("https://example.com", "i.png").unwrap(),
+            "https://example.com/i.png"
+        );
+        assert_eq!(
+            resolve(URL, "http://other.test/i.png").unwrap(),
+            "http://other.test/i.png"
+        );
+        assert_eq!(resolve("ftp://example.com/", "i.png"), None);
+    }
+
+    #[test]
+    fn test_entry_from_page() {
+        let entry = Entry::from_page(URL, &PageMetadata::default());
+        assert_eq!(entry.title, "login.example.com");
+        assert_eq!(entry.url, Some(URL.to_string()));
+
+        let metadata = PageMetadata {
+            title: Some("Example".to_string()),
+            icon_url: None,
+        };
+        assert_eq!(Entry::from_page(URL, &metadata).title, "Example");
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Read},
+    num::NonZeroUsize,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+use super::{entry_dto::from_json, import_error::ImportError, import_report::SkippedRecord};
+use crate::data::{data_store::DataStore, model::Entry, store_error::StoreError};
+
+// Imports JSON Lines files too large to parse on one thread in good time.
+// The calling thread reads batches of lines and hands them to workers that
+// parse and validate them, then saves what comes back in input order, so
+// when an id appears twice the later line wins as with `jsonl::import`. At
+// most two batches per worker are in flight, which bounds memory however
+// large the file is.
+
+// Checks an entry beyond it being valid JSON, returning why it is rejected
+pub type Validator = Arc<dyn Fn(&Entry) -> Result<(), String> + Send + Sync>;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub saved: usize,
+    pub skipped: Vec<SkippedRecord>,
+}
+
+// The lines of a batch with the line number of the first
+struct Batch {
+    sequence: usize,
+    first_line: u64,
+    lines: Vec<String>,
+}
+
+type Parsed = Vec<Result<Entry, SkippedRecord>>;
+
+pub struct ParallelImport {
+    threads: usize,
+    batch_size: usize,
+    validator: Option<Validator>,
+}
+
+impl Default for ParallelImport {
+    fn default() -> Self {
+        ParallelImport {
+            threads: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            batch_size: 1000,
+            validator: None,
+        }
+    }
+}
+
+impl ParallelImport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    // Lines per batch handed to a worker
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn with_validator<F>(mut self, validator: F) -> Self
+    where
+        F: Fn(&Entry) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.validator = Some(Arc::new(validator));
+        self
+    }
+
+    // Saves every valid entry of `reader` to `store`. Invalid lines are
+    // skipped and listed, an I/O or store error stops the import with the
+    // entries before it saved. With a group committing store the saves are
+    // committed in batches, see `Durability`.
+    pub fn import_into<R, S>(&self, reader: R, store: &mut S) -> Result<ImportSummary, ImportError>
+    where
+        R: Read,
+        S: DataStore<String, Entry, StoreError> + ?Sized,
+    {
+        let (batch_sender, batch_receiver) = mpsc::channel::<Batch>();
+        let batch_receiver = Arc::new(Mutex::new(batch_receiver));
+        let (parsed_sender, parsed_receiver) = mpsc::channel::<(usize, Parsed)>();
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads {
+                let batch_receiver = Arc::clone(&batch_receiver);
+                let parsed_sender = parsed_sender.clone();
+                scope.spawn(move || loop {
+                    // The lock is only held to take a batch, not to parse it
+                    let batch = match batch_receiver.lock().map(|receiver| receiver.recv()) {
+                        Ok(Ok(batch)) => batch,
+                        _ => return,
+                    };
+                    let sequence = batch.sequence;
+                    if parsed_sender.send((sequence, self.parse(batch))).is_err() {
+                        return;
+                    }
+                });
+            }
+            drop(parsed_sender);
+
+            let mut committer = Committer::new(store);
+            let result = self.feed(reader, &batch_sender, &parsed_receiver, &mut committer);
+            // Lets the workers finish once the queue is empty
+            drop(batch_sender);
+            result.map(|()| committer.summary)
+        })
+    }
+
+    // Reads batches and sends them off, committing finished ones whenever
+    // the limit of batches in flight is reached and at the end
+    fn feed<R: Read, S>(
+        &self,
+        reader: R,
+        batches: &mpsc::Sender<Batch>,
+        parsed: &mpsc::Receiver<(usize, Parsed)>,
+        committer: &mut Committer<'_, S>,
+    ) -> Result<(), ImportError>
+    where
+        S: DataStore<String, Entry, StoreError> + ?Sized,
+    {
+        let max_in_flight = 2 * self.threads;
+        let mut lines = BufReader::new(reader).lines();
+        let mut sent = 0;
+        let mut line = 1;
+
+        loop {
+            let batch: Vec<String> = lines
+                .by_ref()
+                .take(self.batch_size)
+                .collect::<Result<_, _>>()?;
+            if batch.is_empty() {
+                break;
+            }
+
+            let first_line = line;
+            line += batch.len() as u64;
+            batches
+                .send(Batch {
+                    sequence: sent,
+                    first_line,
+                    lines: batch,
+                })
+                .expect("workers run until the batch sender is dropped");
+            sent += 1;
+
+            while sent - committer.next >= max_in_flight {
+                committer.receive(parsed)?;
+            }
+        }
+
+        while committer.next < sent {
+            committer.receive(parsed)?;
+        }
+        Ok(())
+    }
+
+    fn parse(&self, batch: Batch) -> Parsed {
+        let mut parsed = Vec::with_capacity(batch.lines.len());
+        for (line, text) in (batch.first_line..).zip(&batch.lines) {
+            if text.trim().is_empty() {
+                continue;
+            }
+            let skip = |reason: String| SkippedRecord { line, reason };
+
+            let entry = match from_json(text) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    parsed.push(Err(skip(e.to_string())));
+                    continue;
+                }
+            };
+            let checked = if entry.id.is_empty() {
+                Err("Entry has no id".to_string())
+            } else {
+                self.validator
+                    .as_ref()
+                    .map_or(Ok(()), |validator| validator(&entry))
+            };
+            parsed.push(checked.map(|()| entry).map_err(skip));
+        }
+        parsed
+    }
+}
+
+// Saves parsed batches in the order they were read, holding on to those
+// that arrive ahead of their turn
+struct Committer<'a, S: ?Sized> {
+    store: &'a mut S,
+    // Sequence number of the next batch to save
+    next: usize,
+    waiting: BTreeMap<usize, Parsed>,
+    summary: ImportSummary,
+}
+
+impl<'a, S: DataStore<String, Entry, StoreError> + ?Sized> Committer<'a, S> {
+    fn new(store: &'a mut S) -> Self {
+        Committer {
+            store,
+            next: 0,
+            waiting: BTreeMap::new(),
+            summary: ImportSummary::default(),
+        }
+    }
+
+    fn receive(&mut self, parsed: &mpsc::Receiver<(usize, Parsed)>) -> Result<(), ImportError> {
+        let (sequence, batch) = parsed
+            .recv()
+            .expect("a worker answers every batch it takes");
+        self.waiting.insert(sequence, batch);
+
+        while let Some(batch) = self.waiting.remove(&self.next) {
+            for record in batch {
+                match record {
+                    Ok(entry) => {
+                        self.store.save(&entry.id, &entry)?;
+                        self.summary.saved += 1;
+                    }
+                    Err(skipped) => self.summary.skipped.push(skipped),
+                }
+            }
+            self.next += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{binary_file_entry_store::BinaryFileEntryStore, data_store::filter_fn};
+    use crate::interop::jsonl;
+    use std::fs;
+    use uuid::Uuid;
+
+    fn entry(id: usize, title: &str) -> Entry {
+        Entry {
+            id: id.to_string(),
+            title: title.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_saves_in_input_order() {
+        let mut entries: Vec<Entry> = (0..500).map(|i| entry(i, "First")).collect();
+        // Later lines replace earlier ones with the same id
+        entries.extend((0..50).map(|i| entry(i * 10, "Second")));
+        let mut input = Vec::new();
+        jsonl::export(&entries, &mut input).unwrap();
+        input.extend_from_slice(b"\nnot json\n{\"schema\":1,\"id\":\"\",\"title\":\"No id\"}\n");
+        jsonl::export([&entry(1000, "Skip me")], &mut input).unwrap();
+
+        let file_path = format!("test_parallel_import_{}.bin", Uuid::new_v4());
+        let mut store = BinaryFileEntryStore::new(file_path.clone());
+        let summary = ParallelImport::new()
+            .with_threads(4)
+            .with_batch_size(7)
+            .with_validator(|entry| match entry.title.as_str() {
+                "Skip me" => Err("Skipped".to_string()),
+                _ => Ok(()),
+            })
+            .import_into(input.as_slice(), &mut store)
+            .unwrap();
+
+        assert_eq!(summary.saved, 550);
+        let lines: Vec<u64> = summary.skipped.iter().map(|s| s.line).collect();
+        assert_eq!(lines, vec![552, 553, 554]);
+        let stored = store.search(&filter_fn(|_: &Entry| true)).unwrap();
+        assert_eq!(stored.len(), 500);
+        for entry in stored {
+            let expected = match entry.id.parse::<usize>().unwrap() % 10 {
+                0 => "Second",
+                _ => "First",
+            };
+            assert_eq!(entry.title, expected, "{}", entry.id);
+        }
+
+        drop(store);
+        fs::remove_file(file_path).unwrap();
+    }
+}
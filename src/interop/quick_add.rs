@@ -0,0 +1,253 @@
+use std::{
+    fmt,
+    io::{BufRead, BufReader, Read},
+};
+use uuid::Uuid;
+
+use super::{import_error::ImportError, import_report::ImportReport};
+use crate::data::model::{Entry, EntryKind};
+use crate::secret::password_generator::{generate_password, DEFAULT_PASSWORD_LENGTH};
+
+// Turns one line like `GitHub alice@example.com https://github.com hunter2`
+// into an entry. Words are split on whitespace, double quotes keep spaces in
+// one word (`"My Bank"`). A word with `://` or starting with `www.` is the
+// url, one shaped like an email address is the username. The words before
+// those are the title and a single word after them is the password, which
+// is generated when left out. Without a url or username every word is part
+// of the title, since a password couldn't be told apart from it.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuickAddError {
+    Empty,
+    UnterminatedQuote,
+    MissingTitle,
+    // Only a single word may follow the url and username. The words aren't
+    // kept, one of them may be the password.
+    TooManyWords(usize),
+}
+
+impl fmt::Display for QuickAddError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            QuickAddError::Empty => write!(f, "Nothing to add"),
+            QuickAddError::UnterminatedQuote => write!(f, "Unterminated quote"),
+            QuickAddError::MissingTitle => write!(f, "Missing title"),
+            QuickAddError::TooManyWords(count) => write!(
+                f,
+                "Expected one password after the url and username, found {} words",
+                count
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QuickAddError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuickAdd {
+    pub entry: Entry,
+    // The password wasn't given, so the caller may want to show it
+    pub generated_password: bool,
+}
+
+fn split_words(line: &str) -> Result<Vec<String>, QuickAddError> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quoted = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quoted {
+        return Err(QuickAddError::UnterminatedQuote);
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+fn is_url(word: &str) -> bool {
+    word.contains("://") || word.to_ascii_lowercase().starts_with("www.")
+}
+
+fn is_email(word: &str) -> bool {
+    match word.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && !domain.contains('/')
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+        }
+        None => false,
+    }
+}
+
+// The host of a url, for a title when none is given
+fn host_of(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.split(['/', '?', '#', ':']).next().unwrap_or(rest)
+}
+
+pub fn parse(line: &str) -> Result<QuickAdd, QuickAddError> {
+    let words = split_words(line)?;
+    if words.is_empty() {
+        return Err(QuickAddError::Empty);
+    }
+
+    let mut title = Vec::new();
+    let mut rest = Vec::new();
+    let mut url = None;
+    let mut username = None;
+    for word in words {
+        if url.is_none() && is_url(&word) {
+            url = Some(word);
+        } else if username.is_none() && is_email(&word) {
+            username = Some(word);
+        } else if url.is_none() && username.is_none() {
+            title.push(word);
+        } else {
+            rest.push(word);
+        }
+    }
+
+    let url = url.map(|url| {
+        if url.contains("://") {
+            url
+        } else {
+            format!("https://{}", url)
+        }
+    });
+    let title = if title.is_empty() {
+        url.as_deref()
+            .map(host_of)
+            .filter(|host| !host.is_empty())
+            .ok_or(QuickAddError::MissingTitle)?
+            .to_string()
+    } else {
+        title.join(" ")
+    };
+    if rest.len() > 1 {
+        return Err(QuickAddError::TooManyWords(rest.len()));
+    }
+    let generated_password = rest.is_empty();
+    let password = rest
+        .pop()
+        .unwrap_or_else(|| generate_password(DEFAULT_PASSWORD_LENGTH));
+
+    Ok(QuickAdd {
+        entry: Entry {
+            id: Uuid::new_v4().to_string(),
+            title,
+            kind: EntryKind::Login,
+            token: None,
+            username,
+            password: Some(password),
+            url,
+            note: None,
+            favorite: false,
+            label: None,
+            tags: Vec::new(),
+            archived: false,
+            sort_key: None,
+            updated_at: 0,
+            password_changed_at: 0,
+        },
+        generated_password,
+    })
+}
+
+// One entry per line, e.g. piped to stdin. Blank lines are ignored, lines
+// that can't be parsed are reported as skipped.
+pub fn import<R: Read>(reader: R) -> Result<ImportReport, ImportError> {
+    let mut report = ImportReport::default();
+
+    for (number, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse(&line) {
+            Ok(added) => report.add(added.entry, None),
+            Err(e) => report.skip(number as u64 + 1, e.to_string()),
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_full_line() {
+        let added = parse("\"My Bank\" alice@example.com www.bank.test/login hunter2").unwrap();
+
+        assert!(!added.generated_password);
+        assert_eq!(added.entry.title, "My Bank");
+        assert_eq!(added.entry.username.as_deref(), Some("alice@example.com"));
+        assert_eq!(
+            added.entry.url.as_deref(),
+            Some("https://www.bank.test/login")
+        );
+        assert_eq!(added.entry.password.as_deref(), Some("hunter2"));
+    }
+
+    #[test]
+    fn test_parse_generates_password_and_title() {
+        let added = parse("https://mail.example.com/inbox").unwrap();
+
+        assert!(added.generated_password);
+        assert_eq!(added.entry.title, "mail.example.com");
+        assert_eq!(
+            added.entry.password.map(|password| password.len()),
+            Some(DEFAULT_PASSWORD_LENGTH)
+        );
+
+        // No url or username, so every word is title
+        let added = parse("Home wifi router").unwrap();
+        assert_eq!(added.entry.title, "Home wifi router");
+        assert!(added.generated_password);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("  "), Err(QuickAddError::Empty));
+        assert_eq!(parse("\"Bank"), Err(QuickAddError::UnterminatedQuote));
+        assert_eq!(parse("bob@example.com"), Err(QuickAddError::MissingTitle));
+        assert_eq!(
+            parse("Bank bob@example.com two words"),
+            Err(QuickAddError::TooManyWords(2))
+        );
+    }
+
+    #[test]
+    fn test_import_lines() {
+        let input = "GitHub alice@example.com s3cret\n\n\"Bad\n";
+
+        let report = import(input.as_bytes()).unwrap();
+
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].title, "GitHub");
+        assert_eq!(report.skipped[0].line, 3);
+    }
+}
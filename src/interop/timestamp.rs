@@ -0,0 +1,277 @@
+use std::fmt;
+
+// Timestamps leave the crate as RFC 3339 in UTC with milliseconds, e.g.
+// `2024-03-01T12:30:00.000Z`, so they survive formats and tools that would
+// take a bare number for seconds or a local time. Inside the crate they are
+// unix milliseconds like `Entry::updated_at`, where 0 means unknown.
+
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimestampError {
+    Invalid(String),
+    // Unix milliseconds can't go before 1970
+    BeforeEpoch(String),
+}
+
+impl fmt::Display for TimestampError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TimestampError::Invalid(ref text) => write!(f, "Invalid RFC 3339 timestamp: {}", text),
+            TimestampError::BeforeEpoch(ref text) => {
+                write!(f, "Timestamp is before 1970: {}", text)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TimestampError {}
+
+// Days since 1970-01-01 of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_from_march = i64::from((month + 9) % 12);
+    let day_of_year = (153 * month_from_march + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    } as u32;
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+pub fn to_rfc3339(millis: u64) -> String {
+    to_rfc3339_with_offset(millis, 0)
+}
+
+// The same instant as wall-clock time `offset_minutes` east of UTC, e.g.
+// for showing it in the user's time zone. The offset is the caller's, the
+// crate has no time zone database.
+pub fn to_rfc3339_with_offset(millis: u64, offset_minutes: i32) -> String {
+    let local = millis as i64 + i64::from(offset_minutes) * 60 * 1000;
+    let (year, month, day) = civil_from_days(local.div_euclid(MILLIS_PER_DAY));
+    let of_day = local.rem_euclid(MILLIS_PER_DAY);
+
+    let time = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}",
+        year,
+        month,
+        day,
+        of_day / 3_600_000,
+        of_day / 60_000 % 60,
+        of_day / 1000 % 60,
+        of_day % 1000
+    );
+    match offset_minutes {
+        0 => format!("{}Z", time),
+        offset => format!(
+            "{}{}{:02}:{:02}",
+            time,
+            if offset < 0 { '-' } else { '+' },
+            offset.unsigned_abs() / 60,
+            offset.unsigned_abs() % 60
+        ),
+    }
+}
+
+fn number(text: &str, range: std::ops::Range<usize>) -> Option<u32> {
+    let digits = text.get(range)?;
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+// Any RFC 3339 timestamp, with a `Z` or numeric offset and as many
+// fraction digits as given (beyond milliseconds they are dropped)
+pub fn from_rfc3339(text: &str) -> Result<u64, TimestampError> {
+    let invalid = || TimestampError::Invalid(text.to_string());
+    let bytes = text.as_bytes();
+    if bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !matches!(bytes[10], b'T' | b't' | b' ')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return Err(invalid());
+    }
+
+    let year = i64::from(number(text, 0..4).ok_or_else(invalid)?);
+    let month = number(text, 5..7).ok_or_else(invalid)?;
+    let day = number(text, 8..10).ok_or_else(invalid)?;
+    let hour = number(text, 11..13).ok_or_else(invalid)?;
+    let minute = number(text, 14..16).ok_or_else(invalid)?;
+    // A leap second is held at the last millisecond before it
+    let (second, leap) = match number(text, 17..19).ok_or_else(invalid)? {
+        60 => (59, 999),
+        second => (second, 0),
+    };
+    if !(1..=12).contains(&month)
+        || day == 0
+        || day > days_in_month(year, month)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return Err(invalid());
+    }
+
+    let mut rest = &text[19..];
+    let mut millis = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            return Err(invalid());
+        }
+        millis = format!("{:0<3}", &fraction[..digits.min(3)])
+            .parse::<i64>()
+            .map_err(|_| invalid())?;
+        rest = &fraction[digits..];
+    }
+
+    let offset_minutes = match rest.as_bytes().first() {
+        Some(b'Z' | b'z') if rest.len() == 1 => 0,
+        Some(sign @ (b'+' | b'-')) if rest.len() == 6 && rest.as_bytes()[3] == b':' => {
+            let hours = number(rest, 1..3).ok_or_else(invalid)?;
+            let minutes = number(rest, 4..6).ok_or_else(invalid)?;
+            if hours > 23 || minutes > 59 {
+                return Err(invalid());
+            }
+            let offset = i64::from(hours * 60 + minutes);
+            if *sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => return Err(invalid()),
+    };
+
+    let local = days_from_civil(year, month, day) * MILLIS_PER_DAY
+        + i64::from(hour * 3600 + minute * 60 + second) * 1000
+        + millis.max(leap);
+    let utc = local - offset_minutes * 60 * 1000;
+    u64::try_from(utc).map_err(|_| TimestampError::BeforeEpoch(text.to_string()))
+}
+
+// For `#[serde(with = ...)]` on unix millisecond fields. Writes RFC 3339,
+// or null for 0, and reads either that or the plain numbers written before.
+pub mod rfc3339_millis {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use super::{from_rfc3339, to_rfc3339};
+
+    pub fn serialize<S: Serializer>(millis: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        match *millis {
+            0 => serializer.serialize_none(),
+            millis => serializer.serialize_some(&to_rfc3339(millis)),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Timestamp {
+            Millis(u64),
+            Text(String),
+        }
+
+        match Option::<Timestamp>::deserialize(deserializer)? {
+            None => Ok(0),
+            Some(Timestamp::Millis(millis)) => Ok(millis),
+            Some(Timestamp::Text(text)) => from_rfc3339(&text).map_err(D::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-02-29T23:59:58.123Z
+    const LEAP_DAY: u64 = 1_709_251_198_123;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(to_rfc3339(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(to_rfc3339(LEAP_DAY), "2024-02-29T23:59:58.123Z");
+        assert_eq!(
+            to_rfc3339_with_offset(LEAP_DAY, 90),
+            "2024-03-01T01:29:58.123+01:30"
+        );
+        assert_eq!(
+            to_rfc3339_with_offset(LEAP_DAY, -300),
+            "2024-02-29T18:59:58.123-05:00"
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(from_rfc3339("2024-02-29T23:59:58.123Z"), Ok(LEAP_DAY));
+        assert_eq!(
+            from_rfc3339("2024-03-01t01:29:58.1234567+01:30"),
+            Ok(LEAP_DAY)
+        );
+        assert_eq!(from_rfc3339("1970-01-01 00:00:00z"), Ok(0));
+        assert_eq!(
+            from_rfc3339("2016-12-31T23:59:60Z"),
+            from_rfc3339("2016-12-31T23:59:59.999Z")
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects() {
+        for text in [
+            "2024-02-29",
+            "2023-02-29T00:00:00Z",
+            "2024-13-01T00:00:00Z",
+            "2024-01-01T24:00:00Z",
+            "2024-01-01T00:00:00",
+            "2024-01-01T00:00:00.Z",
+            "2024-01-01T00:00:00+0100",
+        ] {
+            assert!(
+                matches!(from_rfc3339(text), Err(TimestampError::Invalid(_))),
+                "{}",
+                text
+            );
+        }
+        assert!(matches!(
+            from_rfc3339("1969-12-31T23:59:59Z"),
+            Err(TimestampError::BeforeEpoch(_))
+        ));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for millis in [1, 951_782_400_000, LEAP_DAY, 4_102_444_800_000] {
+            assert_eq!(from_rfc3339(&to_rfc3339(millis)), Ok(millis));
+        }
+    }
+}
@@ -0,0 +1,5 @@
+pub mod audit;
+pub mod data;
+pub mod interop;
+pub mod output;
+pub mod secret;
@@ -1,20 +1,30 @@
-mod data;
-mod secret;
-
-use data::{binary_file_entry_store::BinaryFileEntryStore, data_store::DataStore, model::Entry};
+use tuggerah::data::{
+    binary_file_entry_store::BinaryFileEntryStore,
+    data_store::DataStore,
+    model::{Entry, EntryKind},
+};
 fn main() {
     let e = Entry {
         id: "1".to_string(),
         title: "title".to_string(),
+        kind: EntryKind::Login,
+        token: None,
         username: Some("username".to_string()),
         password: None,
         url: None,
         note: None,
+        favorite: false,
+        label: None,
+        tags: Vec::new(),
+        archived: false,
+        sort_key: None,
+        updated_at: 0,
+        password_changed_at: 0,
     };
 
     let file = "db.txt".to_string();
 
-    let store = BinaryFileEntryStore::new(file);
+    let store: BinaryFileEntryStore = BinaryFileEntryStore::new(file);
 
     //let _ = store.save(&e.id, &e);
 }
@@ -0,0 +1,293 @@
+use serde_json::{Map, Value};
+use std::{fmt, str::FromStr};
+
+use crate::data::model::Entry;
+
+const MASK: &str = "********";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryField {
+    Id,
+    Title,
+    Username,
+    Password,
+    Url,
+    Note,
+}
+
+impl EntryField {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EntryField::Id => "id",
+            EntryField::Title => "title",
+            EntryField::Username => "username",
+            EntryField::Password => "password",
+            EntryField::Url => "url",
+            EntryField::Note => "note",
+        }
+    }
+
+    // Protected fields are masked unless the caller explicitly reveals them
+    pub fn is_protected(&self) -> bool {
+        matches!(self, EntryField::Password)
+    }
+
+    fn value<'a>(&self, entry: &'a Entry) -> Option<&'a str> {
+        match self {
+            EntryField::Id => Some(&entry.id),
+            EntryField::Title => Some(&entry.title),
+            EntryField::Username => entry.username.as_deref(),
+            EntryField::Password => entry.password.as_deref(),
+            EntryField::Url => entry.url.as_deref(),
+            EntryField::Note => entry.note.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownField(pub String);
+
+impl fmt::Display for UnknownField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown field: {}", self.0)
+    }
+}
+
+impl FromStr for EntryField {
+    type Err = UnknownField;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "id" => Ok(EntryField::Id),
+            "title" => Ok(EntryField::Title),
+            "username" => Ok(EntryField::Username),
+            "password" => Ok(EntryField::Password),
+            "url" => Ok(EntryField::Url),
+            "note" => Ok(EntryField::Note),
+            other => Err(UnknownField(other.to_string())),
+        }
+    }
+}
+
+// Parses a `--fields` value such as "title,username,url"
+pub fn parse_fields(fields: &str) -> Result<Vec<EntryField>, UnknownField> {
+    fields
+        .split(',')
+        .filter(|field| !field.trim().is_empty())
+        .map(EntryField::from_str)
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Table,
+    Plain,
+}
+
+pub struct EntryFormatter {
+    fields: Vec<EntryField>,
+    format: OutputFormat,
+    reveal: bool,
+}
+
+impl EntryFormatter {
+    pub fn new(fields: Vec<EntryField>, format: OutputFormat) -> Self {
+        Self {
+            fields,
+            format,
+            reveal: false,
+        }
+    }
+
+    pub fn reveal(mut self, reveal: bool) -> Self {
+        self.reveal = reveal;
+        self
+    }
+
+    fn cell(&self, field: &EntryField, entry: &Entry) -> Option<String> {
+        field.value(entry).map(|value| {
+            if field.is_protected() && !self.reveal {
+                MASK.to_string()
+            } else {
+                value.to_string()
+            }
+        })
+    }
+
+    pub fn format(&self, entries: &[Entry]) -> String {
+        match self.format {
+            OutputFormat::Json => self.format_json(entries),
+            OutputFormat::Table => self.format_table(entries),
+            OutputFormat::Plain => self.format_plain(entries),
+        }
+    }
+
+    fn format_json(&self, entries: &[Entry]) -> String {
+        let rows: Vec<Value> = entries
+            .iter()
+            .map(|entry| {
+                let mut object = Map::new();
+                for field in &self.fields {
+                    let value = self.cell(field, entry).map_or(Value::Null, Value::String);
+                    object.insert(field.name().to_string(), value);
+                }
+                Value::Object(object)
+            })
+            .collect();
+
+        Value::Array(rows).to_string()
+    }
+
+    fn format_table(&self, entries: &[Entry]) -> String {
+        let rows: Vec<Vec<String>> = entries
+            .iter()
+            .map(|entry| {
+                self.fields
+                    .iter()
+                    .map(|field| self.cell(field, entry).unwrap_or_default())
+                    .collect()
+            })
+            .collect();
+
+        let widths: Vec<usize> = self
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                rows.iter()
+                    .map(|row| row[i].chars().count())
+                    .fold(field.name().len(), usize::max)
+            })
+            .collect();
+
+        let header: Vec<String> = self
+            .fields
+            .iter()
+            .map(|field| field.name().to_uppercase())
+            .collect();
+
+        std::iter::once(header)
+            .chain(rows)
+            .map(|row| {
+                row.iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                    .collect::<Vec<_>>()
+                    .join("  ")
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn format_plain(&self, entries: &[Entry]) -> String {
+        entries
+            .iter()
+            .map(|entry| {
+                self.fields
+                    .iter()
+                    .filter_map(|field| {
+                        self.cell(field, entry)
+                            .map(|value| format!("{}: {}", field.name(), value))
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+impl Default for EntryFormatter {
+    fn default() -> Self {
+        Self::new(
+            vec![EntryField::Title, EntryField::Username, EntryField::Url],
+            OutputFormat::Plain,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry {
+            id: "1".to_string(),
+            title: "Example".to_string(),
+            username: Some("user1".to_string()),
+            password: Some("secret".to_string()),
+            url: Some("https://example.com".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_fields() {
+        assert_eq!(
+            parse_fields("title, username,URL").unwrap(),
+            vec![EntryField::Title, EntryField::Username, EntryField::Url]
+        );
+        assert_eq!(
+            parse_fields("title,secret"),
+            Err(UnknownField("secret".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_password_masked_by_default() {
+        let formatter = EntryFormatter::new(
+            vec![EntryField::Title, EntryField::Password],
+            OutputFormat::Plain,
+        );
+
+        let output = formatter.format(&[entry()]);
+
+        assert_eq!(output, "title: Example\npassword: ********");
+    }
+
+    #[test]
+    fn test_password_revealed() {
+        let formatter =
+            EntryFormatter::new(vec![EntryField::Password], OutputFormat::Plain).reveal(true);
+
+        assert_eq!(formatter.format(&[entry()]), "password: secret");
+    }
+
+    #[test]
+    fn test_json_output() {
+        let formatter = EntryFormatter::new(
+            vec![EntryField::Title, EntryField::Password, EntryField::Note],
+            OutputFormat::Json,
+        );
+
+        let output: Value = serde_json::from_str(&formatter.format(&[entry()])).unwrap();
+
+        assert_eq!(
+            output,
+            serde_json::json!([{"title": "Example", "password": "********", "note": null}])
+        );
+    }
+
+    #[test]
+    fn test_table_output() {
+        let formatter = EntryFormatter::new(
+            vec![EntryField::Title, EntryField::Username],
+            OutputFormat::Table,
+        );
+
+        let output = formatter.format(&[entry()]);
+
+        assert_eq!(output, "TITLE    USERNAME\nExample  user1");
+    }
+
+    #[test]
+    fn test_default_fields_hide_password() {
+        let output = EntryFormatter::default().format(&[entry()]);
+
+        assert!(!output.contains("secret"));
+        assert!(output.contains("url: https://example.com"));
+    }
+}
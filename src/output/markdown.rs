@@ -0,0 +1,230 @@
+use pulldown_cmark::{html, CowStr, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+// Notes are treated as Markdown. Raw HTML in a note is shown as text, links
+// with schemes other than these are dropped and images are replaced by their
+// alt text, so rendering a note never runs script or fetches anything.
+const SAFE_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+const BOLD: &str = "\x1b[1m";
+const NORMAL_INTENSITY: &str = "\x1b[22m";
+const ITALIC: &str = "\x1b[3m";
+const NOT_ITALIC: &str = "\x1b[23m";
+const UNDERLINE: &str = "\x1b[4m";
+const NOT_UNDERLINED: &str = "\x1b[24m";
+const STRIKE: &str = "\x1b[9m";
+const NOT_STRUCK: &str = "\x1b[29m";
+const CODE: &str = "\x1b[36m";
+const DEFAULT_COLOR: &str = "\x1b[39m";
+
+fn parser(note: &str) -> Parser<'_> {
+    Parser::new_ext(
+        note,
+        Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS,
+    )
+}
+
+fn is_safe_url(url: &str) -> bool {
+    // Relative links and fragments have no scheme
+    match url.split_once(':') {
+        Some((scheme, _)) if !scheme.contains(['/', '?', '#']) => SAFE_SCHEMES
+            .iter()
+            .any(|safe| scheme.trim().eq_ignore_ascii_case(safe)),
+        _ => true,
+    }
+}
+
+fn sanitized(note: &str) -> impl Iterator<Item = Event<'_>> {
+    parser(note).filter_map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Some(Event::Text(html)),
+        Event::Start(Tag::Image { .. }) | Event::End(TagEnd::Image) => None,
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) if !is_safe_url(&dest_url) => Some(Event::Start(Tag::Link {
+            link_type,
+            dest_url: CowStr::Borrowed(""),
+            title,
+            id,
+        })),
+        event => Some(event),
+    })
+}
+
+// HTML for the web view
+pub fn render_html(note: &str) -> String {
+    let mut output = String::new();
+    html::push_html(&mut output, sanitized(note));
+    output
+}
+
+// Control characters in a note could move the cursor or retitle the terminal
+fn push_printable(output: &mut String, text: &str) {
+    output.extend(
+        text.chars()
+            .filter(|c| !c.is_control() || *c == '\n' || *c == '\t'),
+    );
+}
+
+// Text with ANSI styling for the terminal
+pub fn render_ansi(note: &str) -> String {
+    let mut output = String::new();
+    // Next number of each open list, None for bullet lists
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut links: Vec<String> = Vec::new();
+    let mut in_code_block = false;
+
+    for event in sanitized(note) {
+        match event {
+            Event::Start(Tag::Heading { .. }) | Event::Start(Tag::Strong) => output.push_str(BOLD),
+            Event::End(TagEnd::Heading(level)) => {
+                output.push_str(NORMAL_INTENSITY);
+                if level == HeadingLevel::H1 {
+                    output.push('\n');
+                }
+                output.push_str("\n\n");
+            }
+            Event::End(TagEnd::Strong) => output.push_str(NORMAL_INTENSITY),
+            Event::Start(Tag::Emphasis) => output.push_str(ITALIC),
+            Event::End(TagEnd::Emphasis) => output.push_str(NOT_ITALIC),
+            Event::Start(Tag::Strikethrough) => output.push_str(STRIKE),
+            Event::End(TagEnd::Strikethrough) => output.push_str(NOT_STRUCK),
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                output.push_str(UNDERLINE);
+                links.push(dest_url.to_string());
+            }
+            Event::End(TagEnd::Link) => {
+                output.push_str(NOT_UNDERLINED);
+                if let Some(url) = links.pop().filter(|url| !url.is_empty()) {
+                    output.push_str(" (");
+                    push_printable(&mut output, &url);
+                    output.push(')');
+                }
+            }
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code_block = true;
+                output.push_str(CODE);
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                in_code_block = false;
+                output.push_str(DEFAULT_COLOR);
+                output.push('\n');
+            }
+            Event::Start(Tag::List(start)) => {
+                if !lists.is_empty() && !output.ends_with('\n') {
+                    output.push('\n');
+                }
+                lists.push(start);
+            }
+            Event::End(TagEnd::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    output.push('\n');
+                }
+            }
+            Event::Start(Tag::Item) => {
+                output.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(number)) => {
+                        output.push_str(&format!("{}. ", number));
+                        *number += 1;
+                    }
+                    _ => output.push_str("• "),
+                }
+            }
+            Event::End(TagEnd::Item) if !output.ends_with('\n') => output.push('\n'),
+            Event::End(TagEnd::Paragraph) if lists.is_empty() => output.push_str("\n\n"),
+            Event::Text(text) if in_code_block => {
+                for line in text.lines() {
+                    output.push_str("    ");
+                    push_printable(&mut output, line);
+                    output.push('\n');
+                }
+            }
+            Event::Text(text) => push_printable(&mut output, &text),
+            Event::Code(code) => {
+                output.push_str(CODE);
+                push_printable(&mut output, &code);
+                output.push_str(DEFAULT_COLOR);
+            }
+            Event::SoftBreak | Event::HardBreak => output.push('\n'),
+            Event::Rule => output.push_str("────────\n\n"),
+            Event::TaskListMarker(done) => output.push_str(if done { "[x] " } else { "[ ] " }),
+            _ => {}
+        }
+    }
+
+    output.trim_end().to_string()
+}
+
+// The words of a note without markdown syntax, for searching
+pub fn plain_text(note: &str) -> String {
+    let mut output = String::new();
+
+    for event in parser(note) {
+        match event {
+            Event::Text(text) | Event::Code(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                output.push_str(&text)
+            }
+            Event::SoftBreak | Event::HardBreak => output.push(' '),
+            Event::End(
+                TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::Item | TagEnd::CodeBlock,
+            ) => output.push('\n'),
+            _ => {}
+        }
+    }
+
+    output.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_html_escapes_raw_html() {
+        let html = render_html("Hello <script>alert(1)</script> **world**");
+
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(html.contains("<strong>world</strong>"));
+    }
+
+    #[test]
+    fn test_render_html_drops_unsafe_links_and_images() {
+        let html = render_html(
+            "[ok](https://example.com) [bad](JavaScript:alert(1)) ![tracker](https://x.test/p.png)",
+        );
+
+        assert!(html.contains("href=\"https://example.com\""));
+        assert!(!html.to_lowercase().contains("javascript"));
+        assert!(!html.contains("<img"));
+        assert!(html.contains("tracker"));
+    }
+
+    #[test]
+    fn test_render_ansi() {
+        let ansi = render_ansi(
+            "# Wifi\n\n- **ssid**: home\n- pin `1234`\n\nSee [router](http://192.168.0.1)",
+        );
+
+        assert_eq!(
+            ansi,
+            "\x1b[1mWifi\x1b[22m\n\n\n• \x1b[1mssid\x1b[22m: home\n• pin \x1b[36m1234\x1b[39m\n\nSee \x1b[4mrouter\x1b[24m (http://192.168.0.1)"
+        );
+    }
+
+    #[test]
+    fn test_render_ansi_strips_control_characters() {
+        assert_eq!(render_ansi("a\x1b]0;pwned\x07b"), "a]0;pwnedb");
+    }
+
+    #[test]
+    fn test_plain_text() {
+        assert_eq!(
+            plain_text("# Recovery\n\n1. Use the **backup** code\n2. Call `support`"),
+            "Recovery\nUse the backup code\nCall support"
+        );
+    }
+}
@@ -0,0 +1,2 @@
+pub mod entry_formatter;
+pub mod markdown;
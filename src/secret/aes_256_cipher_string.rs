@@ -1,8 +1,13 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
 use std::fmt;
 
 use super::{aes_256_cipher::Aes256Cipher, cryp_dec::CrypDec};
 
-struct Aes256CipherString {
+const BLOCK_SIZE: usize = 16;
+
+// Encrypts strings block by block with `Aes256Cipher`, padded with PKCS#7
+// and encoded as base64
+pub struct Aes256CipherString {
     byte_cipher: Aes256Cipher,
 }
 
@@ -12,29 +17,36 @@ impl Aes256CipherString {
         Aes256CipherString { byte_cipher }
     }
 
-    // Private method to pad bytes to a multiple of 16
+    // Private method to pad bytes to a multiple of 16 (PKCS#7). It works on
+    // the UTF-8 bytes, so multi-byte characters may straddle blocks.
     fn pad_bytes(&self, bytes: &[u8]) -> Vec<u8> {
-        let block_size = 16;
-        let padding_length = block_size - (bytes.len() % block_size);
+        let padding_length = BLOCK_SIZE - (bytes.len() % BLOCK_SIZE);
         let mut padded_bytes = bytes.to_vec();
         padded_bytes.extend(vec![padding_length as u8; padding_length]);
         padded_bytes
     }
 
-    // Private method to remove padding from bytes
-    fn unpad_bytes(&self, bytes: &[u8]) -> Vec<u8> {
-        if bytes.is_empty() {
-            return Vec::new(); // Return empty vector if input is empty
+    // Private method to remove PKCS#7 padding from bytes. The whole last
+    // block is checked whatever the padding length, so how long a bad
+    // padding takes to reject doesn't tell where it went wrong.
+    fn unpad_bytes(&self, bytes: &[u8]) -> Result<Vec<u8>, CrypDecStringError> {
+        if bytes.is_empty() || !bytes.len().is_multiple_of(BLOCK_SIZE) {
+            return Err(CrypDecStringError::InvalidLength);
         }
 
-        let padding_length = bytes[bytes.len() - 1] as usize;
-
-        // Ensure the padding length is valid
-        if padding_length == 0 || padding_length > bytes.len() {
-            return bytes.to_vec(); // Return the original bytes if padding is invalid
+        let padding_length = bytes[bytes.len() - 1];
+        let mut invalid =
+            u8::from(padding_length == 0) | u8::from(padding_length as usize > BLOCK_SIZE);
+        for (i, byte) in bytes[bytes.len() - BLOCK_SIZE..].iter().rev().enumerate() {
+            // All ones for the bytes that should be padding, zero otherwise
+            let mask = ((i as i16 - i16::from(padding_length)) >> 15) as u8;
+            invalid |= (byte ^ padding_length) & mask;
+        }
+        if invalid != 0 {
+            return Err(CrypDecStringError::InvalidPadding);
         }
 
-        bytes[..bytes.len() - padding_length].to_vec()
+        Ok(bytes[..bytes.len() - padding_length as usize].to_vec())
     }
 }
 
@@ -42,6 +54,7 @@ impl Aes256CipherString {
 #[derive(Debug)]
 pub enum CrypDecStringError {
     InvalidLength,
+    InvalidPadding,
     Utf8Error(std::string::FromUtf8Error),
 }
 
@@ -50,6 +63,7 @@ impl fmt::Display for CrypDecStringError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             CrypDecStringError::InvalidLength => write!(f, "Invalid Length"),
+            CrypDecStringError::InvalidPadding => write!(f, "Invalid Padding"),
             CrypDecStringError::Utf8Error(e) => write!(f, "UTF-8 Error: {}", e),
         }
     }
@@ -82,13 +96,17 @@ impl CrypDec for Aes256CipherString {
         }
 
         // Convert the encrypted bytes to a base64-encoded string
-        Ok(base64::encode(encrypted_bytes))
+        Ok(STANDARD.encode(encrypted_bytes))
     }
 
     fn decrypt(&self, data: &Self::Input) -> Result<Self::Output, Self::Error> {
         // Decode the base64-encoded string to bytes
-        let encrypted_bytes =
-            base64::decode(data).map_err(|_| CrypDecStringError::InvalidLength)?;
+        let encrypted_bytes = STANDARD
+            .decode(data)
+            .map_err(|_| CrypDecStringError::InvalidLength)?;
+        if encrypted_bytes.is_empty() || !encrypted_bytes.len().is_multiple_of(BLOCK_SIZE) {
+            return Err(CrypDecStringError::InvalidLength);
+        }
 
         // Decrypt each 16-byte block
         let mut decrypted_bytes = Vec::new();
@@ -102,7 +120,7 @@ impl CrypDec for Aes256CipherString {
         }
 
         // Remove padding and convert bytes to a string
-        let unpadded_bytes = self.unpad_bytes(&decrypted_bytes);
+        let unpadded_bytes = self.unpad_bytes(&decrypted_bytes)?;
         String::from_utf8(unpadded_bytes).map_err(CrypDecStringError::Utf8Error)
     }
 }
@@ -202,12 +220,13 @@ mod tests {
         let key = [0u8; 32];
         let aes_cipher_string = Aes256CipherString::new(key);
 
-        // Create invalid UTF-8 data by encrypting and then corrupting the result
-        let plaintext = String::from("Hello, world!");
+        // Create invalid UTF-8 data by encrypting and then corrupting the result.
+        // The padding is in the second block, so it stays valid.
+        let plaintext = String::from("Hello, world! Hello again!");
         let ciphertext = aes_cipher_string.encrypt(&plaintext).unwrap();
-        let mut corrupted_bytes = base64::decode(ciphertext).unwrap();
+        let mut corrupted_bytes = STANDARD.decode(ciphertext).unwrap();
         corrupted_bytes[0] = 0xff; // Introduce invalid UTF-8
-        let corrupted_ciphertext = base64::encode(corrupted_bytes);
+        let corrupted_ciphertext = STANDARD.encode(corrupted_bytes);
 
         // Attempt to decrypt corrupted ciphertext
         let result = aes_cipher_string.decrypt(&corrupted_ciphertext);
@@ -219,4 +238,51 @@ mod tests {
             CrypDecStringError::Utf8Error(_)
         ));
     }
+
+    #[test]
+    fn test_encrypt_decrypt_multibyte_characters() {
+        let key = [0u8; 32];
+        let aes_cipher_string = Aes256CipherString::new(key);
+
+        // 12 characters but 16 bytes, so a whole block of padding follows
+        let plaintext = String::from("Grüße, 世界!");
+
+        let ciphertext = aes_cipher_string.encrypt(&plaintext).unwrap();
+        let decrypted_text = aes_cipher_string.decrypt(&ciphertext).unwrap();
+
+        assert_eq!(plaintext, decrypted_text);
+    }
+
+    #[test]
+    fn test_unpad_rejects_invalid_padding() {
+        let aes_cipher_string = Aes256CipherString::new([0u8; 32]);
+        let mut block = [b'a'; 16];
+
+        block[15] = 0;
+        assert!(matches!(
+            aes_cipher_string.unpad_bytes(&block),
+            Err(CrypDecStringError::InvalidPadding)
+        ));
+        block[15] = 17;
+        assert!(matches!(
+            aes_cipher_string.unpad_bytes(&block),
+            Err(CrypDecStringError::InvalidPadding)
+        ));
+        // Claims three bytes of padding, but only the last two match
+        block[14] = 3;
+        block[15] = 3;
+        assert!(matches!(
+            aes_cipher_string.unpad_bytes(&block),
+            Err(CrypDecStringError::InvalidPadding)
+        ));
+        block[13] = 3;
+        assert_eq!(
+            aes_cipher_string.unpad_bytes(&block).unwrap(),
+            vec![b'a'; 13]
+        );
+        assert!(matches!(
+            aes_cipher_string.unpad_bytes(&block[..15]),
+            Err(CrypDecStringError::InvalidLength)
+        ));
+    }
 }
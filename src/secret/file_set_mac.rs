@@ -0,0 +1,214 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::Path,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// An HMAC-SHA256 over the contents of several files, so tampering with any
+// of them is detected by whoever holds the key.
+pub struct FileSetMac {
+    key: [u8; 32],
+}
+
+impl FileSetMac {
+    pub fn new(key: [u8; 32]) -> Self {
+        FileSetMac { key }
+    }
+
+    fn mac<P: AsRef<Path>>(&self, files: &[P]) -> io::Result<HmacSha256> {
+        let prefixes = files
+            .iter()
+            .map(|file| Ok((file, fs::metadata(file)?.len())))
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(self
+            .mac_prefixes(&prefixes)?
+            .expect("whole files are as long as themselves"))
+    }
+
+    // None if a file is shorter than its prefix. Empty prefixes are hashed
+    // without opening their file, which need not exist.
+    fn mac_prefixes<P: AsRef<Path>>(&self, files: &[(P, u64)]) -> io::Result<Option<HmacSha256>> {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length");
+        let mut buffer = vec![0; 64 * 1024];
+
+        for (file, len) in files {
+            // Length prefix so bytes can't be shifted from one file to the next
+            mac.update(&len.to_le_bytes());
+            if *len == 0 {
+                continue;
+            }
+            let file = File::open(file)?;
+            if file.metadata()?.len() < *len {
+                return Ok(None);
+            }
+            let mut prefix = file.take(*len);
+            loop {
+                let read = prefix.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                mac.update(&buffer[..read]);
+            }
+        }
+
+        Ok(Some(mac))
+    }
+
+    pub fn compute<P: AsRef<Path>>(&self, files: &[P]) -> io::Result<Vec<u8>> {
+        Ok(self.mac(files)?.finalize().into_bytes().to_vec())
+    }
+
+    pub fn write<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        files: &[P],
+        mac_file: Q,
+    ) -> io::Result<()> {
+        fs::write(mac_file, self.compute(files)?)
+    }
+
+    // Compares in constant time against the tag stored in `mac_file`
+    pub fn verify<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        files: &[P],
+        mac_file: Q,
+    ) -> io::Result<bool> {
+        let expected = fs::read(mac_file)?;
+        Ok(self.mac(files)?.verify_slice(&expected).is_ok())
+    }
+
+    // Like `compute`, over the first `len` bytes of each file only, e.g. the
+    // part of an append-only file written before some point
+    pub fn compute_prefixes<P: AsRef<Path>>(&self, files: &[(P, u64)]) -> io::Result<Vec<u8>> {
+        match self.mac_prefixes(files)? {
+            Some(mac) => Ok(mac.finalize().into_bytes().to_vec()),
+            None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "file is shorter than its prefix",
+            )),
+        }
+    }
+
+    // A file shorter than its prefix fails the check
+    pub fn verify_prefixes<P: AsRef<Path>>(
+        &self,
+        files: &[(P, u64)],
+        expected: &[u8],
+    ) -> io::Result<bool> {
+        Ok(self
+            .mac_prefixes(files)?
+            .is_some_and(|mac| mac.verify_slice(expected).is_ok()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn test_files() -> (String, String, String) {
+        let id = Uuid::new_v4();
+        (
+            format!("test_mac_data_{}.bin", id),
+            format!("test_mac_index_{}.bin", id),
+            format!("test_mac_{}.mac", id),
+        )
+    }
+
+    #[test]
+    fn test_verify_unchanged_files() {
+        let (data, index, mac_file) = test_files();
+        fs::write(&data, b"data").unwrap();
+        fs::write(&index, b"index").unwrap();
+
+        let mac = FileSetMac::new([7u8; 32]);
+        mac.write(&[&data, &index], &mac_file).unwrap();
+
+        assert!(mac.verify(&[&data, &index], &mac_file).unwrap());
+
+        for file in [data, index, mac_file] {
+            fs::remove_file(file).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_detects_tampering() {
+        let (data, index, mac_file) = test_files();
+        fs::write(&data, b"data").unwrap();
+        fs::write(&index, b"index").unwrap();
+
+        let mac = FileSetMac::new([7u8; 32]);
+        mac.write(&[&data, &index], &mac_file).unwrap();
+        fs::write(&index, b"indeX").unwrap();
+
+        assert!(!mac.verify(&[&data, &index], &mac_file).unwrap());
+
+        for file in [data, index, mac_file] {
+            fs::remove_file(file).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_detects_bytes_moved_between_files() {
+        let (data, index, mac_file) = test_files();
+        fs::write(&data, b"dataX").unwrap();
+        fs::write(&index, b"index").unwrap();
+
+        let mac = FileSetMac::new([7u8; 32]);
+        mac.write(&[&data, &index], &mac_file).unwrap();
+        fs::write(&data, b"data").unwrap();
+        fs::write(&index, b"Xindex").unwrap();
+
+        assert!(!mac.verify(&[&data, &index], &mac_file).unwrap());
+
+        for file in [data, index, mac_file] {
+            fs::remove_file(file).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_wrong_key_fails() {
+        let (data, index, mac_file) = test_files();
+        fs::write(&data, b"data").unwrap();
+        fs::write(&index, b"index").unwrap();
+
+        FileSetMac::new([7u8; 32])
+            .write(&[&data, &index], &mac_file)
+            .unwrap();
+
+        assert!(!FileSetMac::new([8u8; 32])
+            .verify(&[&data, &index], &mac_file)
+            .unwrap());
+
+        for file in [data, index, mac_file] {
+            fs::remove_file(file).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_verify_prefixes_ignores_appended_bytes() {
+        let (data, index, _) = test_files();
+        fs::write(&data, b"data").unwrap();
+
+        let mac = FileSetMac::new([7u8; 32]);
+        let tag = mac.compute_prefixes(&[(&data, 4), (&index, 0)]).unwrap();
+        fs::write(&data, b"data appended").unwrap();
+
+        assert!(mac
+            .verify_prefixes(&[(&data, 4), (&index, 0)], &tag)
+            .unwrap());
+        assert!(!mac
+            .verify_prefixes(&[(&data, 5), (&index, 0)], &tag)
+            .unwrap());
+        fs::write(&data, b"dat").unwrap();
+        assert!(!mac
+            .verify_prefixes(&[(&data, 4), (&index, 0)], &tag)
+            .unwrap());
+
+        fs::remove_file(data).unwrap();
+    }
+}
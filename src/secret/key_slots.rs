@@ -0,0 +1,330 @@
+use rand::{Rng, RngCore};
+use sha2::Sha256;
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+};
+
+use super::sealed::{self, derive_key, NONCE_SIZE, TAG_SIZE};
+
+// Unlocks a vault key with a password. The key slot file has room for two
+// keys, each sealed with a key derived from a password by PBKDF2: the real
+// vault's, and optionally a decoy's opened by a duress password. The decoy
+// is a vault of its own, filled by the user with innocuous entries, and
+// its key can't open the real vault.
+//
+// Both slots are always written and the unused one is random bytes, and
+// which slot is which is chosen at random, so the file looks the same with
+// or without a duress password. Unlocking derives a key for every slot
+// whichever opens, so it takes as long either way. Callers should pick the
+// vault's files by `vault_id` rather than by which password was given, so
+// nothing outside the key itself tells the vaults apart.
+
+pub const SLOT_COUNT: usize = 2;
+// PBKDF2-HMAC-SHA256 iterations for new slot files
+pub const DEFAULT_ITERATIONS: u32 = 600_000;
+
+const MAGIC: &[u8; 7] = b"TUGSLOT";
+const SLOTS_VERSION: u8 = 1;
+const SALT_SIZE: usize = 16;
+// What a slot seals: whether it is the real vault's, then the vault key
+const PAYLOAD_SIZE: usize = 1 + 32;
+const SLOT_SIZE: usize = SALT_SIZE + NONCE_SIZE + PAYLOAD_SIZE + TAG_SIZE;
+const HEADER_SIZE: usize = MAGIC.len() + 1 + 4;
+const FILE_SIZE: usize = HEADER_SIZE + SLOT_COUNT * SLOT_SIZE;
+
+const PRIMARY: u8 = 1;
+const DECOY: u8 = 2;
+
+#[derive(Debug)]
+pub enum KeySlotError {
+    IoError(io::Error),
+    // Not a key slot file, or one of an unknown version
+    InvalidFile,
+    // No slot opens with the password
+    WrongPassword,
+    // The duress password is the vault's own, so it couldn't tell them apart
+    SamePassword,
+}
+
+impl From<io::Error> for KeySlotError {
+    fn from(error: io::Error) -> Self {
+        KeySlotError::IoError(error)
+    }
+}
+
+impl fmt::Display for KeySlotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            KeySlotError::IoError(ref err) => write!(f, "I/O error: {}", err),
+            KeySlotError::InvalidFile => write!(f, "Not a key slot file"),
+            KeySlotError::WrongPassword => write!(f, "Wrong password"),
+            KeySlotError::SamePassword => {
+                write!(f, "The duress password must differ from the password")
+            }
+        }
+    }
+}
+
+impl std::error::Error for KeySlotError {}
+
+pub struct UnlockedVault {
+    key: [u8; 32],
+    primary: bool,
+}
+
+impl UnlockedVault {
+    pub fn key(&self) -> &[u8; 32] {
+        &self.key
+    }
+
+    // Names the vault's files, e.g. "vault-{id}.bin", without saying
+    // whether it is the real one
+    pub fn vault_id(&self) -> String {
+        hex::encode(&derive_key(&self.key, b"vault id")[..8])
+    }
+}
+
+fn slot_key(password: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, iterations, &mut key);
+    key
+}
+
+fn seal_slot(password: &str, iterations: u32, role: u8, key: &[u8; 32]) -> Vec<u8> {
+    let mut salt = [0; SALT_SIZE];
+    rand::rng().fill_bytes(&mut salt);
+    let payload = [&[role][..], key].concat();
+    let sealed = sealed::seal(&slot_key(password, &salt, iterations), b"", &payload);
+    [&salt[..], &sealed].concat()
+}
+
+fn random_slot() -> Vec<u8> {
+    let mut slot = vec![0; SLOT_SIZE];
+    rand::rng().fill_bytes(&mut slot);
+    slot
+}
+
+fn new_key() -> [u8; 32] {
+    let mut key = [0; 32];
+    rand::rng().fill_bytes(&mut key);
+    key
+}
+
+struct SlotFile {
+    iterations: u32,
+    slots: Vec<Vec<u8>>,
+}
+
+impl SlotFile {
+    fn read(file_path: &str) -> Result<Self, KeySlotError> {
+        let bytes = fs::read(file_path)?;
+        if bytes.len() != FILE_SIZE
+            || !bytes.starts_with(MAGIC)
+            || bytes[MAGIC.len()] != SLOTS_VERSION
+        {
+            return Err(KeySlotError::InvalidFile);
+        }
+        let iterations = u32::from_le_bytes(
+            bytes[MAGIC.len() + 1..HEADER_SIZE]
+                .try_into()
+                .expect("the header ends with the iterations"),
+        );
+        Ok(SlotFile {
+            iterations,
+            slots: bytes[HEADER_SIZE..]
+                .chunks(SLOT_SIZE)
+                .map(<[u8]>::to_vec)
+                .collect(),
+        })
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FILE_SIZE);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(SLOTS_VERSION);
+        bytes.extend_from_slice(&self.iterations.to_le_bytes());
+        for slot in &self.slots {
+            bytes.extend_from_slice(slot);
+        }
+        bytes
+    }
+
+    // The slot `password` opens, with what it holds
+    fn unlock(&self, password: &str) -> Option<(usize, UnlockedVault)> {
+        let mut unlocked = None;
+        // Every slot is tried, so the time taken doesn't say which opened
+        for (index, slot) in self.slots.iter().enumerate() {
+            let (salt, sealed) = slot.split_at(SALT_SIZE);
+            let key = slot_key(password, salt, self.iterations);
+            if let Ok(opened) = sealed::open(&key, 0, sealed) {
+                let (role, vault_key) = opened.plaintext.split_at(1);
+                unlocked.get_or_insert((
+                    index,
+                    UnlockedVault {
+                        key: vault_key.try_into().expect("slots seal a 32 byte key"),
+                        primary: role[0] == PRIMARY,
+                    },
+                ));
+            }
+        }
+        unlocked
+    }
+}
+
+// Writes a new slot file with a random vault key opened by `password`.
+// Fails if the file exists, so a vault's key is never replaced.
+pub fn create(
+    file_path: &str,
+    password: &str,
+    iterations: u32,
+) -> Result<UnlockedVault, KeySlotError> {
+    let key = new_key();
+    // Each unused slot gets bytes of its own, so none stands out as unused
+    let mut slots: Vec<_> = (0..SLOT_COUNT).map(|_| random_slot()).collect();
+    slots[rand::rng().random_range(0..SLOT_COUNT)] = seal_slot(password, iterations, PRIMARY, &key);
+    let bytes = SlotFile { iterations, slots }.bytes();
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(file_path)?;
+    file.write_all(&bytes)?;
+    file.sync_all()?;
+    Ok(UnlockedVault { key, primary: true })
+}
+
+pub fn unlock(file_path: &str, password: &str) -> Result<UnlockedVault, KeySlotError> {
+    SlotFile::read(file_path)?
+        .unlock(password)
+        .map(|(_, vault)| vault)
+        .ok_or(KeySlotError::WrongPassword)
+}
+
+// Sets the duress password, replacing any earlier one and its decoy vault.
+// Only the real vault's password can do so, the duress password fails as a
+// wrong one would. Returns the new decoy vault to fill with entries.
+pub fn set_duress_password(
+    file_path: &str,
+    password: &str,
+    duress_password: &str,
+) -> Result<UnlockedVault, KeySlotError> {
+    if duress_password == password {
+        return Err(KeySlotError::SamePassword);
+    }
+    let mut file = SlotFile::read(file_path)?;
+    let index = match file.unlock(password) {
+        Some((index, vault)) if vault.primary => index,
+        _ => return Err(KeySlotError::WrongPassword),
+    };
+
+    let key = new_key();
+    file.slots[(index + 1) % SLOT_COUNT] = seal_slot(duress_password, file.iterations, DECOY, &key);
+    let temp_file_path = format!("{}.tmp", file_path);
+    let mut temp = fs::File::create(&temp_file_path)?;
+    temp.write_all(&file.bytes())?;
+    temp.sync_all()?;
+    fs::rename(temp_file_path, file_path)?;
+    Ok(UnlockedVault {
+        key,
+        primary: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    const ITERATIONS: u32 = 1000;
+
+    #[test]
+    fn test_duress_password_opens_decoy() {
+        let file_path = format!("test_key_slots_{}.bin", Uuid::new_v4());
+        let real = create(&file_path, "correct horse", ITERATIONS).unwrap();
+        assert!(create(&file_path, "other", ITERATIONS).is_err());
+        let before = fs::read(&file_path).unwrap();
+
+        assert_eq!(
+            unlock(&file_path, "correct horse").unwrap().key(),
+            real.key()
+        );
+        assert!(matches!(
+            unlock(&file_path, "duress"),
+            Err(KeySlotError::WrongPassword)
+        ));
+
+        let decoy = set_duress_password(&file_path, "correct horse", "duress").unwrap();
+        assert_ne!(decoy.key(), real.key());
+        assert_ne!(decoy.vault_id(), real.vault_id());
+        // Nothing but the contents of the slots changed
+        let after = fs::read(&file_path).unwrap();
+        assert_eq!(after.len(), before.len());
+        assert_eq!(after[..HEADER_SIZE], before[..HEADER_SIZE]);
+
+        let unlocked = unlock(&file_path, "duress").unwrap();
+        assert_eq!(unlocked.vault_id(), decoy.vault_id());
+        assert_eq!(
+            unlock(&file_path, "correct horse").unwrap().vault_id(),
+            real.vault_id()
+        );
+
+        // The duress password can't replace the real vault's key
+        assert!(matches!(
+            set_duress_password(&file_path, "duress", "correct horse"),
+            Err(KeySlotError::WrongPassword)
+        ));
+        assert_eq!(
+            unlock(&file_path, "correct horse").unwrap().key(),
+            real.key()
+        );
+
+        fs::remove_file(file_path).unwrap();
+    }
+
+    #[test]
+    fn test_duress_password_must_differ() {
+        let file_path = format!("test_key_slots_{}.bin", Uuid::new_v4());
+        let real = create(&file_path, "correct horse", ITERATIONS).unwrap();
+        let before = fs::read(&file_path).unwrap();
+
+        assert!(matches!(
+            set_duress_password(&file_path, "correct horse", "correct horse"),
+            Err(KeySlotError::SamePassword)
+        ));
+        assert_eq!(fs::read(&file_path).unwrap(), before);
+        assert_eq!(
+            unlock(&file_path, "correct horse").unwrap().key(),
+            real.key()
+        );
+
+        fs::remove_file(file_path).unwrap();
+    }
+
+    #[test]
+    fn test_unused_slots_differ() {
+        let file_path = format!("test_key_slots_{}.bin", Uuid::new_v4());
+        create(&file_path, "correct horse", ITERATIONS).unwrap();
+
+        let file = SlotFile::read(&file_path).unwrap();
+        for (i, slot) in file.slots.iter().enumerate() {
+            assert!(file.slots[i + 1..].iter().all(|other| other != slot));
+        }
+
+        fs::remove_file(file_path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_file() {
+        let file_path = format!("test_key_slots_{}.bin", Uuid::new_v4());
+        fs::write(&file_path, b"TUGSLOT").unwrap();
+
+        assert!(matches!(
+            unlock(&file_path, "password"),
+            Err(KeySlotError::InvalidFile)
+        ));
+
+        fs::remove_file(file_path).unwrap();
+    }
+}
@@ -1,3 +1,9 @@
 pub mod aes_256_cipher;
-mod aes_256_cipher_string;
+pub mod aes_256_cipher_string;
 pub mod cryp_dec;
+pub mod file_set_mac;
+pub mod key_slots;
+pub mod password_generator;
+pub mod password_policy;
+pub mod sealed;
+pub mod stream;
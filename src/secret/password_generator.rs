@@ -0,0 +1,52 @@
+use rand::{seq::IndexedRandom, seq::SliceRandom, Rng};
+
+pub const DEFAULT_PASSWORD_LENGTH: usize = 20;
+
+const LOWERCASE: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &[u8] = b"0123456789";
+// Symbols that don't need quoting in a shell or escaping in a url
+const SYMBOLS: &[u8] = b"-_.~!*";
+
+// A random password of `length` characters with at least one lowercase and
+// uppercase letter, digit and symbol, so it passes the usual policies.
+// Shorter lengths are raised to fit one of each.
+pub fn generate_password(length: usize) -> String {
+    let classes = [LOWERCASE, UPPERCASE, DIGITS, SYMBOLS];
+    let all: Vec<u8> = classes.concat();
+    let mut rng = rand::rng();
+
+    let mut password: Vec<u8> = classes
+        .iter()
+        .filter_map(|class| class.choose(&mut rng).copied())
+        .collect();
+    while password.len() < length {
+        password.push(all[rng.random_range(0..all.len())]);
+    }
+    password.shuffle(&mut rng);
+
+    String::from_utf8(password).expect("password characters are ascii")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secret::password_policy::{CharClass, PasswordPolicy};
+
+    #[test]
+    fn test_generated_password_meets_policy() {
+        let policy = PasswordPolicy::default()
+            .with_min_length(DEFAULT_PASSWORD_LENGTH)
+            .with_required_class(CharClass::Lowercase)
+            .with_required_class(CharClass::Uppercase)
+            .with_required_class(CharClass::Digit)
+            .with_required_class(CharClass::Symbol);
+
+        for _ in 0..100 {
+            let password = generate_password(DEFAULT_PASSWORD_LENGTH);
+            assert_eq!(password.len(), DEFAULT_PASSWORD_LENGTH);
+            assert_eq!(policy.check(&password), Ok(()));
+        }
+        assert_eq!(generate_password(0).len(), 4);
+    }
+}
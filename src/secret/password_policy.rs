@@ -0,0 +1,189 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    io::{self, BufRead},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CharClass {
+    Lowercase,
+    Uppercase,
+    Digit,
+    Symbol,
+}
+
+impl CharClass {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            CharClass::Lowercase => c.is_lowercase(),
+            CharClass::Uppercase => c.is_uppercase(),
+            CharClass::Digit => c.is_numeric(),
+            CharClass::Symbol => !c.is_alphanumeric() && !c.is_whitespace(),
+        }
+    }
+}
+
+impl fmt::Display for CharClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CharClass::Lowercase => write!(f, "lowercase letter"),
+            CharClass::Uppercase => write!(f, "uppercase letter"),
+            CharClass::Digit => write!(f, "digit"),
+            CharClass::Symbol => write!(f, "symbol"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyViolation {
+    TooShort { min_length: usize, length: usize },
+    MissingClass(CharClass),
+    DenyListed,
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            PolicyViolation::TooShort { min_length, length } => write!(
+                f,
+                "Password has {} characters, at least {} are required",
+                length, min_length
+            ),
+            PolicyViolation::MissingClass(class) => {
+                write!(f, "Password needs at least one {}", class)
+            }
+            PolicyViolation::DenyListed => write!(f, "Password is on the deny-list"),
+        }
+    }
+}
+
+impl std::error::Error for PolicyViolation {}
+
+// Rules a password has to meet before it is saved. The default policy
+// accepts everything, rules are added with the `with_*` methods.
+#[derive(Debug, Clone, Default)]
+pub struct PasswordPolicy {
+    min_length: usize,
+    required_classes: Vec<CharClass>,
+    // Lower-cased so the check is case-insensitive
+    deny_list: HashSet<String>,
+}
+
+impl PasswordPolicy {
+    pub fn with_min_length(mut self, min_length: usize) -> Self {
+        self.min_length = min_length;
+        self
+    }
+
+    pub fn with_required_class(mut self, class: CharClass) -> Self {
+        if !self.required_classes.contains(&class) {
+            self.required_classes.push(class);
+        }
+        self
+    }
+
+    pub fn with_denied<I, S>(mut self, passwords: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.deny_list
+            .extend(passwords.into_iter().map(|p| p.as_ref().to_lowercase()));
+        self
+    }
+
+    // One password per line, blank lines are ignored
+    pub fn with_deny_list_from<R: BufRead>(self, reader: R) -> io::Result<Self> {
+        let passwords = reader.lines().collect::<io::Result<Vec<_>>>()?;
+        Ok(self.with_denied(passwords.iter().filter(|p| !p.trim().is_empty())))
+    }
+
+    // Returns the first rule the password breaks
+    pub fn check(&self, password: &str) -> Result<(), PolicyViolation> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Err(PolicyViolation::TooShort {
+                min_length: self.min_length,
+                length,
+            });
+        }
+
+        if let Some(class) = self
+            .required_classes
+            .iter()
+            .find(|class| !password.chars().any(|c| class.matches(c)))
+        {
+            return Err(PolicyViolation::MissingClass(*class));
+        }
+
+        if self.deny_list.contains(&password.to_lowercase()) {
+            return Err(PolicyViolation::DenyListed);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy::default()
+            .with_min_length(8)
+            .with_required_class(CharClass::Uppercase)
+            .with_required_class(CharClass::Digit)
+            .with_denied(["Password1"])
+    }
+
+    #[test]
+    fn test_default_accepts_everything() {
+        assert_eq!(PasswordPolicy::default().check(""), Ok(()));
+    }
+
+    #[test]
+    fn test_accepts_compliant_password() {
+        assert_eq!(policy().check("Tuggerah42"), Ok(()));
+    }
+
+    #[test]
+    fn test_too_short() {
+        assert_eq!(
+            policy().check("Ab1"),
+            Err(PolicyViolation::TooShort {
+                min_length: 8,
+                length: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_missing_class() {
+        assert_eq!(
+            policy().check("tuggerah42"),
+            Err(PolicyViolation::MissingClass(CharClass::Uppercase))
+        );
+        assert_eq!(
+            policy().check("Tuggerahhh"),
+            Err(PolicyViolation::MissingClass(CharClass::Digit))
+        );
+    }
+
+    #[test]
+    fn test_deny_list_ignores_case() {
+        assert_eq!(
+            policy().check("PASSWORD1"),
+            Err(PolicyViolation::DenyListed)
+        );
+    }
+
+    #[test]
+    fn test_deny_list_from_reader() {
+        let policy = PasswordPolicy::default()
+            .with_deny_list_from("123456\n\nqwerty\n".as_bytes())
+            .unwrap();
+
+        assert_eq!(policy.check("qwerty"), Err(PolicyViolation::DenyListed));
+        assert_eq!(policy.check(""), Ok(()));
+    }
+}
@@ -0,0 +1,178 @@
+use aes::Aes256;
+use cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+
+// Authenticated encryption for data written out whole, such as exports.
+// A sealed message is the caller's header in the clear, a random nonce, the
+// AES-256-CTR encrypted plaintext and an HMAC-SHA256 over all of it. The
+// encryption and MAC keys are derived from the one key given, so a wrong
+// key fails the MAC like a modified message does.
+
+pub const NONCE_SIZE: usize = 12;
+pub const TAG_SIZE: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// The MAC didn't match, or the message is too short to hold one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tampered;
+
+pub struct Opened<'a> {
+    pub header: &'a [u8],
+    pub nonce: [u8; NONCE_SIZE],
+    pub plaintext: Vec<u8>,
+}
+
+pub(crate) fn derive_key(key: &[u8; 32], purpose: &[u8]) -> [u8; 32] {
+    let mut mac =
+        <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(purpose);
+    mac.finalize().into_bytes().into()
+}
+
+fn mac_for(key: &[u8; 32]) -> HmacSha256 {
+    <HmacSha256 as Mac>::new_from_slice(&derive_key(key, b"sealed mac"))
+        .expect("HMAC accepts keys of any length")
+}
+
+// CTR mode: the nonce followed by a big-endian block counter, encrypted and
+// XORed over the data. The same call encrypts and decrypts. `cipher_key` is
+// a derived key, not the one the caller holds.
+pub(crate) fn apply_keystream(cipher_key: &[u8; 32], nonce: &[u8; NONCE_SIZE], data: &mut [u8]) {
+    let cipher = Aes256::new(GenericArray::from_slice(cipher_key));
+    for (counter, chunk) in data.chunks_mut(16).enumerate() {
+        let mut block = [0; 16];
+        block[..NONCE_SIZE].copy_from_slice(nonce);
+        block[NONCE_SIZE..].copy_from_slice(&(counter as u32).to_be_bytes());
+        let mut block = GenericArray::from(block);
+        cipher.encrypt_block(&mut block);
+        for (byte, keystream) in chunk.iter_mut().zip(block) {
+            *byte ^= keystream;
+        }
+    }
+}
+
+pub fn seal(key: &[u8; 32], header: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut nonce = [0; NONCE_SIZE];
+    rand::rng().fill_bytes(&mut nonce);
+    seal_with_nonce(key, header, plaintext, nonce)
+}
+
+// Like `seal`, but the nonce is derived from the header and plaintext as in
+// SIV, so the same input always seals to the same message. Such messages
+// can be compared for equality without the key, which is also what they
+// give away: anyone who reads them sees which values repeat. Only for
+// values meant to be looked up, opened with `open` as usual.
+pub fn seal_deterministic(key: &[u8; 32], header: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(&derive_key(key, b"sealed siv"))
+        .expect("HMAC accepts keys of any length");
+    mac.update(&(header.len() as u64).to_be_bytes());
+    mac.update(header);
+    mac.update(plaintext);
+    let nonce = mac.finalize().into_bytes()[..NONCE_SIZE]
+        .try_into()
+        .expect("HMAC-SHA256 is longer than a nonce");
+    seal_with_nonce(key, header, plaintext, nonce)
+}
+
+fn seal_with_nonce(
+    key: &[u8; 32],
+    header: &[u8],
+    plaintext: &[u8],
+    nonce: [u8; NONCE_SIZE],
+) -> Vec<u8> {
+    let mut sealed = Vec::with_capacity(header.len() + NONCE_SIZE + plaintext.len() + TAG_SIZE);
+    sealed.extend_from_slice(header);
+    sealed.extend_from_slice(&nonce);
+    let body = sealed.len();
+    sealed.extend_from_slice(plaintext);
+    apply_keystream(
+        &derive_key(key, b"sealed encryption"),
+        &nonce,
+        &mut sealed[body..],
+    );
+
+    let mut mac = mac_for(key);
+    mac.update(&sealed);
+    sealed.extend_from_slice(&mac.finalize().into_bytes());
+    sealed
+}
+
+// Checks and decrypts a message sealed with a header of `header_len` bytes
+pub fn open<'a>(
+    key: &[u8; 32],
+    header_len: usize,
+    sealed: &'a [u8],
+) -> Result<Opened<'a>, Tampered> {
+    if sealed.len() < header_len + NONCE_SIZE + TAG_SIZE {
+        return Err(Tampered);
+    }
+    let (signed, tag) = sealed.split_at(sealed.len() - TAG_SIZE);
+    let mut mac = mac_for(key);
+    mac.update(signed);
+    mac.verify_slice(tag).map_err(|_| Tampered)?;
+
+    let (header, rest) = signed.split_at(header_len);
+    let (nonce, ciphertext) = rest.split_at(NONCE_SIZE);
+    let nonce: [u8; NONCE_SIZE] = nonce.try_into().expect("split at the nonce size");
+    let mut plaintext = ciphertext.to_vec();
+    apply_keystream(
+        &derive_key(key, b"sealed encryption"),
+        &nonce,
+        &mut plaintext,
+    );
+
+    Ok(Opened {
+        header,
+        nonce,
+        plaintext,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let key = [3; 32];
+        let plaintext = b"more than one block of plaintext to encrypt";
+
+        let sealed = seal(&key, b"HDR", plaintext);
+        assert!(sealed.starts_with(b"HDR"));
+        assert!(!sealed.windows(4).any(|window| window == b"more"));
+
+        let opened = open(&key, 3, &sealed).unwrap();
+        assert_eq!(opened.header, b"HDR");
+        assert_eq!(opened.plaintext, plaintext);
+        assert_ne!(seal(&key, b"HDR", plaintext), sealed);
+    }
+
+    #[test]
+    fn test_open_rejects_changes() {
+        let key = [3; 32];
+        let sealed = seal(&key, b"HDR", b"secret");
+
+        assert!(open(&[4; 32], 3, &sealed).is_err());
+        for i in 0..sealed.len() {
+            let mut changed = sealed.clone();
+            changed[i] ^= 1;
+            assert!(open(&key, 3, &changed).is_err(), "byte {}", i);
+        }
+        assert!(open(&key, 3, &sealed[..20]).is_err());
+    }
+
+    #[test]
+    fn test_seal_deterministic() {
+        let key = [3; 32];
+        let sealed = seal_deterministic(&key, b"HDR", b"secret");
+
+        assert_eq!(sealed, seal_deterministic(&key, b"HDR", b"secret"));
+        assert_ne!(sealed, seal_deterministic(&key, b"HDR", b"other"));
+        assert_ne!(sealed, seal_deterministic(&key, b"HD", b"Rsecret"));
+        assert_ne!(sealed, seal_deterministic(&[4; 32], b"HDR", b"secret"));
+        assert_eq!(open(&key, 3, &sealed).unwrap().plaintext, b"secret");
+    }
+}
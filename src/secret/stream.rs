@@ -0,0 +1,303 @@
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use std::io::{self, Read, Write};
+
+use super::sealed::{apply_keystream, derive_key, NONCE_SIZE, TAG_SIZE};
+
+// Encryption for data too large to hold in memory at once, e.g. an
+// attachment. After a header the plaintext is cut into chunks, each
+// encrypted and MACed on its own like a sealed message, so a reader can
+// check and hand out one chunk at a time. The nonce of a chunk is a random
+// prefix, the chunk's number and whether it is the last, so chunks can't be
+// reordered, dropped or cut off at a chunk boundary without it showing.
+// Every chunk but the last holds exactly `CHUNK_SIZE` bytes.
+
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+const MAGIC: &[u8; 7] = b"TUGSTRM";
+const STREAM_VERSION: u8 = 1;
+// The nonce less the 4 byte chunk number and the last chunk flag
+const PREFIX_SIZE: usize = NONCE_SIZE - 5;
+const HEADER_SIZE: usize = MAGIC.len() + 1 + PREFIX_SIZE;
+
+type HmacSha256 = Hmac<Sha256>;
+
+struct ChunkKeys {
+    cipher: [u8; 32],
+    mac: [u8; 32],
+    prefix: [u8; PREFIX_SIZE],
+}
+
+impl ChunkKeys {
+    fn new(key: &[u8; 32], prefix: [u8; PREFIX_SIZE]) -> Self {
+        ChunkKeys {
+            cipher: derive_key(key, b"stream encryption"),
+            mac: derive_key(key, b"stream mac"),
+            prefix,
+        }
+    }
+
+    fn nonce(&self, counter: u32, last: bool) -> [u8; NONCE_SIZE] {
+        let mut nonce = [0; NONCE_SIZE];
+        nonce[..PREFIX_SIZE].copy_from_slice(&self.prefix);
+        nonce[PREFIX_SIZE..NONCE_SIZE - 1].copy_from_slice(&counter.to_be_bytes());
+        nonce[NONCE_SIZE - 1] = u8::from(last);
+        nonce
+    }
+
+    fn mac(&self, nonce: &[u8; NONCE_SIZE], ciphertext: &[u8]) -> HmacSha256 {
+        let mut mac = <HmacSha256 as Mac>::new_from_slice(&self.mac)
+            .expect("HMAC accepts keys of any length");
+        mac.update(nonce);
+        mac.update(ciphertext);
+        mac
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Encrypts what is written to it in chunks. `finish` must be called to
+// write the last chunk, without it a reader takes the stream as cut off.
+pub struct StreamWriter<W: Write> {
+    writer: W,
+    keys: ChunkKeys,
+    counter: u32,
+    // Plaintext not yet making up a whole chunk
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> StreamWriter<W> {
+    pub fn new(mut writer: W, key: &[u8; 32]) -> io::Result<Self> {
+        let mut prefix = [0; PREFIX_SIZE];
+        rand::rng().fill_bytes(&mut prefix);
+        writer.write_all(&[&MAGIC[..], &[STREAM_VERSION], &prefix].concat())?;
+
+        Ok(StreamWriter {
+            writer,
+            keys: ChunkKeys::new(key, prefix),
+            counter: 0,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+        })
+    }
+
+    // Writes the last chunk and returns the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_chunk(true)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_chunk(&mut self, last: bool) -> io::Result<()> {
+        let length = self.buffer.len().min(CHUNK_SIZE);
+        let mut chunk: Vec<u8> = self.buffer.drain(..length).collect();
+        let nonce = self.keys.nonce(self.counter, last);
+        apply_keystream(&self.keys.cipher, &nonce, &mut chunk);
+
+        self.writer.write_all(&chunk)?;
+        self.writer
+            .write_all(&self.keys.mac(&nonce, &chunk).finalize().into_bytes())?;
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| io::Error::other("stream too long"))?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for StreamWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let taken = buf.len().min(CHUNK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..taken]);
+        // A full chunk is written right away, so the last one is always
+        // shorter, even if empty
+        if self.buffer.len() == CHUNK_SIZE {
+            self.write_chunk(false)?;
+        }
+        Ok(taken)
+    }
+
+    // Only flushes the inner writer, a chunk is written once it is full
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// Decrypts a stream from `StreamWriter` chunk by chunk. A chunk's plaintext
+// is only handed out once its MAC is checked, and reading fails with
+// `InvalidData` on a wrong key, a changed or missing chunk or a cut off
+// stream, so a caller may already have the chunks before the bad one.
+pub struct StreamReader<R: Read> {
+    reader: R,
+    keys: ChunkKeys,
+    counter: u32,
+    plaintext: Vec<u8>,
+    // Of the next byte of `plaintext` to hand out
+    position: usize,
+    done: bool,
+}
+
+impl<R: Read> StreamReader<R> {
+    pub fn new(mut reader: R, key: &[u8; 32]) -> io::Result<Self> {
+        let mut header = [0; HEADER_SIZE];
+        reader
+            .read_exact(&mut header)
+            .map_err(|_| invalid("not an encrypted stream"))?;
+        if !header.starts_with(MAGIC) {
+            return Err(invalid("not an encrypted stream"));
+        }
+        if header[MAGIC.len()] != STREAM_VERSION {
+            return Err(invalid("unsupported stream version"));
+        }
+        let prefix = header[MAGIC.len() + 1..]
+            .try_into()
+            .expect("the prefix ends the header");
+
+        Ok(StreamReader {
+            reader,
+            keys: ChunkKeys::new(key, prefix),
+            counter: 0,
+            plaintext: Vec::new(),
+            position: 0,
+            done: false,
+        })
+    }
+
+    fn read_chunk(&mut self) -> io::Result<()> {
+        let mut chunk = vec![0; CHUNK_SIZE + TAG_SIZE];
+        let read = read_full(&mut self.reader, &mut chunk)?;
+        // Only the last chunk is short
+        let last = read < chunk.len();
+        if read < TAG_SIZE {
+            return Err(invalid("encrypted stream is cut off"));
+        }
+        chunk.truncate(read);
+
+        let tag = chunk.split_off(read - TAG_SIZE);
+        let nonce = self.keys.nonce(self.counter, last);
+        self.keys
+            .mac(&nonce, &chunk)
+            .verify_slice(&tag)
+            .map_err(|_| invalid("wrong key or modified stream"))?;
+        apply_keystream(&self.keys.cipher, &nonce, &mut chunk);
+
+        if last && read_full(&mut self.reader, &mut [0])? != 0 {
+            return Err(invalid("data after the end of the encrypted stream"));
+        }
+        self.done = last;
+        self.counter = self.counter.wrapping_add(1);
+        self.plaintext = chunk;
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for StreamReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.plaintext.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.read_chunk()?;
+        }
+
+        let read = buf.len().min(self.plaintext.len() - self.position);
+        buf[..read].copy_from_slice(&self.plaintext[self.position..self.position + read]);
+        self.position += read;
+        Ok(read)
+    }
+}
+
+// Like `read_exact`, but returns how much was read when the end comes first
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [5; 32];
+    const CHUNK: usize = CHUNK_SIZE + TAG_SIZE;
+
+    fn encrypt(plaintext: &[u8]) -> Vec<u8> {
+        let mut writer = StreamWriter::new(Vec::new(), &KEY).unwrap();
+        // Uneven writes, so chunks don't line up with them
+        for part in plaintext.chunks(1000) {
+            writer.write_all(part).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn decrypt(stream: &[u8], key: &[u8; 32]) -> io::Result<Vec<u8>> {
+        let mut plaintext = Vec::new();
+        StreamReader::new(stream, key)?.read_to_end(&mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for length in [0, 1, CHUNK_SIZE - 1, CHUNK_SIZE, 2 * CHUNK_SIZE + 5] {
+            let plaintext: Vec<u8> = (0..length).map(|i| (i % 251) as u8).collect();
+            let stream = encrypt(&plaintext);
+
+            let chunks = length / CHUNK_SIZE + 1;
+            assert_eq!(stream.len(), HEADER_SIZE + length + chunks * TAG_SIZE);
+            assert_eq!(decrypt(&stream, &KEY).unwrap(), plaintext, "{}", length);
+        }
+    }
+
+    #[test]
+    fn test_reads_in_small_pieces() {
+        let plaintext = vec![9; CHUNK_SIZE + 10];
+        let stream = encrypt(&plaintext);
+        let mut reader = StreamReader::new(stream.as_slice(), &KEY).unwrap();
+
+        let mut piece = [0; 7];
+        let mut read = Vec::new();
+        loop {
+            match reader.read(&mut piece).unwrap() {
+                0 => break,
+                n => read.extend_from_slice(&piece[..n]),
+            }
+        }
+        assert_eq!(read, plaintext);
+    }
+
+    #[test]
+    fn test_rejects_changes() {
+        let stream = encrypt(&vec![1; 2 * CHUNK_SIZE + 5]);
+        let is_invalid = |stream: &[u8], key: &[u8; 32]| {
+            decrypt(stream, key).unwrap_err().kind() == io::ErrorKind::InvalidData
+        };
+
+        assert!(is_invalid(&stream, &[6; 32]));
+        // Cut off at a chunk boundary, and within the last chunk
+        assert!(is_invalid(&stream[..HEADER_SIZE + CHUNK], &KEY));
+        assert!(is_invalid(&stream[..stream.len() - 1], &KEY));
+        // The first two chunks swapped
+        let mut swapped = stream[..HEADER_SIZE].to_vec();
+        swapped.extend_from_slice(&stream[HEADER_SIZE + CHUNK..HEADER_SIZE + 2 * CHUNK]);
+        swapped.extend_from_slice(&stream[HEADER_SIZE..HEADER_SIZE + CHUNK]);
+        swapped.extend_from_slice(&stream[HEADER_SIZE + 2 * CHUNK..]);
+        assert!(is_invalid(&swapped, &KEY));
+        let mut changed = stream.clone();
+        changed[HEADER_SIZE + CHUNK + 3] ^= 1;
+        assert!(is_invalid(&changed, &KEY));
+        let mut extended = stream.clone();
+        extended.push(0);
+        assert!(is_invalid(&extended, &KEY));
+        assert!(is_invalid(b"not a stream", &KEY));
+    }
+}